@@ -1,86 +1,70 @@
-use serde::{Deserialize, Serialize};
 use std::error::Error;
-use crate::config::Config;
-
-// Tag generation with API
-#[derive(Serialize)]
-struct ChatRequest {
-    model: String,
-    messages: Vec<ChatMessage>,
-    temperature: f32,
-}
-
-#[derive(Serialize)]
-struct ChatMessage {
-    role: String,
-    content: String,
-}
+use std::time::{Duration, Instant};
+use crate::config::{Config, ProviderRole};
+use crate::provider::{ChatMessage, ChatRequest, ProviderError};
+use crate::{crypto, secrets};
 
-#[derive(Deserialize)]
-struct ChatResponse {
-    choices: Vec<ChatChoice>,
-}
+/// Longest slice of each snippet sent in a batched tagging request.
+const BATCH_SNIPPET_CHARS: usize = 2_000;
 
-#[derive(Deserialize)]
-struct ChatChoice {
-    message: ChatMessageResponse,
+/// Normalize a comma-separated tag list: lower-case, collapse inner
+/// whitespace, drop empties and duplicates, keep at most 10 tags.
+pub fn sanitize_tags(tags: &str) -> String {
+    split_tags(tags).join(", ")
 }
 
-#[derive(Deserialize)]
-struct ChatMessageResponse {
-    role: String,
-    content: String,
+/// Split a comma-separated tag list into normalized, de-duplicated names.
+pub fn split_tags(tags: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for tag in tags.split(',') {
+        let tag = normalize_tag(tag);
+        if !tag.is_empty() && !out.contains(&tag) {
+            out.push(tag);
+        }
+        if out.len() == 10 {  // Limit to 10 tags
+            break;
+        }
+    }
+    out
 }
 
-pub fn sanitize_tags(tags: &str) -> String {
-    tags.split(',')
-        .map(|tag| tag.trim())
-        .filter(|tag| !tag.is_empty())
-        .take(10)  // Limit to 10 tags
+/// Normalize a single tag name.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.split_whitespace()
         .collect::<Vec<_>>()
-        .join(", ")
+        .join(" ")
+        .to_lowercase()
 }
 
 pub async fn get_tags(content: &str, config: &Config) -> Result<String, Box<dyn Error>> {
-    // Skip if auto-generate is disabled
-    if !config.auto_generate_tags {
+    // Skip if auto-generate is disabled; encrypted content never leaves the machine
+    if !config.auto_generate_tags || crypto::is_encrypted(content) {
         return Ok(config.default_tags.join(", "));
     }
 
-    // Try using API if key is available
-    if let Some(key) = &config.tag_api_key {
-        let model = config.ai_model.as_deref().unwrap_or("glm-5-turbo");
-        let base_url = config.ai_base_url.as_deref().unwrap_or("https://api.z.ai/api/coding/paas/v4");
-        let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
-
-        let reqbody = serde_json::json!({
-            "model": model,
-            "messages": [{"role":"user","content":format!("Extract 3-5 relevant tags separated by commas:\n{}",content)}],
-            "temperature": 0.1,
-        });
-        
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&url)
-            .header("Authorization", format!("Bearer {}", key))
-            .json(&reqbody)
-            .send()
-            .await;
-            
-        // If successful, parse and return tags
-        if let Ok(r) = response {
-            if r.status().is_success() {
-                if let Ok(resp) = r.json::<ChatResponse>().await {
-                    if let Some(choice) = resp.choices.first() {
-                        let tags = choice.message.content.trim().to_string();
-                        return Ok(sanitize_tags(&tags));
-                    }
-                }
+    // Try the configured provider first
+    if let Ok(provider) = config.provider(ProviderRole::Tagging) {
+        let prompt = format!("Extract 3-5 relevant tags separated by commas:\n{}", secrets::redact(content));
+        let mut request = ChatRequest::new(provider.model(), vec![ChatMessage::User(prompt)]);
+        request.temperature = Some(0.1);
+
+        // If successful, return the tags
+        if let Ok(response) = provider.chat(&request).await {
+            let tags = sanitize_tags(response.text.trim());
+            if !tags.is_empty() {
+                return Ok(tags);
             }
         }
     }
     
-    // Fallback: Extract common programming words or use default tags
+    Ok(fallback_tags(content, config))
+}
+
+/// Tags without AI: language words found in the content, else the defaults.
+fn fallback_tags(content: &str, config: &Config) -> String {
+    if crypto::is_encrypted(content) {
+        return config.default_tags.join(", ");
+    }
     let common_langs = ["rust", "python", "javascript", "html", "css", "sql", "bash", "code", "snippet"];
     let detected: Vec<&str> = common_langs
         .iter()
@@ -89,8 +73,137 @@ pub async fn get_tags(content: &str, config: &Config) -> Result<String, Box<dyn
         .collect();
     
     if !detected.is_empty() {
-        Ok(detected.join(", "))
+        detected.join(", ")
     } else {
-        Ok(config.default_tags.join(", "))
+        config.default_tags.join(", ")
+    }
+}
+
+/// Spaces out AI calls to at most `per_minute` per minute (0 = unlimited).
+pub struct RateLimiter {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn per_minute(per_minute: u32) -> Self {
+        let interval = match per_minute {
+            0 => Duration::ZERO,
+            n => Duration::from_secs(60) / n,
+        };
+        Self { interval, last: None }
+    }
+
+    /// Sleep until the next call is allowed.
+    pub async fn wait(&mut self) {
+        if let Some(last) = self.last {
+            let next = last + self.interval;
+            let now = Instant::now();
+            if next > now {
+                tokio::time::sleep(next - now).await;
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+}
+
+/// Tags for several snippets from one AI request. Snippets the reply leaves
+/// out, or every snippet if the request fails, get the heuristic tags. A
+/// rate-limited (429) request is retried once after a pause. Encrypted
+/// snippets are left out of the request and get the default tags.
+pub async fn get_tags_batch(contents: &[&str], config: &Config, limiter: &mut RateLimiter) -> Vec<String> {
+    let mut tags: Vec<Option<String>> = vec![None; contents.len()];
+    if !config.auto_generate_tags {
+        return vec![config.default_tags.join(", "); contents.len()];
+    }
+
+    let any_plain = contents.iter().any(|c| !crypto::is_encrypted(c));
+    if let Some(provider) = config.provider(ProviderRole::Tagging).ok().filter(|_| any_plain) {
+        let mut prompt = String::from(
+            "Extract 3-5 relevant tags for each numbered snippet below. Reply with one line \
+             per snippet, formatted as `<number>: tag, tag, tag`, and nothing else.\n",
+        );
+        for (i, content) in contents.iter().enumerate().filter(|(_, c)| !crypto::is_encrypted(c)) {
+            let excerpt: String = secrets::redact(content).chars().take(BATCH_SNIPPET_CHARS).collect();
+            prompt.push_str(&format!("\n### {}\n{}\n", i + 1, excerpt));
+        }
+        let mut request = ChatRequest::new(provider.model(), vec![ChatMessage::User(prompt)]);
+        request.temperature = Some(0.1);
+
+        for attempt in 0..2 {
+            limiter.wait().await;
+            match provider.chat(&request).await {
+                Ok(response) => {
+                    for (i, line_tags) in parse_numbered_tags(&response.text) {
+                        let sealed = contents.get(i.wrapping_sub(1)).is_some_and(|c| crypto::is_encrypted(c));
+                        if let Some(slot) = tags.get_mut(i.wrapping_sub(1)).filter(|_| !sealed) {
+                            let line_tags = sanitize_tags(&line_tags);
+                            if !line_tags.is_empty() {
+                                *slot = Some(line_tags);
+                            }
+                        }
+                    }
+                    break;
+                }
+                Err(ProviderError::Http { status: 429, .. }) if attempt == 0 => {
+                    tokio::time::sleep(limiter.interval.max(Duration::from_secs(5))).await;
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    tags.into_iter()
+        .zip(contents)
+        .map(|(t, content)| t.unwrap_or_else(|| fallback_tags(content, config)))
+        .collect()
+}
+
+/// `(number, tags)` pairs from lines like `2: sql, joins` or `2. sql, joins`.
+fn parse_numbered_tags(text: &str) -> Vec<(usize, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches(['#', '-', '*', ' ']);
+            let digits: String = line.chars().take_while(char::is_ascii_digit).collect();
+            let n = digits.parse().ok()?;
+            let rest = line[digits.len()..].trim_start().strip_prefix([':', '.', ')'])?;
+            Some((n, rest.trim().to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize_tags_normalizes() {
+        assert_eq!(sanitize_tags(" Rust ,  Async   IO,rust,, "), "rust, async io");
+        let many: Vec<String> = (0..15).map(|i| format!("t{}", i)).collect();
+        assert_eq!(split_tags(&many.join(",")).len(), 10);
+    }
+
+    #[test]
+    fn parses_numbered_tag_lines() {
+        let reply = "Here you go:\n1: SQL, Joins\n2. bash , cli\n- 3) python\n4 no separator\n";
+        assert_eq!(
+            parse_numbered_tags(reply),
+            vec![
+                (1, "SQL, Joins".to_string()),
+                (2, "bash , cli".to_string()),
+                (3, "python".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_calls() {
+        let mut limiter = RateLimiter::per_minute(1200); // one per 50ms
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(RateLimiter::per_minute(0).interval, Duration::ZERO);
     }
 }
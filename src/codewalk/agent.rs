@@ -31,8 +31,8 @@ use serde::Deserialize;
 use serde_json::json;
 use tokio::sync::mpsc;
 
-use super::meerkat_spike::OpenRouterChatClient;
-use super::types::{ApiConfig, ApiProvider, ConversationMessage, DeepDiveTag, StreamEvent};
+use super::meerkat_spike::ProviderChatClient;
+use super::types::{ApiConfig, ConversationMessage, DeepDiveTag, StreamEvent};
 
 // ── Tool dispatcher ───────────────────────────────────────────────────────────
 
@@ -324,17 +324,10 @@ async fn run_walk_step(
     step_number: usize,
     next_file_hint: Option<String>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let (api_key, base_url) = match &api_config.provider {
-        ApiProvider::OpenRouter { api_key, base_url } => (api_key.clone(), base_url.clone()),
-        ApiProvider::Anthropic { .. } => {
-            return Err("Walk agent requires OpenRouter (Anthropic not supported yet)".into());
-        }
-    };
-
     let tmp = tempfile::TempDir::new()?;
     let factory = AgentFactory::new(tmp.path().to_path_buf());
 
-    let client: Arc<dyn LlmClient> = Arc::new(OpenRouterChatClient::new(&api_key, &base_url));
+    let client: Arc<dyn LlmClient> = Arc::new(ProviderChatClient::new(Arc::clone(&api_config.provider)));
     let llm = factory.build_llm_adapter(client, &api_config.model).await;
     let store = Arc::new(StoreAdapter::new(Arc::new(JsonlStore::new(
         factory.store_path.clone(),
@@ -29,8 +29,8 @@ use serde::Deserialize;
 use serde_json::json;
 use tokio::sync::{Mutex, Semaphore};
 
-use super::meerkat_spike::OpenRouterChatClient;
-use super::types::{ApiConfig, ApiProvider, BudgetConfig, FileRef, ModuleFindings, ModuleSummary};
+use super::meerkat_spike::ProviderChatClient;
+use super::types::{ApiConfig, BudgetConfig, FileRef, ModuleFindings, ModuleSummary};
 
 // ── Budget state ──────────────────────────────────────────────────────────────
 
@@ -275,20 +275,6 @@ async fn audit_one_module(
         };
     }
 
-    let (api_key, base_url) = match &api_config.provider {
-        ApiProvider::OpenRouter { api_key, base_url } => (api_key.clone(), base_url.clone()),
-        ApiProvider::Anthropic { .. } => {
-            return ModuleFindings {
-                module_path: module.path,
-                purpose: module.purpose,
-                findings: vec!["Error: Anthropic not supported for sub-agents (use OpenRouter).".to_string()],
-                risks: vec![],
-                file_refs: vec![],
-                tool_calls_used: 0,
-            };
-        }
-    };
-
     let tmp = match tempfile::TempDir::new() {
         Ok(t) => t,
         Err(e) => {
@@ -304,7 +290,7 @@ async fn audit_one_module(
     };
 
     let factory = AgentFactory::new(tmp.path().to_path_buf());
-    let client: Arc<dyn LlmClient> = Arc::new(OpenRouterChatClient::new(&api_key, &base_url));
+    let client: Arc<dyn LlmClient> = Arc::new(ProviderChatClient::new(Arc::clone(&api_config.provider)));
     let llm = factory.build_llm_adapter(client, &api_config.model).await;
     let store = Arc::new(StoreAdapter::new(Arc::new(JsonlStore::new(
         factory.store_path.clone(),
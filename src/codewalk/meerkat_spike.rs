@@ -1,11 +1,11 @@
-//! Phase 0 spike: validate Meerkat integration with OpenRouter.
+//! Phase 0 spike: validate Meerkat integration with the configured provider.
 //!
 //! This module is a throwaway proof-of-concept. It is entirely gated behind the
 //! `meerkat` Cargo feature and has zero impact on the default build.
 //!
 //! Goal: confirm that
 //!   1. Meerkat compiles into this binary
-//!   2. A custom `LlmClient` backed by OpenRouter's Chat Completions API works
+//!   2. A custom `LlmClient` backed by a `Provider` works
 //!   3. Tool-call round-trips work (model calls `read_file`, gets content, responds)
 //!
 //! Run: `cargo run --features meerkat -- codewalk --meerkat-spike <path>`
@@ -26,6 +26,9 @@ use meerkat_store::StoreAdapter;
 use serde::Deserialize;
 use serde_json::json;
 
+use crate::codewalk::types::ApiConfig;
+use crate::provider::{ChatMessage, ChatRequest, Provider, ProviderError, ToolCall, ToolSpec};
+
 // ── helpers ─────────────────────────────────────────────────────────────────
 
 fn text_from_blocks(blocks: &[ContentBlock]) -> String {
@@ -39,69 +42,45 @@ fn text_from_blocks(blocks: &[ContentBlock]) -> String {
         .join("\n")
 }
 
-// ── OpenRouter Chat Completions client ──────────────────────────────────────
+// ── Provider-backed chat client ─────────────────────────────────────────────
 
-/// A minimal `LlmClient` that talks to any OpenAI-compatible Chat Completions
-/// endpoint. Used here to bridge Meerkat's agent harness with OpenRouter.
-pub struct OpenRouterChatClient {
-    api_key: String,
-    base_url: String,
+/// A `LlmClient` backed by any configured `Provider`, bridging Meerkat's agent
+/// harness to OpenAI-compatible, Anthropic and Ollama backends alike.
+pub struct ProviderChatClient {
+    provider: Arc<dyn Provider>,
 }
 
-impl OpenRouterChatClient {
-    pub fn new(api_key: impl Into<String>, base_url: impl Into<String>) -> Self {
-        Self {
-            api_key: api_key.into(),
-            base_url: base_url.into(),
-        }
+impl ProviderChatClient {
+    pub fn new(provider: Arc<dyn Provider>) -> Self {
+        Self { provider }
     }
 }
 
-/// Convert Meerkat message history to OpenAI Chat Completions message array.
-fn to_chat_messages(messages: &[Message]) -> Vec<serde_json::Value> {
+/// Convert Meerkat message history to provider messages.
+fn to_chat_messages(messages: &[Message]) -> Vec<ChatMessage> {
     messages
         .iter()
-        .flat_map(|msg| -> Vec<serde_json::Value> {
+        .flat_map(|msg| -> Vec<ChatMessage> {
             match msg {
-                Message::System(s) => {
-                    vec![json!({"role": "system", "content": s.content})]
-                }
-                Message::User(u) => {
-                    vec![json!({"role": "user", "content": text_from_blocks(&u.content)})]
-                }
-                Message::Assistant(a) => {
-                    let mut obj = json!({
-                        "role": "assistant",
-                        "content": if a.content.is_empty() {
-                            serde_json::Value::Null
-                        } else {
-                            json!(a.content)
-                        },
-                    });
-                    if !a.tool_calls.is_empty() {
-                        obj["tool_calls"] = json!(a
-                            .tool_calls
-                            .iter()
-                            .map(|tc| json!({
-                                "id": tc.id,
-                                "type": "function",
-                                "function": {
-                                    "name": tc.name,
-                                    "arguments": tc.args.to_string(),
-                                }
-                            }))
-                            .collect::<Vec<_>>());
-                    }
-                    vec![obj]
-                }
+                Message::System(s) => vec![ChatMessage::System(s.content.clone())],
+                Message::User(u) => vec![ChatMessage::User(text_from_blocks(&u.content))],
+                Message::Assistant(a) => vec![ChatMessage::Assistant {
+                    text: a.content.clone(),
+                    tool_calls: a
+                        .tool_calls
+                        .iter()
+                        .map(|tc| ToolCall {
+                            id: tc.id.clone(),
+                            name: tc.name.clone(),
+                            arguments: tc.args.clone(),
+                        })
+                        .collect(),
+                }],
                 Message::ToolResults { results } => results
                     .iter()
-                    .map(|r| {
-                        json!({
-                            "role": "tool",
-                            "tool_call_id": r.tool_use_id,
-                            "content": text_from_blocks(&r.content),
-                        })
+                    .map(|r| ChatMessage::ToolResult {
+                        call_id: r.tool_use_id.clone(),
+                        content: text_from_blocks(&r.content),
                     })
                     .collect(),
                 // BlockAssistant and future variants — skip for spike
@@ -111,94 +90,55 @@ fn to_chat_messages(messages: &[Message]) -> Vec<serde_json::Value> {
         .collect()
 }
 
-/// Convert Meerkat tool definitions to Chat Completions format.
-fn to_chat_tools(tools: &[Arc<ToolDef>]) -> Vec<serde_json::Value> {
+/// Convert Meerkat tool definitions to provider tool specs.
+fn to_chat_tools(tools: &[Arc<ToolDef>]) -> Vec<ToolSpec> {
     tools
         .iter()
-        .map(|t| {
-            json!({
-                "type": "function",
-                "function": {
-                    "name": t.name,
-                    "description": t.description,
-                    "parameters": t.input_schema,
-                }
-            })
+        .map(|t| ToolSpec {
+            name: t.name.clone(),
+            description: t.description.clone(),
+            parameters: t.input_schema.clone(),
         })
         .collect()
 }
 
-/// Make a single non-streaming Chat Completions call and return normalized events.
+fn to_llm_error(e: ProviderError) -> LlmError {
+    match e {
+        ProviderError::Http { status, body } => LlmError::from_http_status(status, body),
+        ProviderError::Network(_) => LlmError::NetworkTimeout { duration_ms: 30_000 },
+        other => LlmError::Unknown { message: other.to_string() },
+    }
+}
+
+/// Make a single non-streaming chat call and return normalized events.
 async fn do_chat_completion(
-    api_key: &str,
-    base_url: &str,
+    provider: &dyn Provider,
     request: LlmRequest,
 ) -> Result<Vec<LlmEvent>, LlmError> {
-    let messages = to_chat_messages(&request.messages);
-    let tools = to_chat_tools(&request.tools);
-
-    let mut body = json!({
-        "model": request.model,
-        "messages": messages,
-        "max_tokens": request.max_tokens,
-        "stream": false,
-    });
-    if !tools.is_empty() {
-        body["tools"] = json!(tools);
-        body["tool_choice"] = json!("auto");
-    }
-    if let Some(temp) = request.temperature {
-        body["temperature"] = json!(temp);
-    }
+    let mut chat = ChatRequest::new(request.model.clone(), to_chat_messages(&request.messages));
+    chat.tools = to_chat_tools(&request.tools);
+    chat.max_tokens = Some(request.max_tokens);
+    chat.temperature = request.temperature;
 
-    let http = reqwest::Client::new();
-    let resp = http
-        .post(format!("{}/chat/completions", base_url))
-        .header("Authorization", format!("Bearer {api_key}"))
-        .header("Content-Type", "application/json")
-        .json(&body)
-        .send()
-        .await
-        .map_err(|_| LlmError::NetworkTimeout { duration_ms: 30_000 })?;
-
-    if !resp.status().is_success() {
-        let status = resp.status().as_u16();
-        let msg = resp.text().await.unwrap_or_default();
-        return Err(LlmError::from_http_status(status, msg));
-    }
-
-    let data: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| LlmError::Unknown { message: e.to_string() })?;
+    let response = provider.chat(&chat).await.map_err(to_llm_error)?;
 
     let mut events = Vec::<LlmEvent>::new();
-    let message = &data["choices"][0]["message"];
 
     // Tool calls — emit before text so the agent can dispatch them
-    if let Some(tool_calls) = message["tool_calls"].as_array() {
-        for tc in tool_calls {
-            let id = tc["id"].as_str().unwrap_or("").to_string();
-            let name = tc["function"]["name"].as_str().unwrap_or("").to_string();
-            let args: serde_json::Value =
-                serde_json::from_str(tc["function"]["arguments"].as_str().unwrap_or("{}"))
-                    .unwrap_or_default();
-            events.push(LlmEvent::ToolCallComplete { id, name, args, meta: None });
-        }
+    for tc in response.tool_calls {
+        events.push(LlmEvent::ToolCallComplete { id: tc.id, name: tc.name, args: tc.arguments, meta: None });
     }
 
     // Text
-    if let Some(text) = message["content"].as_str() {
-        if !text.is_empty() {
-            events.push(LlmEvent::TextDelta { delta: text.to_string(), meta: None });
-        }
+    if !response.text.is_empty() {
+        events.push(LlmEvent::TextDelta { delta: response.text, meta: None });
     }
 
     // Usage
     events.push(LlmEvent::UsageUpdate {
         usage: Usage {
-            input_tokens: data["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
-            output_tokens: data["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+            input_tokens: response.usage.input_tokens,
+            output_tokens: response.usage.output_tokens,
             cache_creation_tokens: None,
             cache_read_tokens: None,
         },
@@ -213,9 +153,9 @@ async fn do_chat_completion(
 }
 
 #[async_trait]
-impl LlmClient for OpenRouterChatClient {
+impl LlmClient for ProviderChatClient {
     fn provider(&self) -> &'static str {
-        "openrouter"
+        self.provider.kind().as_str()
     }
 
     async fn health_check(&self) -> Result<(), LlmError> {
@@ -228,13 +168,12 @@ impl LlmClient for OpenRouterChatClient {
     ) -> std::pin::Pin<
         Box<dyn futures_util::Stream<Item = Result<LlmEvent, LlmError>> + Send + 'a>,
     > {
-        let api_key = self.api_key.clone();
-        let base_url = self.base_url.clone();
+        let provider = Arc::clone(&self.provider);
         let request = request.clone();
 
         Box::pin(
             futures_util::stream::once(async move {
-                do_chat_completion(&api_key, &base_url, request).await
+                do_chat_completion(provider.as_ref(), request).await
             })
             .flat_map(|result| {
                 let events = match result {
@@ -312,17 +251,16 @@ impl AgentToolDispatcher for ReadFileTool {
 /// prints the model's summary. All output goes to stdout — this is a debug run,
 /// not a TUI session.
 pub async fn run_spike(
-    api_key: &str,
-    base_url: &str,
-    model: &str,
+    api_config: &ApiConfig,
     repo_path: &std::path::Path,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let model = api_config.model.as_str();
     let _tmp = tempfile::TempDir::new()?;
     let store_dir = _tmp.path().to_path_buf();
     let factory = AgentFactory::new(store_dir.clone());
 
     let client: Arc<dyn LlmClient> =
-        Arc::new(OpenRouterChatClient::new(api_key, base_url));
+        Arc::new(ProviderChatClient::new(Arc::clone(&api_config.provider)));
     let llm = factory.build_llm_adapter(client, model).await;
 
     let store = Arc::new(StoreAdapter::new(Arc::new(JsonlStore::new(
@@ -341,7 +279,11 @@ pub async fn run_spike(
         .build(Arc::new(llm), tools, store)
         .await;
 
-    println!("[spike] model={model}  base_url={base_url}");
+    println!(
+        "[spike] model={model}  provider={}  base_url={}",
+        api_config.provider.name(),
+        api_config.provider.endpoint().base_url
+    );
     let result = agent
         .run(ContentInput::Text(
             "Read src/main.rs using the read_file tool and explain what this program does."
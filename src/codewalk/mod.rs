@@ -17,7 +17,7 @@ pub mod ui;
 
 use crate::config::Config;
 use app::{CWInputMode, CWPanel, CodeWalkApp};
-use claude::{resolve_api_config, spawn_stream_request};
+use claude::spawn_stream_request;
 use crossterm::{
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEvent, MouseEventKind},
     execute,
@@ -30,11 +30,22 @@ use std::{error::Error, io, path::PathBuf, time::Duration};
 use tokio::sync::mpsc;
 use types::{StreamEvent, TechDebtNote, WalkMode};
 
+/// Which model a CodeWalk session talks to, and whether it runs without a terminal.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Provider name from the config registry; None uses the default one
+    pub provider: Option<String>,
+    /// Model override for that provider
+    pub model: Option<String>,
+    /// Run this many steps without the TUI, e.g. against a mock server in tests
+    pub headless_steps: Option<usize>,
+}
+
 /// Main entry point for a CodeWalk session
 pub async fn run_codewalk(
     scope: String,
     repo_path: PathBuf,
-    model: Option<String>,
+    options: WalkOptions,
     prompt_file: Option<PathBuf>,
     notes_file: Option<PathBuf>,
     output_path: Option<PathBuf>,
@@ -43,16 +54,11 @@ pub async fn run_codewalk(
     mode: WalkMode,
     resume_id: Option<String>,
 ) -> Result<(), Box<dyn Error>> {
-    let model = model.unwrap_or_else(|| config.ai_model.clone().unwrap_or_else(|| "z-ai/glm-5-turbo".to_string()));
-
-    // Resolve API config
-    let api_config = resolve_api_config(
-        &model,
-        config.anthropic_api_key.as_deref(),
-        config.tag_api_key.as_deref(),
-        config.ai_base_url.as_deref(),
-    )
-    .map_err(|e| -> Box<dyn Error> { e.into() })?;
+    // Resolve provider and model from the config registry
+    let api_config = config
+        .resolve_api_config(options.provider.as_deref(), options.model.as_deref())
+        .map_err(|e| -> Box<dyn Error> { e.into() })?;
+    let model = api_config.model.clone();
 
     // Index repository
     eprintln!("Indexing repository...");
@@ -244,13 +250,6 @@ pub async fn run_codewalk(
         }
     }
 
-    // Setup terminal
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
     // Create streaming channel
     let (stream_tx, mut stream_rx) = mpsc::unbounded_channel::<StreamEvent>();
 
@@ -334,26 +333,14 @@ pub async fn run_codewalk(
         );
     }
 
-    // Main event loop
-    let result = run_event_loop(
-        &mut terminal,
-        &mut app,
-        &mut stream_rx,
-        &stream_tx,
-        &api_config,
-        &system_prompt,
-        &mut repo_index,
-    )
-    .await;
-
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    // Main loop: interactive TUI, or a fixed number of steps without one
+    let result = match options.headless_steps {
+        Some(steps) => {
+            run_headless(&mut app, &mut stream_rx, &stream_tx, &api_config, &system_prompt, &mut repo_index, steps)
+                .await
+        }
+        None => run_tui(&mut app, &mut stream_rx, &stream_tx, &api_config, &system_prompt, &mut repo_index).await,
+    };
 
     // Export session if output path is set
     if let Some(output_path) = &app.output_path {
@@ -386,7 +373,7 @@ pub async fn run_codewalk(
         eprintln!("Session exported to {}", output_path.display());
     }
 
-    // Auto-save full session to ~/.config/gist/sessions/
+    // Auto-save full session to the sessions directory
     if !app.steps.is_empty() {
         let walk_mode = {
             #[cfg(feature = "meerkat")]
@@ -403,6 +390,74 @@ pub async fn run_codewalk(
     result
 }
 
+async fn run_tui(
+    app: &mut CodeWalkApp,
+    stream_rx: &mut mpsc::UnboundedReceiver<StreamEvent>,
+    stream_tx: &mpsc::UnboundedSender<StreamEvent>,
+    api_config: &types::ApiConfig,
+    system_prompt: &str,
+    repo_index: &mut RepoIndex,
+) -> Result<(), Box<dyn Error>> {
+    // Setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let result = run_event_loop(
+        &mut terminal,
+        app,
+        stream_rx,
+        stream_tx,
+        api_config,
+        system_prompt,
+        repo_index,
+    )
+    .await;
+
+    // Restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    result
+}
+
+/// Drive the walkthrough without a terminal UI: request steps until `steps`
+/// are loaded, printing progress to stderr. Used by `--headless`.
+async fn run_headless(
+    app: &mut CodeWalkApp,
+    stream_rx: &mut mpsc::UnboundedReceiver<StreamEvent>,
+    stream_tx: &mpsc::UnboundedSender<StreamEvent>,
+    api_config: &types::ApiConfig,
+    system_prompt: &str,
+    repo_index: &mut RepoIndex,
+    steps: usize,
+) -> Result<(), Box<dyn Error>> {
+    while app.is_streaming {
+        match stream_rx.recv().await {
+            Some(StreamEvent::Token(text)) => app.streaming_text.push_str(&text),
+            Some(StreamEvent::Done) => {
+                finalize_current_step(app, repo_index);
+                session::compact_conversation(&mut app.conversation, app.compaction_threshold);
+                let step = &app.steps[app.steps.len() - 1].response;
+                eprintln!("Step {}/{}: {}", app.steps.len(), steps, step.file);
+                if app.steps.len() < steps {
+                    request_next_step(app, stream_tx, api_config, system_prompt, repo_index);
+                }
+            }
+            Some(StreamEvent::Error(e)) => return Err(e.into()),
+            None => break,
+        }
+    }
+    Ok(())
+}
+
 async fn run_event_loop(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     app: &mut CodeWalkApp,
@@ -27,8 +27,8 @@ use serde::Deserialize;
 use serde_json::json;
 use tokio::sync::Mutex;
 
-use super::meerkat_spike::OpenRouterChatClient;
-use super::types::{ApiConfig, ApiProvider, RepoMap};
+use super::meerkat_spike::ProviderChatClient;
+use super::types::{ApiConfig, RepoMap};
 
 /// Fallback limits if no config is provided.
 const RECON_DEFAULT_MAX_TOOL_CALLS: usize = 100;
@@ -302,17 +302,10 @@ pub async fn run_recon(
     max_tool_calls: usize,
     max_wall_secs: u64,
 ) -> Result<RepoMap, Box<dyn std::error::Error>> {
-    let (api_key, base_url) = match &api_config.provider {
-        ApiProvider::OpenRouter { api_key, base_url } => (api_key.as_str(), base_url.as_str()),
-        ApiProvider::Anthropic { .. } => {
-            return Err("Recon agent requires OpenRouter (Anthropic not yet supported)".into());
-        }
-    };
-
     let tmp = tempfile::TempDir::new()?;
     let factory = AgentFactory::new(tmp.path().to_path_buf());
 
-    let client: Arc<dyn LlmClient> = Arc::new(OpenRouterChatClient::new(api_key, base_url));
+    let client: Arc<dyn LlmClient> = Arc::new(ProviderChatClient::new(Arc::clone(&api_config.provider)));
     let llm = factory.build_llm_adapter(client, &api_config.model).await;
     let store = Arc::new(StoreAdapter::new(Arc::new(JsonlStore::new(factory.store_path.clone()))));
 
@@ -375,12 +368,9 @@ pub async fn run_recon(
     })
 }
 
-/// Write the RepoMap to `~/.config/gist/sessions/<timestamp>-<repo>-recon.json`.
+/// Write the RepoMap to `<sessions dir>/<timestamp>-<repo>-recon.json`.
 pub fn save_recon_log(repo_map: &RepoMap, repo_path: &std::path::Path) {
-    let sessions_dir = dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("gist")
-        .join("sessions");
+    let sessions_dir = crate::codewalk::session::sessions_dir();
     let _ = std::fs::create_dir_all(&sessions_dir);
 
     let ts = chrono::Local::now().format("%Y%m%d-%H%M%S");
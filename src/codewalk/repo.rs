@@ -12,6 +12,19 @@ pub struct RepoIndex {
     file_cache: HashMap<String, String>,
 }
 
+/// Tree walker shared by CodeWalk and `gist add --dir`: skips hidden files
+/// and anything ignored by the repo's .gitignore or .git/info/exclude.
+pub fn walker(root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .hidden(true)
+        .git_ignore(true)
+        .git_global(false)
+        .git_exclude(true)
+        .max_depth(Some(12));
+    builder
+}
+
 impl RepoIndex {
     /// Build a file tree index of the repository (structure only, no file contents)
     pub fn build(repo_path: &Path) -> io::Result<Self> {
@@ -20,13 +33,7 @@ impl RepoIndex {
         let mut lang_counts: HashMap<String, usize> = HashMap::new();
         let mut file_count: usize = 0;
 
-        let walker = WalkBuilder::new(&repo_path)
-            .hidden(true)
-            .git_ignore(true)
-            .git_global(false)
-            .git_exclude(true)
-            .max_depth(Some(12))
-            .build();
+        let walker = walker(&repo_path).build();
 
         for entry in walker {
             let entry = match entry {
@@ -1,6 +1,7 @@
 //! Phase 3: Session persistence — save, load, list, resume, purge.
 //!
-//! Sessions are stored as JSON files in ~/.config/gist/sessions/<id>-walk.json.
+//! Sessions are stored as JSON files in <data dir>/sessions/<id>-walk.json
+//! (`~/.config/gist` unless `--data-dir` / `GIST_HOME` is set).
 //! Session IDs are timestamp-slug strings: "20260326-123456-myrepo".
 
 use std::path::{Path, PathBuf};
@@ -11,9 +12,8 @@ use crate::codewalk::types::{FullSession, RepoMap, SessionSummary, WalkMode};
 // ── Directory helpers ─────────────────────────────────────────────────────────
 
 pub fn sessions_dir() -> PathBuf {
-    dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join("gist")
+    crate::config::get_gist_dir()
+        .unwrap_or_else(|_| PathBuf::from("."))
         .join("sessions")
 }
 
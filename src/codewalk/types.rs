@@ -1,4 +1,6 @@
+use crate::provider::Provider;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// Claude's structured step response parsed from the JSON envelope
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,17 +55,10 @@ pub enum StreamEvent {
     Error(String),
 }
 
-/// Which API provider to use
-#[derive(Debug, Clone)]
-pub enum ApiProvider {
-    Anthropic { api_key: String },
-    OpenRouter { api_key: String, base_url: String },
-}
-
-/// Full API configuration for a session
+/// Full API configuration for a session, resolved by `Config::resolve_api_config`
 #[derive(Debug, Clone)]
 pub struct ApiConfig {
-    pub provider: ApiProvider,
+    pub provider: Arc<dyn Provider>,
     pub model: String,
 }
 
@@ -194,7 +189,7 @@ impl Default for BudgetConfig {
 
 // ── Session persistence ───────────────────────────────────────────────────────
 
-/// A complete serialized walk session (written to ~/.config/gist/sessions/)
+/// A complete serialized walk session (written to the sessions directory)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FullSession {
     pub id: String,
@@ -1,6 +1,9 @@
 use serde::{Deserialize, Serialize};
-use std::{env, error::Error, fs, path::PathBuf, process::Command};
+use std::{env, error::Error, fs, path::{Path, PathBuf}, process::Command, sync::{Arc, OnceLock}};
+use crate::codewalk::types::ApiConfig;
 use crate::models::Theme;
+use crate::provider::{self, Endpoint, Provider, ProviderKind};
+use crate::secrets::SecretPolicy;
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct CodewalkConfig {
@@ -52,6 +55,123 @@ impl Default for CodewalkConfig {
     }
 }
 
+/// `[github]` settings for `gist github pull/push`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct GithubConfig {
+    /// Gists REST API root; GitHub Enterprise uses `https://<host>/api/v3`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Personal access token with the `gist` scope; GITHUB_TOKEN takes precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+pub const DEFAULT_GITHUB_API_URL: &str = "https://api.github.com";
+
+/// `[run]` settings for `gist run`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RunConfig {
+    /// Seconds before a run is killed, unless `--timeout` is given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Interpreter command per language, e.g. `python = "python3.12"`
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub interpreters: std::collections::BTreeMap<String, String>,
+}
+
+/// `[secrets]` settings for the scan run when snippets are saved.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SecretsConfig {
+    /// `warn` (default), `block` or `off`
+    #[serde(default)]
+    pub policy: SecretPolicy,
+}
+
+/// `[encryption]` settings for passphrase-sealed snippets.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct EncryptionConfig {
+    /// Seal every snippet as it is saved or imported, not just `--encrypt` ones
+    #[serde(default)]
+    pub encrypt_all: bool,
+}
+
+/// `[sync]` settings for `gist sync`.
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct SyncConfig {
+    /// Shared folder (or git repository) used when `--dir` is not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+}
+
+/// A team library in the `[[remotes]]` list: a git repository of snippet
+/// files, cloned under `remotes/<name>` in the gist directory.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct RemoteConfig {
+    pub name: String,
+    pub url: String,
+}
+
+/// A named AI backend in the `[[providers]]` registry.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct ProviderConfig {
+    pub name: String,
+    pub kind: ProviderKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// Environment variable holding the key; takes precedence over `api_key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+}
+
+impl ProviderConfig {
+    fn new(name: &str, kind: ProviderKind) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            base_url: None,
+            api_key: None,
+            api_key_env: None,
+            model: None,
+            embedding_model: None,
+        }
+    }
+
+    fn key(&self, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+        self.api_key_env
+            .as_deref()
+            .and_then(env)
+            .or_else(|| self.api_key.clone())
+            .filter(|k| !k.is_empty())
+    }
+
+    fn is_usable(&self, env: &dyn Fn(&str) -> Option<String>) -> bool {
+        !self.kind.needs_key() || self.key(env).is_some()
+    }
+
+    /// Whether a key is available, for display.
+    pub fn has_key(&self) -> bool {
+        self.key(&env_var).is_some()
+    }
+}
+
+/// What a provider is being picked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderRole {
+    Tagging,
+    Embeddings,
+    Codewalk,
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok()
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Config {
     pub editor: String,
@@ -61,11 +181,38 @@ pub struct Config {
     pub tag_api_key: Option<String>,
     pub ai_model: Option<String>,
     pub ai_base_url: Option<String>,
+    /// Model used for `/embeddings` (semantic search)
+    #[serde(default)]
+    pub embedding_model: Option<String>,
     pub anthropic_api_key: Option<String>,
     #[serde(default)]
     pub codewalk: Option<CodewalkConfig>,
+    /// Days a deleted snippet stays in the trash; 0 keeps it until `gist trash empty`
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+    /// Provider used for tagging and embeddings (and CodeWalk unless overridden)
+    #[serde(default)]
+    pub default_provider: Option<String>,
+    #[serde(default)]
+    pub codewalk_provider: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<ProviderConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub github: Option<GithubConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secrets: Option<SecretsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<SyncConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remotes: Vec<RemoteConfig>,
 }
 
+fn default_trash_retention_days() -> u32 { 30 }
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -76,15 +223,209 @@ impl Default for Config {
             tag_api_key: None,
             ai_model: Some("glm-5-turbo".to_string()),
             ai_base_url: Some("https://api.z.ai/api/coding/paas/v4".to_string()),
+            embedding_model: None,
             anthropic_api_key: None,
             codewalk: None,
+            trash_retention_days: default_trash_retention_days(),
+            default_provider: None,
+            codewalk_provider: None,
+            providers: Vec::new(),
+            github: None,
+            run: None,
+            secrets: None,
+            encryption: None,
+            sync: None,
+            remotes: Vec::new(),
         }
     }
 }
 
+impl Config {
+    /// Gists API root: `override_url`, then `[github] api_url`, then api.github.com.
+    pub fn github_api_url(&self, override_url: Option<&str>) -> String {
+        override_url
+            .map(str::to_string)
+            .or_else(|| self.github.as_ref().and_then(|g| g.api_url.clone()))
+            .unwrap_or_else(|| DEFAULT_GITHUB_API_URL.to_string())
+    }
+
+    /// GitHub token from GITHUB_TOKEN, else `[github] token`.
+    pub fn github_token(&self) -> Option<String> {
+        env_var("GITHUB_TOKEN")
+            .filter(|t| !t.is_empty())
+            .or_else(|| self.github.as_ref().and_then(|g| g.token.clone()))
+            .filter(|t| !t.is_empty())
+    }
+
+    /// What saving a snippet that looks like it holds a secret does.
+    pub fn secret_policy(&self) -> SecretPolicy {
+        self.secrets.as_ref().map(|s| s.policy).unwrap_or_default()
+    }
+
+    /// Whether new and imported snippets are sealed automatically.
+    pub fn encrypt_all(&self) -> bool {
+        self.encryption.as_ref().is_some_and(|e| e.encrypt_all)
+    }
+
+    /// The team library called `name`, or the only one when no name is given.
+    pub fn remote(&self, name: Option<&str>) -> Result<&RemoteConfig, String> {
+        match name {
+            Some(name) => self
+                .remotes
+                .iter()
+                .find(|r| r.name == name)
+                .ok_or_else(|| format!("No team library named '{}'; see `gist remote list`", name)),
+            None => match self.remotes.as_slice() {
+                [only] => Ok(only),
+                [] => Err("No team library yet; add one with `gist remote add <name> <url>`".to_string()),
+                _ => Err(format!(
+                    "Several team libraries; name one of: {}",
+                    self.remotes.iter().map(|r| r.name.as_str()).collect::<Vec<_>>().join(", ")
+                )),
+            },
+        }
+    }
+
+    /// Every known provider: the `[[providers]]` entries, then the legacy
+    /// keys as `anthropic` (ANTHROPIC_API_KEY or `anthropic_api_key`) and
+    /// `default` (`tag_api_key` at `ai_base_url`), unless those names are taken.
+    pub fn provider_registry(&self) -> Vec<ProviderConfig> {
+        self.registry_with(&env_var)
+    }
+
+    fn registry_with(&self, env: &dyn Fn(&str) -> Option<String>) -> Vec<ProviderConfig> {
+        let mut registry = self.providers.clone();
+        let taken = |registry: &[ProviderConfig], name: &str| registry.iter().any(|p| p.name == name);
+
+        let anthropic_key = env("ANTHROPIC_API_KEY")
+            .filter(|k| !k.is_empty())
+            .or_else(|| self.anthropic_api_key.clone().filter(|k| !k.is_empty()));
+        if let Some(key) = anthropic_key {
+            if !taken(&registry, "anthropic") {
+                let mut p = ProviderConfig::new("anthropic", ProviderKind::Anthropic);
+                p.api_key = Some(key);
+                registry.push(p);
+            }
+        }
+
+        if let Some(key) = self.tag_api_key.clone().filter(|k| !k.is_empty()) {
+            if !taken(&registry, "default") {
+                let mut p = ProviderConfig::new("default", ProviderKind::OpenAi);
+                p.api_key = Some(key);
+                p.base_url = self.ai_base_url.clone();
+                registry.push(p);
+            }
+        }
+        registry
+    }
+
+    /// The provider to use for `role`.
+    pub fn provider(&self, role: ProviderRole) -> Result<Arc<dyn Provider>, String> {
+        self.provider_with(role, None, None, &env_var)
+    }
+
+    /// CodeWalk's provider and model; `name` and `model` override the configured choice.
+    pub fn resolve_api_config(&self, name: Option<&str>, model: Option<&str>) -> Result<ApiConfig, String> {
+        let provider = self.provider_with(ProviderRole::Codewalk, name, model, &env_var)?;
+        Ok(ApiConfig {
+            model: provider.model().to_string(),
+            provider,
+        })
+    }
+
+    /// Explicit names win. Otherwise CodeWalk keeps its old preference for
+    /// Anthropic, then the tagging key; tagging and embeddings use the
+    /// tagging key, then the first usable non-Anthropic entry.
+    fn provider_with(
+        &self,
+        role: ProviderRole,
+        name: Option<&str>,
+        model: Option<&str>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Arc<dyn Provider>, String> {
+        let registry = self.registry_with(env);
+        let wanted = name.map(str::to_string).or_else(|| match role {
+            ProviderRole::Codewalk => self.codewalk_provider.clone().or_else(|| self.default_provider.clone()),
+            _ => self.default_provider.clone(),
+        });
+
+        let entry = match wanted {
+            Some(name) => {
+                let entry = registry
+                    .into_iter()
+                    .find(|p| p.name == name)
+                    .ok_or_else(|| format!("No provider named '{}' in config.toml", name))?;
+                if !entry.is_usable(env) {
+                    return Err(format!("Provider '{}' has no API key (set api_key or api_key_env)", name));
+                }
+                entry
+            }
+            None => {
+                let preferred: &[&str] = match role {
+                    ProviderRole::Codewalk => &["anthropic", "default"],
+                    _ => &["default"],
+                };
+                let candidates: Vec<ProviderConfig> = registry
+                    .into_iter()
+                    .filter(|p| p.is_usable(env))
+                    .filter(|p| role == ProviderRole::Codewalk || p.kind != ProviderKind::Anthropic)
+                    .collect();
+                preferred
+                    .iter()
+                    .find_map(|n| candidates.iter().find(|p| p.name == *n))
+                    .or_else(|| candidates.first())
+                    .cloned()
+                    .ok_or_else(|| {
+                        "No AI provider configured. Set ANTHROPIC_API_KEY, configure anthropic_api_key \
+                         or tag_api_key in config.toml, or add a [[providers]] entry."
+                            .to_string()
+                    })?
+            }
+        };
+
+        let default_model = match role {
+            ProviderRole::Codewalk => "z-ai/glm-5-turbo",
+            _ => "glm-5-turbo",
+        };
+        let endpoint = Endpoint {
+            base_url: entry.base_url.clone().unwrap_or_else(|| entry.kind.default_base_url().to_string()),
+            api_key: entry.key(env),
+            model: model
+                .map(str::to_string)
+                .or_else(|| entry.model.clone())
+                .or_else(|| self.ai_model.clone())
+                .unwrap_or_else(|| default_model.to_string()),
+            embedding_model: entry.kind.default_embedding_model().map(|default| {
+                entry
+                    .embedding_model
+                    .clone()
+                    .or_else(|| self.embedding_model.clone())
+                    .unwrap_or_else(|| default.to_string())
+            }),
+            name: entry.name,
+        };
+        Ok(provider::build(entry.kind, endpoint))
+    }
+}
+
+/// Data directory chosen with `--data-dir` / `GIST_HOME` for this process.
+static DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Relocate the database, config, sessions and recon logs. Only the first call takes effect.
+pub fn set_data_dir(dir: &Path) -> Result<(), Box<dyn Error>> {
+    let _ = DATA_DIR.set(std::path::absolute(dir)?);
+    Ok(())
+}
+
+/// The data directory: `--data-dir` / `GIST_HOME` if set, else `~/.config/gist`.
 pub fn get_gist_dir() -> Result<PathBuf, Box<dyn Error>> {
-    let home = dirs::home_dir().ok_or("Could not determine home directory")?;
-    let dir = home.join(".config").join("gist");
+    let dir = match DATA_DIR.get() {
+        Some(dir) => dir.clone(),
+        None => {
+            let home = dirs::home_dir().ok_or("Could not determine home directory")?;
+            home.join(".config").join("gist")
+        }
+    };
 
     fs::create_dir_all(&dir)?;
     Ok(dir)
@@ -148,3 +489,90 @@ pub fn get_editor() -> String {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    fn ollama(name: &str) -> ProviderConfig {
+        ProviderConfig::new(name, ProviderKind::Ollama)
+    }
+
+    #[test]
+    fn legacy_keys_become_registry_entries() {
+        let mut config = Config { anthropic_api_key: Some("sk-ant".to_string()), ..Config::default() };
+        config.tag_api_key = Some("sk-tag".to_string());
+
+        let names: Vec<String> = config.registry_with(&no_env).into_iter().map(|p| p.name).collect();
+        assert_eq!(names, vec!["anthropic", "default"]);
+
+        let walk = config.provider_with(ProviderRole::Codewalk, None, None, &no_env).unwrap();
+        assert_eq!(walk.kind(), ProviderKind::Anthropic);
+        assert_eq!(walk.model(), "glm-5-turbo");
+        let tagging = config.provider_with(ProviderRole::Tagging, None, None, &no_env).unwrap();
+        assert_eq!(tagging.name(), "default");
+        assert_eq!(tagging.endpoint().base_url, "https://api.z.ai/api/coding/paas/v4");
+        assert_eq!(tagging.embedding_model(), Some("text-embedding-3-small"));
+
+        let env = |name: &str| (name == "ANTHROPIC_API_KEY").then(|| "from-env".to_string());
+        let walk = config.provider_with(ProviderRole::Codewalk, None, Some("claude-x"), &env).unwrap();
+        assert_eq!(walk.endpoint().api_key.as_deref(), Some("from-env"));
+        assert_eq!(walk.model(), "claude-x");
+    }
+
+    #[test]
+    fn configured_providers_and_explicit_choices() {
+        let mut local = ollama("local");
+        local.model = Some("llama3".to_string());
+        let mut hosted = ProviderConfig::new("hosted", ProviderKind::OpenAi);
+        hosted.api_key_env = Some("HOSTED_KEY".to_string());
+        let config = Config {
+            providers: vec![local, hosted],
+            anthropic_api_key: Some("sk-ant".to_string()),
+            codewalk_provider: Some("hosted".to_string()),
+            ..Config::default()
+        };
+
+        // Anthropic is never picked implicitly for tagging
+        let tagging = config.provider_with(ProviderRole::Tagging, None, None, &no_env).unwrap();
+        assert_eq!(tagging.name(), "local");
+        assert_eq!(tagging.model(), "llama3");
+        assert_eq!(tagging.endpoint().base_url, "http://localhost:11434");
+        assert_eq!(tagging.embedding_model(), Some("nomic-embed-text"));
+
+        let err = config.provider_with(ProviderRole::Codewalk, None, None, &no_env).unwrap_err();
+        assert!(err.contains("no API key"));
+        let env = |name: &str| (name == "HOSTED_KEY").then(|| "k".to_string());
+        let walk = config.provider_with(ProviderRole::Codewalk, None, None, &env).unwrap();
+        assert_eq!(walk.name(), "hosted");
+
+        let walk = config.provider_with(ProviderRole::Codewalk, Some("anthropic"), None, &no_env).unwrap();
+        assert_eq!(walk.embedding_model(), None);
+        assert!(config.provider_with(ProviderRole::Tagging, Some("missing"), None, &no_env).is_err());
+    }
+
+    #[test]
+    fn no_provider_is_an_error() {
+        let config = Config::default();
+        assert!(config.provider_with(ProviderRole::Embeddings, None, None, &no_env).is_err());
+    }
+
+    #[test]
+    fn providers_round_trip_through_toml() {
+        let config = Config {
+            providers: vec![ollama("local")],
+            default_provider: Some("local".to_string()),
+            ..Config::default()
+        };
+        let text = toml::to_string_pretty(&config).unwrap();
+        assert!(text.contains("[[providers]]"));
+        assert!(text.contains("kind = \"ollama\""));
+        let back: Config = toml::from_str(&text).unwrap();
+        assert_eq!(back.providers.len(), 1);
+        assert_eq!(back.default_provider.as_deref(), Some("local"));
+    }
+}
@@ -0,0 +1,112 @@
+//! Encrypted snippets: content sealed with XChaCha20-Poly1305 under a key
+//! derived from a passphrase with Argon2id.
+//!
+//! Sealed content is stored in place of the plaintext as one line of text,
+//! `gist:enc:v1:` followed by base64 of salt, nonce and ciphertext, so it
+//! passes through the database, export and import like any other content.
+//! Titles, descriptions and tags are not encrypted.
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use std::collections::HashMap;
+
+/// Marks content as sealed, and which format it uses.
+pub const PREFIX: &str = "gist:enc:v1:";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Whether `content` is sealed.
+pub fn is_encrypted(content: &str) -> bool {
+    content.starts_with(PREFIX)
+}
+
+/// A passphrase and the keys derived from it so far. Deriving is slow on
+/// purpose, so a keyring seals everything under one salt and remembers the
+/// key for each salt it has opened.
+pub struct Keyring {
+    passphrase: String,
+    salt: [u8; SALT_LEN],
+    keys: HashMap<[u8; SALT_LEN], Key>,
+}
+
+impl Keyring {
+    pub fn new(passphrase: &str) -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        salt.copy_from_slice(&XChaCha20Poly1305::generate_nonce(&mut OsRng)[..SALT_LEN]);
+        Keyring { passphrase: passphrase.to_string(), salt, keys: HashMap::new() }
+    }
+
+    fn key(&mut self, salt: [u8; SALT_LEN]) -> Result<Key, String> {
+        if let Some(key) = self.keys.get(&salt) {
+            return Ok(*key);
+        }
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(self.passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Could not derive a key: {}", e))?;
+        self.keys.insert(salt, key);
+        Ok(key)
+    }
+
+    /// Seal `plaintext`; sealing sealed content is a no-op.
+    pub fn encrypt(&mut self, plaintext: &str) -> Result<String, String> {
+        if is_encrypted(plaintext) {
+            return Ok(plaintext.to_string());
+        }
+        let key = self.key(self.salt)?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = XChaCha20Poly1305::new(&key)
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| "Encryption failed".to_string())?;
+        let mut blob = Vec::with_capacity(SALT_LEN + NONCE_LEN + sealed.len());
+        blob.extend_from_slice(&self.salt);
+        blob.extend_from_slice(&nonce);
+        blob.extend_from_slice(&sealed);
+        Ok(format!("{}{}", PREFIX, STANDARD.encode(blob)))
+    }
+
+    /// Open sealed content; plaintext comes back unchanged.
+    pub fn decrypt(&mut self, content: &str) -> Result<String, String> {
+        let Some(encoded) = content.strip_prefix(PREFIX) else {
+            return Ok(content.to_string());
+        };
+        let blob = STANDARD.decode(encoded.trim()).map_err(|_| "Encrypted content is corrupted".to_string())?;
+        if blob.len() < SALT_LEN + NONCE_LEN {
+            return Err("Encrypted content is corrupted".to_string());
+        }
+        let (salt, rest) = blob.split_at(SALT_LEN);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let key = self.key(salt.try_into().expect("salt length"))?;
+        let plain = XChaCha20Poly1305::new(&key)
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| "Wrong passphrase (or the encrypted content is corrupted)".to_string())?;
+        String::from_utf8(plain).map_err(|_| "Encrypted content is not text".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_wrong_passphrase() {
+        let mut ring = Keyring::new("correct horse");
+        let sealed = ring.encrypt("password=hunter2\n").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert!(!sealed.contains("hunter2"));
+        assert_eq!(ring.encrypt(&sealed).unwrap(), sealed);
+        // Same plaintext, fresh nonce
+        assert_ne!(ring.encrypt("password=hunter2\n").unwrap(), sealed);
+
+        let mut other = Keyring::new("correct horse");
+        assert_eq!(other.decrypt(&sealed).unwrap(), "password=hunter2\n");
+        assert_eq!(other.decrypt("plain").unwrap(), "plain");
+        assert!(Keyring::new("wrong").decrypt(&sealed).unwrap_err().contains("Wrong passphrase"));
+        assert!(other.decrypt("gist:enc:v1:AAAA").is_err());
+    }
+}
@@ -1,43 +1,50 @@
 use rusqlite::{params, Connection, Result as SqlResult};
-use std::{error::Error, path::PathBuf, fs};
+use std::{collections::HashMap, error::Error, path::PathBuf, fs};
 use serde::{Deserialize, Serialize};
-use crate::models::Gist;
+use crate::models::{Gist, GistMeta, Revision};
+use crate::ai::split_tags;
+use crate::tags::TagQuery;
+use crate::query::Query;
 use crate::config::get_gist_dir;
+use crate::crypto::{self, Keyring};
+use crate::extract::LineRange;
+use crate::migrations;
+use crate::utils::content_hash;
 
 /// Get the path to the database file.
 pub fn get_db_path() -> Result<PathBuf, Box<dyn Error>> {
     Ok(get_gist_dir()?.join("gists.db"))
 }
 
-/// Initialize the database connection and create tables if they don't exist.
+/// Open the database file without touching its schema.
+pub fn open_db() -> Result<Connection, Box<dyn Error>> {
+    let db = get_db_path()?;
+    Ok(Connection::open(db)?)
+}
+
+/// Initialize the database connection and apply any pending schema migrations.
+/// Existing databases are backed up before they are upgraded.
 pub fn init_db() -> Result<Connection, Box<dyn Error>> {
     let db = get_db_path()?;
-    let conn = Connection::open(db)?;
-    
-    // Create table if it doesn't exist
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS gists (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            content TEXT NOT NULL,
-            tags TEXT,
-            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-        )",
-        [],
-    )?;
-    
-    // Create indices if they don't exist
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_gists_content ON gists(content)",
-        [],
-    )?;
-    conn.execute(
-        "CREATE INDEX IF NOT EXISTS idx_gists_tags ON gists(tags)",
-        [],
-    )?;
-    
+    let conn = Connection::open(&db)?;
+    let report = migrations::migrate(&conn, Some(&db))?;
+    if let Some(backup) = &report.backup {
+        eprintln!(
+            "Upgraded database schema v{} -> v{} (backup: {})",
+            report.from_version,
+            report.to_version,
+            backup.display()
+        );
+    }
     Ok(conn)
 }
 
+/// Bring an open connection up to the latest schema, without a backup.
+pub fn init_schema(conn: &Connection) -> Result<(), Box<dyn Error>> {
+    migrations::migrate(conn, None)?;
+    Ok(())
+}
+
 /// Optimize the database by running VACUUM and ANALYZE.
 pub fn optimize_database(conn: &Connection) -> SqlResult<()> {
     // Run VACUUM to reclaim space and optimize the database
@@ -49,19 +56,93 @@ pub fn optimize_database(conn: &Connection) -> SqlResult<()> {
     Ok(())
 }
 
+/// Condition that hides trashed gists from normal queries.
+const NOT_TRASHED: &str = "gists.deleted_at IS NULL";
+
+/// Condition that leaves out snippets mirrored from team libraries.
+const PRIVATE: &str = "gists.library IS NULL";
+
+/// Columns selected for a `Gist`, in the order `row_to_gist` reads them.
+const GIST_COLUMNS: &str = "gists.id, gists.content, gists.tags, gists.created_at, \
+    gists.title, gists.description, gists.language, gists.source_path, gists.source_url, gists.updated_at, gists.library";
+const GIST_COLUMN_COUNT: usize = 11;
+
+fn row_to_gist(r: &rusqlite::Row) -> SqlResult<Gist> {
+    Ok(Gist {
+        id: r.get(0)?,
+        content: r.get(1)?,
+        tags: r.get::<_, Option<String>>(2)?.unwrap_or_default(),
+        created_at: r.get(3)?,
+        title: r.get(4)?,
+        description: r.get(5)?,
+        language: r.get(6)?,
+        source_path: r.get(7)?,
+        source_url: r.get(8)?,
+        updated_at: r.get(9)?,
+        library: r.get(10)?,
+    })
+}
+
+/// Blank strings are stored as NULL so "unset" has a single representation.
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
 /// Insert a new gist into the database.
 pub fn insert_gist(c: &Connection, content: &str, tags: &str) -> SqlResult<i64> {
+    insert_gist_with_meta(c, content, tags, &GistMeta::default())
+}
+
+/// Insert a new gist along with its title, description, language and source.
+pub fn insert_gist_with_meta(c: &Connection, content: &str, tags: &str, meta: &GistMeta) -> SqlResult<i64> {
+    // Normalize up front so the row is written once and logs a single change
+    let tags = split_tags(tags).join(", ");
     c.execute(
-        "INSERT INTO gists (content, tags) VALUES (?1, ?2)",
-        params![content, tags],
+        "INSERT INTO gists (content, tags, title, description, language, source_path, source_url, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, CURRENT_TIMESTAMP)",
+        params![
+            content,
+            tags,
+            non_empty(&meta.title),
+            non_empty(&meta.description),
+            non_empty(&meta.language),
+            non_empty(&meta.source_path),
+            non_empty(&meta.source_url),
+        ],
+    )?;
+    let id = c.last_insert_rowid();
+    sync_gist_tags(c, id, &tags)?;
+    record_revision(c, id)?;
+    Ok(id)
+}
+
+/// Replace a gist's title, description, language and source.
+pub fn update_gist_meta(c: &Connection, id: i64, meta: &GistMeta) -> SqlResult<()> {
+    let result = c.execute(
+        "UPDATE gists SET title=?1, description=?2, language=?3, source_path=?4, source_url=?5,
+                updated_at=CURRENT_TIMESTAMP
+         WHERE id=?6 AND deleted_at IS NULL",
+        params![
+            non_empty(&meta.title),
+            non_empty(&meta.description),
+            non_empty(&meta.language),
+            non_empty(&meta.source_path),
+            non_empty(&meta.source_url),
+            id,
+        ],
     )?;
-    Ok(c.last_insert_rowid())
+    
+    if result == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    Ok(())
 }
 
 /// Update an existing gist.
 pub fn update_gist(c: &Connection, id: i64, content: &str, tags: &str) -> SqlResult<()> {
+    let tags = split_tags(tags).join(", ");
     let result = c.execute(
-        "UPDATE gists SET content=?1, tags=?2 WHERE id=?3",
+        "UPDATE gists SET content=?1, tags=?2, updated_at=CURRENT_TIMESTAMP WHERE id=?3 AND deleted_at IS NULL",
         params![content, tags, id],
     )?;
     
@@ -69,90 +150,1403 @@ pub fn update_gist(c: &Connection, id: i64, content: &str, tags: &str) -> SqlRes
         return Err(rusqlite::Error::QueryReturnedNoRows);
     }
     
+    sync_gist_tags(c, id, &tags)?;
+    record_revision(c, id)?;
     Ok(())
 }
 
-/// Delete a gist by ID.
+/// Replace a gist's tag links with the normalized form of `tags`, and store
+/// the normalized comma-joined string back on the gist row.
+pub fn sync_gist_tags(c: &Connection, gist_id: i64, tags: &str) -> SqlResult<()> {
+    let names = split_tags(tags);
+    
+    c.execute("DELETE FROM gist_tags WHERE gist_id = ?1", params![gist_id])?;
+    for name in &names {
+        c.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", params![name])?;
+        c.execute(
+            "INSERT OR IGNORE INTO gist_tags (gist_id, tag_id)
+             SELECT ?1, id FROM tags WHERE name = ?2",
+            params![gist_id, name],
+        )?;
+    }
+    
+    let joined = names.join(", ");
+    c.execute(
+        "UPDATE gists SET tags = ?1 WHERE id = ?2 AND IFNULL(tags, '') != ?1",
+        params![joined, gist_id],
+    )?;
+    prune_unused_tags(c)?;
+    Ok(())
+}
+
+fn prune_unused_tags(c: &Connection) -> SqlResult<()> {
+    c.execute(
+        "DELETE FROM tags WHERE id NOT IN (SELECT DISTINCT tag_id FROM gist_tags)",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Rebuild the comma-joined `gists.tags` column from the link table for the given gists.
+fn refresh_tag_strings(c: &Connection, gist_ids: &[i64]) -> SqlResult<()> {
+    for id in gist_ids {
+        c.execute(
+            "UPDATE gists SET tags = (
+                SELECT IFNULL(GROUP_CONCAT(name, ', '), '') FROM (
+                    SELECT t.name FROM gist_tags gt JOIN tags t ON t.id = gt.tag_id
+                    WHERE gt.gist_id = ?1 ORDER BY gt.rowid
+                )
+             ) WHERE id = ?1",
+            params![id],
+        )?;
+    }
+    Ok(())
+}
+
+fn gists_with_tag(c: &Connection, tag_id: i64) -> SqlResult<Vec<i64>> {
+    let mut stmt = c.prepare("SELECT gist_id FROM gist_tags WHERE tag_id = ?1")?;
+    let ids = stmt.query_map(params![tag_id], |r| r.get(0))?;
+    ids.collect()
+}
+
+fn tag_id(c: &Connection, name: &str) -> SqlResult<Option<i64>> {
+    match c.query_row("SELECT id FROM tags WHERE name = ?1", params![name], |r| r.get(0)) {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Tags in use by live gists, with the number of gists using each, most used first.
+pub fn list_tags(c: &Connection) -> SqlResult<Vec<(String, i64)>> {
+    let sql = format!(
+        "SELECT t.name, COUNT(gists.id) AS uses FROM tags t
+         JOIN gist_tags gt ON gt.tag_id = t.id
+         JOIN gists ON gists.id = gt.gist_id AND {}
+         GROUP BY t.id ORDER BY uses DESC, t.name ASC",
+        NOT_TRASHED
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// Rename a tag everywhere. Fails if the target name is already in use.
+/// Returns the number of gists affected.
+pub fn rename_tag(c: &Connection, from: &str, to: &str) -> Result<usize, Box<dyn Error>> {
+    let from = crate::ai::normalize_tag(from);
+    let to = crate::ai::normalize_tag(to);
+    if to.is_empty() || to.contains(',') {
+        return Err(format!("Invalid tag name '{}'", to).into());
+    }
+    let id = tag_id(c, &from)?.ok_or_else(|| format!("Tag '{}' not found", from))?;
+    if tag_id(c, &to)?.is_some() {
+        return Err(format!("Tag '{}' already exists; use `gist tags merge` instead", to).into());
+    }
+    
+    let tx = c.unchecked_transaction()?;
+    tx.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![to, id])?;
+    let affected = gists_with_tag(&tx, id)?;
+    refresh_tag_strings(&tx, &affected)?;
+    tx.commit()?;
+    Ok(affected.len())
+}
+
+/// Fold each of `sources` into `target`, creating the target if needed.
+/// Returns the number of gists affected.
+pub fn merge_tags(c: &Connection, sources: &[String], target: &str) -> Result<usize, Box<dyn Error>> {
+    let target = crate::ai::normalize_tag(target);
+    if target.is_empty() || target.contains(',') {
+        return Err(format!("Invalid tag name '{}'", target).into());
+    }
+    
+    let tx = c.unchecked_transaction()?;
+    tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", params![target])?;
+    let target_id = tag_id(&tx, &target)?.ok_or("Failed to create target tag")?;
+    
+    let mut affected: Vec<i64> = Vec::new();
+    for source in sources {
+        let source = crate::ai::normalize_tag(source);
+        if source == target {
+            continue;
+        }
+        let id = tag_id(&tx, &source)?.ok_or_else(|| format!("Tag '{}' not found", source))?;
+        for gist_id in gists_with_tag(&tx, id)? {
+            if !affected.contains(&gist_id) {
+                affected.push(gist_id);
+            }
+        }
+        tx.execute(
+            "INSERT OR IGNORE INTO gist_tags (gist_id, tag_id)
+             SELECT gist_id, ?1 FROM gist_tags WHERE tag_id = ?2",
+            params![target_id, id],
+        )?;
+        tx.execute("DELETE FROM gist_tags WHERE tag_id = ?1", params![id])?;
+        tx.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+    }
+    
+    refresh_tag_strings(&tx, &affected)?;
+    prune_unused_tags(&tx)?;
+    tx.commit()?;
+    Ok(affected.len())
+}
+
+/// Remove a tag from every gist. Returns the number of gists affected.
+pub fn delete_tag(c: &Connection, name: &str) -> Result<usize, Box<dyn Error>> {
+    let name = crate::ai::normalize_tag(name);
+    let id = tag_id(c, &name)?.ok_or_else(|| format!("Tag '{}' not found", name))?;
+    
+    let tx = c.unchecked_transaction()?;
+    let affected = gists_with_tag(&tx, id)?;
+    tx.execute("DELETE FROM gist_tags WHERE tag_id = ?1", params![id])?;
+    tx.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+    refresh_tag_strings(&tx, &affected)?;
+    tx.commit()?;
+    Ok(affected.len())
+}
+
+/// Find gists whose tags satisfy a boolean tag query (exact tag matches).
+pub fn search_by_tags(c: &Connection, query: &str) -> Result<Vec<Gist>, Box<dyn Error>> {
+    let parsed = TagQuery::parse(query)?;
+    let mut values: Vec<String> = Vec::new();
+    let condition = parsed.to_sql(&mut values);
+    let sql = format!(
+        "SELECT {} FROM gists WHERE {} AND {} ORDER BY created_at DESC",
+        GIST_COLUMNS, NOT_TRASHED, condition
+    );
+    
+    let mut stmt = c.prepare(&sql)?;
+    let res = stmt.query_map(rusqlite::params_from_iter(values.iter()), row_to_gist)?;
+    
+    let mut out = Vec::new();
+    for g in res {
+        out.push(g?);
+    }
+    Ok(out)
+}
+
+/// Snapshot a gist's current content and tags as its next revision, unless
+/// they are identical to the latest revision.
+pub fn record_revision(c: &Connection, gist_id: i64) -> SqlResult<()> {
+    c.execute(
+        "INSERT INTO gist_revisions (gist_id, rev, content, tags, created_at)
+         SELECT g.id,
+                IFNULL((SELECT MAX(rev) FROM gist_revisions WHERE gist_id = g.id), 0) + 1,
+                g.content, g.tags, IFNULL(g.updated_at, CURRENT_TIMESTAMP)
+         FROM gists g
+         WHERE g.id = ?1 AND NOT EXISTS (
+             SELECT 1 FROM gist_revisions r
+             WHERE r.gist_id = g.id
+               AND r.rev = (SELECT MAX(rev) FROM gist_revisions WHERE gist_id = g.id)
+               AND r.content = g.content AND IFNULL(r.tags, '') = IFNULL(g.tags, '')
+         )",
+        params![gist_id],
+    )?;
+    Ok(())
+}
+
+fn row_to_revision(r: &rusqlite::Row) -> SqlResult<Revision> {
+    Ok(Revision {
+        gist_id: r.get(0)?,
+        rev: r.get(1)?,
+        content: r.get(2)?,
+        tags: r.get::<_, Option<String>>(3)?.unwrap_or_default(),
+        created_at: r.get(4)?,
+    })
+}
+
+/// All revisions of a gist, newest first.
+pub fn list_revisions(c: &Connection, gist_id: i64) -> SqlResult<Vec<Revision>> {
+    let mut stmt = c.prepare(
+        "SELECT gist_id, rev, content, tags, created_at FROM gist_revisions
+         WHERE gist_id = ?1 ORDER BY rev DESC",
+    )?;
+    let rows = stmt.query_map(params![gist_id], row_to_revision)?;
+    rows.collect()
+}
+
+/// A single revision of a gist.
+pub fn get_revision(c: &Connection, gist_id: i64, rev: i64) -> SqlResult<Option<Revision>> {
+    let result = c.query_row(
+        "SELECT gist_id, rev, content, tags, created_at FROM gist_revisions
+         WHERE gist_id = ?1 AND rev = ?2",
+        params![gist_id, rev],
+        row_to_revision,
+    );
+    
+    match result {
+        Ok(r) => Ok(Some(r)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Roll a gist back to an earlier revision. The restore is recorded as a new
+/// revision, so nothing is lost. Returns the revision number now current.
+pub fn restore_revision(c: &Connection, gist_id: i64, rev: i64) -> Result<i64, Box<dyn Error>> {
+    let target = get_revision(c, gist_id, rev)?
+        .ok_or_else(|| format!("Gist #{} has no revision {}", gist_id, rev))?;
+    
+    let tx = c.unchecked_transaction()?;
+    update_gist(&tx, gist_id, &target.content, &target.tags)?;
+    let current: i64 = tx.query_row(
+        "SELECT MAX(rev) FROM gist_revisions WHERE gist_id = ?1",
+        params![gist_id],
+        |r| r.get(0),
+    )?;
+    tx.commit()?;
+    Ok(current)
+}
+
+/// Replace a gist's content with its sealed (encrypted) form and drop what
+/// was derived from the plaintext: earlier revisions, embeddings and stored
+/// run output. The sealed content becomes revision 1.
+pub fn seal_gist(c: &Connection, id: i64, sealed: &str) -> SqlResult<()> {
+    let tx = c.unchecked_transaction()?;
+    let before: Option<String> = match tx.query_row("SELECT content FROM gists WHERE id = ?1", params![id], |r| r.get(0)) {
+        Ok(content) => Some(content),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e),
+    };
+    let result = tx.execute(
+        "UPDATE gists SET content=?1, updated_at=CURRENT_TIMESTAMP WHERE id=?2",
+        params![sealed, id],
+    )?;
+    if result == 0 {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    tx.execute("DELETE FROM gist_revisions WHERE gist_id = ?1", params![id])?;
+    tx.execute("DELETE FROM gist_embeddings WHERE gist_id = ?1", params![id])?;
+    tx.execute("UPDATE gist_runs SET output = NULL WHERE gist_id = ?1", params![id])?;
+    if let Some(before) = before {
+        carry_run_approval(&tx, id, &before)?;
+    }
+    // Earlier changelog snapshots still carry the plain text; lines already
+    // written to the sync folder are out of reach, the local ones are not
+    tx.execute(
+        "UPDATE sync_log SET gist = json_set(gist, '$.content', ?1)
+         WHERE op = 'put' AND uuid = (SELECT uuid FROM gists WHERE id = ?2)",
+        params![sealed, id],
+    )?;
+    record_revision(&tx, id)?;
+    tx.commit()
+}
+
+/// Content of some encrypted gist, trashed or not, to check a passphrase against.
+pub fn encrypted_sample(c: &Connection) -> SqlResult<Option<String>> {
+    match c.query_row(
+        "SELECT content FROM gists WHERE substr(content, 1, ?1) = ?2 LIMIT 1",
+        params![crypto::PREFIX.len() as i64, crypto::PREFIX],
+        |r| r.get(0),
+    ) {
+        Ok(content) => Ok(Some(content)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// IDs and content of your own gists, trashed or not, that are not encrypted.
+pub fn plaintext_gists(c: &Connection) -> SqlResult<Vec<(i64, String)>> {
+    let sql = format!("SELECT id, content FROM gists WHERE substr(content, 1, ?1) != ?2 AND {} ORDER BY id", PRIVATE);
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map(params![crypto::PREFIX.len() as i64, crypto::PREFIX], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// Move a gist to the trash. Returns false if there is no live gist with that ID.
 pub fn delete_gist(c: &Connection, id: i64) -> SqlResult<bool> {
+    let result = c.execute(
+        "UPDATE gists SET deleted_at=CURRENT_TIMESTAMP WHERE id=?1 AND deleted_at IS NULL",
+        params![id],
+    )?;
+    Ok(result > 0)
+}
+
+/// Permanently delete a gist, along with its tag links and revisions.
+pub fn purge_gist(c: &Connection, id: i64) -> SqlResult<bool> {
     let result = c.execute("DELETE FROM gists WHERE id=?1", params![id])?;
+    if result > 0 {
+        prune_unused_tags(c)?;
+    }
+    Ok(result > 0)
+}
+
+/// A gist in the trash.
+#[derive(Debug, Clone)]
+pub struct TrashedGist {
+    pub gist: Gist,
+    pub deleted_at: String,
+}
+
+/// Trashed gists, most recently deleted first.
+pub fn list_trash(c: &Connection) -> SqlResult<Vec<TrashedGist>> {
+    let sql = format!(
+        "SELECT {}, gists.deleted_at FROM gists
+         WHERE gists.deleted_at IS NOT NULL ORDER BY gists.deleted_at DESC, gists.id DESC",
+        GIST_COLUMNS
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| {
+        Ok(TrashedGist {
+            gist: row_to_gist(r)?,
+            deleted_at: r.get(GIST_COLUMN_COUNT)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Take a gist back out of the trash. Returns false if it wasn't trashed.
+pub fn restore_gist(c: &Connection, id: i64) -> SqlResult<bool> {
+    let result = c.execute(
+        "UPDATE gists SET deleted_at=NULL WHERE id=?1 AND deleted_at IS NOT NULL",
+        params![id],
+    )?;
     Ok(result > 0)
 }
 
+/// Permanently delete trashed gists. With `older_than_days`, only those
+/// trashed at least that many days ago. Returns the number removed.
+pub fn empty_trash(c: &Connection, older_than_days: Option<u32>) -> SqlResult<usize> {
+    let removed = match older_than_days {
+        Some(days) => c.execute(
+            "DELETE FROM gists WHERE deleted_at IS NOT NULL
+             AND deleted_at <= datetime('now', '-' || ?1 || ' days')",
+            params![days],
+        )?,
+        None => c.execute("DELETE FROM gists WHERE deleted_at IS NOT NULL", [])?,
+    };
+    if removed > 0 {
+        prune_unused_tags(c)?;
+    }
+    Ok(removed)
+}
+
+/// Call `f` with every live gist of your own (or those in `collection` and
+/// below it), oldest first, without loading them all at once.
+pub fn for_each_gist(
+    c: &Connection,
+    collection: Option<i64>,
+    mut f: impl FnMut(Gist) -> Result<(), Box<dyn Error>>,
+) -> Result<usize, Box<dyn Error>> {
+    let in_collection = collection.map(in_collection_sql).unwrap_or_else(|| "1".to_string());
+    let sql = format!(
+        "SELECT {} FROM gists WHERE {} AND {} AND {} ORDER BY created_at, id",
+        GIST_COLUMNS, NOT_TRASHED, PRIVATE, in_collection
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let mut rows = stmt.query([])?;
+    let mut count = 0;
+    while let Some(row) = rows.next()? {
+        f(row_to_gist(row)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
 /// Retrieve a gist by ID.
 pub fn get_gist(c: &Connection, id: i64) -> SqlResult<Option<Gist>> {
     let result = c.query_row(
-        "SELECT id, content, tags, created_at FROM gists WHERE id = ?1",
+        &format!("SELECT {} FROM gists WHERE id = ?1 AND {}", GIST_COLUMNS, NOT_TRASHED),
         params![id],
+        row_to_gist,
+    );
+    
+    match result {
+        Ok(gist) => Ok(Some(gist)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Store (or replace) a gist's embedding vector.
+pub fn store_embedding(c: &Connection, gist_id: i64, model: &str, vector: &[f32], text_hash: &str) -> SqlResult<()> {
+    let blob: Vec<u8> = vector.iter().flat_map(|v| v.to_le_bytes()).collect();
+    c.execute(
+        "INSERT OR REPLACE INTO gist_embeddings (gist_id, model, dims, vector, text_hash, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)",
+        params![gist_id, model, vector.len() as i64, blob, text_hash],
+    )?;
+    Ok(())
+}
+
+/// Embedding vectors of live gists made with `model`.
+pub fn list_embeddings(c: &Connection, model: &str) -> SqlResult<Vec<(i64, Vec<f32>)>> {
+    let sql = format!(
+        "SELECT e.gist_id, e.vector FROM gist_embeddings e
+         JOIN gists ON gists.id = e.gist_id
+         WHERE e.model = ?1 AND {}",
+        NOT_TRASHED
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map(params![model], |r| {
+        let blob: Vec<u8> = r.get(1)?;
+        let vector = blob
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        Ok((r.get(0)?, vector))
+    })?;
+    rows.collect()
+}
+
+/// Hash of the text each gist was embedded from, for gists embedded with `model`.
+pub fn embedding_hashes(c: &Connection, model: &str) -> SqlResult<std::collections::HashMap<i64, String>> {
+    let mut stmt = c.prepare("SELECT gist_id, text_hash FROM gist_embeddings WHERE model = ?1")?;
+    let rows = stmt.query_map(params![model], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// Where in its source file a snippet was taken from, for `gist view` to
+/// report whether the original has changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Provenance {
+    pub gist_id: i64,
+    /// Unset when the whole file was saved
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub symbol: Option<String>,
+    pub git_commit: Option<String>,
+    /// Content hash of the extracted text at capture time
+    pub source_hash: String,
+}
+
+impl Provenance {
+    pub fn line_range(&self) -> Option<LineRange> {
+        Some(LineRange { start: self.start_line?, end: self.end_line? })
+    }
+}
+
+/// Record (or replace) where a gist was extracted from.
+pub fn save_provenance(c: &Connection, p: &Provenance) -> SqlResult<()> {
+    c.execute(
+        "INSERT OR REPLACE INTO provenance (gist_id, start_line, end_line, symbol, git_commit, source_hash, captured_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, CURRENT_TIMESTAMP)",
+        params![
+            p.gist_id,
+            p.start_line.map(|n| n as i64),
+            p.end_line.map(|n| n as i64),
+            p.symbol,
+            p.git_commit,
+            p.source_hash
+        ],
+    )?;
+    Ok(())
+}
+
+/// Provenance of a gist added from a file, if recorded.
+pub fn get_provenance(c: &Connection, gist_id: i64) -> SqlResult<Option<Provenance>> {
+    let result = c.query_row(
+        "SELECT gist_id, start_line, end_line, symbol, git_commit, source_hash FROM provenance WHERE gist_id = ?1",
+        params![gist_id],
         |r| {
-            Ok(Gist {
-                id: r.get(0)?,
-                content: r.get(1)?,
-                tags: r.get(2)?,
-                created_at: r.get(3)?,
+            Ok(Provenance {
+                gist_id: r.get(0)?,
+                start_line: r.get::<_, Option<i64>>(1)?.map(|n| n as usize),
+                end_line: r.get::<_, Option<i64>>(2)?.map(|n| n as usize),
+                symbol: r.get(3)?,
+                git_commit: r.get(4)?,
+                source_hash: r.get(5)?,
             })
         },
     );
-    
     match result {
-        Ok(gist) => Ok(Some(gist)),
+        Ok(p) => Ok(Some(p)),
         Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
         Err(e) => Err(e),
     }
 }
 
-/// Search gists by content or tags.
-pub fn search_gists(c: &Connection, query: &str, tags_only: bool) -> SqlResult<Vec<Gist>> {
-    let like = format!("%{}%", query);
-    let sql = if tags_only {
-        "SELECT id, content, tags, created_at FROM gists 
-         WHERE tags LIKE ?1 ORDER BY created_at DESC"
-    } else {
-        "SELECT id, content, tags, created_at FROM gists
-         WHERE content LIKE ?1 OR tags LIKE ?1 ORDER BY created_at DESC"
+/// Whether a gist may be run, and how its last `gist run` went.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunInfo {
+    pub gist_id: i64,
+    /// Marked runnable, and the content hasn't changed since
+    pub runnable: bool,
+    /// None when the process was killed by a signal (or never ran)
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// Combined stdout and stderr, trimmed to the last part
+    pub output: Option<String>,
+    pub duration_ms: Option<i64>,
+    pub ran_at: Option<String>,
+    pub last_success_at: Option<String>,
+}
+
+impl RunInfo {
+    pub fn succeeded(&self) -> bool {
+        self.exit_code == Some(0) && !self.timed_out
+    }
+}
+
+const RUN_QUERY: &str =
+    "SELECT r.gist_id, r.runnable, r.exit_code, r.timed_out, r.output, r.duration_ms, r.ran_at, r.last_success_at,
+            r.approved_hash, g.content
+     FROM gist_runs r JOIN gists g ON g.id = r.gist_id";
+
+fn row_to_run_info(r: &rusqlite::Row) -> SqlResult<RunInfo> {
+    let approved: Option<String> = r.get(8)?;
+    Ok(RunInfo {
+        gist_id: r.get(0)?,
+        runnable: r.get::<_, bool>(1)? && approved == Some(content_hash(&r.get::<_, String>(9)?)),
+        exit_code: r.get(2)?,
+        timed_out: r.get(3)?,
+        output: r.get(4)?,
+        duration_ms: r.get(5)?,
+        ran_at: r.get(6)?,
+        last_success_at: r.get(7)?,
+    })
+}
+
+/// Allow or forbid `gist run` for a gist. Allowing approves its current
+/// content; once that changes the gist has to be approved again.
+pub fn set_runnable(c: &Connection, gist_id: i64, runnable: bool) -> SqlResult<()> {
+    let approved = match runnable {
+        true => Some(content_hash(&c.query_row(
+            "SELECT content FROM gists WHERE id = ?1",
+            params![gist_id],
+            |r| r.get::<_, String>(0),
+        )?)),
+        false => None,
+    };
+    c.execute(
+        "INSERT INTO gist_runs (gist_id, runnable, approved_hash) VALUES (?1, ?2, ?3)
+         ON CONFLICT(gist_id) DO UPDATE SET runnable = excluded.runnable, approved_hash = excluded.approved_hash",
+        params![gist_id, runnable, approved],
+    )?;
+    Ok(())
+}
+
+/// Move a run approval of `before` over to the gist's current content, for
+/// changes the user made themselves (edits, encrypting, decrypting).
+pub fn carry_run_approval(c: &Connection, gist_id: i64, before: &str) -> SqlResult<()> {
+    let content: String = c.query_row("SELECT content FROM gists WHERE id = ?1", params![gist_id], |r| r.get(0))?;
+    c.execute(
+        "UPDATE gist_runs SET approved_hash = ?1 WHERE gist_id = ?2 AND approved_hash = ?3",
+        params![content_hash(&content), gist_id, content_hash(before)],
+    )?;
+    Ok(())
+}
+
+/// Store the outcome of a run, keeping when it last succeeded.
+pub fn record_run(
+    c: &Connection,
+    gist_id: i64,
+    exit_code: Option<i32>,
+    timed_out: bool,
+    output: &str,
+    duration_ms: i64,
+) -> SqlResult<()> {
+    c.execute(
+        "INSERT INTO gist_runs (gist_id, exit_code, timed_out, output, duration_ms, ran_at, last_success_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP,
+                 CASE WHEN ?2 = 0 AND NOT ?3 THEN CURRENT_TIMESTAMP END)
+         ON CONFLICT(gist_id) DO UPDATE SET
+            exit_code = excluded.exit_code,
+            timed_out = excluded.timed_out,
+            output = excluded.output,
+            duration_ms = excluded.duration_ms,
+            ran_at = excluded.ran_at,
+            last_success_at = COALESCE(excluded.last_success_at, gist_runs.last_success_at)",
+        params![gist_id, exit_code, timed_out, output, duration_ms],
+    )?;
+    Ok(())
+}
+
+/// Run state of one gist; never-marked gists have none.
+pub fn get_run_info(c: &Connection, gist_id: i64) -> SqlResult<Option<RunInfo>> {
+    let sql = format!("{} WHERE r.gist_id = ?1", RUN_QUERY);
+    match c.query_row(&sql, params![gist_id], row_to_run_info) {
+        Ok(info) => Ok(Some(info)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Run state of every gist that has any, by gist id.
+pub fn list_run_info(c: &Connection) -> SqlResult<HashMap<i64, RunInfo>> {
+    let mut stmt = c.prepare(RUN_QUERY)?;
+    let rows = stmt.query_map([], row_to_run_info)?;
+    rows.map(|r| r.map(|info| (info.gist_id, info))).collect()
+}
+
+/// A named group of snippets, possibly inside another collection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub id: i64,
+    pub name: String,
+    pub parent_id: Option<i64>,
+    /// Slash-separated names from the top, e.g. `work/sql`
+    pub path: String,
+    /// Live snippets directly in this collection
+    pub gist_count: usize,
+}
+
+impl Collection {
+    /// Nesting level; top-level collections are 0.
+    pub fn depth(&self) -> usize {
+        self.path.matches('/').count()
+    }
+}
+
+/// Split `a/b/c` into names, rejecting empty segments.
+fn collection_segments(path: &str) -> Result<Vec<&str>, String> {
+    let segments: Vec<&str> = path.trim().trim_matches('/').split('/').map(str::trim).collect();
+    if segments.iter().any(|s| s.is_empty()) {
+        return Err(format!("Invalid collection path '{}'", path));
+    }
+    Ok(segments)
+}
+
+fn child_collection(c: &Connection, parent: Option<i64>, name: &str) -> SqlResult<Option<i64>> {
+    let result = c.query_row(
+        "SELECT id FROM collections WHERE COALESCE(parent_id, 0) = COALESCE(?1, 0) AND name = ?2",
+        params![parent, name],
+        |r| r.get(0),
+    );
+    match result {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Create a collection by path, creating missing parents like `mkdir -p`.
+/// Fails if the collection itself already exists.
+pub fn create_collection(c: &Connection, path: &str) -> Result<i64, Box<dyn Error>> {
+    let segments = collection_segments(path)?;
+    let mut parent = None;
+    for (i, name) in segments.iter().enumerate() {
+        parent = match child_collection(c, parent, name)? {
+            Some(_) if i == segments.len() - 1 => {
+                return Err(format!("Collection '{}' already exists", segments.join("/")).into())
+            }
+            Some(id) => Some(id),
+            None => {
+                c.execute("INSERT INTO collections (name, parent_id) VALUES (?1, ?2)", params![name, parent])?;
+                Some(c.last_insert_rowid())
+            }
+        };
+    }
+    Ok(parent.expect("path has at least one segment"))
+}
+
+/// The id of the collection at `path`, if it exists.
+pub fn find_collection(c: &Connection, path: &str) -> Result<Option<i64>, Box<dyn Error>> {
+    let mut parent = None;
+    for name in collection_segments(path)? {
+        match child_collection(c, parent, name)? {
+            Some(id) => parent = Some(id),
+            None => return Ok(None),
+        }
+    }
+    Ok(parent)
+}
+
+/// Every collection, ordered by path so children follow their parent.
+pub fn list_collections(c: &Connection) -> SqlResult<Vec<Collection>> {
+    let sql = format!(
+        "WITH RECURSIVE tree(id, name, parent_id, path) AS (
+            SELECT id, name, parent_id, name FROM collections WHERE parent_id IS NULL
+            UNION ALL
+            SELECT c.id, c.name, c.parent_id, tree.path || '/' || c.name
+            FROM collections c JOIN tree ON c.parent_id = tree.id
+         )
+         SELECT tree.id, tree.name, tree.parent_id, tree.path,
+                (SELECT COUNT(*) FROM collection_gists cg JOIN gists ON gists.id = cg.gist_id
+                 WHERE cg.collection_id = tree.id AND {})
+         FROM tree ORDER BY tree.path",
+        NOT_TRASHED
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| {
+        Ok(Collection {
+            id: r.get(0)?,
+            name: r.get(1)?,
+            parent_id: r.get(2)?,
+            path: r.get(3)?,
+            gist_count: r.get::<_, i64>(4)? as usize,
+        })
+    })?;
+    rows.collect()
+}
+
+/// SQL condition matching gists in collection `id` or any collection under it.
+fn in_collection_sql(id: i64) -> String {
+    format!(
+        "gists.id IN (
+            WITH RECURSIVE sub(id) AS (
+                SELECT {id} UNION ALL SELECT c.id FROM collections c JOIN sub ON c.parent_id = sub.id
+            )
+            SELECT gist_id FROM collection_gists WHERE collection_id IN (SELECT id FROM sub)
+        )"
+    )
+}
+
+/// Ids of the gists in collection `id` and its sub-collections.
+pub fn collection_gist_ids(c: &Connection, id: i64) -> SqlResult<std::collections::HashSet<i64>> {
+    let sql = format!("SELECT gists.id FROM gists WHERE {} AND {}", in_collection_sql(id), NOT_TRASHED);
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| r.get(0))?;
+    rows.collect()
+}
+
+/// `(collection id, gist id)` for every membership.
+pub fn collection_memberships(c: &Connection) -> SqlResult<Vec<(i64, i64)>> {
+    let mut stmt = c.prepare("SELECT collection_id, gist_id FROM collection_gists")?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// Put gists into a collection. Returns how many were not already in it.
+pub fn add_to_collection(c: &Connection, collection_id: i64, gist_ids: &[i64]) -> SqlResult<usize> {
+    let mut added = 0;
+    for id in gist_ids {
+        added += c.execute(
+            "INSERT OR IGNORE INTO collection_gists (collection_id, gist_id) VALUES (?1, ?2)",
+            params![collection_id, id],
+        )?;
+    }
+    Ok(added)
+}
+
+/// Take gists out of a collection (the snippets themselves stay).
+pub fn remove_from_collection(c: &Connection, collection_id: i64, gist_ids: &[i64]) -> SqlResult<usize> {
+    let mut removed = 0;
+    for id in gist_ids {
+        removed += c.execute(
+            "DELETE FROM collection_gists WHERE collection_id = ?1 AND gist_id = ?2",
+            params![collection_id, id],
+        )?;
+    }
+    Ok(removed)
+}
+
+/// Delete a collection and everything nested under it. Snippets are kept.
+pub fn delete_collection(c: &Connection, id: i64) -> SqlResult<usize> {
+    c.execute(
+        "WITH RECURSIVE sub(id) AS (
+            SELECT ?1 UNION ALL SELECT c.id FROM collections c JOIN sub ON c.parent_id = sub.id
+         )
+         DELETE FROM collections WHERE id IN (SELECT id FROM sub)",
+        params![id],
+    )
+}
+
+/// Paths of the collections a gist is directly in.
+pub fn gist_collections(c: &Connection, gist_id: i64) -> SqlResult<Vec<String>> {
+    let member_of: std::collections::HashSet<i64> = c
+        .prepare("SELECT collection_id FROM collection_gists WHERE gist_id = ?1")?
+        .query_map(params![gist_id], |r| r.get(0))?
+        .collect::<SqlResult<_>>()?;
+    Ok(list_collections(c)?
+        .into_iter()
+        .filter(|col| member_of.contains(&col.id))
+        .map(|col| col.path)
+        .collect())
+}
+
+/// Pin state and how often a gist has been looked at or copied.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Usage {
+    pub gist_id: i64,
+    pub pinned: bool,
+    pub view_count: i64,
+    pub copy_count: i64,
+    pub last_used_at: Option<String>,
+}
+
+/// What a gist was used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageKind {
+    View,
+    Copy,
+}
+
+const USAGE_COLUMNS: &str = "gist_id, pinned, view_count, copy_count, last_used_at";
+
+fn row_to_usage(r: &rusqlite::Row) -> SqlResult<Usage> {
+    Ok(Usage {
+        gist_id: r.get(0)?,
+        pinned: r.get(1)?,
+        view_count: r.get(2)?,
+        copy_count: r.get(3)?,
+        last_used_at: r.get(4)?,
+    })
+}
+
+/// Count a view or copy and mark the gist as just used.
+pub fn record_usage(c: &Connection, gist_id: i64, kind: UsageKind) -> SqlResult<()> {
+    let (views, copies) = match kind {
+        UsageKind::View => (1, 0),
+        UsageKind::Copy => (0, 1),
+    };
+    c.execute(
+        "INSERT INTO gist_usage (gist_id, view_count, copy_count, last_used_at)
+         VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+         ON CONFLICT(gist_id) DO UPDATE SET
+            view_count = view_count + excluded.view_count,
+            copy_count = copy_count + excluded.copy_count,
+            last_used_at = excluded.last_used_at",
+        params![gist_id, views, copies],
+    )?;
+    Ok(())
+}
+
+/// Pin or unpin a gist.
+pub fn set_pinned(c: &Connection, gist_id: i64, pinned: bool) -> SqlResult<()> {
+    c.execute(
+        "INSERT INTO gist_usage (gist_id, pinned) VALUES (?1, ?2)
+         ON CONFLICT(gist_id) DO UPDATE SET pinned = excluded.pinned",
+        params![gist_id, pinned],
+    )?;
+    Ok(())
+}
+
+/// Usage of one gist; never-used gists have none.
+pub fn get_usage(c: &Connection, gist_id: i64) -> SqlResult<Option<Usage>> {
+    let sql = format!("SELECT {} FROM gist_usage WHERE gist_id = ?1", USAGE_COLUMNS);
+    match c.query_row(&sql, params![gist_id], row_to_usage) {
+        Ok(usage) => Ok(Some(usage)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Usage of every gist that has any, by gist id.
+pub fn list_usage(c: &Connection) -> SqlResult<std::collections::HashMap<i64, Usage>> {
+    let sql = format!("SELECT {} FROM gist_usage", USAGE_COLUMNS);
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], row_to_usage)?;
+    rows.map(|r| r.map(|usage| (usage.gist_id, usage))).collect()
+}
+
+/// Frecency: uses (copies count double) decayed by a week per step of age,
+/// so something used daily outranks something used a lot last year.
+const FRECENCY_SQL: &str = "(COALESCE(u.view_count, 0) + 2 * COALESCE(u.copy_count, 0)) \
+    / (1.0 + COALESCE(julianday('now') - julianday(u.last_used_at), 365.0) / 7.0)";
+
+/// A snippet that mirrors one file of a GitHub gist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GithubLink {
+    pub gist_id: i64,
+    pub remote_id: String,
+    pub filename: String,
+    /// Content hash at the last pull or push, to tell which side changed
+    pub synced_hash: String,
+}
+
+fn row_to_github_link(r: &rusqlite::Row) -> SqlResult<GithubLink> {
+    Ok(GithubLink {
+        gist_id: r.get(0)?,
+        remote_id: r.get(1)?,
+        filename: r.get(2)?,
+        synced_hash: r.get(3)?,
+    })
+}
+
+/// Record (or replace) the GitHub file a gist is linked to.
+pub fn save_github_link(c: &Connection, link: &GithubLink) -> SqlResult<()> {
+    c.execute(
+        "INSERT OR REPLACE INTO github_links (gist_id, remote_id, filename, synced_hash, synced_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)",
+        params![link.gist_id, link.remote_id, link.filename, link.synced_hash],
+    )?;
+    Ok(())
+}
+
+/// The link for a local gist, if it has been pulled or pushed.
+pub fn get_github_link(c: &Connection, gist_id: i64) -> SqlResult<Option<GithubLink>> {
+    let result = c.query_row(
+        "SELECT gist_id, remote_id, filename, synced_hash FROM github_links WHERE gist_id = ?1",
+        params![gist_id],
+        row_to_github_link,
+    );
+    match result {
+        Ok(link) => Ok(Some(link)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The local gist mirroring `filename` of remote gist `remote_id`.
+pub fn find_github_link(c: &Connection, remote_id: &str, filename: &str) -> SqlResult<Option<GithubLink>> {
+    let result = c.query_row(
+        "SELECT gist_id, remote_id, filename, synced_hash FROM github_links
+         WHERE remote_id = ?1 AND filename = ?2",
+        params![remote_id, filename],
+        row_to_github_link,
+    );
+    match result {
+        Ok(link) => Ok(Some(link)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Links of live (not trashed) gists, grouped by remote gist.
+pub fn list_github_links(c: &Connection) -> SqlResult<Vec<GithubLink>> {
+    let sql = format!(
+        "SELECT l.gist_id, l.remote_id, l.filename, l.synced_hash FROM github_links l
+         JOIN gists ON gists.id = l.gist_id
+         WHERE {}
+         ORDER BY l.remote_id, l.filename",
+        NOT_TRASHED
+    );
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], row_to_github_link)?;
+    rows.collect()
+}
+
+/// A snippet that mirrors one file of a team library's repository.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryFile {
+    pub gist_id: i64,
+    pub library: String,
+    /// Path relative to the repository root, with `/` separators
+    pub path: String,
+    /// Content hash at the last pull or push, to tell which side changed
+    pub synced_hash: String,
+}
+
+fn row_to_library_file(r: &rusqlite::Row) -> SqlResult<LibraryFile> {
+    Ok(LibraryFile {
+        gist_id: r.get(0)?,
+        library: r.get(1)?,
+        path: r.get(2)?,
+        synced_hash: r.get(3)?,
+    })
+}
+
+/// Add a snippet mirrored from `path` in a team library's repository.
+pub fn insert_library_gist(
+    c: &Connection,
+    library: &str,
+    path: &str,
+    content: &str,
+    tags: &str,
+    meta: &GistMeta,
+) -> SqlResult<i64> {
+    c.execute(
+        "INSERT INTO gists (content, tags, title, description, language, source_path, source_url, updated_at, library)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, CURRENT_TIMESTAMP, ?8)",
+        params![
+            content,
+            tags,
+            non_empty(&meta.title),
+            non_empty(&meta.description),
+            non_empty(&meta.language),
+            non_empty(&meta.source_path),
+            non_empty(&meta.source_url),
+            library,
+        ],
+    )?;
+    let id = c.last_insert_rowid();
+    sync_gist_tags(c, id, tags)?;
+    record_revision(c, id)?;
+    save_library_file(
+        c,
+        &LibraryFile { gist_id: id, library: library.to_string(), path: path.to_string(), synced_hash: content_hash(content) },
+    )?;
+    Ok(id)
+}
+
+/// Record (or replace) the library file a gist mirrors.
+pub fn save_library_file(c: &Connection, file: &LibraryFile) -> SqlResult<()> {
+    c.execute(
+        "INSERT OR REPLACE INTO library_files (gist_id, library, path, synced_hash, synced_at)
+         VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)",
+        params![file.gist_id, file.library, file.path, file.synced_hash],
+    )?;
+    Ok(())
+}
+
+/// Every file mirrored from `library`, trashed or not, by path.
+pub fn list_library_files(c: &Connection, library: &str) -> SqlResult<Vec<LibraryFile>> {
+    let mut stmt = c.prepare(
+        "SELECT gist_id, library, path, synced_hash FROM library_files WHERE library = ?1 ORDER BY path",
+    )?;
+    let rows = stmt.query_map(params![library], row_to_library_file)?;
+    rows.collect()
+}
+
+/// Content of every gist mirrored from `library`, trashed or not.
+pub fn library_contents(c: &Connection, library: &str) -> SqlResult<HashMap<i64, String>> {
+    let mut stmt = c.prepare("SELECT id, content FROM gists WHERE library = ?1")?;
+    let rows = stmt.query_map(params![library], |r| Ok((r.get(0)?, r.get(1)?)))?;
+    rows.collect()
+}
+
+/// `duplicate_key` of each live gist of your own.
+pub fn private_content_keys(c: &Connection) -> SqlResult<std::collections::HashSet<String>> {
+    let sql = format!("SELECT content FROM gists WHERE {} AND {}", NOT_TRASHED, PRIVATE);
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| r.get::<_, String>(0))?;
+    rows.map(|content| content.map(|c| duplicate_key(&c))).collect()
+}
+
+/// Live snippets per team library.
+pub fn library_counts(c: &Connection) -> SqlResult<HashMap<String, usize>> {
+    let sql = format!("SELECT library, COUNT(*) FROM gists WHERE library IS NOT NULL AND {} GROUP BY library", NOT_TRASHED);
+    let mut stmt = c.prepare(&sql)?;
+    let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get::<_, i64>(1)? as usize)))?;
+    rows.collect()
+}
+
+/// Permanently delete every snippet mirrored from `library`.
+pub fn drop_library(c: &Connection, library: &str) -> SqlResult<usize> {
+    let removed = c.execute("DELETE FROM gists WHERE library = ?1", params![library])?;
+    if removed > 0 {
+        prune_unused_tags(c)?;
+    }
+    Ok(removed)
+}
+
+/// One entry of the sync changelog: a gist written (`put`, with a snapshot
+/// of its synced fields) or purged, by `device` at Lamport time `lamport`.
+/// `base` is the version (`lamport@device`) the change was made on top of.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncChange {
+    pub uuid: String,
+    pub lamport: i64,
+    pub device: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    pub op: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gist: Option<GistSnapshot>,
+}
+
+impl SyncChange {
+    /// This change's version, as other changes name it in `base`.
+    pub fn version(&self) -> String {
+        format!("{}@{}", self.lamport, self.device)
+    }
+}
+
+/// The fields of a gist that sync between devices.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GistSnapshot {
+    pub content: String,
+    #[serde(default)]
+    pub tags: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+    #[serde(default)]
+    pub source_path: Option<String>,
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+}
+
+/// This library's device ID, which names its changelog file.
+pub fn sync_device(c: &Connection) -> SqlResult<String> {
+    c.query_row("SELECT device FROM sync_state", [], |r| r.get(0))
+}
+
+fn row_to_sync_change(r: &rusqlite::Row) -> SqlResult<SyncChange> {
+    let gist: Option<String> = r.get(5)?;
+    Ok(SyncChange {
+        uuid: r.get(0)?,
+        lamport: r.get(1)?,
+        device: r.get(2)?,
+        base: r.get(3)?,
+        op: r.get(4)?,
+        gist: gist.and_then(|g| serde_json::from_str(&g).ok()),
+    })
+}
+
+/// Changes made on this device, oldest first.
+pub fn local_changes(c: &Connection) -> SqlResult<Vec<SyncChange>> {
+    let mut stmt = c.prepare(
+        "SELECT uuid, lamport, device, base, op, gist FROM sync_log
+         WHERE device = (SELECT device FROM sync_state) ORDER BY seq",
+    )?;
+    let rows = stmt.query_map([], row_to_sync_change)?;
+    rows.collect()
+}
+
+/// Whether `change` is already in the changelog, i.e. was made or merged here.
+pub fn has_sync_change(c: &Connection, change: &SyncChange) -> SqlResult<bool> {
+    c.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sync_log WHERE uuid = ?1 AND lamport = ?2 AND device = ?3)",
+        params![change.uuid, change.lamport, change.device],
+        |r| r.get(0),
+    )
+}
+
+/// A gist's version here and its id and row, unless it has been purged.
+pub type SyncState = (String, Option<(i64, GistSnapshot)>);
+
+/// The current version of a gist here and its row, if it hasn't been purged.
+pub fn sync_state_of(c: &Connection, uuid: &str) -> SqlResult<Option<SyncState>> {
+    let version: Option<String> = match c.query_row("SELECT version FROM sync_versions WHERE uuid = ?1", params![uuid], |r| r.get(0)) {
+        Ok(v) => Some(v),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(e),
+    };
+    let Some(version) = version else { return Ok(None) };
+    let row = c.query_row(
+        "SELECT id, content, tags, title, description, language, source_path, source_url, created_at, updated_at, deleted_at
+         FROM gists WHERE uuid = ?1",
+        params![uuid],
+        |r| {
+            Ok((r.get(0)?, GistSnapshot {
+                content: r.get(1)?,
+                tags: r.get(2)?,
+                title: r.get(3)?,
+                description: r.get(4)?,
+                language: r.get(5)?,
+                source_path: r.get(6)?,
+                source_url: r.get(7)?,
+                created_at: r.get(8)?,
+                updated_at: r.get(9)?,
+                deleted_at: r.get(10)?,
+            }))
+        },
+    );
+    match row {
+        Ok(row) => Ok(Some((version, Some(row)))),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(Some((version, None))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Record a change from another device as merged, writing it to the gist
+/// only if `write`. Its effects are not logged as a local change. Call
+/// inside a transaction.
+pub fn merge_sync_change(c: &Connection, change: &SyncChange, write: bool) -> SqlResult<()> {
+    c.execute("UPDATE sync_state SET applying = 1, clock = MAX(clock, ?1)", params![change.lamport])?;
+    if write {
+        let id: Option<i64> = match c.query_row("SELECT id FROM gists WHERE uuid = ?1", params![change.uuid], |r| r.get(0)) {
+            Ok(id) => Some(id),
+            Err(rusqlite::Error::QueryReturnedNoRows) => None,
+            Err(e) => return Err(e),
+        };
+        match (&change.gist, id) {
+            (Some(g), Some(id)) => {
+                c.execute(
+                    "UPDATE gists SET content=?1, tags=?2, title=?3, description=?4, language=?5, source_path=?6,
+                     source_url=?7, updated_at=IFNULL(?8, CURRENT_TIMESTAMP), deleted_at=?9 WHERE id=?10",
+                    params![g.content, g.tags, g.title, g.description, g.language, g.source_path, g.source_url, g.updated_at, g.deleted_at, id],
+                )?;
+                sync_gist_tags(c, id, g.tags.as_deref().unwrap_or(""))?;
+                record_revision(c, id)?;
+            }
+            (Some(g), None) => {
+                c.execute(
+                    "INSERT INTO gists (uuid, content, tags, title, description, language, source_path, source_url,
+                     created_at, updated_at, deleted_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, IFNULL(?9, CURRENT_TIMESTAMP), ?10, ?11)",
+                    params![change.uuid, g.content, g.tags, g.title, g.description, g.language, g.source_path,
+                        g.source_url, g.created_at, g.updated_at, g.deleted_at],
+                )?;
+                let id = c.last_insert_rowid();
+                sync_gist_tags(c, id, g.tags.as_deref().unwrap_or(""))?;
+                record_revision(c, id)?;
+            }
+            (None, Some(id)) => {
+                purge_gist(c, id)?;
+            }
+            (None, None) => {}
+        }
+        c.execute(
+            "INSERT OR REPLACE INTO sync_versions (uuid, version) VALUES (?1, ?2)",
+            params![change.uuid, change.version()],
+        )?;
+    }
+    c.execute(
+        "INSERT OR IGNORE INTO sync_log (uuid, lamport, device, base, op, gist) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            change.uuid,
+            change.lamport,
+            change.device,
+            change.base,
+            change.op,
+            change.gist.as_ref().map(|g| serde_json::to_string(g).unwrap_or_default()),
+        ],
+    )?;
+    c.execute("UPDATE sync_state SET applying = 0", [])?;
+    Ok(())
+}
+
+/// Marks the start of a highlighted match in `SearchHit::snippet`.
+pub const MATCH_START: char = '\u{2}';
+/// Marks the end of a highlighted match in `SearchHit::snippet`.
+pub const MATCH_END: char = '\u{3}';
+
+/// A ranked full-text search result.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub gist: Gist,
+    /// Excerpt around the best match, with matches wrapped in `MATCH_START`/`MATCH_END`.
+    pub snippet: String,
+    /// BM25 score; lower is a better match.
+    pub rank: f64,
+}
+
+/// Translate user input into an FTS5 query.
+///
+/// Quoted text is kept as a phrase, a trailing `*` makes a prefix query and
+/// upper-case `AND`/`OR`/`NOT` pass through as operators. Every other term is
+/// quoted so punctuation in snippets (`c++`, `foo::bar`) can't break the syntax.
+pub fn build_fts_query(input: &str) -> String {
+    let mut terms: Vec<String> = Vec::new();
+    let mut chars = input.chars().peekable();
+    
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        
+        if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&ch| ch != '"').collect();
+            let phrase = phrase.trim();
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase));
+            }
+            continue;
+        }
+        
+        let mut word = String::new();
+        while let Some(&ch) = chars.peek() {
+            if ch.is_whitespace() || ch == '"' {
+                break;
+            }
+            word.push(ch);
+            chars.next();
+        }
+        
+        match word.as_str() {
+            "AND" | "OR" | "NOT" => terms.push(word),
+            _ => {
+                let (stem, prefix) = match word.strip_suffix('*') {
+                    Some(stem) => (stem, true),
+                    None => (word.as_str(), false),
+                };
+                let stem = stem.replace('"', "");
+                if stem.is_empty() {
+                    continue;
+                }
+                terms.push(if prefix {
+                    format!("\"{}\"*", stem)
+                } else {
+                    format!("\"{}\"", stem)
+                });
+            }
+        }
+    }
+    
+    // Operators are only valid between terms
+    while matches!(terms.first().map(String::as_str), Some("AND" | "OR")) {
+        terms.remove(0);
+    }
+    while matches!(terms.last().map(String::as_str), Some("AND" | "OR" | "NOT")) {
+        terms.pop();
+    }
+    
+    terms.join(" ")
+}
+
+/// Search gists with a `query` language expression, best full-text matches
+/// first and newest first among the rest.
+pub fn search_gists_ranked(c: &Connection, query: &str) -> Result<Vec<SearchHit>, Box<dyn Error>> {
+    Ok(run_query(c, &Query::parse(query)?)?)
+}
+
+/// Run a parsed search query.
+pub fn run_query(c: &Connection, query: &Query) -> SqlResult<Vec<SearchHit>> {
+    let mut values: Vec<String> = Vec::new();
+    let condition = query.to_sql(&mut values);
+    let sql = match query.rank_query() {
+        Some(rank_query) => {
+            values.push(rank_query);
+            format!(
+                "SELECT {}, COALESCE(m.snip, substr(gists.content, 1, 120)), COALESCE(m.rank, 0.0)
+                 FROM gists LEFT JOIN (
+                    SELECT rowid, snippet(gists_fts, -1, char(2), char(3), '...', 16) AS snip,
+                           bm25(gists_fts, 1.0, 2.0) AS rank
+                    FROM gists_fts WHERE gists_fts MATCH ?{}
+                 ) m ON m.rowid = gists.id
+                 WHERE {} AND {}
+                 ORDER BY m.rank IS NULL, m.rank, gists.created_at DESC, gists.id DESC",
+                GIST_COLUMNS,
+                values.len(),
+                NOT_TRASHED,
+                condition
+            )
+        }
+        None => format!(
+            "SELECT {}, substr(gists.content, 1, 120), 0.0 FROM gists
+             WHERE {} AND {} ORDER BY gists.created_at DESC, gists.id DESC",
+            GIST_COLUMNS, NOT_TRASHED, condition
+        ),
     };
     
-    let mut stmt = c.prepare(sql)?;
-    let res = stmt.query_map(params![like], |r| {
-        Ok(Gist {
-            id: r.get(0)?,
-            content: r.get(1)?,
-            tags: r.get(2)?,
-            created_at: r.get(3)?,
+    let mut stmt = c.prepare(&sql)?;
+    let res = stmt.query_map(rusqlite::params_from_iter(values.iter()), |r| {
+        Ok(SearchHit {
+            gist: row_to_gist(r)?,
+            snippet: r.get(GIST_COLUMN_COUNT)?,
+            rank: r.get(GIST_COLUMN_COUNT + 1)?,
         })
     })?;
     
     let mut out = Vec::new();
-    for g in res {
-        out.push(g?);
+    for h in res {
+        out.push(h?);
     }
     Ok(out)
 }
 
+/// Search gists by content or tags. With `tags_only`, `query` is a tag expression.
+pub fn search_gists(c: &Connection, query: &str, tags_only: bool) -> Result<Vec<Gist>, Box<dyn Error>> {
+    if tags_only {
+        return search_by_tags(c, query);
+    }
+    Ok(search_gists_ranked(c, query)?
+        .into_iter()
+        .map(|h| h.gist)
+        .collect())
+}
+
 /// List gists with sorting and limit.
 pub fn list_gists(c: &Connection, limit: usize, sort_by: &str) -> SqlResult<Vec<Gist>> {
+    list_gists_in(c, limit, sort_by, None)
+}
+
+/// Like `list_gists`, restricted to a collection and its sub-collections.
+pub fn list_gists_in(c: &Connection, limit: usize, sort_by: &str, collection: Option<i64>) -> SqlResult<Vec<Gist>> {
     // Validate sort_by to prevent SQL injection
     let order_by = match sort_by.to_lowercase().as_str() {
-        "id" => "id",
-        "tags" => "tags",
-        "created" | "created_at" => "created_at",
-        _ => "created_at", // Default
+        "id" => "gists.id DESC".to_string(),
+        "tags" => "gists.tags DESC".to_string(),
+        "created" | "created_at" => "gists.created_at DESC".to_string(),
+        "updated" | "updated_at" => "COALESCE(gists.updated_at, gists.created_at) DESC".to_string(),
+        "used" => "u.last_used_at IS NULL, u.last_used_at DESC".to_string(),
+        "frecency" => format!("COALESCE(u.pinned, 0) DESC, {} DESC", FRECENCY_SQL),
+        _ => "gists.created_at DESC".to_string(), // Default
     };
     
+    let in_collection = collection.map(in_collection_sql).unwrap_or_else(|| "1".to_string());
     let sql = format!(
-        "SELECT id, content, tags, created_at FROM gists 
-         ORDER BY {} DESC LIMIT ?1", 
-        order_by
+        "SELECT {} FROM gists LEFT JOIN gist_usage u ON u.gist_id = gists.id
+         WHERE {} AND {} ORDER BY {}, gists.id DESC LIMIT ?1",
+        GIST_COLUMNS, NOT_TRASHED, in_collection, order_by
     );
     
     let mut stmt = c.prepare(&sql)?;
-    let res = stmt.query_map(params![limit as i64], |r| {
-        Ok(Gist {
-            id: r.get(0)?,
-            content: r.get(1)?,
-            tags: r.get(2)?,
-            created_at: r.get(3)?,
-        })
-    })?;
+    let res = stmt.query_map(params![limit as i64], row_to_gist)?;
     
     let mut out = Vec::new();
     for g in res {
@@ -161,16 +1555,33 @@ pub fn list_gists(c: &Connection, limit: usize, sort_by: &str) -> SqlResult<Vec<
     Ok(out)
 }
 
-#[derive(Serialize, Deserialize)]
+/// Version 2 added the optional metadata fields; version 1 files still import.
+const EXPORT_VERSION: u8 = 2;
+
+#[derive(Serialize)]
 struct GistExport {
     version: u8,
     gists: Vec<Gist>,
 }
 
-pub fn export_gists(c: &Connection, path: &PathBuf) -> Result<usize, Box<dyn Error>> {
-    let gists = list_gists(c, usize::MAX, "created_at")?;
+/// Write every gist, or those in `collection`, to a JSON export file.
+/// Encrypted content is written sealed unless a `keyring` is given to open it.
+/// Team library snippets are left out; their repository is their backup.
+pub fn export_gists(
+    c: &Connection,
+    path: &PathBuf,
+    collection: Option<i64>,
+    keyring: Option<&mut crypto::Keyring>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut gists = list_gists_in(c, usize::MAX, "created_at", collection)?;
+    gists.retain(|g| g.library.is_none());
+    if let Some(keyring) = keyring {
+        for g in &mut gists {
+            g.content = keyring.decrypt(&g.content)?;
+        }
+    }
     let export = GistExport {
-        version: 1,
+        version: EXPORT_VERSION,
         gists,
     };
     
@@ -179,30 +1590,260 @@ pub fn export_gists(c: &Connection, path: &PathBuf) -> Result<usize, Box<dyn Err
     Ok(export.gists.len())
 }
 
-pub fn import_gists(c: &Connection, path: &PathBuf) -> Result<usize, Box<dyn Error>> {
+/// What to do with an imported row whose content is already in the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStrategy {
+    /// Leave the existing gist alone
+    Skip,
+    /// Replace the existing gist's tags and metadata with the imported ones
+    Overwrite,
+    /// Import it as a separate gist anyway
+    KeepBoth,
+}
+
+impl std::str::FromStr for ImportStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ImportStrategy::Skip),
+            "overwrite" => Ok(ImportStrategy::Overwrite),
+            "keep-both" | "keepboth" => Ok(ImportStrategy::KeepBoth),
+            other => Err(format!("Unknown strategy '{}'. Use: skip, overwrite, keep-both", other)),
+        }
+    }
+}
+
+/// A row that could not be imported. `row` counts from 1 in input order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportError {
+    pub row: usize,
+    pub label: String,
+    pub message: String,
+}
+
+/// Outcome of an import (or of a dry run, which changes nothing).
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub added: usize,
+    pub updated: usize,
+    /// Duplicates left alone, including overwrites that changed nothing
+    pub skipped: usize,
+    pub errors: Vec<ImportError>,
+}
+
+/// Import a JSON export file; see `import_rows`.
+pub fn import_gists(
+    c: &Connection,
+    path: &PathBuf,
+    strategy: ImportStrategy,
+    dry_run: bool,
+    keyring: Option<&mut Keyring>,
+) -> Result<ImportReport, Box<dyn Error>> {
     let content = fs::read_to_string(path)?;
-    let import: GistExport = serde_json::from_str(&content)?;
+    // Rows are decoded one by one so a bad row doesn't sink the whole file
+    let import: ImportFile = serde_json::from_str(&content)?;
+    if import.version > EXPORT_VERSION {
+        return Err(format!(
+            "Export format v{} is newer than this build supports (v{})",
+            import.version, EXPORT_VERSION
+        )
+        .into());
+    }
     
-    if import.gists.is_empty() {
-        return Ok(0);
+    import_rows(c, import.gists.into_iter().map(Ok), strategy, dry_run, keyring)
+}
+
+/// Content hash used to spot duplicates; trailing whitespace doesn't count,
+/// since editors and note tools add or drop final newlines.
+pub fn duplicate_key(content: &str) -> String {
+    content_hash(content.trim_end())
+}
+
+/// `duplicate_key` of the plain text, opening sealed content with `keyring`
+/// when there is one; content it can't open is keyed as it is.
+fn opened_duplicate_key(content: &str, keyring: Option<&mut Keyring>) -> String {
+    match keyring {
+        Some(keyring) if crypto::is_encrypted(content) => match keyring.decrypt(content) {
+            Ok(plain) => duplicate_key(&plain),
+            Err(_) => duplicate_key(content),
+        },
+        _ => duplicate_key(content),
     }
+}
+
+/// Live gists by `duplicate_key`, keeping the oldest id for repeated content.
+/// With `keyring`, encrypted gists are keyed by their plain text so they still
+/// match what is added or imported in the clear.
+pub fn content_index(c: &Connection, mut keyring: Option<&mut Keyring>) -> Result<HashMap<String, i64>, Box<dyn Error>> {
+    let mut by_hash = HashMap::new();
+    for g in list_gists(c, usize::MAX, "id")? {
+        by_hash.entry(opened_duplicate_key(&g.content, keyring.as_deref_mut())).or_insert(g.id);
+    }
+    Ok(by_hash)
+}
+
+/// Import Gist-shaped JSON rows. Rows are matched to live gists by content
+/// (and to earlier rows), and `strategy` decides what happens to duplicates.
+/// Each row succeeds or fails on its own; failures, including rows the
+/// source could not read, are listed in the report with their position.
+/// With `dry_run` everything is rolled back. `keyring` opens sealed content,
+/// stored or imported, so duplicates are found by their plain text.
+pub fn import_rows(
+    c: &Connection,
+    rows: impl IntoIterator<Item = Result<serde_json::Value, ImportError>>,
+    strategy: ImportStrategy,
+    dry_run: bool,
+    mut keyring: Option<&mut Keyring>,
+) -> Result<ImportReport, Box<dyn Error>> {
+    let mut report = ImportReport::default();
+    let mut by_hash = content_index(c, keyring.as_deref_mut())?;
     
-    let mut count = 0;
-    c.execute("BEGIN TRANSACTION", [])?;
+    // Dropping the transaction (or a row's savepoint) without committing rolls it back
+    let mut tx = c.unchecked_transaction()?;
     
-    for gist in import.gists {
-        let result = c.execute(
-            "INSERT INTO gists (content, tags, created_at) VALUES (?1, ?2, ?3)",
-            params![gist.content, gist.tags, gist.created_at],
-        );
-        
-        if result.is_ok() {
-            count += 1;
+    for (i, row) in rows.into_iter().enumerate() {
+        let row = match row {
+            Ok(row) => row,
+            Err(e) => {
+                report.errors.push(ImportError { row: i + 1, ..e });
+                continue;
+            }
+        };
+        let gist: Gist = match serde_json::from_value(row.clone()) {
+            Ok(gist) => gist,
+            Err(e) => {
+                report.errors.push(ImportError {
+                    row: i + 1,
+                    label: row_label(&row),
+                    message: format!("invalid row: {}", e),
+                });
+                continue;
+            }
+        };
+        let hash = opened_duplicate_key(&gist.content, keyring.as_deref_mut());
+        let row_tx = tx.savepoint()?;
+        let result = import_row(&row_tx, &gist, by_hash.get(&hash).copied(), strategy);
+        match result {
+            Ok((id, outcome)) => {
+                row_tx.commit()?;
+                by_hash.entry(hash).or_insert(id);
+                match outcome {
+                    RowOutcome::Added => report.added += 1,
+                    RowOutcome::Updated => report.updated += 1,
+                    RowOutcome::Skipped => report.skipped += 1,
+                }
+            }
+            Err(e) => {
+                drop(row_tx);
+                report.errors.push(ImportError {
+                    row: i + 1,
+                    label: row_label(&row),
+                    message: e.to_string(),
+                });
+            }
         }
     }
     
-    c.execute("COMMIT", [])?;
-    Ok(count)
+    if !dry_run {
+        tx.commit()?;
+    }
+    Ok(report)
+}
+
+#[derive(Deserialize)]
+struct ImportFile {
+    version: u8,
+    gists: Vec<serde_json::Value>,
+}
+
+/// Short name for a row in error messages: its title or first content line.
+pub fn row_label(row: &serde_json::Value) -> String {
+    let text = row["title"]
+        .as_str()
+        .filter(|t| !t.trim().is_empty())
+        .or_else(|| row["content"].as_str().and_then(|c| c.lines().map(str::trim).find(|l| !l.is_empty())))
+        .unwrap_or("(untitled)");
+    text.chars().take(40).collect()
+}
+
+enum RowOutcome {
+    Added,
+    Updated,
+    Skipped,
+}
+
+/// Apply one imported row given the live gist it duplicates, if any.
+fn import_row(
+    c: &Connection,
+    gist: &Gist,
+    existing: Option<i64>,
+    strategy: ImportStrategy,
+) -> Result<(i64, RowOutcome), Box<dyn Error>> {
+    if gist.content.trim().is_empty() {
+        return Err("empty content".into());
+    }
+    
+    match (existing, strategy) {
+        (Some(id), ImportStrategy::Skip) => Ok((id, RowOutcome::Skipped)),
+        (Some(id), ImportStrategy::Overwrite) => {
+            let current = get_gist(c, id)?.ok_or("duplicate vanished during import")?;
+            let tags = split_tags(&gist.tags).join(", ");
+            let meta = gist.meta();
+            let same_meta = [
+                (&current.title, &meta.title),
+                (&current.description, &meta.description),
+                (&current.language, &meta.language),
+                (&current.source_path, &meta.source_path),
+                (&current.source_url, &meta.source_url),
+            ]
+            .iter()
+            .all(|(a, b)| non_empty(a) == non_empty(b));
+            if current.tags == tags && same_meta {
+                return Ok((id, RowOutcome::Skipped));
+            }
+            // One statement, so the row logs a single change
+            c.execute(
+                "UPDATE gists SET tags=?1, title=?2, description=?3, language=?4, source_path=?5, source_url=?6,
+                        updated_at=CURRENT_TIMESTAMP
+                 WHERE id=?7",
+                params![
+                    tags,
+                    non_empty(&meta.title),
+                    non_empty(&meta.description),
+                    non_empty(&meta.language),
+                    non_empty(&meta.source_path),
+                    non_empty(&meta.source_url),
+                    id,
+                ],
+            )?;
+            sync_gist_tags(c, id, &tags)?;
+            record_revision(c, id)?;
+            Ok((id, RowOutcome::Updated))
+        }
+        _ => {
+            let tags = split_tags(&gist.tags).join(", ");
+            c.execute(
+                "INSERT INTO gists (content, tags, created_at, title, description, language,
+                                    source_path, source_url, updated_at)
+                 VALUES (?1, ?2, IFNULL(?3, CURRENT_TIMESTAMP), ?4, ?5, ?6, ?7, ?8, IFNULL(?9, ?3))",
+                params![
+                    gist.content,
+                    tags,
+                    Some(gist.created_at.as_str()).filter(|t| !t.is_empty()),
+                    non_empty(&gist.title),
+                    non_empty(&gist.description),
+                    non_empty(&gist.language),
+                    non_empty(&gist.source_path),
+                    non_empty(&gist.source_url),
+                    gist.updated_at,
+                ],
+            )?;
+            let id = c.last_insert_rowid();
+            sync_gist_tags(c, id, &tags)?;
+            record_revision(c, id)?;
+            Ok((id, RowOutcome::Added))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -211,15 +1852,7 @@ mod tests {
 
     fn setup_db() -> Connection {
         let conn = Connection::open_in_memory().unwrap();
-        conn.execute(
-            "CREATE TABLE gists (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                content TEXT NOT NULL,
-                tags TEXT,
-                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
-            )",
-            [],
-        ).unwrap();
+        init_schema(&conn).unwrap();
         conn
     }
 
@@ -262,4 +1895,629 @@ mod tests {
         assert_eq!(results.len(), 1);
         assert_eq!(results[0].content, "rust code");
     }
+
+    #[test]
+    fn test_search_ranks_and_highlights() {
+        let conn = setup_db();
+        insert_gist(&conn, "fn retry() { retry_with_backoff(); }", "rust, retry").unwrap();
+        insert_gist(&conn, "mentions retry once", "notes").unwrap();
+        
+        let hits = search_gists_ranked(&conn, "retry").unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(hits[0].rank <= hits[1].rank);
+        assert!(hits[0].snippet.contains(MATCH_START));
+    }
+
+    #[test]
+    fn test_search_phrase_and_prefix() {
+        let conn = setup_db();
+        insert_gist(&conn, "exponential backoff helper", "").unwrap();
+        insert_gist(&conn, "backoff exponential order", "").unwrap();
+        
+        assert_eq!(search_gists(&conn, "\"exponential backoff\"", false).unwrap().len(), 1);
+        assert_eq!(search_gists(&conn, "expon*", false).unwrap().len(), 2);
+        assert!(search_gists(&conn, "expon", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fts_tracks_updates_and_deletes() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "old words", "").unwrap();
+        update_gist(&conn, id, "new words", "").unwrap();
+        assert!(search_gists(&conn, "old", false).unwrap().is_empty());
+        assert_eq!(search_gists(&conn, "new", false).unwrap().len(), 1);
+        
+        delete_gist(&conn, id).unwrap();
+        assert!(search_gists(&conn, "words", false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_existing_rows_backfilled() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE gists (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                content TEXT NOT NULL,
+                tags TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        ).unwrap();
+        conn.execute("INSERT INTO gists (content, tags) VALUES ('legacy row', 'old')", []).unwrap();
+        
+        init_schema(&conn).unwrap();
+        assert_eq!(search_gists(&conn, "legacy", false).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_build_fts_query() {
+        assert_eq!(build_fts_query("c++ foo::bar"), "\"c++\" \"foo::bar\"");
+        assert_eq!(build_fts_query("\"a b\" pre*"), "\"a b\" \"pre\"*");
+        assert_eq!(build_fts_query("rust OR go NOT"), "\"rust\" OR \"go\"");
+        assert_eq!(build_fts_query("  "), "");
+    }
+
+    #[test]
+    fn test_tag_search_is_exact() {
+        let conn = setup_db();
+        insert_gist(&conn, "a", "rust, cli").unwrap();
+        insert_gist(&conn, "b", "cursor").unwrap();
+        insert_gist(&conn, "c", "rs").unwrap();
+        
+        let hits = search_gists(&conn, "rs", true).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].content, "c");
+    }
+
+    #[test]
+    fn test_tag_search_boolean() {
+        let conn = setup_db();
+        insert_gist(&conn, "a", "rust, async").unwrap();
+        insert_gist(&conn, "b", "rust, old").unwrap();
+        insert_gist(&conn, "c", "go").unwrap();
+        
+        let names = |q: &str| -> Vec<String> {
+            let mut v: Vec<String> = search_by_tags(&conn, q).unwrap().into_iter().map(|g| g.content).collect();
+            v.sort();
+            v
+        };
+        assert_eq!(names("rust AND NOT old"), vec!["a"]);
+        assert_eq!(names("async OR go"), vec!["a", "c"]);
+        assert_eq!(names("RUST"), vec!["a", "b"]);
+        assert!(search_by_tags(&conn, "rust AND").is_err());
+    }
+
+    #[test]
+    fn test_tags_are_normalized_on_write() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "x", " Rust ,  Async  IO, rust").unwrap();
+        let gist = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(gist.tags, "rust, async io");
+        
+        let tags = list_tags(&conn).unwrap();
+        assert_eq!(tags.len(), 2);
+    }
+
+    #[test]
+    fn test_each_write_logs_one_change() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "x", " Rust , CLI").unwrap();
+        update_gist(&conn, id, "y", "Rust").unwrap();
+        let ops: Vec<String> = conn
+            .prepare("SELECT op FROM sync_log ORDER BY seq").unwrap()
+            .query_map([], |r| r.get(0)).unwrap()
+            .collect::<SqlResult<_>>().unwrap();
+        assert_eq!(ops, ["put", "put"]);
+    }
+
+    #[test]
+    fn test_rename_merge_delete_tags() {
+        let conn = setup_db();
+        let a = insert_gist(&conn, "a", "js, web").unwrap();
+        let b = insert_gist(&conn, "b", "javascript").unwrap();
+        
+        assert_eq!(rename_tag(&conn, "web", "frontend").unwrap(), 1);
+        assert_eq!(get_gist(&conn, a).unwrap().unwrap().tags, "js, frontend");
+        assert!(rename_tag(&conn, "js", "javascript").is_err());
+        
+        assert_eq!(merge_tags(&conn, &["js".to_string()], "javascript").unwrap(), 1);
+        assert_eq!(get_gist(&conn, a).unwrap().unwrap().tags, "frontend, javascript");
+        assert_eq!(list_tags(&conn).unwrap()[0], ("javascript".to_string(), 2));
+        
+        assert_eq!(delete_tag(&conn, "javascript").unwrap(), 2);
+        assert_eq!(get_gist(&conn, b).unwrap().unwrap().tags, "");
+        assert_eq!(list_tags(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_purge_gist_removes_tag_links() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "x", "solo").unwrap();
+        insert_gist(&conn, "y", "other").unwrap();
+        
+        delete_gist(&conn, id).unwrap();
+        let tags: Vec<String> = list_tags(&conn).unwrap().into_iter().map(|t| t.0).collect();
+        assert_eq!(tags, vec!["other"]);
+        
+        purge_gist(&conn, id).unwrap();
+        let n: i64 = conn.query_row("SELECT COUNT(*) FROM tags WHERE name = 'solo'", [], |r| r.get(0)).unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let conn = setup_db();
+        let meta = GistMeta {
+            title: Some("Retry helper".into()),
+            description: Some("  ".into()),
+            language: Some("rust".into()),
+            source_path: Some("/src/retry.rs".into()),
+            ..Default::default()
+        };
+        let id = insert_gist_with_meta(&conn, "fn retry() {}", "rust", &meta).unwrap();
+        let gist = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(gist.title.as_deref(), Some("Retry helper"));
+        assert_eq!(gist.description, None);
+        assert_eq!(gist.language.as_deref(), Some("rust"));
+        assert_eq!(gist.source(), Some("/src/retry.rs"));
+        assert!(gist.updated_at.is_some());
+        
+        let mut meta = gist.meta();
+        meta.source_url = Some("https://example.com/retry".into());
+        update_gist_meta(&conn, id, &meta).unwrap();
+        let gist = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(gist.source(), Some("https://example.com/retry"));
+        assert_eq!(gist.title.as_deref(), Some("Retry helper"));
+    }
+
+    #[test]
+    fn test_export_import_keeps_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("export.json");
+        
+        let src = setup_db();
+        let meta = GistMeta {
+            title: Some("Query".into()),
+            language: Some("sql".into()),
+            ..Default::default()
+        };
+        insert_gist_with_meta(&src, "SELECT 1;", "sql", &meta).unwrap();
+        assert_eq!(export_gists(&src, &path, None, None).unwrap(), 1);
+        
+        let dst = setup_db();
+        assert_eq!(import_gists(&dst, &path, ImportStrategy::Skip, false, None).unwrap().added, 1);
+        let gist = &list_gists(&dst, 10, "created").unwrap()[0];
+        assert_eq!(gist.title.as_deref(), Some("Query"));
+        assert_eq!(gist.language.as_deref(), Some("sql"));
+        assert!(gist.updated_at.is_some());
+    }
+
+    #[test]
+    fn test_import_v1_export() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("v1.json");
+        fs::write(
+            &path,
+            r#"{"version":1,"gists":[{"id":7,"content":"echo hi","tags":"bash","created_at":"2024-01-01 00:00:00"}]}"#,
+        )
+        .unwrap();
+        
+        let conn = setup_db();
+        assert_eq!(import_gists(&conn, &path, ImportStrategy::Skip, false, None).unwrap().added, 1);
+        let gist = &list_gists(&conn, 10, "created").unwrap()[0];
+        assert_eq!(gist.title, None);
+        assert_eq!(gist.updated_at.as_deref(), Some("2024-01-01 00:00:00"));
+    }
+
+    fn import(conn: &Connection, path: &PathBuf, strategy: ImportStrategy, dry_run: bool) -> (usize, usize, usize, usize) {
+        let r = import_gists(conn, path, strategy, dry_run, None).unwrap();
+        (r.added, r.updated, r.skipped, r.errors.len())
+    }
+
+    fn sync_log_len(conn: &Connection) -> i64 {
+        conn.query_row("SELECT count(*) FROM sync_log", [], |r| r.get(0)).unwrap()
+    }
+
+    #[test]
+    fn test_import_is_idempotent() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("export.json");
+        let conn = setup_db();
+        insert_gist(&conn, "one", "a").unwrap();
+        insert_gist(&conn, "two", "b").unwrap();
+        export_gists(&conn, &path, None, None).unwrap();
+
+        assert_eq!(import(&conn, &path, ImportStrategy::Skip, false), (0, 0, 2, 0));
+        assert_eq!(list_gists(&conn, 10, "id").unwrap().len(), 2);
+
+        assert_eq!(import(&conn, &path, ImportStrategy::KeepBoth, true), (2, 0, 0, 0));
+        assert_eq!(list_gists(&conn, 10, "id").unwrap().len(), 2);
+        assert_eq!(import(&conn, &path, ImportStrategy::KeepBoth, false), (2, 0, 0, 0));
+        assert_eq!(list_gists(&conn, 10, "id").unwrap().len(), 4);
+        // One change logged per imported row
+        assert_eq!(sync_log_len(&conn), 4);
+    }
+
+    #[test]
+    fn test_import_overwrite_replaces_tags_and_metadata() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("export.json");
+        fs::write(
+            &path,
+            r#"{"version":2,"gists":[
+                {"id":1,"content":"same","tags":"New,tags ","created_at":"2024-01-01 00:00:00","title":"Fresh"},
+                {"id":2,"content":"other","tags":" X","created_at":"2024-01-01 00:00:00"}
+            ]}"#,
+        )
+        .unwrap();
+        let conn = setup_db();
+        let id = insert_gist(&conn, "same", "old").unwrap();
+
+        assert_eq!(import(&conn, &path, ImportStrategy::Overwrite, true), (1, 1, 0, 0));
+        assert_eq!(get_gist(&conn, id).unwrap().unwrap().tags, "old");
+
+        let logged = sync_log_len(&conn);
+        assert_eq!(import(&conn, &path, ImportStrategy::Overwrite, false), (1, 1, 0, 0));
+        assert_eq!(sync_log_len(&conn), logged + 2);
+        let g = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(g.tags, "new, tags");
+        assert_eq!(g.title.as_deref(), Some("Fresh"));
+
+        // Nothing left to change
+        assert_eq!(import(&conn, &path, ImportStrategy::Overwrite, false), (0, 0, 2, 0));
+    }
+
+    #[test]
+    fn test_import_reports_bad_rows() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("export.json");
+        fs::write(
+            &path,
+            r#"{"version":2,"gists":[
+                {"content":"fine"},
+                {"title":"No body","tags":"x"},
+                {"content":"   ","title":"Blank"},
+                {"content":"fine"}
+            ]}"#,
+        )
+        .unwrap();
+        let conn = setup_db();
+        let report = import_gists(&conn, &path, ImportStrategy::Skip, false, None).unwrap();
+        assert_eq!((report.added, report.skipped), (1, 1));
+        let rows: Vec<(usize, &str)> = report.errors.iter().map(|e| (e.row, e.label.as_str())).collect();
+        assert_eq!(rows, vec![(2, "No body"), (3, "Blank")]);
+        assert!(report.errors[0].message.contains("content"));
+        assert_eq!(report.errors[1].message, "empty content");
+    }
+
+    #[test]
+    fn test_updates_record_revisions() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "v1", "a").unwrap();
+        update_gist(&conn, id, "v2", "a").unwrap();
+        update_gist(&conn, id, "v2", "a").unwrap(); // no-op, no new revision
+        update_gist(&conn, id, "v2", "a, b").unwrap();
+        
+        let revs = list_revisions(&conn, id).unwrap();
+        let summary: Vec<(i64, &str, &str)> = revs.iter().map(|r| (r.rev, r.content.as_str(), r.tags.as_str())).collect();
+        assert_eq!(summary, vec![(3, "v2", "a, b"), (2, "v2", "a"), (1, "v1", "a")]);
+    }
+
+    #[test]
+    fn test_restore_revision() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "original", "a").unwrap();
+        update_gist(&conn, id, "broken edit", "b").unwrap();
+        
+        assert_eq!(restore_revision(&conn, id, 1).unwrap(), 3);
+        let gist = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(gist.content, "original");
+        assert_eq!(gist.tags, "a");
+        assert_eq!(get_revision(&conn, id, 2).unwrap().unwrap().content, "broken edit");
+        assert!(restore_revision(&conn, id, 9).is_err());
+        
+        purge_gist(&conn, id).unwrap();
+        assert!(list_revisions(&conn, id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_trash_hides_and_restores() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "trash me", "junk").unwrap();
+        insert_gist(&conn, "keep me", "junk").unwrap();
+        
+        assert!(delete_gist(&conn, id).unwrap());
+        assert!(!delete_gist(&conn, id).unwrap());
+        assert!(get_gist(&conn, id).unwrap().is_none());
+        assert_eq!(list_gists(&conn, 10, "created").unwrap().len(), 1);
+        assert!(search_gists(&conn, "trash", false).unwrap().is_empty());
+        assert_eq!(search_gists(&conn, "junk", true).unwrap().len(), 1);
+        assert!(update_gist(&conn, id, "edit", "junk").is_err());
+        
+        let trash = list_trash(&conn).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].gist.content, "trash me");
+        
+        assert!(restore_gist(&conn, id).unwrap());
+        assert!(get_gist(&conn, id).unwrap().is_some());
+        assert!(list_trash(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_empty_trash_respects_age() {
+        let conn = setup_db();
+        let old = insert_gist(&conn, "old", "a").unwrap();
+        let recent = insert_gist(&conn, "recent", "b").unwrap();
+        delete_gist(&conn, old).unwrap();
+        delete_gist(&conn, recent).unwrap();
+        conn.execute(
+            "UPDATE gists SET deleted_at = datetime('now', '-40 days') WHERE id = ?1",
+            params![old],
+        )
+        .unwrap();
+        
+        assert_eq!(empty_trash(&conn, Some(30)).unwrap(), 1);
+        let trash = list_trash(&conn).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].gist.id, recent);
+        assert!(list_revisions(&conn, old).unwrap().is_empty());
+        
+        assert_eq!(empty_trash(&conn, None).unwrap(), 1);
+        assert!(list_trash(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_github_links() {
+        let conn = setup_db();
+        let a = insert_gist(&conn, "a", "t").unwrap();
+        let b = insert_gist(&conn, "b", "t").unwrap();
+        let link = |id, file: &str| GithubLink {
+            gist_id: id,
+            remote_id: "abc".into(),
+            filename: file.into(),
+            synced_hash: "h".into(),
+        };
+        save_github_link(&conn, &link(a, "a.rs")).unwrap();
+        save_github_link(&conn, &link(b, "b.rs")).unwrap();
+
+        assert_eq!(find_github_link(&conn, "abc", "b.rs").unwrap(), Some(link(b, "b.rs")));
+        assert_eq!(get_github_link(&conn, a).unwrap(), Some(link(a, "a.rs")));
+        assert!(find_github_link(&conn, "abc", "c.rs").unwrap().is_none());
+
+        // Trashed gists drop out of the listing; purged ones lose the link
+        delete_gist(&conn, a).unwrap();
+        assert_eq!(list_github_links(&conn).unwrap(), vec![link(b, "b.rs")]);
+        purge_gist(&conn, a).unwrap();
+        assert!(get_github_link(&conn, a).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_seal_gist_drops_plaintext_history() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "password=one", "ops").unwrap();
+        let other = insert_gist(&conn, "echo hi", "sh").unwrap();
+        update_gist(&conn, id, "password=two", "ops").unwrap();
+        store_embedding(&conn, id, "m", &[1.0], "h").unwrap();
+        record_run(&conn, id, Some(0), false, "two", 5).unwrap();
+        assert!(encrypted_sample(&conn).unwrap().is_none());
+
+        let sealed = format!("{}c2VhbGVk", crypto::PREFIX);
+        seal_gist(&conn, id, &sealed).unwrap();
+        let revisions = list_revisions(&conn, id).unwrap();
+        assert_eq!(revisions.len(), 1);
+        assert_eq!((revisions[0].rev, revisions[0].content.as_str()), (1, sealed.as_str()));
+        assert!(list_embeddings(&conn, "m").unwrap().is_empty());
+        assert_eq!(get_run_info(&conn, id).unwrap().unwrap().output, None);
+        assert_eq!(encrypted_sample(&conn).unwrap(), Some(sealed.clone()));
+        assert!(search_gists_ranked(&conn, "password").unwrap().is_empty());
+        assert_eq!(plaintext_gists(&conn).unwrap(), vec![(other, "echo hi".to_string())]);
+        let leaked: i64 = conn
+            .query_row("SELECT count(*) FROM sync_log WHERE gist LIKE '%password%'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(leaked, 0);
+        let sealed_puts: i64 = conn
+            .query_row("SELECT count(*) FROM sync_log WHERE json_extract(gist, '$.content') = ?1", [&sealed], |r| r.get(0))
+            .unwrap();
+        assert_eq!(sealed_puts, 3);
+    }
+
+    #[test]
+    fn test_provenance() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "fn f() {}", "rust").unwrap();
+        let p = Provenance {
+            gist_id: id,
+            start_line: Some(40),
+            end_line: Some(80),
+            symbol: Some("f".into()),
+            git_commit: Some("abc123".into()),
+            source_hash: "h".into(),
+        };
+        save_provenance(&conn, &p).unwrap();
+        assert_eq!(get_provenance(&conn, id).unwrap(), Some(p.clone()));
+        assert_eq!(p.line_range(), Some(LineRange { start: 40, end: 80 }));
+
+        purge_gist(&conn, id).unwrap();
+        assert!(get_provenance(&conn, id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_run_info() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "echo hi", "bash").unwrap();
+        assert!(get_run_info(&conn, id).unwrap().is_none());
+
+        set_runnable(&conn, id, true).unwrap();
+        record_run(&conn, id, Some(0), false, "hi\n", 12).unwrap();
+        let ok = get_run_info(&conn, id).unwrap().unwrap();
+        assert!(ok.runnable && ok.succeeded());
+        assert_eq!(ok.output.as_deref(), Some("hi\n"));
+        assert!(ok.last_success_at.is_some());
+
+        // A failure keeps the last success and the runnable flag
+        record_run(&conn, id, Some(2), false, "boom", 5).unwrap();
+        let failed = get_run_info(&conn, id).unwrap().unwrap();
+        assert!(failed.runnable && !failed.succeeded());
+        assert_eq!(failed.last_success_at, ok.last_success_at);
+        assert_eq!(list_run_info(&conn).unwrap()[&id].exit_code, Some(2));
+
+        set_runnable(&conn, id, false).unwrap();
+        assert!(!get_run_info(&conn, id).unwrap().unwrap().runnable);
+    }
+
+    #[test]
+    fn test_run_approval_follows_content() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "echo hi", "bash").unwrap();
+        set_runnable(&conn, id, true).unwrap();
+
+        // Content written by anyone else needs approving again
+        update_gist(&conn, id, "rm -rf ~", "bash").unwrap();
+        assert!(!get_run_info(&conn, id).unwrap().unwrap().runnable);
+        update_gist(&conn, id, "echo hi", "bash").unwrap();
+        assert!(get_run_info(&conn, id).unwrap().unwrap().runnable);
+
+        // The user's own edits and encryption keep it
+        update_gist(&conn, id, "echo hello", "bash").unwrap();
+        carry_run_approval(&conn, id, "echo hi").unwrap();
+        assert!(list_run_info(&conn).unwrap()[&id].runnable);
+        seal_gist(&conn, id, &format!("{}c2VhbGVk", crypto::PREFIX)).unwrap();
+        assert!(get_run_info(&conn, id).unwrap().unwrap().runnable);
+
+        // Carrying never approves content that wasn't approved before
+        set_runnable(&conn, id, false).unwrap();
+        update_gist(&conn, id, "echo again", "bash").unwrap();
+        carry_run_approval(&conn, id, "echo hello").unwrap();
+        assert!(!get_run_info(&conn, id).unwrap().unwrap().runnable);
+    }
+
+    #[test]
+    fn test_collections() {
+        let conn = setup_db();
+        let a = insert_gist(&conn, "SELECT 1", "sql").unwrap();
+        let b = insert_gist(&conn, "SELECT 2", "sql").unwrap();
+        let c = insert_gist(&conn, "echo hi", "bash").unwrap();
+
+        let sql = create_collection(&conn, "work/sql").unwrap();
+        let work = find_collection(&conn, "work").unwrap().unwrap();
+        assert_eq!(find_collection(&conn, "/work/sql/").unwrap(), Some(sql));
+        assert!(find_collection(&conn, "home").unwrap().is_none());
+        assert!(create_collection(&conn, "work/sql").is_err());
+        assert!(create_collection(&conn, "work//x").is_err());
+
+        add_to_collection(&conn, sql, &[a, b]).unwrap();
+        assert_eq!(add_to_collection(&conn, work, &[c, c]).unwrap(), 1);
+
+        let tree = list_collections(&conn).unwrap();
+        let paths: Vec<(&str, usize)> = tree.iter().map(|t| (t.path.as_str(), t.gist_count)).collect();
+        assert_eq!(paths, vec![("work", 1), ("work/sql", 2)]);
+        assert_eq!(tree[1].depth(), 1);
+
+        // A collection includes everything nested under it
+        assert_eq!(collection_gist_ids(&conn, work).unwrap(), [a, b, c].into_iter().collect());
+        assert_eq!(list_gists_in(&conn, 10, "id", Some(sql)).unwrap().len(), 2);
+        assert_eq!(gist_collections(&conn, a).unwrap(), vec!["work/sql".to_string()]);
+
+        remove_from_collection(&conn, sql, &[a]).unwrap();
+        delete_gist(&conn, b).unwrap();
+        assert!(collection_gist_ids(&conn, sql).unwrap().is_empty());
+
+        // Deleting a collection removes its children but keeps the snippets
+        assert_eq!(delete_collection(&conn, work).unwrap(), 2);
+        assert!(list_collections(&conn).unwrap().is_empty());
+        assert!(collection_memberships(&conn).unwrap().is_empty());
+        assert!(get_gist(&conn, c).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_usage_and_sorting() {
+        let conn = setup_db();
+        let old = insert_gist(&conn, "old", "a").unwrap();
+        let used = insert_gist(&conn, "used", "a").unwrap();
+        let pinned = insert_gist(&conn, "pinned", "a").unwrap();
+        let newest = insert_gist(&conn, "newest", "a").unwrap();
+        assert!(get_usage(&conn, old).unwrap().is_none());
+
+        record_usage(&conn, used, UsageKind::View).unwrap();
+        record_usage(&conn, used, UsageKind::Copy).unwrap();
+        record_usage(&conn, old, UsageKind::View).unwrap();
+        // An old use counts for less than a recent one
+        conn.execute("UPDATE gist_usage SET last_used_at = datetime('now', '-90 days') WHERE gist_id = ?1", params![old])
+            .unwrap();
+        set_pinned(&conn, pinned, true).unwrap();
+
+        let u = get_usage(&conn, used).unwrap().unwrap();
+        assert_eq!((u.view_count, u.copy_count, u.pinned), (1, 1, false));
+        assert!(u.last_used_at.is_some());
+        assert!(list_usage(&conn).unwrap()[&pinned].pinned);
+
+        let ids = |sort: &str| -> Vec<i64> { list_gists(&conn, 10, sort).unwrap().iter().map(|g| g.id).collect() };
+        assert_eq!(ids("frecency"), vec![pinned, used, old, newest]);
+        assert_eq!(ids("used"), vec![used, old, newest, pinned]);
+
+        // Pins and counts survive an unpin, and go with the gist
+        set_pinned(&conn, pinned, false).unwrap();
+        assert_eq!(ids("frecency")[0], used);
+        purge_gist(&conn, used).unwrap();
+        assert!(get_usage(&conn, used).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_structured_search() {
+        let conn = setup_db();
+        let sql_meta = GistMeta { language: Some("sql".into()), ..Default::default() };
+        let query = insert_gist_with_meta(&conn, "SELECT retry FROM jobs", "db", &sql_meta).unwrap();
+        let fresh = insert_gist(&conn, "fn retry() {}", "rust").unwrap();
+        let old = insert_gist(&conn, "fn retry_old() {}", "rust,old").unwrap();
+        let tagged_sql = insert_gist(&conn, "DELETE FROM jobs", "sql").unwrap();
+        conn.execute("UPDATE gists SET created_at = '2019-06-01 12:00:00' WHERE id = ?1", params![old]).unwrap();
+        set_pinned(&conn, fresh, true).unwrap();
+
+        let ids = |q: &str| -> Vec<i64> { search_gists_ranked(&conn, q).unwrap().iter().map(|h| h.gist.id).collect() };
+        assert_eq!(ids("tag:rust -tag:old"), vec![fresh]);
+        assert_eq!(ids("lang:sql"), vec![tagged_sql, query]);
+        assert_eq!(ids("created:<2020"), vec![old]);
+        assert_eq!(ids("pinned:true"), vec![fresh]);
+        let mut either = ids("retry lang:sql OR pinned:true");
+        either.sort();
+        assert_eq!(either, vec![query, fresh]);
+        assert_eq!(ids("\"retry from\" -jobs"), Vec::<i64>::new());
+        // Filters without text still return previews, newest first
+        let hits = search_gists_ranked(&conn, "-tag:rust").unwrap();
+        assert_eq!(hits.iter().map(|h| h.gist.id).collect::<Vec<_>>(), vec![tagged_sql, query]);
+        assert_eq!(hits[0].snippet, "DELETE FROM jobs");
+
+        let err = search_gists_ranked(&conn, "tag:rust AND").unwrap_err();
+        assert!(err.to_string().contains("ends after AND"), "{}", err);
+    }
+
+    #[test]
+    fn test_viewer_and_cli_search_agree() {
+        let conn = setup_db();
+        let sql_meta = GistMeta { language: Some("SQL".into()), title: Some("Nightly jobs".into()), ..Default::default() };
+        insert_gist_with_meta(&conn, "SELECT retry FROM jobs", "db", &sql_meta).unwrap();
+        let pinned = insert_gist(&conn, "fn retry_later() { std::io::stdin(); }", "rust, async io").unwrap();
+        insert_gist(&conn, "Retrying is not retry-safe", "sql").unwrap();
+        insert_gist(&conn, "echo 12", "sh").unwrap();
+        set_pinned(&conn, pinned, true).unwrap();
+
+        let gists = list_gists(&conn, 100, "created").unwrap();
+        let usage = list_usage(&conn).unwrap();
+        for q in [
+            "retry", "retr*", "RETRY", "ret", "\"retry from\"", "\"from retry\"", "std::io", "io::stdin",
+            "async", "\"async io\"", "nightly", "12", "1", "retry-safe", "tag:sql", "lang:sql", "-lang:sql",
+            "retry OR pinned:true", "retr* -tag:db", "(jobs OR later) NOT pinned:true",
+        ] {
+            let query = Query::parse(q).unwrap();
+            let mut cli: Vec<i64> = run_query(&conn, &query).unwrap().iter().map(|h| h.gist.id).collect();
+            let mut viewer: Vec<i64> = gists
+                .iter()
+                .filter(|g| query.matches(g, usage.get(&g.id).is_some_and(|u| u.pinned)))
+                .map(|g| g.id)
+                .collect();
+            cli.sort();
+            viewer.sort();
+            assert_eq!(cli, viewer, "{}", q);
+        }
+    }
 }
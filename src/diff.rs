@@ -0,0 +1,78 @@
+//! Unified diffs between snippet revisions.
+
+use colored::*;
+use similar::TextDiff;
+
+/// Unified diff of `old` against `new` with three lines of context.
+/// Returns an empty string when the texts are identical.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(old_label, new_label)
+        .missing_newline_hint(false)
+        .to_string()
+}
+
+/// How a line of unified diff output should be styled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    Header,
+    Hunk,
+    Added,
+    Removed,
+    Context,
+}
+
+/// Classify one line of `unified_diff` output.
+pub fn line_kind(line: &str) -> LineKind {
+    if line.starts_with("+++") || line.starts_with("---") {
+        LineKind::Header
+    } else if line.starts_with("@@") {
+        LineKind::Hunk
+    } else if line.starts_with('+') {
+        LineKind::Added
+    } else if line.starts_with('-') {
+        LineKind::Removed
+    } else {
+        LineKind::Context
+    }
+}
+
+/// Colour a unified diff for the terminal.
+pub fn colorize(diff: &str) -> String {
+    diff.lines()
+        .map(|line| match line_kind(line) {
+            LineKind::Header => line.bold().to_string(),
+            LineKind::Hunk => line.cyan().to_string(),
+            LineKind::Added => line.green().to_string(),
+            LineKind::Removed => line.red().to_string(),
+            LineKind::Context => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_marks_changed_lines() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n", "rev 1", "rev 2");
+        let lines: Vec<&str> = diff.lines().collect();
+        assert_eq!(lines[0], "--- rev 1");
+        assert_eq!(lines[1], "+++ rev 2");
+        assert!(lines.contains(&"-b"));
+        assert!(lines.contains(&"+B"));
+        assert_eq!(line_kind(lines[2]), LineKind::Hunk);
+    }
+
+    #[test]
+    fn identical_texts_have_no_diff() {
+        assert!(unified_diff("same\n", "same\n", "a", "b").is_empty());
+    }
+}
@@ -0,0 +1,132 @@
+//! Boolean expressions such as `a AND (b OR c) NOT d`, shared by tag queries
+//! (`tags`) and search queries (`query`).
+//!
+//! Each query language tokenizes its own input into [`Token`]s; parsing them is
+//! the same for both. `OR` binds loosest, then `AND` (which adjacent terms get
+//! implicitly), then `NOT`.
+
+/// Parsed expression over terms of type `T`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr<T> {
+    Term(T),
+    Not(Box<Expr<T>>),
+    And(Box<Expr<T>>, Box<Expr<T>>),
+    Or(Box<Expr<T>>, Box<Expr<T>>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<T> {
+    Term(T),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+/// How a query language names itself and its terms in error messages.
+pub struct Names {
+    /// e.g. "search query"
+    pub query: &'static str,
+    /// e.g. "a search term"
+    pub term: &'static str,
+}
+
+impl Names {
+    fn capitalized(&self) -> String {
+        let mut chars = self.query.chars();
+        chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+    }
+
+    fn describe<T>(&self, token: &Token<T>) -> String {
+        match token {
+            Token::Term(_) => self.term.to_string(),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+        }
+    }
+}
+
+/// Parse a whole token list; anything left over is an error.
+pub fn parse<T: Clone>(tokens: Vec<Token<T>>, names: &Names) -> Result<Expr<T>, String> {
+    if tokens.is_empty() {
+        return Err(format!("{} is empty", names.capitalized()));
+    }
+    let mut parser = Parser { tokens, pos: 0, names };
+    let expr = parser.parse_or()?;
+    if let Some(t) = parser.peek() {
+        return Err(format!("Unexpected {} in {}", names.describe(t), names.query));
+    }
+    Ok(expr)
+}
+
+struct Parser<'a, T> {
+    tokens: Vec<Token<T>>,
+    pos: usize,
+    names: &'a Names,
+}
+
+impl<T: Clone> Parser<'_, T> {
+    fn peek(&self) -> Option<&Token<T>> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token<T>> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn parse_or(&mut self) -> Result<Expr<T>, String> {
+        let mut left = self.parse_and()?;
+        while matches!(self.peek(), Some(Token::Or)) {
+            self.next();
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr<T>, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                }
+                Some(Token::Term(_)) | Some(Token::Not) | Some(Token::Open) => {}
+                _ => break,
+            }
+            let right = self.parse_unary()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr<T>, String> {
+        let names = self.names;
+        let after = self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)).map(|t| names.describe(t));
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.parse_unary()?))),
+            Some(Token::Open) => {
+                let inner = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(format!("Missing closing ')' in {}", names.query)),
+                }
+            }
+            Some(Token::Term(term)) => Ok(Expr::Term(term)),
+            Some(other) => Err(match after {
+                Some(prev) => format!("Expected {} after {} but found {}", names.term, prev, names.describe(&other)),
+                None => format!("{} can't start with {}", names.capitalized(), names.describe(&other)),
+            }),
+            None => Err(match after {
+                Some(prev) => format!("{} ends after {}; add a term", names.capitalized(), prev),
+                None => format!("{} is empty", names.capitalized()),
+            }),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+//! Pulling one item out of a source file for `gist add --lines/--symbol`,
+//! and checking later whether the original has changed.
+//!
+//! The symbol finder is deliberately lightweight: it recognises definitions
+//! by keyword per language and finds their end by braces, indentation or
+//! `end`, which covers ordinary code without a parser per language.
+
+use std::{fs, path::Path, process::Command};
+use crate::db::Provenance;
+use crate::utils::content_hash;
+
+/// An inclusive, 1-based line range such as `40-80`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl std::str::FromStr for LineRange {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| n.trim().parse::<usize>().ok().filter(|&n| n > 0);
+        let (start, end) = match s.split_once(['-', ':']) {
+            Some((a, b)) => (parse(a), parse(b)),
+            None => (parse(s), parse(s)),
+        };
+        match (start, end) {
+            (Some(start), Some(end)) if start <= end => Ok(LineRange { start, end }),
+            _ => Err(format!("Invalid line range '{}'. Use START-END, e.g. 40-80", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for LineRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// The text of `range`, which must start inside the file; an end past the
+/// last line is clamped.
+pub fn extract_lines(content: &str, range: LineRange) -> Result<(String, LineRange), String> {
+    let lines: Vec<&str> = content.lines().collect();
+    if range.start > lines.len() {
+        return Err(format!("Line {} is past the end of the file ({} lines)", range.start, lines.len()));
+    }
+    let range = LineRange { start: range.start, end: range.end.min(lines.len()) };
+    Ok((join_lines(&lines[range.start - 1..range.end]), range))
+}
+
+/// The definition of `name` (with its doc comments and attributes) and the
+/// lines it spans.
+pub fn extract_symbol(content: &str, language: Option<&str>, name: &str) -> Option<(String, LineRange)> {
+    if name.trim().is_empty() {
+        return None;
+    }
+    let lines: Vec<&str> = content.lines().collect();
+    let range = find_symbol(&lines, language.unwrap_or(""), name)?;
+    Some((join_lines(&lines[range.start - 1..range.end]), range))
+}
+
+fn join_lines(lines: &[&str]) -> String {
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// How a symbol's body is delimited.
+enum BlockStyle {
+    Braces,
+    Indent,
+    /// Closed by `end` at the definition's indent (Ruby, Lua)
+    End,
+    /// Runs to the next `;` outside `$$` quoting (SQL)
+    Statement,
+    /// Runs to the next blank line
+    Paragraph,
+}
+
+fn block_style(language: &str) -> BlockStyle {
+    match language {
+        "python" => BlockStyle::Indent,
+        "ruby" | "lua" => BlockStyle::End,
+        "sql" => BlockStyle::Statement,
+        "haskell" | "yaml" | "toml" | "markdown" => BlockStyle::Paragraph,
+        _ => BlockStyle::Braces,
+    }
+}
+
+/// Words that introduce a named definition, per language.
+fn definition_keywords(language: &str) -> &'static [&'static str] {
+    match language {
+        "rust" => &["fn", "struct", "enum", "trait", "mod", "type", "const", "static", "union", "impl", "macro_rules"],
+        "python" => &["def", "class"],
+        "javascript" | "typescript" => &["function", "class", "interface", "type", "enum", "const", "let", "var", "namespace"],
+        "go" => &["func", "type", "var", "const"],
+        "ruby" => &["def", "class", "module"],
+        "lua" => &["function"],
+        "perl" => &["sub", "package"],
+        "bash" => &["function"],
+        "sql" => &["function", "procedure", "view", "table", "trigger", "index", "type"],
+        "php" => &["function", "class", "interface", "trait", "enum"],
+        "swift" => &["func", "class", "struct", "enum", "protocol", "extension"],
+        "kotlin" | "scala" => &["fun", "def", "class", "object", "interface", "trait", "val", "var"],
+        _ => &["class", "interface", "enum", "struct", "record", "union", "namespace", "typedef", "function", "def", "fn", "func", "sub"],
+    }
+}
+
+/// Languages where `Type name(...) {` declares a function without a keyword.
+fn has_c_style_functions(language: &str) -> bool {
+    matches!(language, "c" | "cpp" | "java" | "csharp" | "php" | "kotlin" | "scala" | "swift" | "")
+}
+
+/// Line prefixes of comments and attributes that belong to the item below them.
+fn leading_markers(language: &str) -> &'static [&'static str] {
+    match language {
+        "rust" => &["///", "//", "#[", "/*", "*"],
+        "python" => &["#", "@"],
+        "ruby" | "bash" | "perl" | "yaml" | "toml" => &["#"],
+        "sql" | "lua" | "haskell" => &["--"],
+        _ => &["//", "/*", "*", "@", "#["],
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Byte offsets where `name` appears as a whole word in `line`.
+fn word_positions<'a>(line: &'a str, name: &'a str) -> impl Iterator<Item = usize> + 'a {
+    line.match_indices(name).map(|(i, _)| i).filter(move |&i| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + name.len()..].chars().next();
+        !before.is_some_and(is_ident_char) && !after.is_some_and(is_ident_char)
+    })
+}
+
+fn is_definition(line: &str, name: &str, language: &str) -> bool {
+    let trimmed = line.trim();
+    if trimmed.starts_with("//") || trimmed.starts_with('*') || trimmed.starts_with("--") || trimmed.starts_with("#!") {
+        return false;
+    }
+    if trimmed.starts_with('#') && !matches!(language, "c" | "cpp") {
+        return false;
+    }
+    let keywords = definition_keywords(language);
+    let first_word: String = trimmed.chars().take_while(|&c| is_ident_char(c)).collect();
+
+    word_positions(trimmed, name).any(|i| {
+        let prefix = &trimmed[..i];
+        let rest = trimmed[i + name.len()..].trim_start();
+        let prev_word: String = prefix
+            .trim_end()
+            .chars()
+            .rev()
+            .take_while(|&c| is_ident_char(c) || c == '!')
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+        let prev_word = prev_word.trim_end_matches('!').to_lowercase();
+
+        // `fn name`, `class name`, `CREATE FUNCTION name`
+        if keywords.contains(&prev_word.as_str()) {
+            return true;
+        }
+        // `func (r *T) name(`, `def self.name`, `function M.name(`
+        if ["func", "function", "def", "fun", "sub"].contains(&first_word.as_str()) && (rest.starts_with('(') || rest.is_empty()) {
+            return true;
+        }
+        // `name() {` in shell scripts
+        if language == "bash" && prefix.is_empty() && rest.starts_with("()") {
+            return true;
+        }
+        // `static int name(...) {` and methods in C-family languages
+        if has_c_style_functions(language) && rest.starts_with('(') && !trimmed.ends_with(';') {
+            let before = prefix.trim_end();
+            let control = ["return", "if", "while", "for", "switch", "else", "new", "throw", "case", "catch", "await", "sizeof"];
+            return !before.is_empty()
+                && !before.contains('=')
+                && !before.contains('(')
+                && !control.contains(&first_word.as_str())
+                && before.ends_with(|c: char| is_ident_char(c) || "*&>]".contains(c));
+        }
+        false
+    })
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// 1-based range of the definition of `name`, or None if it isn't found.
+fn find_symbol(lines: &[&str], language: &str, name: &str) -> Option<LineRange> {
+    let def = lines.iter().position(|l| is_definition(l, name, language))?;
+    let end = block_end(lines, def, language);
+
+    let markers = leading_markers(language);
+    let mut start = def;
+    while start > 0 {
+        let prev = lines[start - 1].trim_start();
+        if prev.is_empty() || prev.starts_with("#!") || !markers.iter().any(|m| prev.starts_with(m)) {
+            break;
+        }
+        start -= 1;
+    }
+    Some(LineRange { start: start + 1, end: end + 1 })
+}
+
+/// 0-based index of the last line of the block starting at `def`.
+fn block_end(lines: &[&str], def: usize, language: &str) -> usize {
+    let last = lines.len() - 1;
+    match block_style(language) {
+        BlockStyle::Braces => {
+            let mut depth = 0i32;
+            let mut opened = false;
+            for (i, line) in lines.iter().enumerate().skip(def) {
+                let mut in_string = false;
+                let mut chars = line.chars().peekable();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' if in_string => {
+                            chars.next();
+                        }
+                        '"' => in_string = !in_string,
+                        '/' if !in_string && chars.peek() == Some(&'/') => break,
+                        '\'' if !in_string && matches!(chars.peek(), Some('{') | Some('}')) => {
+                            chars.next();
+                        }
+                        '{' if !in_string => {
+                            depth += 1;
+                            opened = true;
+                        }
+                        '}' if !in_string => depth -= 1,
+                        ';' if !in_string && !opened && depth == 0 => return i,
+                        _ => {}
+                    }
+                }
+                if opened && depth <= 0 {
+                    return i;
+                }
+            }
+            last
+        }
+        BlockStyle::Indent => {
+            let base = indent_of(lines[def]);
+            // Skip a signature that spans several lines
+            let body = (def..=last).find(|&i| lines[i].trim_end().ends_with(':')).unwrap_or(def);
+            let mut end = body;
+            for (i, line) in lines.iter().enumerate().skip(body + 1) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if indent_of(line) <= base {
+                    break;
+                }
+                end = i;
+            }
+            end
+        }
+        BlockStyle::End => {
+            let base = indent_of(lines[def]);
+            if lines[def].trim_end().ends_with("end") {
+                return def;
+            }
+            (def + 1..=last)
+                .find(|&i| {
+                    let t = lines[i].trim_start();
+                    indent_of(lines[i]) == base && (t == "end" || t.starts_with("end ") || t.starts_with("end)") || t.starts_with("end,"))
+                })
+                .unwrap_or(last)
+        }
+        BlockStyle::Statement => {
+            let mut dollar_quoted = false;
+            for (i, line) in lines.iter().enumerate().skip(def) {
+                if line.matches("$$").count() % 2 == 1 {
+                    dollar_quoted = !dollar_quoted;
+                }
+                if !dollar_quoted && line.trim_end().ends_with(';') {
+                    return i;
+                }
+            }
+            last
+        }
+        BlockStyle::Paragraph => (def + 1..=last).find(|&i| lines[i].trim().is_empty()).map_or(last, |i| i - 1),
+    }
+}
+
+/// Full SHA of HEAD in the git repository containing `path`, if any.
+pub fn git_commit(path: &Path) -> Option<String> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let output = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// How the original compares to what was captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceStatus {
+    Unchanged,
+    /// Same text, now at these lines
+    Moved(LineRange),
+    Changed,
+    SymbolMissing,
+    FileMissing,
+}
+
+impl std::fmt::Display for SourceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SourceStatus::Unchanged => write!(f, "unchanged"),
+            SourceStatus::Moved(range) => write!(f, "unchanged, now at lines {}", range),
+            SourceStatus::Changed => write!(f, "changed since it was saved"),
+            SourceStatus::SymbolMissing => write!(f, "symbol no longer found"),
+            SourceStatus::FileMissing => write!(f, "file no longer exists"),
+        }
+    }
+}
+
+/// Re-extract the captured item from the file at `path` and compare it with
+/// the hash taken when the snippet was saved.
+pub fn source_status(path: &Path, language: Option<&str>, provenance: &Provenance) -> SourceStatus {
+    let Ok(content) = fs::read_to_string(path) else {
+        return SourceStatus::FileMissing;
+    };
+    let captured = match (&provenance.symbol, provenance.line_range()) {
+        (Some(symbol), _) => match extract_symbol(&content, language, symbol) {
+            Some(found) => Some(found),
+            None => return SourceStatus::SymbolMissing,
+        },
+        (None, Some(range)) => extract_lines(&content, range).ok(),
+        (None, None) => {
+            let whole = LineRange { start: 1, end: content.lines().count().max(1) };
+            Some((content, whole))
+        }
+    };
+    match captured {
+        Some((text, range)) if content_hash(&text) == provenance.source_hash => {
+            match provenance.line_range() {
+                Some(saved) if saved != range => SourceStatus::Moved(range),
+                _ => SourceStatus::Unchanged,
+            }
+        }
+        _ => SourceStatus::Changed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RUST: &str = "use std::fs;\n\n/// Reads the config.\n#[allow(dead_code)]\npub fn parse_config(path: &str) -> String {\n    let s = fs::read_to_string(path).unwrap();\n    if s.is_empty() { return \"{}\".into(); }\n    s\n}\n\nfn other() {}\n";
+
+    #[test]
+    fn parses_line_ranges() {
+        assert_eq!("40-80".parse::<LineRange>(), Ok(LineRange { start: 40, end: 80 }));
+        assert_eq!("7".parse::<LineRange>(), Ok(LineRange { start: 7, end: 7 }));
+        assert!("80-40".parse::<LineRange>().is_err());
+        assert!("0-3".parse::<LineRange>().is_err());
+        assert!("a-b".parse::<LineRange>().is_err());
+    }
+
+    #[test]
+    fn extracts_and_clamps_lines() {
+        let (text, range) = extract_lines("a\nb\nc\n", LineRange { start: 2, end: 9 }).unwrap();
+        assert_eq!(text, "b\nc\n");
+        assert_eq!(range, LineRange { start: 2, end: 3 });
+        assert!(extract_lines("a\n", LineRange { start: 3, end: 4 }).is_err());
+    }
+
+    #[test]
+    fn finds_rust_function_with_docs() {
+        let (text, range) = extract_symbol(RUST, Some("rust"), "parse_config").unwrap();
+        assert_eq!(range, LineRange { start: 3, end: 9 });
+        assert!(text.starts_with("/// Reads the config."));
+        assert!(text.trim_end().ends_with('}'));
+        assert_eq!(extract_symbol(RUST, Some("rust"), "other").unwrap().1, LineRange { start: 11, end: 11 });
+        // A call is not a definition
+        assert!(extract_symbol("fn main() {\n    parse_config(\"x\");\n}\n", Some("rust"), "parse_config").is_none());
+    }
+
+    #[test]
+    fn finds_symbols_in_other_languages() {
+        let py = "import os\n\n@cache\ndef load(\n    path,\n):\n    data = open(path).read()\n\n    return data\n\nx = load('a')\n";
+        assert_eq!(extract_symbol(py, Some("python"), "load").unwrap().1, LineRange { start: 3, end: 9 });
+
+        let go = "package main\n\nfunc (s *Server) Start(port int) error {\n\treturn nil\n}\n";
+        assert_eq!(extract_symbol(go, Some("go"), "Start").unwrap().1, LineRange { start: 3, end: 5 });
+
+        let java = "class A {\n    public static int add(int a, int b) {\n        return a + b;\n    }\n}\n";
+        assert_eq!(extract_symbol(java, Some("java"), "add").unwrap().1, LineRange { start: 2, end: 4 });
+
+        let rb = "class Greeter\n  def hello(name)\n    puts name\n  end\nend\n";
+        assert_eq!(extract_symbol(rb, Some("ruby"), "hello").unwrap().1, LineRange { start: 2, end: 4 });
+
+        let sql = "-- Totals per user\nCREATE FUNCTION totals() RETURNS int AS $$\n  SELECT 1;\n$$ LANGUAGE sql;\n";
+        assert_eq!(extract_symbol(sql, Some("sql"), "totals").unwrap().1, LineRange { start: 1, end: 4 });
+
+        let sh = "#!/bin/sh\ndeploy() {\n  echo \"}\"\n}\n";
+        assert_eq!(extract_symbol(sh, Some("bash"), "deploy").unwrap().1, LineRange { start: 2, end: 4 });
+    }
+
+    #[test]
+    fn detects_moved_and_changed_sources() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("lib.rs");
+        fs::write(&path, RUST).unwrap();
+        let (text, range) = extract_symbol(RUST, Some("rust"), "parse_config").unwrap();
+        let provenance = Provenance {
+            gist_id: 1,
+            start_line: Some(range.start),
+            end_line: Some(range.end),
+            symbol: Some("parse_config".into()),
+            git_commit: None,
+            source_hash: content_hash(&text),
+        };
+        assert_eq!(source_status(&path, Some("rust"), &provenance), SourceStatus::Unchanged);
+
+        fs::write(&path, format!("// header\n{}", RUST)).unwrap();
+        assert_eq!(
+            source_status(&path, Some("rust"), &provenance),
+            SourceStatus::Moved(LineRange { start: 4, end: 10 })
+        );
+
+        fs::write(&path, RUST.replace("unwrap()", "expect(\"config\")")).unwrap();
+        assert_eq!(source_status(&path, Some("rust"), &provenance), SourceStatus::Changed);
+
+        fs::write(&path, "fn main() {}\n").unwrap();
+        assert_eq!(source_status(&path, Some("rust"), &provenance), SourceStatus::SymbolMissing);
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(source_status(&path, Some("rust"), &provenance), SourceStatus::FileMissing);
+    }
+}
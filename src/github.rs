@@ -0,0 +1,554 @@
+//! GitHub Gists sync. Each file of a remote gist is one local snippet; the
+//! `github_links` table remembers which, so pulling or pushing again updates
+//! in place instead of duplicating.
+
+use reqwest::{Client, Method, RequestBuilder};
+use rusqlite::Connection;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Map, Value};
+use std::{collections::BTreeMap, error::Error, path::Path};
+use crate::db::{
+    find_github_link, get_github_link, get_gist, insert_gist_with_meta, list_github_links, save_github_link,
+    update_gist, update_gist_meta, GithubLink,
+};
+use crate::crypto::Keyring;
+use crate::language;
+use crate::models::{Gist, GistMeta};
+use crate::utils::content_hash;
+
+/// Tag given to snippets created by a pull.
+pub const PULL_TAG: &str = "github";
+
+const API_VERSION: &str = "2022-11-28";
+const PER_PAGE: usize = 100;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoteFile {
+    /// Missing in list responses; cut short when `truncated` is set
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub raw_url: Option<String>,
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoteGist {
+    pub id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub html_url: Option<String>,
+    #[serde(default)]
+    pub files: BTreeMap<String, RemoteFile>,
+}
+
+/// Minimal client for the Gists REST API at any base URL.
+pub struct GithubClient {
+    http: Client,
+    base_url: String,
+    token: Option<String>,
+}
+
+impl GithubClient {
+    pub fn new(base_url: &str, token: Option<String>) -> Self {
+        Self {
+            http: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token,
+        }
+    }
+
+    pub fn has_token(&self) -> bool {
+        self.token.is_some()
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let req = self
+            .http
+            .request(method, url)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", API_VERSION)
+            .header("User-Agent", concat!("gist-cli/", env!("CARGO_PKG_VERSION")));
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T, Box<dyn Error>> {
+        let resp = req.send().await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|v| v["message"].as_str().map(str::to_string))
+                .unwrap_or(body);
+            return Err(format!("GitHub API error {}: {}", status.as_u16(), message.trim()).into());
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// Every gist of the authenticated user, or `user`'s public gists.
+    pub async fn list(&self, user: Option<&str>) -> Result<Vec<RemoteGist>, Box<dyn Error>> {
+        let path = match user {
+            Some(user) => format!("{}/users/{}/gists", self.base_url, user),
+            None => format!("{}/gists", self.base_url),
+        };
+        let mut all = Vec::new();
+        for page in 1.. {
+            let url = format!("{}?per_page={}&page={}", path, PER_PAGE, page);
+            let batch: Vec<RemoteGist> = self.send(self.request(Method::GET, &url)).await?;
+            let done = batch.len() < PER_PAGE;
+            all.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(all)
+    }
+
+    /// One gist with file contents.
+    pub async fn get(&self, id: &str) -> Result<RemoteGist, Box<dyn Error>> {
+        let url = format!("{}/gists/{}", self.base_url, id);
+        self.send(self.request(Method::GET, &url)).await
+    }
+
+    pub async fn create(
+        &self,
+        description: Option<&str>,
+        public: bool,
+        files: &BTreeMap<String, String>,
+    ) -> Result<RemoteGist, Box<dyn Error>> {
+        let body = json!({
+            "description": description.unwrap_or_default(),
+            "public": public,
+            "files": file_map(files),
+        });
+        let url = format!("{}/gists", self.base_url);
+        self.send(self.request(Method::POST, &url).json(&body)).await
+    }
+
+    /// Replace the given files; other files of the gist are left alone.
+    pub async fn update(
+        &self,
+        id: &str,
+        description: Option<&str>,
+        files: &BTreeMap<String, String>,
+    ) -> Result<RemoteGist, Box<dyn Error>> {
+        let mut body = json!({ "files": file_map(files) });
+        if let Some(description) = description {
+            body["description"] = json!(description);
+        }
+        let url = format!("{}/gists/{}", self.base_url, id);
+        self.send(self.request(Method::PATCH, &url).json(&body)).await
+    }
+
+    /// Full text of a file, following `raw_url` when the API truncated it.
+    pub async fn file_content(&self, file: &RemoteFile) -> Result<String, Box<dyn Error>> {
+        match (&file.content, file.truncated, &file.raw_url) {
+            (Some(content), false, _) => Ok(content.clone()),
+            (_, _, Some(raw_url)) => {
+                let resp = self.request(Method::GET, raw_url).send().await?.error_for_status()?;
+                Ok(resp.text().await?)
+            }
+            _ => Err("GitHub returned a file without content".into()),
+        }
+    }
+}
+
+fn file_map(files: &BTreeMap<String, String>) -> Value {
+    let map: Map<String, Value> = files
+        .iter()
+        .map(|(name, content)| (name.clone(), json!({ "content": content })))
+        .collect();
+    Value::Object(map)
+}
+
+/// What a pull or push did.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Local gists created (pull) or newly uploaded (push)
+    pub created: Vec<i64>,
+    pub updated: Vec<i64>,
+    pub unchanged: usize,
+    /// Human-readable reasons for files that were left alone
+    pub skipped: Vec<String>,
+}
+
+/// Import the user's gists (or `user`'s, or just `ids`) as local snippets.
+/// With `keyring` (the whole store is encrypted) pulled content is sealed.
+pub async fn pull(
+    c: &Connection,
+    client: &GithubClient,
+    user: Option<&str>,
+    ids: &[String],
+    mut keyring: Option<&mut Keyring>,
+) -> Result<SyncReport, Box<dyn Error>> {
+    let ids: Vec<String> = if ids.is_empty() {
+        client.list(user).await?.into_iter().map(|g| g.id).collect()
+    } else {
+        ids.to_vec()
+    };
+
+    let mut report = SyncReport::default();
+    for id in &ids {
+        // The list endpoint leaves out file contents, so fetch each gist
+        let remote = match client.get(id).await {
+            Ok(remote) => remote,
+            Err(e) => {
+                report.skipped.push(format!("{}: {}", id, e));
+                continue;
+            }
+        };
+        for (filename, file) in &remote.files {
+            let content = match client.file_content(file).await {
+                Ok(content) => content,
+                Err(e) => {
+                    report.skipped.push(format!("{}/{}: {}", remote.id, filename, e));
+                    continue;
+                }
+            };
+            let language = file.language.as_deref();
+            apply_remote_file(c, &remote, filename, language, &content, keyring.as_deref_mut(), &mut report)?;
+        }
+    }
+    Ok(report)
+}
+
+/// Create or refresh the local copy of one remote file. A file unchanged
+/// since the last sync is left alone, so local edits survive a re-pull;
+/// otherwise GitHub wins and the old local text stays in the history.
+/// With `keyring` the local copy is stored sealed; without one, an encrypted
+/// local copy is never overwritten.
+pub fn apply_remote_file(
+    c: &Connection,
+    remote: &RemoteGist,
+    filename: &str,
+    remote_language: Option<&str>,
+    content: &str,
+    mut keyring: Option<&mut Keyring>,
+    report: &mut SyncReport,
+) -> Result<(), Box<dyn Error>> {
+    if content.trim().is_empty() {
+        report.skipped.push(format!("{}/{}: empty file", remote.id, filename));
+        return Ok(());
+    }
+    let hash = content_hash(content);
+    let description = remote.description.clone().filter(|d| !d.trim().is_empty());
+
+    if let Some(link) = find_github_link(c, &remote.id, filename)? {
+        let Some(local) = get_gist(c, link.gist_id)? else {
+            report
+                .skipped
+                .push(format!("{}/{}: local gist #{} is in the trash", remote.id, filename, link.gist_id));
+            return Ok(());
+        };
+        if link.synced_hash == hash {
+            report.unchanged += 1;
+            return Ok(());
+        }
+        let current = match keyring.as_deref_mut() {
+            Some(keyring) => keyring.decrypt(&local.content)?,
+            None if local.is_encrypted() => {
+                report.skipped.push(format!(
+                    "{}/{}: local gist #{} is encrypted; decrypt it to pull changes",
+                    remote.id, filename, local.id
+                ));
+                return Ok(());
+            }
+            None => local.content.clone(),
+        };
+        if current != content {
+            let stored = match keyring {
+                Some(keyring) => keyring.encrypt(content)?,
+                None => content.to_string(),
+            };
+            update_gist(c, local.id, &stored, &local.tags)?;
+        }
+        let mut meta = local.meta();
+        if meta.description != description {
+            meta.description = description;
+            update_gist_meta(c, local.id, &meta)?;
+        }
+        save_github_link(c, &GithubLink { synced_hash: hash, ..link })?;
+        report.updated.push(local.id);
+        return Ok(());
+    }
+
+    let lang = language::from_path(Path::new(filename)).or_else(|| remote_language.map(language::normalize));
+    let tags = match &lang {
+        Some(lang) => format!("{}, {}", PULL_TAG, lang),
+        None => PULL_TAG.to_string(),
+    };
+    let meta = GistMeta {
+        title: Some(filename.to_string()),
+        description,
+        language: lang,
+        source_url: remote.html_url.clone(),
+        ..Default::default()
+    };
+    let stored = match keyring {
+        Some(keyring) => keyring.encrypt(content)?,
+        None => content.to_string(),
+    };
+    let id = insert_gist_with_meta(c, &stored, &tags, &meta)?;
+    save_github_link(
+        c,
+        &GithubLink {
+            gist_id: id,
+            remote_id: remote.id.clone(),
+            filename: filename.to_string(),
+            synced_hash: hash,
+        },
+    )?;
+    report.created.push(id);
+    Ok(())
+}
+
+/// File name a snippet is uploaded under: its title, or `gist-<id>`, with
+/// the language's extension added when the name has none.
+pub fn filename_for(g: &Gist) -> String {
+    let ext = g.language.as_deref().and_then(language::file_extension);
+    let base = match g.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(title) => title.replace(['/', '\\'], "-"),
+        None => format!("gist-{}", g.id),
+    };
+    match ext {
+        Some(ext) if Path::new(&base).extension().is_none() => format!("{}.{}", base, ext),
+        None if Path::new(&base).extension().is_none() => format!("{}.txt", base),
+        _ => base,
+    }
+}
+
+/// Upload snippets. Linked ones update their GitHub file; others become new
+/// gists. With no `ids`, every linked snippet edited since its last sync is
+/// pushed. Files changed on GitHub since the last sync are skipped unless `force`,
+/// and encrypted snippets are always skipped.
+pub async fn push(
+    c: &Connection,
+    client: &GithubClient,
+    ids: &[i64],
+    public: bool,
+    force: bool,
+) -> Result<SyncReport, Box<dyn Error>> {
+    let mut report = SyncReport::default();
+    let mut linked: BTreeMap<String, Vec<(Gist, GithubLink)>> = BTreeMap::new();
+    let mut unlinked = Vec::new();
+
+    let encrypted = |g: &Gist, report: &mut SyncReport| {
+        if g.is_encrypted() {
+            report.skipped.push(format!("#{}: encrypted; decrypt it before pushing", g.id));
+        }
+        g.is_encrypted()
+    };
+    if ids.is_empty() {
+        for link in list_github_links(c)? {
+            if let Some(g) = get_gist(c, link.gist_id)? {
+                if !encrypted(&g, &mut report) {
+                    linked.entry(link.remote_id.clone()).or_default().push((g, link));
+                }
+            }
+        }
+    } else {
+        for &id in ids {
+            let Some(g) = get_gist(c, id)? else {
+                report.skipped.push(format!("#{}: not found", id));
+                continue;
+            };
+            if encrypted(&g, &mut report) {
+                continue;
+            }
+            match get_github_link(c, id)? {
+                Some(link) => linked.entry(link.remote_id.clone()).or_default().push((g, link)),
+                None => unlinked.push(g),
+            }
+        }
+    }
+
+    for (remote_id, items) in linked {
+        let (changed, same): (Vec<_>, Vec<_>) = items
+            .into_iter()
+            .partition(|(g, link)| content_hash(&g.content) != link.synced_hash);
+        report.unchanged += same.len();
+        if changed.is_empty() {
+            continue;
+        }
+
+        let mut pending = Vec::new();
+        if force {
+            pending = changed;
+        } else {
+            let remote = match client.get(&remote_id).await {
+                Ok(remote) => remote,
+                Err(e) => {
+                    report.skipped.push(format!("{}: {}", remote_id, e));
+                    continue;
+                }
+            };
+            for (g, link) in changed {
+                let remote_hash = match remote.files.get(&link.filename) {
+                    Some(file) => client.file_content(file).await.ok().map(|t| content_hash(&t)),
+                    None => None,
+                };
+                if remote_hash.is_some_and(|h| h != link.synced_hash) {
+                    report.skipped.push(format!(
+                        "#{}: {}/{} changed on GitHub; pull first or use --force",
+                        g.id, remote_id, link.filename
+                    ));
+                } else {
+                    pending.push((g, link));
+                }
+            }
+            if pending.is_empty() {
+                continue;
+            }
+        }
+
+        let files: BTreeMap<String, String> = pending
+            .iter()
+            .map(|(g, link)| (link.filename.clone(), g.content.clone()))
+            .collect();
+        let description = pending.iter().find_map(|(g, _)| g.description.as_deref());
+        if let Err(e) = client.update(&remote_id, description, &files).await {
+            report.skipped.push(format!("{}: {}", remote_id, e));
+            continue;
+        }
+        for (g, link) in pending {
+            save_github_link(c, &GithubLink { synced_hash: content_hash(&g.content), ..link })?;
+            report.updated.push(g.id);
+        }
+    }
+
+    for g in unlinked {
+        let filename = filename_for(&g);
+        let files = BTreeMap::from([(filename.clone(), g.content.clone())]);
+        let description = g.description.clone().or_else(|| g.title.clone());
+        match client.create(description.as_deref(), public, &files).await {
+            Ok(remote) => {
+                save_github_link(
+                    c,
+                    &GithubLink {
+                        gist_id: g.id,
+                        remote_id: remote.id,
+                        filename,
+                        synced_hash: content_hash(&g.content),
+                    },
+                )?;
+                report.created.push(g.id);
+            }
+            Err(e) => report.skipped.push(format!("#{}: {}", g.id, e)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{delete_gist, init_schema, list_revisions};
+
+    fn setup_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    fn remote(description: &str) -> RemoteGist {
+        RemoteGist {
+            id: "abc123".into(),
+            description: Some(description.into()),
+            html_url: Some("https://gist.github.com/abc123".into()),
+            files: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn pull_creates_then_updates_in_place() {
+        let conn = setup_db();
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote("Helpers"), "retry.py", None, "import time\n", None, &mut report).unwrap();
+        let id = report.created[0];
+
+        let g = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(g.title.as_deref(), Some("retry.py"));
+        assert_eq!(g.description.as_deref(), Some("Helpers"));
+        assert_eq!(g.language.as_deref(), Some("python"));
+        assert_eq!(g.tags, "github, python");
+        assert_eq!(g.source_url.as_deref(), Some("https://gist.github.com/abc123"));
+
+        // Same text again: nothing to do
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote("Helpers"), "retry.py", None, "import time\n", None, &mut report).unwrap();
+        assert_eq!((report.created.len(), report.updated.len(), report.unchanged), (0, 0, 1));
+
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote("Retry helpers"), "retry.py", None, "import time, random\n", None, &mut report)
+            .unwrap();
+        assert_eq!(report.updated, vec![id]);
+        let g = get_gist(&conn, id).unwrap().unwrap();
+        assert_eq!(g.content, "import time, random\n");
+        assert_eq!(g.description.as_deref(), Some("Retry helpers"));
+        assert_eq!(list_revisions(&conn, id).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn pull_keeps_local_edits_and_skips_trash() {
+        let conn = setup_db();
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo a\n", None, &mut report).unwrap();
+        let id = report.created[0];
+        update_gist(&conn, id, "echo local\n", "github").unwrap();
+
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo a\n", None, &mut report).unwrap();
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(get_gist(&conn, id).unwrap().unwrap().content, "echo local\n");
+
+        delete_gist(&conn, id).unwrap();
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo b\n", None, &mut report).unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.created.is_empty());
+    }
+
+    #[test]
+    fn pull_seals_with_a_keyring_and_leaves_sealed_copies_alone() {
+        let conn = setup_db();
+        let mut keyring = Keyring::new("s3cret");
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo a\n", Some(&mut keyring), &mut report).unwrap();
+        let id = report.created[0];
+        let g = get_gist(&conn, id).unwrap().unwrap();
+        assert!(g.is_encrypted());
+        assert_eq!(keyring.decrypt(&g.content).unwrap(), "echo a\n");
+
+        // Without the keyring a changed file can't replace the sealed copy
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo b\n", None, &mut report).unwrap();
+        assert!(report.skipped[0].contains("encrypted"), "{:?}", report.skipped);
+        assert_eq!(get_gist(&conn, id).unwrap().unwrap().content, g.content);
+
+        let mut report = SyncReport::default();
+        apply_remote_file(&conn, &remote(""), "a.sh", None, "echo b\n", Some(&mut keyring), &mut report).unwrap();
+        assert_eq!(report.updated, vec![id]);
+        let sealed = get_gist(&conn, id).unwrap().unwrap().content;
+        assert_eq!(keyring.decrypt(&sealed).unwrap(), "echo b\n");
+    }
+
+    #[test]
+    fn upload_filenames() {
+        let mut g = Gist { id: 7, ..Default::default() };
+        assert_eq!(filename_for(&g), "gist-7.txt");
+        g.language = Some("rust".into());
+        assert_eq!(filename_for(&g), "gist-7.rs");
+        g.title = Some("retry/backoff".into());
+        assert_eq!(filename_for(&g), "retry-backoff.rs");
+        g.title = Some("main.go".into());
+        assert_eq!(filename_for(&g), "main.go");
+    }
+}
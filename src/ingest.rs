@@ -0,0 +1,234 @@
+//! Bulk `gist add --dir`: walk a tree with the same ignore rules as CodeWalk
+//! and save each matching file as one snippet, tagging them in batches.
+
+use ignore::overrides::OverrideBuilder;
+use rusqlite::Connection;
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+use crate::ai::{get_tags_batch, sanitize_tags, RateLimiter};
+use crate::codewalk::repo::walker;
+use crate::config::Config;
+use crate::crypto::Keyring;
+use crate::db::{content_index, duplicate_key, insert_gist_with_meta, save_provenance, Provenance};
+use crate::extract::git_commit;
+use crate::language;
+use crate::models::GistMeta;
+use crate::secrets::{self, SecretPolicy};
+use crate::semantic;
+use crate::utils::content_hash;
+
+/// Files larger than this are skipped; they are rarely snippets.
+pub const MAX_FILE_BYTES: u64 = 512 * 1024;
+
+/// A file picked up by the walk.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    /// Path relative to the walked root, used as the title
+    pub relative: String,
+    pub content: String,
+}
+
+/// Why a walked file was not turned into a snippet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    Empty,
+    TooLarge,
+    NotText,
+    Duplicate(i64),
+    /// Blocked by `[secrets] policy = "block"`
+    Secrets(String),
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SkipReason::Empty => write!(f, "empty"),
+            SkipReason::TooLarge => write!(f, "larger than {} KiB", MAX_FILE_BYTES / 1024),
+            SkipReason::NotText => write!(f, "not UTF-8 text"),
+            SkipReason::Duplicate(id) => write!(f, "already saved as #{}", id),
+            SkipReason::Secrets(found) => write!(f, "possible secrets: {}", found),
+        }
+    }
+}
+
+/// A relative path and why it was skipped.
+pub type Skipped = (String, SkipReason);
+
+#[derive(Debug, Default)]
+pub struct IngestReport {
+    /// `(gist id, relative path)` for each snippet saved
+    pub added: Vec<(i64, String)>,
+    pub skipped: Vec<Skipped>,
+    /// `(relative path, findings)` for snippets saved with suspected secrets
+    pub flagged: Vec<(String, String)>,
+}
+
+/// Files under `root` matching any of `globs` (every file when empty), in
+/// walk order. Unreadable, empty and oversized files come back as skips.
+pub fn collect_files(
+    root: &Path,
+    globs: &[String],
+) -> Result<(Vec<SourceFile>, Vec<Skipped>), Box<dyn Error>> {
+    let mut builder = walker(root);
+    if !globs.is_empty() {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in globs {
+            overrides.add(glob)?;
+        }
+        builder.overrides(overrides.build()?);
+    }
+    builder.sort_by_file_path(|a, b| a.cmp(b));
+
+    let mut files = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in builder.build() {
+        let entry = entry?;
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().into_owned();
+        if entry.metadata().map(|m| m.len() > MAX_FILE_BYTES).unwrap_or(false) {
+            skipped.push((relative, SkipReason::TooLarge));
+            continue;
+        }
+        match fs::read_to_string(path) {
+            Ok(content) if content.trim().is_empty() => skipped.push((relative, SkipReason::Empty)),
+            Ok(content) => files.push(SourceFile { path: path.to_path_buf(), relative, content }),
+            Err(_) => skipped.push((relative, SkipReason::NotText)),
+        }
+    }
+    Ok((files, skipped))
+}
+
+/// Save `files` as snippets. Content already in the library is skipped, as
+/// is content with suspected secrets when the secrets policy blocks it.
+/// With `keyring` every file is saved encrypted instead, and never scanned
+/// or sent for tagging. With `tags` every file gets those tags; otherwise
+/// they are AI-tagged `batch_size` files per request, paced by `limiter`.
+/// `progress` is called after each file is saved or skipped.
+#[allow(clippy::too_many_arguments)]
+pub async fn add_files(
+    c: &Connection,
+    config: &Config,
+    files: Vec<SourceFile>,
+    tags: Option<&str>,
+    mut keyring: Option<&mut Keyring>,
+    batch_size: usize,
+    limiter: &mut RateLimiter,
+    mut progress: impl FnMut(&str, Result<i64, &SkipReason>),
+) -> Result<IngestReport, Box<dyn Error>> {
+    let mut report = IngestReport::default();
+    let mut by_hash = content_index(c, keyring.as_deref_mut())?;
+
+    let policy = if keyring.is_some() { SecretPolicy::Off } else { config.secret_policy() };
+    let mut fresh = Vec::new();
+    for file in files {
+        let hash = duplicate_key(&file.content);
+        let found = match policy {
+            SecretPolicy::Off => Vec::new(),
+            _ => secrets::scan(&file.content),
+        };
+        let blocked = policy == SecretPolicy::Block && !found.is_empty();
+        match by_hash.get(&hash) {
+            Some(&id) => {
+                let reason = SkipReason::Duplicate(id);
+                progress(&file.relative, Err(&reason));
+                report.skipped.push((file.relative, reason));
+            }
+            None if blocked => {
+                let reason = SkipReason::Secrets(secrets::describe(&found));
+                progress(&file.relative, Err(&reason));
+                report.skipped.push((file.relative, reason));
+            }
+            None => {
+                if !found.is_empty() {
+                    report.flagged.push((file.relative.clone(), secrets::describe(&found)));
+                }
+                // Reserve the hash so repeats within the walk are caught too
+                by_hash.insert(hash, 0);
+                fresh.push(file);
+            }
+        }
+    }
+
+    let commit = fresh.first().and_then(|f| git_commit(&f.path));
+    let hashes: Vec<String> = fresh.iter().map(|f| content_hash(&f.content)).collect();
+    // Sealed before tagging, so the provider only ever sees ciphertext markers
+    if let Some(keyring) = keyring {
+        for file in &mut fresh {
+            file.content = keyring.encrypt(&file.content)?;
+        }
+    }
+    let batch_size = batch_size.max(1);
+    for (b, batch) in fresh.chunks(batch_size).enumerate() {
+        let batch_tags = match tags {
+            Some(t) => vec![sanitize_tags(t); batch.len()],
+            None => {
+                let contents: Vec<&str> = batch.iter().map(|f| f.content.as_str()).collect();
+                get_tags_batch(&contents, config, limiter).await
+            }
+        };
+        for (j, (file, tags_str)) in batch.iter().zip(batch_tags).enumerate() {
+            let source = fs::canonicalize(&file.path).unwrap_or_else(|_| file.path.clone());
+            let meta = GistMeta {
+                title: Some(file.relative.clone()),
+                language: language::from_path(&file.path),
+                source_path: Some(source.to_string_lossy().into_owned()),
+                ..Default::default()
+            };
+            let id = insert_gist_with_meta(c, &file.content, &tags_str, &meta)?;
+            save_provenance(c, &Provenance {
+                gist_id: id,
+                start_line: None,
+                end_line: None,
+                symbol: None,
+                git_commit: commit.clone(),
+                source_hash: hashes[b * batch_size + j].clone(),
+            })?;
+            progress(&file.relative, Ok(id));
+            report.added.push((id, file.relative.clone()));
+        }
+    }
+
+    // One embeddings pass for the whole batch; `gist reindex` catches up on failure
+    if !report.added.is_empty() {
+        if let Ok((provider, _)) = semantic::embedding_provider(config) {
+            let _ = semantic::reindex(c, provider.as_ref(), false, |_, _| {}).await;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn collects_matching_files_and_reports_skips() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("db/migrations")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join("db/schema.sql"), "CREATE TABLE t (id INT);\n").unwrap();
+        fs::write(root.join("db/migrations/001.sql"), "ALTER TABLE t ADD x INT;\n").unwrap();
+        fs::write(root.join("db/empty.sql"), "  \n").unwrap();
+        fs::write(root.join("db/blob.sql"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(root.join("readme.md"), "# hi\n").unwrap();
+        fs::write(root.join(".hidden/secret.sql"), "SELECT 1;\n").unwrap();
+
+        let (files, skipped) = collect_files(root, &["**/*.sql".to_string()]).unwrap();
+        let names: Vec<&str> = files.iter().map(|f| f.relative.as_str()).collect();
+        assert_eq!(names, vec!["db/migrations/001.sql", "db/schema.sql"]);
+        assert!(skipped.contains(&("db/empty.sql".to_string(), SkipReason::Empty)));
+        assert!(skipped.contains(&("db/blob.sql".to_string(), SkipReason::NotText)));
+
+        let (all, _) = collect_files(root, &[]).unwrap();
+        assert!(all.iter().any(|f| f.relative == "readme.md"));
+    }
+}
@@ -0,0 +1,131 @@
+//! Snippet language detection and mapping to syntax highlighting.
+
+use std::path::Path;
+
+/// Known languages: (canonical name, file extensions, aliases accepted on input).
+const LANGUAGES: &[(&str, &[&str], &[&str])] = &[
+    ("rust", &["rs"], &[]),
+    ("python", &["py", "pyw"], &["py"]),
+    ("javascript", &["js", "mjs", "cjs", "jsx"], &["js", "node"]),
+    ("typescript", &["ts", "tsx", "mts"], &["ts"]),
+    ("go", &["go"], &["golang"]),
+    ("java", &["java"], &[]),
+    ("kotlin", &["kt", "kts"], &[]),
+    ("c", &["c", "h"], &[]),
+    ("cpp", &["cpp", "cc", "cxx", "hpp", "hh"], &["c++"]),
+    ("csharp", &["cs"], &["c#", "cs"]),
+    ("ruby", &["rb"], &["rb"]),
+    ("php", &["php"], &[]),
+    ("swift", &["swift"], &[]),
+    ("scala", &["scala"], &[]),
+    ("lua", &["lua"], &[]),
+    ("perl", &["pl", "pm"], &[]),
+    ("haskell", &["hs"], &[]),
+    ("bash", &["sh", "bash", "zsh"], &["sh", "shell", "zsh"]),
+    ("powershell", &["ps1"], &["pwsh"]),
+    ("sql", &["sql"], &[]),
+    ("html", &["html", "htm"], &[]),
+    ("css", &["css", "scss"], &[]),
+    ("json", &["json"], &[]),
+    ("yaml", &["yaml", "yml"], &["yml"]),
+    ("toml", &["toml"], &[]),
+    ("xml", &["xml"], &[]),
+    ("markdown", &["md", "markdown"], &["md"]),
+    ("makefile", &["mk"], &["make"]),
+    ("dockerfile", &[], &["docker"]),
+];
+
+/// Canonical language name for a file, from its extension or well-known file name.
+pub fn from_path(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_string_lossy().to_lowercase();
+    match file_name.as_str() {
+        "makefile" | "gnumakefile" => return Some("makefile".to_string()),
+        "dockerfile" => return Some("dockerfile".to_string()),
+        _ => {}
+    }
+
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(_, exts, _)| exts.contains(&ext.as_str()))
+        .map(|(name, _, _)| name.to_string())
+}
+
+/// Canonical name for user input such as `Py` or `sh`. Unknown names are kept as typed (lower-cased).
+pub fn normalize(name: &str) -> String {
+    let lower = name.trim().to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(canon, _, aliases)| *canon == lower || aliases.contains(&lower.as_str()))
+        .map(|(canon, _, _)| canon.to_string())
+        .unwrap_or(lower)
+}
+
+/// Language implied by a gist's tags, if one of them names a known language exactly.
+pub fn from_tags(tags: &str) -> Option<String> {
+    tags.split(',')
+        .map(|t| t.trim().to_lowercase())
+        .find_map(|t| {
+            LANGUAGES
+                .iter()
+                .find(|(canon, _, aliases)| *canon == t || aliases.contains(&t.as_str()))
+                .map(|(canon, _, _)| canon.to_string())
+        })
+}
+
+/// The usual file extension for a language name, e.g. `rs` for Rust.
+pub fn file_extension(language: &str) -> Option<&'static str> {
+    let canon = normalize(language);
+    LANGUAGES
+        .iter()
+        .find(|(name, _, _)| *name == canon)
+        .and_then(|(_, exts, _)| exts.first().copied())
+}
+
+/// File extension syntect understands for a language name.
+pub fn syntax_extension(language: &str) -> Option<&'static str> {
+    let canon = normalize(language);
+    match canon.as_str() {
+        // syntect ships no TypeScript/Kotlin/Swift grammar; fall back to close relatives
+        "typescript" => Some("js"),
+        "kotlin" | "csharp" => Some("java"),
+        "toml" | "dockerfile" => Some("sh"),
+        "makefile" => Some("make"),
+        _ => LANGUAGES
+            .iter()
+            .find(|(name, _, _)| *name == canon)
+            .and_then(|(_, exts, _)| exts.first().copied()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_from_path() {
+        assert_eq!(from_path(Path::new("src/main.rs")).as_deref(), Some("rust"));
+        assert_eq!(from_path(Path::new("deploy.SH")).as_deref(), Some("bash"));
+        assert_eq!(from_path(Path::new("Makefile")).as_deref(), Some("makefile"));
+        assert_eq!(from_path(Path::new("notes.txt")), None);
+        assert_eq!(from_path(Path::new("README")), None);
+    }
+
+    #[test]
+    fn tags_match_whole_names_only() {
+        assert_eq!(from_tags("cli, sh").as_deref(), Some("bash"));
+        assert_eq!(from_tags("shell-tricks, bashful"), None);
+        assert_eq!(from_tags("notes, Python").as_deref(), Some("python"));
+    }
+
+    #[test]
+    fn normalizes_aliases() {
+        assert_eq!(normalize(" Py "), "python");
+        assert_eq!(normalize("c++"), "cpp");
+        assert_eq!(normalize("elixir"), "elixir");
+        assert_eq!(syntax_extension("ts"), Some("js"));
+        assert_eq!(syntax_extension("rust"), Some("rs"));
+        assert_eq!(file_extension("TypeScript"), Some("ts"));
+        assert_eq!(file_extension("dockerfile"), None);
+    }
+}
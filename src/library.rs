@@ -0,0 +1,325 @@
+//! Team libraries: git repositories of snippet files that a team shares,
+//! cloned under `remotes/<name>` and mirrored into the private database.
+//!
+//! Every file in the repository, except hidden and git-ignored ones, is one
+//! snippet: its path is the title and its folders are tags. Mirrored rows
+//! carry the library's name, so list, search and the TUI show them next to
+//! your own snippets, and they never enter the sync changelog. Your own
+//! library wins: a file whose content you already have is not mirrored, and
+//! a local edit to a team snippet is kept over upstream changes until
+//! `gist push` sends it.
+
+use rusqlite::Connection;
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+use crate::config::{get_gist_dir, RemoteConfig};
+use crate::db::{
+    duplicate_key, get_gist, insert_library_gist, library_contents, list_library_files, private_content_keys,
+    purge_gist, save_library_file, update_gist, LibraryFile,
+};
+use crate::github::filename_for;
+use crate::ingest::collect_files;
+use crate::language;
+use crate::models::{Gist, GistMeta};
+use crate::output::Failure;
+use crate::sync::{git, git_pull};
+use crate::utils::content_hash;
+
+/// What a pull changed in one library.
+#[derive(Debug, Default)]
+pub struct PullReport {
+    pub library: String,
+    pub added: usize,
+    pub updated: usize,
+    /// Snippets whose file was deleted upstream
+    pub removed: usize,
+    /// Files left out because one of your own snippets has the same content
+    pub shadowed: usize,
+    /// Paths of team snippets edited here, whose local version was kept
+    pub kept: Vec<String>,
+}
+
+impl PullReport {
+    /// Whether any snippet was added, changed or removed.
+    pub fn changed(&self) -> bool {
+        self.added + self.updated + self.removed > 0
+    }
+}
+
+/// What a push wrote to a library.
+#[derive(Debug, Default)]
+pub struct PushReport {
+    /// `(gist id, path)` of each file written
+    pub written: Vec<(i64, String)>,
+    /// Whether anything was committed (and pushed, if there is a remote)
+    pub committed: bool,
+}
+
+/// Library names become folder names and labels, so keep them plain.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+/// Where the library called `name` is cloned.
+pub fn clone_dir(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    Ok(get_gist_dir()?.join("remotes").join(name))
+}
+
+fn existing_clone(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = clone_dir(name)?;
+    if !dir.join(".git").exists() {
+        return Err(Failure::not_found(format!(
+            "Team library '{}' is not cloned at {}; remove it and add it again",
+            name,
+            dir.display()
+        ))
+        .into());
+    }
+    Ok(dir)
+}
+
+/// Clone `remote` and mirror its snippets. A URL that is a local path is
+/// made absolute first, so the library keeps working from any directory.
+pub fn add(c: &Connection, remote: &mut RemoteConfig) -> Result<PullReport, Box<dyn Error>> {
+    if !valid_name(&remote.name) {
+        return Err(Failure::invalid(format!(
+            "Invalid library name '{}': use letters, digits, '-' and '_'",
+            remote.name
+        ))
+        .into());
+    }
+    if let Ok(path) = Path::new(&remote.url).canonicalize() {
+        remote.url = path.to_string_lossy().into_owned();
+    }
+    let dir = clone_dir(&remote.name)?;
+    if dir.exists() {
+        return Err(Failure::invalid(format!("{} already exists", dir.display())).into());
+    }
+    fs::create_dir_all(dir.parent().unwrap_or(&dir))?;
+    git(Path::new("."), &["clone", "--quiet", "--", &remote.url, &dir.to_string_lossy()])?;
+    mirror(c, &remote.name, &dir)
+}
+
+/// Pull library `name` with git and mirror what changed.
+pub fn pull(c: &Connection, name: &str) -> Result<PullReport, Box<dyn Error>> {
+    let dir = existing_clone(name)?;
+    git_pull(&dir)?;
+    mirror(c, name, &dir)
+}
+
+/// Tags for a file: its folders, then its language.
+fn tags_for(path: &str, lang: Option<&str>) -> String {
+    let mut tags: Vec<String> = Vec::new();
+    let folders = path.rsplit_once('/').map(|(folders, _)| folders).unwrap_or("");
+    for tag in folders.split('/').chain(lang).map(|t| t.trim().to_lowercase()) {
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags.join(", ")
+}
+
+/// Bring the snippets mirrored from `library` in line with the files in `dir`.
+pub fn mirror(c: &Connection, library: &str, dir: &Path) -> Result<PullReport, Box<dyn Error>> {
+    let mut report = PullReport { library: library.to_string(), ..Default::default() };
+    let (files, _) = collect_files(dir, &[])?;
+    let private = private_content_keys(c)?;
+    let mut links: HashMap<String, LibraryFile> =
+        list_library_files(c, library)?.into_iter().map(|l| (l.path.clone(), l)).collect();
+    let contents = library_contents(c, library)?;
+    let edited = |link: &LibraryFile| contents.get(&link.gist_id).is_some_and(|content| content_hash(content) != link.synced_hash);
+
+    let tx = c.unchecked_transaction()?;
+    for file in files {
+        let path = file.relative.replace('\\', "/");
+        let hash = content_hash(&file.content);
+        let shadowed = private.contains(&duplicate_key(&file.content));
+        let Some(link) = links.remove(&path) else {
+            if shadowed {
+                report.shadowed += 1;
+                continue;
+            }
+            let lang = language::from_path(&file.path);
+            let meta = GistMeta { title: Some(path.clone()), language: lang.clone(), ..Default::default() };
+            insert_library_gist(&tx, library, &path, &file.content, &tags_for(&path, lang.as_deref()), &meta)?;
+            report.added += 1;
+            continue;
+        };
+        if edited(&link) {
+            if hash != link.synced_hash {
+                report.kept.push(path);
+            }
+        } else if shadowed {
+            purge_gist(&tx, link.gist_id)?;
+            report.shadowed += 1;
+        } else if hash != link.synced_hash {
+            // A trashed copy keeps its old content until it is restored and pulled again
+            if let Some(g) = get_gist(&tx, link.gist_id)? {
+                update_gist(&tx, g.id, &file.content, &g.tags)?;
+                save_library_file(&tx, &LibraryFile { synced_hash: hash, ..link })?;
+                report.updated += 1;
+            }
+        }
+    }
+    // Whatever is left was deleted upstream
+    for link in links.into_values() {
+        if edited(&link) {
+            report.kept.push(link.path);
+        } else {
+            purge_gist(&tx, link.gist_id)?;
+            report.removed += 1;
+        }
+    }
+    tx.commit()?;
+    report.kept.sort();
+    Ok(report)
+}
+
+/// The snippets a push to `library` sends, with the path each is written
+/// to: `ids` if given, otherwise every snippet of the library edited here.
+/// Your own snippets get a new file named after their title.
+pub fn push_targets(c: &Connection, library: &str, ids: &[i64]) -> Result<Vec<(Gist, String)>, Box<dyn Error>> {
+    let dir = existing_clone(library)?;
+    let links: HashMap<i64, LibraryFile> =
+        list_library_files(c, library)?.into_iter().map(|l| (l.gist_id, l)).collect();
+    let mut targets = Vec::new();
+    if ids.is_empty() {
+        let mut linked: Vec<&LibraryFile> = links.values().collect();
+        linked.sort_by(|a, b| a.path.cmp(&b.path));
+        for link in linked {
+            if let Some(g) = get_gist(c, link.gist_id)? {
+                if content_hash(&g.content) != link.synced_hash {
+                    targets.push((g, link.path.clone()));
+                }
+            }
+        }
+        return Ok(targets);
+    }
+
+    let mut taken: HashSet<String> = links.values().map(|l| l.path.clone()).collect();
+    for &id in ids {
+        let g = get_gist(c, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+        if let Some(link) = links.get(&id) {
+            targets.push((g, link.path.clone()));
+            continue;
+        }
+        if let Some(other) = &g.library {
+            return Err(Failure::invalid(format!("Gist #{} belongs to team library '{}'", id, other)).into());
+        }
+        if g.is_encrypted() {
+            return Err(Failure::invalid(format!("Gist #{} is encrypted; decrypt it before sharing it", id)).into());
+        }
+        let name = filename_for(&g);
+        let (stem, ext) = match name.rsplit_once('.') {
+            Some((stem, ext)) => (stem.to_string(), format!(".{}", ext)),
+            None => (name.clone(), String::new()),
+        };
+        let path = (1..)
+            .map(|n| if n == 1 { name.clone() } else { format!("{}-{}{}", stem, n, ext) })
+            .find(|p| !taken.contains(p) && !dir.join(p).exists())
+            .expect("a free file name");
+        taken.insert(path.clone());
+        targets.push((g, path));
+    }
+    Ok(targets)
+}
+
+/// Write `targets` into the clone of `library`, then commit and push them.
+pub fn push(c: &Connection, library: &str, targets: &[(Gist, String)]) -> Result<PushReport, Box<dyn Error>> {
+    let dir = existing_clone(library)?;
+    let mut report = PushReport::default();
+    for (g, path) in targets {
+        let file = dir.join(path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file, &g.content)?;
+        git(&dir, &["add", "--", path])?;
+        report.written.push((g.id, path.clone()));
+    }
+    if !git(&dir, &["status", "--porcelain"])?.is_empty() {
+        let message = format!("gist push: {} snippet(s)", report.written.len());
+        git(&dir, &["commit", "--quiet", "-m", &message])?;
+        if !git(&dir, &["remote"])?.is_empty() {
+            git(&dir, &["push", "--quiet"])?;
+        }
+        report.committed = true;
+    }
+    // Team snippets now match their files; your own are left out of the
+    // mirror on the next pull, since you have their content
+    for (g, path) in targets {
+        if g.library.as_deref() == Some(library) {
+            save_library_file(
+                c,
+                &LibraryFile { gist_id: g.id, library: library.to_string(), path: path.clone(), synced_hash: content_hash(&g.content) },
+            )?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{init_schema, insert_gist, list_gists};
+    use tempfile::TempDir;
+
+    fn team(c: &Connection) -> Vec<(String, String, Option<String>)> {
+        let mut rows: Vec<_> = list_gists(c, 100, "id")
+            .unwrap()
+            .into_iter()
+            .map(|g| (g.title.unwrap_or_default(), g.content, g.library))
+            .collect();
+        rows.sort();
+        rows
+    }
+
+    #[test]
+    fn mirrors_files_and_keeps_your_own_edits() {
+        let repo = TempDir::new().unwrap();
+        fs::create_dir_all(repo.path().join("sql")).unwrap();
+        fs::write(repo.path().join("sql/vacuum.sql"), "VACUUM;\n").unwrap();
+        fs::write(repo.path().join("deploy.sh"), "make deploy\n").unwrap();
+        fs::write(repo.path().join("mine.txt"), "already mine\n").unwrap();
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        insert_gist(&conn, "already mine", "").unwrap();
+
+        let report = mirror(&conn, "team", repo.path()).unwrap();
+        assert_eq!((report.added, report.shadowed), (2, 1));
+        let by_title = |title: &str| {
+            list_gists(&conn, 100, "id").unwrap().into_iter().find(|g| g.title.as_deref() == Some(title)).unwrap()
+        };
+        let vacuum = by_title("sql/vacuum.sql");
+        assert_eq!((vacuum.tags.as_str(), vacuum.library.as_deref()), ("sql", Some("team")));
+
+        // Upstream edits land unless the snippet was edited here; deletions too
+        let deploy = by_title("deploy.sh");
+        update_gist(&conn, deploy.id, "make deploy ENV=prod\n", &deploy.tags).unwrap();
+        fs::write(repo.path().join("deploy.sh"), "make release\n").unwrap();
+        fs::write(repo.path().join("sql/vacuum.sql"), "VACUUM FULL;\n").unwrap();
+        let report = mirror(&conn, "team", repo.path()).unwrap();
+        assert_eq!((report.updated, report.kept.as_slice()), (1, &["deploy.sh".to_string()][..]));
+        fs::remove_file(repo.path().join("sql/vacuum.sql")).unwrap();
+        assert_eq!(mirror(&conn, "team", repo.path()).unwrap().removed, 1);
+        assert_eq!(
+            team(&conn),
+            vec![
+                (String::new(), "already mine".to_string(), None),
+                ("deploy.sh".to_string(), "make deploy ENV=prod\n".to_string(), Some("team".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn tags_come_from_folders_and_language() {
+        assert_eq!(tags_for("ops/k8s/rollout.sh", Some("bash")), "ops, k8s, bash");
+        assert_eq!(tags_for("sql/vacuum.sql", Some("sql")), "sql");
+        assert_eq!(tags_for("notes.txt", None), "");
+        assert!(valid_name("team-2") && !valid_name("../x") && !valid_name(""));
+    }
+}
@@ -3,27 +3,61 @@ mod viewer;
 mod models;
 mod config;
 mod db;
+mod migrations;
+mod expr;
+mod tags;
+mod query;
+mod language;
+mod diff;
+mod semantic;
+mod github;
+mod transfer;
+mod ingest;
+mod extract;
+mod template;
+mod runner;
+mod provider;
 mod ai;
+mod output;
+mod secrets;
+mod crypto;
+mod sync;
+mod library;
 mod utils;
 mod codewalk;
 
 use clap::{Parser, Subcommand};
 use colored::*;
-use std::{error::Error, path::PathBuf, io::Write};
+use std::{collections::HashSet, error::Error, path::PathBuf, io::Write};
 use syntect::easy::HighlightLines;
 use syntect::parsing::SyntaxSet;
 use syntect::highlighting::{ThemeSet, Style};
 use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
-use crate::models::{Gist, Theme};
-use crate::config::{load_config, save_config, Config};
+use crate::models::{Gist, GistMeta, Theme};
+use crate::config::{load_config, save_config, Config, ProviderRole};
 use crate::db::*;
 use crate::ai::get_tags;
-use crate::utils::{edit_content, prompt_confirm, validate_content};
+use crate::output::{Failure, GistRecord};
+use crate::utils::{edit_content, prompt_confirm, prompt_input, validate_content};
 
 #[derive(Parser)]
 #[command(author, version, about = "A simple code snippet manager")]
 #[command(long_about = "Store, search and organize your code snippets")]
 struct Cli {
+    /// Keep the database, config and sessions here instead of ~/.config/gist
+    #[arg(long, global = true, env = "GIST_HOME", value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Print results as text, plain (no colour), json, jsonl or tsv; goes
+    /// before the command, e.g. `gist --output json list`
+    #[arg(long, value_name = "FORMAT", env = "GIST_OUTPUT", default_value = "text")]
+    output: output::OutputFormat,
+
+    /// Same as --output, but also accepted after the command, e.g.
+    /// `gist list --output-format json`
+    #[arg(long, global = true, value_name = "FORMAT")]
+    output_format: Option<output::OutputFormat>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,13 +66,73 @@ struct Cli {
 enum Commands {
     /// Add a new snippet
     Add {
-        /// Add initial tags (comma separated)
+        /// Add initial tags (comma separated; with --dir, applied to every file)
         #[arg(short, long)]
         tags: Option<String>,
         
-        /// Initial content from file
-        #[arg(short, long)]
+        /// Initial content from file (also sets language and source path)
+        #[arg(short, long, conflicts_with_all = ["source", "dir", "clipboard"])]
         file: Option<PathBuf>,
+
+        /// Save only these lines of the file, e.g. 40-80
+        #[arg(long, value_name = "START-END", conflicts_with_all = ["symbol", "dir", "clipboard"])]
+        lines: Option<extract::LineRange>,
+
+        /// Save only this function, class or other item from the file
+        #[arg(long, value_name = "NAME", conflicts_with_all = ["dir", "clipboard"])]
+        symbol: Option<String>,
+
+        /// File to read, or - for stdin
+        #[arg(value_name = "FILE", conflicts_with_all = ["dir", "clipboard"])]
+        source: Option<PathBuf>,
+
+        /// Initial content from the clipboard
+        #[arg(long, conflicts_with = "dir")]
+        clipboard: bool,
+
+        /// Add every file under a directory, one snippet each (honours .gitignore)
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["title", "description", "language", "url"])]
+        dir: Option<PathBuf>,
+
+        /// Only add files matching this glob, e.g. '**/*.sql' (repeatable)
+        #[arg(long, requires = "dir")]
+        glob: Vec<String>,
+
+        /// Files tagged per AI request with --dir
+        #[arg(long, default_value_t = 10, requires = "dir")]
+        batch_size: usize,
+
+        /// Most AI tagging requests per minute with --dir (0 = no limit)
+        #[arg(long, default_value_t = 30, requires = "dir")]
+        rate_limit: u32,
+
+        /// Short title
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Longer description
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// Language, e.g. rust or python (inferred from --file when omitted)
+        #[arg(short, long)]
+        language: Option<String>,
+
+        /// URL the snippet came from
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Allow `gist run` to execute this snippet
+        #[arg(long)]
+        runnable: bool,
+
+        /// Save even if the secrets policy would block it
+        #[arg(long)]
+        allow_secrets: bool,
+
+        /// Save it encrypted with your passphrase; it is not sent for AI tagging
+        #[arg(long)]
+        encrypt: bool,
     },
     
     /// Update an existing snippet
@@ -49,6 +143,34 @@ enum Commands {
         /// Update tags for the snippet
         #[arg(short, long)]
         tags: Option<String>,
+
+        /// Set the title (empty string clears it)
+        #[arg(long)]
+        title: Option<String>,
+
+        /// Set the description (empty string clears it)
+        #[arg(short, long)]
+        description: Option<String>,
+
+        /// Set the language (empty string clears it)
+        #[arg(short, long)]
+        language: Option<String>,
+
+        /// Set the source URL (empty string clears it)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Allow or forbid `gist run` for this snippet
+        #[arg(long, value_name = "BOOL")]
+        runnable: Option<bool>,
+
+        /// Save even if the secrets policy would block it
+        #[arg(long)]
+        allow_secrets: bool,
+
+        /// Open the editor even when only setting tags or metadata
+        #[arg(short, long)]
+        edit: bool,
     },
     
     /// View snippet content
@@ -56,8 +178,72 @@ enum Commands {
         /// Snippet ID to view
         id: i64 
     },
+
+    /// Copy a snippet's content to the clipboard
+    Copy {
+        /// Snippet ID to copy
+        id: i64,
+    },
+
+    /// Pin snippets so they stay at the top of the viewer and `list --sort-by frecency`
+    Pin {
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Unpin snippets
+    Unpin {
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Fill in a template snippet's {{placeholders}} and print, copy or save the result
+    Use {
+        /// Snippet ID to expand
+        id: i64,
+
+        /// Value for a placeholder, e.g. --var table=users (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = template::parse_var)]
+        vars: Vec<(String, String)>,
+
+        /// Use defaults for unset placeholders instead of prompting
+        #[arg(long)]
+        defaults: bool,
+
+        /// Copy the result to the clipboard instead of printing it
+        #[arg(short, long)]
+        copy: bool,
+
+        /// Write the result to a file instead of printing it
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
     
-    /// Delete a snippet
+    /// Run a snippet marked runnable, with an interpreter chosen from its language
+    Run {
+        /// Snippet ID to run
+        id: i64,
+
+        /// Arguments passed to the snippet (put them after --)
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// Seconds before the run is killed (default from [run] timeout_secs, else 30)
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+
+        /// Run in the current directory instead of a scratch directory
+        #[arg(long)]
+        here: bool,
+
+        /// Pass the whole environment through instead of a minimal one
+        #[arg(long)]
+        inherit_env: bool,
+    },
+
+    /// Move a snippet to the trash
     Delete {
         /// Snippet ID to delete
         id: i64,
@@ -67,14 +253,68 @@ enum Commands {
         force: bool,
     },
     
-    /// Search for snippets
+    /// List the revisions of a snippet
+    History {
+        /// Snippet ID
+        id: i64,
+    },
+
+    /// Show a unified diff between two revisions of a snippet
+    ///
+    /// With no revisions, compares the previous revision to the current one.
+    /// With one, compares that revision to the current one.
+    Diff {
+        /// Snippet ID
+        id: i64,
+
+        /// Older revision
+        rev_a: Option<i64>,
+
+        /// Newer revision (defaults to the current one)
+        rev_b: Option<i64>,
+    },
+
+    /// Roll a snippet back to an earlier revision
+    Restore {
+        /// Snippet ID
+        id: i64,
+
+        /// Revision to restore
+        rev: i64,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+
+    /// Search for snippets (ranked full-text search)
     Search { 
-        /// Search query
+        /// Search query: words, "exact phrases", prefix*, AND/OR/NOT or -term, and
+        /// tag:NAME lang:NAME created:>2025-01 updated:<=2025-03-15 pinned:true
         query: String,
         
-        /// Search only in tags
+        /// Treat the query as an exact tag expression, e.g. "rust AND NOT old"
         #[arg(short, long)]
         tags_only: bool,
+
+        /// Rank by meaning using embeddings (falls back to full-text without an API key)
+        #[arg(long, conflicts_with = "tags_only")]
+        semantic: bool,
+
+        /// Maximum number of --semantic results
+        #[arg(short, long, default_value = "10")]
+        limit: usize,
+
+        /// Only snippets in this collection (or below it), e.g. work/sql
+        #[arg(long, value_name = "PATH")]
+        collection: Option<String>,
+    },
+
+    /// Compute embeddings for snippets that are missing or out of date
+    Reindex {
+        /// Re-embed every snippet, not just stale ones
+        #[arg(long)]
+        force: bool,
     },
     
     /// List all snippets
@@ -83,9 +323,13 @@ enum Commands {
         #[arg(short, long, default_value = "20")]
         limit: usize,
         
-        /// Sort by (created, id, tags)
+        /// Sort by (created, updated, id, tags, used, frecency)
         #[arg(short, long, default_value = "created")]
         sort_by: String,
+
+        /// Only snippets in this collection (or below it), e.g. work/sql
+        #[arg(long, value_name = "PATH")]
+        collection: Option<String>,
     },
     
     /// Launch interactive UI
@@ -93,16 +337,40 @@ enum Commands {
     
     /// Export all snippets to a file
     Export {
-        /// Path to export file
+        /// Path to export file (a directory for markdown-dir and files)
         #[arg(short, long)]
         output: PathBuf,
+
+        /// json, jsonl, markdown-dir or files
+        #[arg(long, default_value = "json")]
+        format: transfer::Format,
+
+        /// Only snippets in this collection (or below it)
+        #[arg(long, value_name = "PATH")]
+        collection: Option<String>,
+
+        /// Write encrypted snippets decrypted instead of sealed
+        #[arg(long)]
+        decrypt: bool,
     },
     
     /// Import snippets from file
     Import {
-        /// Path to import file
+        /// Path to import file or directory
         #[arg(short, long)]
         input: PathBuf,
+
+        /// json, jsonl, markdown-dir or files (default: guessed from the path)
+        #[arg(long)]
+        format: Option<transfer::Format>,
+
+        /// For snippets already in the library: skip, overwrite or keep-both
+        #[arg(long, default_value = "skip")]
+        strategy: ImportStrategy,
+
+        /// Report what would change without importing anything
+        #[arg(long)]
+        dry_run: bool,
     },
     
     /// Configure application settings
@@ -130,6 +398,30 @@ enum Commands {
         /// Set AI base URL
         #[arg(long)]
         ai_base_url: Option<String>,
+
+        /// Set embedding model for semantic search
+        #[arg(long)]
+        embedding_model: Option<String>,
+
+        /// Set the default provider by registry name ("" to clear)
+        #[arg(long)]
+        provider: Option<String>,
+
+        /// Days to keep deleted snippets in the trash (0 = until emptied)
+        #[arg(long)]
+        trash_days: Option<u32>,
+
+        /// What saving a snippet with suspected secrets does: warn, block or off
+        #[arg(long, value_name = "POLICY")]
+        secrets: Option<secrets::SecretPolicy>,
+
+        /// Encrypt every snippet as it is added or imported
+        #[arg(long, value_name = "BOOL")]
+        encrypt_all: Option<bool>,
+
+        /// Default folder for `gist sync` ("" to clear)
+        #[arg(long, value_name = "PATH")]
+        sync_dir: Option<PathBuf>,
         
         /// Show current configuration
         #[arg(short, long)]
@@ -139,6 +431,98 @@ enum Commands {
     /// Optimize database
     Optimize,
 
+    /// Scan every snippet for API keys, tokens, private keys and other secrets
+    AuditSecrets {
+        /// Also scan earlier revisions, which keep secrets removed since
+        #[arg(long)]
+        history: bool,
+    },
+
+    /// Encrypt snippets with your passphrase (GIST_PASSPHRASE, or asked for)
+    Encrypt {
+        /// Snippet IDs
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        ids: Vec<i64>,
+
+        /// Every snippet, including those in the trash
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Store encrypted snippets as plain text again
+    Decrypt {
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Manage deleted snippets
+    Trash {
+        #[command(subcommand)]
+        action: TrashCommands,
+    },
+
+    /// Database maintenance
+    Db {
+        #[command(subcommand)]
+        action: DbCommands,
+    },
+
+    /// Manage tags
+    Tags {
+        #[command(subcommand)]
+        action: TagCommands,
+    },
+
+    /// Group snippets into named, nestable collections
+    Collection {
+        #[command(subcommand)]
+        action: CollectionCommands,
+    },
+
+    /// Merge snippets with your other machines through a shared folder or git repository
+    Sync {
+        /// Shared folder (default: [sync] dir in config.toml); a git repository is pulled and pushed
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+    },
+
+    /// Manage team libraries: git repositories of shared snippets
+    Remote {
+        #[command(subcommand)]
+        action: RemoteCommands,
+    },
+
+    /// Pull team libraries and update their snippets here
+    Pull {
+        /// Library name (default: every team library)
+        name: Option<String>,
+    },
+
+    /// Share snippets with a team library, or send your edits to its snippets
+    Push {
+        /// Snippet IDs (default: every snippet of the library edited here)
+        ids: Vec<i64>,
+
+        /// Team library to push to (default: the only one)
+        #[arg(long, value_name = "NAME")]
+        to: Option<String>,
+
+        /// Push snippets that look like they hold secrets
+        #[arg(long)]
+        allow_secrets: bool,
+    },
+
+    /// Sync with GitHub Gists
+    Github {
+        /// Gists API base URL (overrides [github] api_url)
+        #[arg(long, global = true)]
+        api_url: Option<String>,
+
+        #[command(subcommand)]
+        action: GithubCommands,
+    },
+
     /// AI-powered repository walkthrough
     Codewalk {
         /// What to explore (e.g., "Trace the auth flow")
@@ -149,6 +533,10 @@ enum Commands {
         #[arg(short, long)]
         model: Option<String>,
 
+        /// Provider from the config registry (defaults to codewalk_provider)
+        #[arg(long)]
+        provider: Option<String>,
+
         /// Custom system prompt file
         #[arg(long)]
         prompt: Option<PathBuf>,
@@ -183,6 +571,14 @@ enum Commands {
         #[arg(long)]
         resume: Option<String>,
 
+        /// Run without the TUI: fetch --steps steps, then export and exit
+        #[arg(long)]
+        headless: bool,
+
+        /// Number of steps to fetch in --headless mode
+        #[arg(long, default_value_t = 3, requires = "headless")]
+        steps: usize,
+
         /// List saved sessions and exit
         #[arg(long)]
         list_sessions: bool,
@@ -193,43 +589,258 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum DbCommands {
+    /// Apply pending schema migrations
+    Migrate {
+        /// Show what would run without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List snippets in the trash
+    List,
+
+    /// Move snippets back out of the trash
+    Restore {
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Permanently delete everything in the trash
+    Empty {
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectionCommands {
+    /// Create a collection; missing parents in a path like work/sql are created too
+    Create {
+        /// Collection path
+        path: String,
+    },
+
+    /// Put snippets into a collection
+    Add {
+        /// Collection path
+        path: String,
+
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Take snippets out of a collection
+    Remove {
+        /// Collection path
+        path: String,
+
+        /// Snippet IDs
+        #[arg(required = true)]
+        ids: Vec<i64>,
+    },
+
+    /// Show the collection tree with snippet counts
+    List,
+
+    /// List the snippets in a collection and its sub-collections
+    Show {
+        /// Collection path
+        path: String,
+    },
+
+    /// Delete a collection and its sub-collections (the snippets are kept)
+    Delete {
+        /// Collection path
+        path: String,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum GithubCommands {
+    /// Import your GitHub gists as snippets, updating ones pulled before
+    Pull {
+        /// Only these gist IDs
+        ids: Vec<String>,
+
+        /// Pull this user's public gists instead of your own
+        #[arg(long)]
+        user: Option<String>,
+    },
+
+    /// Upload snippets; linked ones update their GitHub gist
+    Push {
+        /// Snippet IDs (default: every linked snippet edited since the last sync)
+        ids: Vec<i64>,
+
+        /// Make newly created gists public
+        #[arg(long)]
+        public: bool,
+
+        /// Overwrite files that were also changed on GitHub
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum RemoteCommands {
+    /// Clone a team library and add its snippets
+    Add {
+        /// Name shown next to its snippets, e.g. team
+        name: String,
+
+        /// Git URL or path of the repository
+        url: String,
+    },
+
+    /// List team libraries
+    List,
+
+    /// Forget a team library and remove its snippets here
+    Remove {
+        /// Library name
+        name: String,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagCommands {
+    /// List tags with usage counts
+    List,
+
+    /// Rename a tag on every snippet
+    Rename {
+        /// Current tag name
+        old: String,
+
+        /// New tag name
+        new: String,
+    },
+
+    /// Fold one or more tags into another
+    Merge {
+        /// Tags to merge away
+        #[arg(required = true)]
+        sources: Vec<String>,
+
+        /// Tag to merge into
+        #[arg(long)]
+        into: String,
+    },
+
+    /// Remove a tag from every snippet
+    Delete {
+        /// Tag name
+        name: String,
+
+        /// Skip confirmation prompt
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
 fn print_success(message: &str) {
-    println!("{} {}", "Success:".green().bold(), message);
+    output::emit_success(message, None);
 }
 
-fn display_gist(g: &Gist) {
-    println!(
-        "{} {}\n{} {}\n{} {}\n",
-        "ID:".bold(),
-        g.id.to_string().green(),
-        "Created:".bold(),
-        g.created_at,
-        "Tags:".bold(),
-        g.tags.cyan(),
-    );
+fn display_gist(
+    g: &Gist,
+    provenance: Option<&db::Provenance>,
+    run: Option<&RunInfo>,
+    usage: Option<&Usage>,
+    collections: &[String],
+) {
+    println!("{} {}{}", "ID:".bold(), g.id.to_string().green(), format_library(g));
+    if let Some(title) = &g.title {
+        println!("{} {}", "Title:".bold(), title.bold());
+    }
+    if let Some(description) = &g.description {
+        println!("{} {}", "Description:".bold(), description);
+    }
+    if let Some(language) = &g.language {
+        println!("{} {}", "Language:".bold(), language.magenta());
+    }
+    if let Some(source) = g.source() {
+        println!("{} {}", "Source:".bold(), source.underline());
+    }
+    if let Some(p) = provenance {
+        let mut from = match (p.line_range(), &p.symbol) {
+            (Some(range), Some(symbol)) => format!("{} (lines {})", symbol, range),
+            (Some(range), None) => format!("lines {}", range),
+            _ => "whole file".to_string(),
+        };
+        if let Some(commit) = &p.git_commit {
+            from.push_str(&format!(" at commit {}", &commit[..commit.len().min(7)]));
+        }
+        println!("{} {}", "Extracted:".bold(), from);
+        if let Some(path) = &g.source_path {
+            let status = extract::source_status(std::path::Path::new(path), g.language.as_deref(), p);
+            let status = match status {
+                extract::SourceStatus::Unchanged | extract::SourceStatus::Moved(_) => status.to_string().green(),
+                _ => status.to_string().yellow(),
+            };
+            println!("{} {}", "Original:".bold(), status);
+        }
+    }
+    println!("{} {}", "Created:".bold(), g.created_at);
+    if let Some(updated) = g.updated_at.as_ref().filter(|u| **u != g.created_at) {
+        println!("{} {}", "Updated:".bold(), updated);
+    }
+    if let Some(run) = run.filter(|r| r.runnable || r.ran_at.is_some()) {
+        let runnable = if run.runnable { "yes".green() } else { "no".yellow() };
+        println!("{} {}", "Runnable:".bold(), runnable);
+        if let Some(ran_at) = &run.ran_at {
+            let result = match (run.timed_out, run.exit_code) {
+                (true, _) => "timed out".red(),
+                (false, Some(0)) => "exit 0".green(),
+                (false, Some(code)) => format!("exit {}", code).red(),
+                (false, None) => "killed".red(),
+            };
+            println!("{} {} ({})", "Last run:".bold(), ran_at, result);
+        }
+        if let Some(ok) = run.last_success_at.as_ref().filter(|_| !run.succeeded()) {
+            println!("{} {}", "Last success:".bold(), ok);
+        }
+    }
+    if !collections.is_empty() {
+        println!("{} {}", "Collections:".bold(), collections.join(", "));
+    }
+    if let Some(usage) = usage {
+        if usage.pinned {
+            println!("{} {}", "Pinned:".bold(), "yes".yellow());
+        }
+        println!("{} {} view(s), {} copy(ies)", "Used:".bold(), usage.view_count, usage.copy_count);
+    }
+    println!("{} {}\n", "Tags:".bold(), g.tags.cyan());
 
     // Syntax highlighting
     let ps = SyntaxSet::load_defaults_newlines();
     let ts = ThemeSet::load_defaults();
     
-    // Determine syntax from tags or content
-    let syntax = if g.tags.to_lowercase().contains("rust") {
-        ps.find_syntax_by_extension("rs").unwrap()
-    } else if g.tags.to_lowercase().contains("python") {
-        ps.find_syntax_by_extension("py").unwrap()
-    } else if g.tags.to_lowercase().contains("javascript") || g.tags.to_lowercase().contains("js") {
-        ps.find_syntax_by_extension("js").unwrap()
-    } else if g.tags.to_lowercase().contains("html") {
-        ps.find_syntax_by_extension("html").unwrap()
-    } else if g.tags.to_lowercase().contains("css") {
-        ps.find_syntax_by_extension("css").unwrap()
-    } else if g.tags.to_lowercase().contains("sql") {
-        ps.find_syntax_by_extension("sql").unwrap()
-    } else if g.tags.to_lowercase().contains("sh") || g.tags.to_lowercase().contains("bash") {
-        ps.find_syntax_by_extension("sh").unwrap()
-    } else {
-        ps.find_syntax_by_first_line(&g.content).unwrap_or_else(|| ps.find_syntax_plain_text())
-    };
+    // Prefer the stored language, then a tag that names a language exactly, then the shebang
+    let syntax = g.language
+        .clone()
+        .or_else(|| language::from_tags(&g.tags))
+        .and_then(|lang| language::syntax_extension(&lang))
+        .and_then(|ext| ps.find_syntax_by_extension(ext))
+        .or_else(|| ps.find_syntax_by_first_line(&g.content))
+        .unwrap_or_else(|| ps.find_syntax_plain_text());
 
     let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
     
@@ -245,6 +856,9 @@ fn display_gist(g: &Gist) {
 }
 
 fn display_gist_preview(g: &Gist) {
+    if g.is_encrypted() {
+        return display_encrypted_preview(g);
+    }
     let prev: String = g.content
         .lines()
         .take(3)
@@ -261,63 +875,361 @@ fn display_gist_preview(g: &Gist) {
     };
     
     println!(
-        "{} {} {} {} {} {}\n{}",
+        "{} {}{} {} {} {} {}{}",
         "ID".bold(),
         g.id.to_string().green(),
+        format_library(g),
         "| Time:".bold(),
         format_timestamp(&g.created_at),
         "| Tags:".bold(),
         g.tags.cyan(),
-        preview
+        format_language(g)
     );
-    println!("{}", "-".repeat(60).dimmed());
-}
-
-fn format_timestamp(ts: &str) -> String {
-    // Simple format for display, assuming ISO format input
-    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(ts) {
-        return dt.format("%Y-%m-%d %H:%M").to_string();
+    if let Some(title) = &g.title {
+        println!("{}", title.bold());
     }
-    ts.to_string()
+    println!("{}", preview);
+    println!("{}", "-".repeat(60).dimmed());
 }
 
-// Main function
+/// The preview of an encrypted gist: its metadata, never its content.
+fn display_encrypted_preview(g: &Gist) {
+    println!(
+        "{} {}{} {} {} {} {}{}",
+        "ID".bold(),
+        g.id.to_string().green(),
+        format_library(g),
+        "| Time:".bold(),
+        format_timestamp(&g.created_at),
+        "| Tags:".bold(),
+        g.tags.cyan(),
+        format_language(g)
+    );
+    if let Some(title) = &g.title {
+        println!("{}", title.bold());
+    }
+    println!("{}", "(encrypted)".dimmed());
+    println!("{}", "-".repeat(60).dimmed());
+}
+
+fn format_language(g: &Gist) -> String {
+    match &g.language {
+        Some(lang) => format!(" {} {}", "| Lang:".bold(), lang.magenta()),
+        None => String::new(),
+    }
+}
+
+/// ` [team]` for snippets mirrored from a team library.
+fn format_library(g: &Gist) -> String {
+    match &g.library {
+        Some(library) => format!(" [{}]", library.blue()),
+        None => String::new(),
+    }
+}
+
+fn display_search_hit(hit: &SearchHit) {
+    let g = &hit.gist;
+    if g.is_encrypted() {
+        return display_encrypted_preview(g);
+    }
+    let snippet: String = hit.snippet
+        .split(MATCH_START)
+        .enumerate()
+        .map(|(i, part)| {
+            if i == 0 {
+                return part.replace('\n', " ");
+            }
+            match part.split_once(MATCH_END) {
+                Some((matched, rest)) => format!("{}{}", matched.yellow().bold(), rest.replace('\n', " ")),
+                None => part.replace('\n', " "),
+            }
+        })
+        .collect();
+    
+    println!(
+        "{} {}{} {} {} {} {}{}",
+        "ID".bold(),
+        g.id.to_string().green(),
+        format_library(g),
+        "| Time:".bold(),
+        format_timestamp(&g.created_at),
+        "| Tags:".bold(),
+        g.tags.cyan(),
+        format_language(g)
+    );
+    if let Some(title) = &g.title {
+        println!("{}", title.bold());
+    }
+    println!("{}", snippet.trim());
+    println!("{}", "-".repeat(60).dimmed());
+}
+
+fn pin_gists(conn: &rusqlite::Connection, ids: &[i64], pinned: bool) -> Result<(), Box<dyn Error>> {
+    let mut missing = 0;
+    for &id in ids {
+        if get_gist(conn, id)?.is_none() {
+            output::emit_error(&Failure::gist_not_found(id));
+            missing += 1;
+            continue;
+        }
+        set_pinned(conn, id, pinned)?;
+        print_success(&format!("{} gist #{}", if pinned { "Pinned" } else { "Unpinned" }, id));
+    }
+    some_missing(missing)
+}
+
+/// The overall result of a command that reported missing gists one by one.
+fn some_missing(missing: usize) -> Result<(), Box<dyn Error>> {
+    match missing {
+        0 => Ok(()),
+        n => Err(Failure::not_found(format!("{} gist(s) not found", n)).into()),
+    }
+}
+
+/// The id of a `--collection` path, which must exist.
+fn resolve_collection(conn: &rusqlite::Connection, path: Option<&str>) -> Result<Option<i64>, Box<dyn Error>> {
+    let Some(path) = path else { return Ok(None) };
+    let id = find_collection(conn, path)?.ok_or_else(|| Failure::not_found(format!("No collection named '{}'", path)))?;
+    Ok(Some(id))
+}
+
+/// Members of a `--collection` and its sub-collections, or None without one.
+fn collection_members(conn: &rusqlite::Connection, path: Option<&str>) -> Result<Option<HashSet<i64>>, Box<dyn Error>> {
+    match resolve_collection(conn, path)? {
+        Some(id) => Ok(Some(collection_gist_ids(conn, id)?)),
+        None => Ok(None),
+    }
+}
+
+/// Ranked full-text search, printed with highlighted matches.
+fn search_fulltext(conn: &rusqlite::Connection, query: &str, only: Option<&HashSet<i64>>) -> Result<(), Box<dyn Error>> {
+    let mut results = search_gists_ranked(conn, query)
+        .map_err(|e| Failure::invalid(format!("Invalid search query: {}", e)))?;
+    if let Some(ids) = only {
+        results.retain(|hit| ids.contains(&hit.gist.id));
+    }
+    if output::is_machine() {
+        let records: Vec<GistRecord> = results
+            .iter()
+            .map(|hit| GistRecord { score: Some(hit.rank), ..GistRecord::from(&hit.gist) })
+            .collect();
+        output::emit_list(&records, output::GIST_COLUMNS);
+        return Ok(());
+    }
+    if results.is_empty() {
+        println!("No results found for '{}'.", query);
+        return Ok(());
+    }
+    
+    println!("Found {} results for '{}':", results.len(), query);
+    for hit in &results {
+        display_search_hit(hit);
+    }
+    Ok(())
+}
+
+/// Apply the secrets policy to content about to be saved: warn about
+/// suspected secrets, or refuse unless `allow` is set.
+fn check_secrets(config: &Config, content: &str, allow: bool) -> Result<(), Box<dyn Error>> {
+    let policy = config.secret_policy();
+    if policy == secrets::SecretPolicy::Off {
+        return Ok(());
+    }
+    let found = secrets::scan(content);
+    if found.is_empty() {
+        return Ok(());
+    }
+    if policy == secrets::SecretPolicy::Block && !allow {
+        return Err(Failure::invalid(format!(
+            "Not saved: possible secrets ({}). Remove them or pass --allow-secrets",
+            secrets::describe(&found)
+        ))
+        .into());
+    }
+    eprintln!("{} Possible secrets: {}", "Warning:".yellow().bold(), secrets::describe(&found));
+    Ok(())
+}
+
+/// Decrypt an encrypted gist in place, asking for the passphrase.
+fn unlock(gist: &mut Gist) -> Result<(), Box<dyn Error>> {
+    if gist.is_encrypted() {
+        let mut keyring = crypto::Keyring::new(&utils::prompt_passphrase(false)?);
+        gist.content = keyring.decrypt(&gist.content).map_err(Failure::invalid)?;
+    }
+    Ok(())
+}
+
+/// The keyring for sealing or opening snippets. The library keeps one
+/// passphrase, so it is checked against an encrypted snippet; before the
+/// first one it is asked for twice instead.
+fn library_keyring(conn: &rusqlite::Connection) -> Result<crypto::Keyring, Box<dyn Error>> {
+    let sample = encrypted_sample(conn)?;
+    let mut keyring = crypto::Keyring::new(&utils::prompt_passphrase(sample.is_none())?);
+    if let Some(sample) = sample {
+        keyring.decrypt(&sample).map_err(Failure::invalid)?;
+    }
+    Ok(keyring)
+}
+
+/// Encrypt every snippet that isn't yet, trashed ones included. Returns how many.
+fn seal_all(conn: &rusqlite::Connection, keyring: &mut crypto::Keyring) -> Result<usize, Box<dyn Error>> {
+    let plain = plaintext_gists(conn)?;
+    for (id, content) in &plain {
+        seal_gist(conn, *id, &keyring.encrypt(content)?)?;
+    }
+    Ok(plain.len())
+}
+
+fn format_timestamp(ts: &str) -> String {
+    // Simple format for display, assuming ISO format input
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(ts) {
+        return dt.format("%Y-%m-%d %H:%M").to_string();
+    }
+    ts.to_string()
+}
+
+// Main function
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+async fn main() {
     let cli = Cli::parse();
-    let conn = match init_db() {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("{} {}: {}", "Fatal Error".red().bold(), "Cannot initialize database", e);
-            return Err(e);
-        }
+    output::set_format(cli.output_format.unwrap_or(cli.output));
+    if let Err(e) = run(cli).await {
+        let failure = Failure::from_error(e);
+        output::emit_error(&failure);
+        std::process::exit(failure.exit_code());
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = &cli.data_dir {
+        config::set_data_dir(dir)?;
+    }
+    // `gist db` manages migrations itself, so open it without upgrading
+    let opened = match cli.command {
+        Commands::Db { .. } => open_db(),
+        _ => init_db(),
     };
+    let conn = opened.map_err(|e| Failure::failed(format!("Cannot initialize database: {}", e)))?;
     
     let config = load_config();
+    
+    if config.trash_retention_days > 0 && !matches!(cli.command, Commands::Db { .. }) {
+        if let Err(e) = empty_trash(&conn, Some(config.trash_retention_days)) {
+            eprintln!("{} Could not expire old trash: {}", "Warning:".yellow().bold(), e);
+        }
+    }
 
     match cli.command {
-        Commands::Add { tags, file } => {
-            // Get content from file or editor
-            let content = if let Some(file_path) = file {
+        Commands::Add { dir: Some(dir), tags, glob, batch_size, rate_limit, encrypt, .. } => {
+            if !dir.is_dir() {
+                return Err(Failure::not_found(format!("Not a directory: {:?}", dir)).into());
+            }
+            let (files, unreadable) = ingest::collect_files(&dir, &glob)?;
+            for (path, reason) in &unreadable {
+                println!("  {} {} ({})", "-".dimmed(), path, reason);
+            }
+            if files.is_empty() {
+                println!("No files to add under {}.", dir.display());
+                return Ok(());
+            }
+
+            let mut keyring = match encrypt || config.encrypt_all() {
+                true => Some(library_keyring(&conn)?),
+                false => None,
+            };
+            println!("Adding {} files from {}...", files.len(), dir.display());
+            let mut limiter = ai::RateLimiter::per_minute(rate_limit);
+            let report = ingest::add_files(&conn, &config, files, tags.as_deref(), keyring.as_mut(), batch_size, &mut limiter, |path, result| {
+                match result {
+                    Ok(id) => println!("  {} #{} {}", "+".green(), id, path),
+                    Err(reason) => println!("  {} {} ({})", "-".dimmed(), path, reason),
+                }
+            })
+            .await?;
+            for (path, found) in &report.flagged {
+                eprintln!("{} {} has possible secrets: {}", "Warning:".yellow().bold(), path, found);
+            }
+            print_success(&format!(
+                "Added {} snippets ({} skipped)",
+                report.added.len(),
+                report.skipped.len() + unreadable.len()
+            ));
+        },
+
+        Commands::Add { tags, file, source, clipboard, lines, symbol, title, description, language, url, runnable, allow_secrets, encrypt, .. } => {
+            let mut meta = GistMeta {
+                title,
+                description,
+                language: language.map(|l| language::normalize(&l)),
+                source_url: url,
+                ..Default::default()
+            };
+            
+            // Get content from stdin, the clipboard, a file or the editor
+            let file = file.or(source);
+            let from_file = file.as_deref().is_some_and(|f| f != std::path::Path::new("-"));
+            if (lines.is_some() || symbol.is_some()) && !from_file {
+                return Err(Failure::invalid("--lines and --symbol need a file to read from").into());
+            }
+            let mut provenance = None;
+            let content = if file.as_deref() == Some(std::path::Path::new("-")) {
+                let mut buf = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut buf)?;
+                buf
+            } else if clipboard {
+                use clipboard::ClipboardProvider;
+                clipboard::ClipboardContext::new()
+                    .and_then(|mut ctx| ctx.get_contents())
+                    .map_err(|e| Failure::failed(format!("Could not read the clipboard: {}", e)))?
+            } else if let Some(file_path) = file {
                 if !file_path.exists() {
-                    eprintln!("{} File not found: {:?}", "Error:".red().bold(), file_path);
-                    return Ok(());
+                    return Err(Failure::not_found(format!("File not found: {:?}", file_path)).into());
                 }
-                std::fs::read_to_string(file_path)?
-            } else {
-                match edit_content(None) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        eprintln!("{} {}", "Error editing content:".red().bold(), e);
-                        return Ok(());
-                    }
+                if meta.language.is_none() {
+                    meta.language = language::from_path(&file_path);
                 }
+                let source = std::fs::canonicalize(&file_path).unwrap_or_else(|_| file_path.clone());
+                meta.source_path = Some(source.to_string_lossy().into_owned());
+                let whole = std::fs::read_to_string(&file_path)?;
+
+                // Cut out the requested item and remember where it came from
+                let extracted = if let Some(range) = lines {
+                    Some(extract::extract_lines(&whole, range).map_err(Failure::invalid)?)
+                } else if let Some(name) = &symbol {
+                    let found = extract::extract_symbol(&whole, meta.language.as_deref(), name)
+                        .ok_or_else(|| Failure::not_found(format!("Symbol '{}' not found in {:?}", name, file_path)))?;
+                    Some(found)
+                } else {
+                    None
+                };
+                let content = extracted.as_ref().map_or(whole, |(text, _)| text.clone());
+                if meta.title.is_none() {
+                    meta.title = symbol.clone();
+                }
+                provenance = Some(db::Provenance {
+                    gist_id: 0,
+                    start_line: extracted.as_ref().map(|(_, r)| r.start),
+                    end_line: extracted.as_ref().map(|(_, r)| r.end),
+                    symbol,
+                    git_commit: extract::git_commit(&source),
+                    source_hash: utils::content_hash(&content),
+                });
+                content
+            } else {
+                edit_content(None).map_err(|e| Failure::failed(format!("Could not edit content: {}", e)))?
             };
             
             if content.trim().is_empty() {
                 println!("Nothing saved (empty content).");
                 return Ok(());
             }
+            // Sealed content skips the secrets check, and tagging falls back to the defaults
+            let content = if encrypt || config.encrypt_all() {
+                library_keyring(&conn)?.encrypt(&content).map_err(Failure::failed)?
+            } else {
+                check_secrets(&config, &content, allow_secrets)?;
+                content
+            };
             
             // Get tags
             let tags_str = if let Some(t) = tags {
@@ -330,45 +1242,69 @@ async fn main() -> Result<(), Box<dyn Error>> {
             };
             
             // Insert into database
-            match insert_gist(&conn, &content, &tags_str) {
+            match insert_gist_with_meta(&conn, &content, &tags_str, &meta) {
                 Ok(id) => {
-                    print_success(&format!("Saved as gist #{}", id));
-                }
-                Err(e) => {
-                    eprintln!("{} {}", "Error saving gist:".red().bold(), e);
+                    if let Some(provenance) = provenance {
+                        save_provenance(&conn, &db::Provenance { gist_id: id, ..provenance })?;
+                    }
+                    if runnable {
+                        set_runnable(&conn, id, true)?;
+                    }
+                    semantic::index_gist(&conn, &config, id).await;
+                    output::emit_success(&format!("Saved as gist #{}", id), Some(id));
                 }
+                Err(e) => return Err(Failure::failed(format!("Could not save gist: {}", e)).into()),
             }
         },
 
-        Commands::Update { id, tags } => {
+        Commands::Update { id, tags, title, description, language, url, runnable, allow_secrets, edit } => {
             // Check if gist exists
-            let gist = match get_gist(&conn, id)? {
-                Some(g) => g,
-                None => {
-                    eprintln!("{} Gist #{} not found", "Error:".red().bold(), id);
-                    return Ok(());
-                }
+            let gist = get_gist(&conn, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+            // Setting tags or metadata leaves the content alone unless --edit asks for the editor
+            let flags_only = !edit
+                && (tags.is_some() || title.is_some() || description.is_some() || language.is_some() || url.is_some() || runnable.is_some());
+            // Team snippets are shared in plain text, whatever the local setting
+            let mut keyring = match !flags_only && (gist.is_encrypted() || (config.encrypt_all() && gist.library.is_none())) {
+                true => Some(library_keyring(&conn)?),
+                false => None,
+            };
+            let original = match keyring.as_mut() {
+                Some(k) => k.decrypt(&gist.content).map_err(Failure::invalid)?,
+                None => gist.content.clone(),
             };
             
             // Get updated content
-            let content = match edit_content(Some(&gist.content)) {
-                Ok(c) => c,
-                Err(e) => {
-                    eprintln!("{} {}", "Error editing content:".red().bold(), e);
-                    return Ok(());
-                }
+            let content = match flags_only {
+                true => original.clone(),
+                false => edit_content(Some(&original))
+                    .map_err(|e| Failure::failed(format!("Could not edit content: {}", e)))?,
             };
             
             if content.trim().is_empty() {
                 println!("Nothing updated (empty content).");
                 return Ok(());
             }
+            let changed = content != original;
+            let sealing = keyring.is_some() && !gist.is_encrypted();
+            let content = match keyring.as_mut() {
+                // Unchanged sealed content keeps its ciphertext
+                Some(_) if !changed && !sealing => gist.content.clone(),
+                Some(k) => k.encrypt(&content).map_err(Failure::failed)?,
+                None => {
+                    if changed {
+                        check_secrets(&config, &content, allow_secrets)?;
+                    }
+                    content
+                }
+            };
+            
+            let mut meta = gist.meta();
             
             // Get tags
             let tags_str = if let Some(t) = tags {
                 crate::ai::sanitize_tags(&t)
-            } else if content == gist.content {
-                // If content didn't change, keep existing tags
+            } else if !changed || keyring.is_some() {
+                // If content didn't change or is encrypted, keep existing tags
                 gist.tags
             } else {
                 // Otherwise, regenerate tags
@@ -378,61 +1314,340 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
             };
             
-            // Update in database
-            match update_gist(&conn, id, &content, &tags_str) {
+            // Flags override stored metadata; an empty value clears the field
+            let meta_changed = title.is_some() || description.is_some() || language.is_some() || url.is_some();
+            if let Some(t) = title {
+                meta.title = Some(t);
+            }
+            if let Some(d) = description {
+                meta.description = Some(d);
+            }
+            if let Some(l) = language {
+                meta.language = Some(language::normalize(&l));
+            }
+            if let Some(u) = url {
+                meta.source_url = Some(u);
+            }
+            
+            // Update in database; newly sealed content also drops the plaintext history
+            let saved = match sealing {
+                true => seal_gist(&conn, id, &content).and_then(|_| sync_gist_tags(&conn, id, &tags_str)),
+                false => update_gist(&conn, id, &content, &tags_str),
+            };
+            let result = saved
+                .and_then(|_| {
+                    if meta_changed {
+                        update_gist_meta(&conn, id, &meta)
+                    } else {
+                        Ok(())
+                    }
+                })
+                .and_then(|_| match runnable {
+                    Some(r) => set_runnable(&conn, id, r),
+                    None => carry_run_approval(&conn, id, &gist.content),
+                });
+            match result {
                 Ok(_) => {
-                    print_success(&format!("Updated gist #{}", id));
-                }
-                Err(e) => {
-                    eprintln!("{} {}", "Error updating gist:".red().bold(), e);
+                    semantic::index_gist(&conn, &config, id).await;
+                    output::emit_success(&format!("Updated gist #{}", id), Some(id));
                 }
+                Err(e) => return Err(Failure::failed(format!("Could not update gist: {}", e)).into()),
             }
         },
 
         Commands::View { id } => {
-            match get_gist(&conn, id)? {
-                Some(gist) => {
-                    display_gist(&gist);
+            let mut gist = get_gist(&conn, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+            let encrypted = gist.is_encrypted();
+            unlock(&mut gist)?;
+            record_usage(&conn, id, UsageKind::View)?;
+            if output::is_machine() {
+                output::emit_one(&GistRecord { encrypted, ..GistRecord::from(&gist) }, output::GIST_COLUMNS);
+                return Ok(());
+            }
+            display_gist(
+                &gist,
+                get_provenance(&conn, id)?.as_ref(),
+                get_run_info(&conn, id)?.as_ref(),
+                get_usage(&conn, id)?.as_ref(),
+                &gist_collections(&conn, id)?,
+            );
+        },
+        
+        Commands::Copy { id } => {
+            let mut gist = get_gist(&conn, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+            unlock(&mut gist)?;
+            use clipboard::ClipboardProvider;
+            clipboard::ClipboardContext::new()
+                .and_then(|mut ctx| ctx.set_contents(gist.content))
+                .map_err(|e| Failure::failed(format!("Could not copy to the clipboard: {}", e)))?;
+            record_usage(&conn, id, UsageKind::Copy)?;
+            output::emit_success(&format!("Copied gist #{} to the clipboard", id), Some(id));
+        },
+
+        Commands::Pin { ids } => pin_gists(&conn, &ids, true)?,
+
+        Commands::Unpin { ids } => pin_gists(&conn, &ids, false)?,
+
+        Commands::Use { id, vars, defaults, copy, output } => {
+            let mut gist = get_gist(&conn, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+            unlock(&mut gist)?;
+            let placeholders = template::placeholders(&gist.content);
+            let mut values: std::collections::HashMap<String, String> = vars.into_iter().collect();
+            for name in values.keys() {
+                if !placeholders.iter().any(|p| &p.name == name) {
+                    eprintln!("{} Gist #{} has no placeholder '{}'", "Warning:".yellow().bold(), id, name);
                 }
-                None => {
-                    eprintln!("{} Gist #{} not found", "Error:".red().bold(), id);
+            }
+
+            // Ask for whatever --var left unset, when there is someone to ask
+            if !defaults && std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+                for p in &placeholders {
+                    if values.contains_key(&p.name) {
+                        continue;
+                    }
+                    if let Some(value) = prompt_input(&p.name, p.default.as_deref()) {
+                        values.insert(p.name.clone(), value);
+                    }
                 }
             }
+
+            let expanded = template::expand(&gist.content, &values).map_err(|missing| {
+                Failure::invalid(format!("No value for {} (pass --var NAME=VALUE)", missing.join(", ")))
+            })?;
+
+            if let Some(path) = output {
+                std::fs::write(&path, &expanded)?;
+                print_success(&format!("Wrote gist #{} to {}", id, path.display()));
+            } else if copy {
+                use clipboard::ClipboardProvider;
+                clipboard::ClipboardContext::new()
+                    .and_then(|mut ctx| ctx.set_contents(expanded))
+                    .map_err(|e| Failure::failed(format!("Could not copy to the clipboard: {}", e)))?;
+                print_success(&format!("Copied gist #{} to the clipboard", id));
+            } else {
+                print!("{}", expanded);
+                std::io::stdout().flush()?;
+            }
         },
-        
+
+        Commands::Run { id, args, timeout, here, inherit_env } => {
+            let mut gist = get_gist(&conn, id)?.ok_or_else(|| Failure::gist_not_found(id))?;
+            if !get_run_info(&conn, id)?.is_some_and(|r| r.runnable) {
+                return Err(Failure::invalid(format!(
+                    "Gist #{} is not marked runnable, or changed since it was. Review it, then: gist update {} --runnable true",
+                    id, id
+                ))
+                .into());
+            }
+            let encrypted = gist.is_encrypted();
+            unlock(&mut gist)?;
+            let command = runner::interpreter(&gist, &config).map_err(Failure::invalid)?;
+            let timeout = timeout
+                .or_else(|| config.run.as_ref().and_then(|r| r.timeout_secs))
+                .unwrap_or(runner::DEFAULT_TIMEOUT_SECS);
+            let opts = runner::RunOptions {
+                args,
+                timeout: std::time::Duration::from_secs(timeout),
+                inherit_env,
+                cwd: if here { Some(std::env::current_dir()?) } else { None },
+            };
+
+            let outcome = runner::run(&gist, &command, &opts)?;
+            // The output of an encrypted snippet is not kept in plain text
+            record_run(
+                &conn,
+                id,
+                outcome.exit_code,
+                outcome.timed_out,
+                if encrypted { "" } else { &outcome.output },
+                outcome.duration.as_millis() as i64,
+            )?;
+            if outcome.timed_out {
+                output::emit_error(&Failure::failed(format!("Gist #{} timed out after {}s", id, timeout)));
+            }
+            if outcome.status() != 0 {
+                std::process::exit(outcome.status());
+            }
+        },
+
         Commands::Delete { id, force } => {
             // Check if gist exists
-            match get_gist(&conn, id)? {
-                Some(gist) => {
-                    // Confirm deletion
-                    if !force && !prompt_confirm(&format!("Delete gist #{}?", id)) {
-                        println!("Deletion cancelled.");
-                        return Ok(());
-                    }
-                    
-                    // Delete from database
-                    match delete_gist(&conn, id) {
-                        Ok(true) => {
-                            print_success(&format!("Deleted gist #{}", id));
-                        }
-                        Ok(false) => {
-                            eprintln!("{} Gist #{} not found", "Error:".red().bold(), id);
-                        }
-                        Err(e) => {
-                            eprintln!("{} {}", "Error deleting gist:".red().bold(), e);
-                        }
-                    }
+            if get_gist(&conn, id)?.is_none() {
+                return Err(Failure::gist_not_found(id).into());
+            }
+
+            // Confirm deletion
+            if !force && !prompt_confirm(&format!("Move gist #{} to the trash?", id)) {
+                println!("Deletion cancelled.");
+                return Ok(());
+            }
+
+            // Delete from database
+            match delete_gist(&conn, id) {
+                Ok(true) => {
+                    output::emit_success(&format!("Moved gist #{} to the trash (undo with `gist trash restore {}`)", id, id), Some(id));
                 }
-                None => {
-                    eprintln!("{} Gist #{} not found", "Error:".red().bold(), id);
+                Ok(false) => return Err(Failure::gist_not_found(id).into()),
+                Err(e) => return Err(Failure::failed(format!("Could not delete gist: {}", e)).into()),
+            }
+        },
+
+        Commands::History { id } => {
+            let revisions = list_revisions(&conn, id)?;
+            if revisions.is_empty() {
+                return Err(Failure::gist_not_found(id).into());
+            }
+            if output::is_machine() {
+                output::emit_list(&revisions, &["gist_id", "rev", "created_at", "tags", "content"]);
+                return Ok(());
+            }
+            
+            println!("History of gist #{} ({} revisions):", id, revisions.len());
+            for (i, r) in revisions.iter().enumerate() {
+                let marker = if i == 0 { " (current)".green().to_string() } else { String::new() };
+                println!(
+                    "{} {} {} {} {} {}{}",
+                    "Rev".bold(),
+                    r.rev.to_string().yellow(),
+                    "| Time:".bold(),
+                    format_timestamp(&r.created_at),
+                    "| Lines:".bold(),
+                    r.content.lines().count(),
+                    marker
+                );
+                println!("  {} {}", "Tags:".dimmed(), r.tags.cyan());
+            }
+        },
+
+        Commands::Diff { id, rev_a, rev_b } => {
+            let revisions = list_revisions(&conn, id)?;
+            let latest = revisions.first().ok_or_else(|| Failure::gist_not_found(id))?;
+            
+            let (a, b) = match (rev_a, rev_b) {
+                (Some(a), Some(b)) => (a, b),
+                (Some(a), None) => (a, latest.rev),
+                _ => (latest.rev - 1, latest.rev),
+            };
+            if a < 1 {
+                println!("Gist #{} has only one revision.", id);
+                return Ok(());
+            }
+            
+            let find = |rev: i64| revisions.iter().find(|r| r.rev == rev);
+            let (Some(old), Some(new)) = (find(a), find(b)) else {
+                let missing = if find(a).is_none() { a } else { b };
+                return Err(Failure::not_found(format!("Gist #{} has no revision {}", id, missing)).into());
+            };
+            
+            let mut diff = diff::unified_diff(&old.content, &new.content, &format!("rev {}", a), &format!("rev {}", b));
+            if old.tags != new.tags {
+                diff.push_str(&format!("{} {} -> {}\n", "Tags:".bold(), old.tags.red(), new.tags.green()));
+            }
+            if diff.is_empty() {
+                println!("No differences between revisions {} and {}.", a, b);
+            } else {
+                println!("{}", diff::colorize(&diff));
+            }
+        },
+
+        Commands::Restore { id, rev, force } => {
+            if get_revision(&conn, id, rev)?.is_none() {
+                return Err(Failure::not_found(format!("Gist #{} has no revision {}", id, rev)).into());
+            }
+            if !force && !prompt_confirm(&format!("Restore gist #{} to revision {}?", id, rev)) {
+                println!("Restore cancelled.");
+                return Ok(());
+            }
+            
+            match restore_revision(&conn, id, rev) {
+                Ok(current) => {
+                    output::emit_success(&format!("Restored gist #{} to revision {} (now revision {})", id, rev, current), Some(id));
+                }
+                Err(e) => return Err(Failure::failed(format!("Could not restore gist: {}", e)).into()),
+            }
+        },
+
+        Commands::Search { query, semantic: true, limit, collection, .. } => {
+            let only = collection_members(&conn, collection.as_deref())?;
+            let (provider, model) = match semantic::embedding_provider(&config) {
+                Ok(found) => found,
+                Err(e) => {
+                    eprintln!("{} {}; using full-text search instead.", "Note:".yellow().bold(), e);
+                    return search_fulltext(&conn, &query, only.as_ref());
+                }
+            };
+            
+            match semantic::stale_gists(&conn, &model) {
+                Ok(stale) if !stale.is_empty() => eprintln!(
+                    "{} {} gist(s) are not indexed yet; run `gist reindex` to include them.",
+                    "Note:".yellow().bold(),
+                    stale.len()
+                ),
+                _ => {}
+            }
+            
+            let results = match semantic::search(&conn, provider.as_ref(), &query, limit, only.as_ref()).await {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("{} Semantic search failed ({}); using full-text search instead.", "Warning:".yellow().bold(), e);
+                    return search_fulltext(&conn, &query, only.as_ref());
                 }
+            };
+            if output::is_machine() {
+                let records: Vec<GistRecord> = results
+                    .iter()
+                    .map(|(gist, score)| GistRecord { score: Some(*score as f64), ..GistRecord::from(gist) })
+                    .collect();
+                output::emit_list(&records, output::GIST_COLUMNS);
+                return Ok(());
+            }
+            if results.is_empty() {
+                println!("No indexed gists to search. Run `gist reindex` first.");
+                return Ok(());
+            }
+            
+            println!("Top {} results for '{}':", results.len(), query);
+            for (gist, score) in &results {
+                println!("{} {:.3}", "Similarity:".bold(), score);
+                display_gist_preview(gist);
             }
         },
 
-        Commands::Search { query, tags_only } => {
-            let results = search_gists(&conn, &query, tags_only)?;
+        Commands::Reindex { force } => {
+            let (provider, model) = semantic::embedding_provider(&config).map_err(|e| Failure::failed(e.to_string()))?;
+            
+            println!("Indexing with model {} via {}...", model.cyan(), provider.name());
+            let result = semantic::reindex(&conn, provider.as_ref(), force, |done, total| {
+                print!("\r  {}/{}", done, total);
+                std::io::stdout().flush().ok();
+            })
+            .await;
+            match result {
+                Ok(0) => println!("All gists are already indexed."),
+                Ok(n) => {
+                    println!();
+                    print_success(&format!("Indexed {} gist(s)", n));
+                }
+                Err(e) => {
+                    println!();
+                    return Err(Failure::failed(format!("Could not index gists: {}", e)).into());
+                }
+            }
+        },
+
+        Commands::Search { query, tags_only: true, collection, .. } => {
+            let only = collection_members(&conn, collection.as_deref())?;
+            let mut results = search_gists(&conn, &query, true)
+                .map_err(|e| Failure::invalid(format!("Invalid tag query: {}", e)))?;
+            if let Some(ids) = &only {
+                results.retain(|g| ids.contains(&g.id));
+            }
+            if output::is_machine() {
+                output::emit_list(&results.iter().map(GistRecord::from).collect::<Vec<_>>(), output::GIST_COLUMNS);
+                return Ok(());
+            }
             if results.is_empty() {
-                println!("No results found for '{}'.", query);
+                println!("No gists tagged '{}'.", query);
                 return Ok(());
             }
             
@@ -442,8 +1657,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
             }
         },
 
-        Commands::List { limit, sort_by } => {
-            let results = list_gists(&conn, limit, &sort_by)?;
+        Commands::Search { query, collection, .. } => {
+            let only = collection_members(&conn, collection.as_deref())?;
+            search_fulltext(&conn, &query, only.as_ref())?;
+        },
+
+        Commands::List { limit, sort_by, collection } => {
+            let collection_id = resolve_collection(&conn, collection.as_deref())?;
+            let results = list_gists_in(&conn, limit, &sort_by, collection_id)?;
+            if output::is_machine() {
+                output::emit_list(&results.iter().map(GistRecord::from).collect::<Vec<_>>(), output::GIST_COLUMNS);
+                return Ok(());
+            }
             if results.is_empty() {
                 println!("No saved gists.");
                 return Ok(());
@@ -456,7 +1681,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         },
 
         Commands::UI => {
-            let mut all = list_gists(&conn, usize::MAX, "created_at")?;
+            let mut all = list_gists(&conn, usize::MAX, "frecency")?;
             if all.is_empty() {
                 println!("No gists found. Add some first!");
                 return Ok(());
@@ -472,48 +1697,83 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     println!("No changes made.");
                 }
                 viewer::UIResult::Error(e) => {
-                    eprintln!("{} {}", "Error in UI:".red().bold(), e);
+                    return Err(Failure::failed(format!("The viewer failed: {}", e)).into());
                 }
             }
         },
         
-        Commands::Export { output } => {
-            match export_gists(&conn, &output) {
+        Commands::Export { output, format, collection, decrypt } => {
+            let collection_id = resolve_collection(&conn, collection.as_deref())?;
+            let mut keyring = match decrypt && encrypted_sample(&conn)?.is_some() {
+                true => Some(library_keyring(&conn)?),
+                false => None,
+            };
+            match transfer::export(&conn, format, &output, collection_id, keyring.as_mut()) {
                 Ok(count) => {
                     print_success(&format!("Exported {} gists to {:?}", count, output));
                 }
-                Err(e) => {
-                    eprintln!("{} {}", "Error exporting gists:".red().bold(), e);
-                }
+                Err(e) => return Err(Failure::failed(format!("Could not export gists: {}", e)).into()),
             }
         },
         
-        Commands::Import { input } => {
+        Commands::Import { input, format, strategy, dry_run } => {
             if !input.exists() {
-                eprintln!("{} File not found: {:?}", "Error:".red().bold(), input);
-                return Ok(());
+                return Err(Failure::not_found(format!("File not found: {:?}", input)).into());
             }
             
             // Confirm import
-            if !prompt_confirm(&format!("Import gists from {:?}?", input)) {
+            if !dry_run && !prompt_confirm(&format!("Import gists from {:?}?", input)) {
                 println!("Import cancelled.");
                 return Ok(());
             }
             
-            match import_gists(&conn, &input) {
-                Ok(count) => {
-                    print_success(&format!("Imported {} gists from {:?}", count, input));
-                }
-                Err(e) => {
-                    eprintln!("{} {}", "Error importing gists:".red().bold(), e);
+            let format = format.unwrap_or_else(|| transfer::Format::infer(&input));
+            // The keyring also opens sealed snippets so duplicates are found by their plain text
+            let mut keyring = match config.encrypt_all() {
+                true => Some(library_keyring(&conn)?),
+                false => None,
+            };
+            match transfer::import(&conn, format, &input, strategy, dry_run, keyring.as_mut()) {
+                Ok(report) => {
+                    for err in &report.errors {
+                        output::emit_error(&Failure::invalid(format!("row {} ({}): {}", err.row, err.label, err.message)));
+                    }
+                    // Encrypted rows arrive sealed; seal the rest when the whole store is encrypted
+                    if let Some(keyring) = keyring.as_mut().filter(|_| !dry_run) {
+                        seal_all(&conn, keyring)?;
+                    }
+                    let summary = format!(
+                        "{} added, {} updated, {} skipped, {} failed",
+                        report.added,
+                        report.updated,
+                        report.skipped,
+                        report.errors.len()
+                    );
+                    if dry_run {
+                        println!("{} {}", "Dry run:".bold(), summary);
+                    } else {
+                        print_success(&format!("Imported from {:?}: {}", input, summary));
+                    }
                 }
+                Err(e) => return Err(Failure::failed(format!("Could not import gists: {}", e)).into()),
             }
         },
         
-        Commands::Config { editor, auto_tags, api_key, theme, ai_model, ai_base_url, show } => {
+        Commands::Config { editor, auto_tags, api_key, theme, ai_model, ai_base_url, embedding_model, provider, trash_days, secrets, encrypt_all, sync_dir, show } => {
             let mut config = load_config();
             
             if show {
+                let embedding = config
+                    .provider(ProviderRole::Embeddings)
+                    .ok()
+                    .and_then(|p| p.embedding_model().map(str::to_string))
+                    .unwrap_or_else(|| "(none)".dimmed().to_string());
+                let registry = config.provider_registry();
+                let github_api = config.github_api_url(None);
+                let github_token = config.github_token().is_some();
+                let secret_policy = config.secret_policy();
+                let encrypt_all = config.encrypt_all();
+                let sync_folder = config.sync.as_ref().and_then(|s| s.dir.as_ref()).map(|d| d.display().to_string());
                 println!("{} Configuration:", "Current".green().bold());
                 println!("  {}: {}", "Editor".bold(), if config.editor.is_empty() { "(auto-detect)".dimmed().to_string() } else { config.editor.clone() });
                 println!("  {}: {}", "Theme".bold(), config.theme.to_string());
@@ -522,6 +1782,28 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 println!("  {}: {}", "API Key".bold(), config.tag_api_key.map(|_| "(set)".to_string()).unwrap_or_else(|| "(not set)".dimmed().to_string()));
                 println!("  {}: {}", "AI Model".bold(), config.ai_model.unwrap_or_default());
                 println!("  {}: {}", "AI Base URL".bold(), config.ai_base_url.unwrap_or_default());
+                println!("  {}: {}", "Embedding model".bold(), embedding);
+                println!("  {}: {}", "Default provider".bold(), config.default_provider.as_deref().unwrap_or("(auto)"));
+                println!("  {}: {}", "CodeWalk provider".bold(), config.codewalk_provider.as_deref().unwrap_or("(auto)"));
+                println!("  {}:", "Providers".bold());
+                if registry.is_empty() {
+                    println!("    {}", "(none)".dimmed());
+                }
+                for p in &registry {
+                    println!(
+                        "    {} [{}] {} key {}",
+                        p.name.cyan(),
+                        p.kind,
+                        p.base_url.as_deref().unwrap_or(p.kind.default_base_url()),
+                        if p.has_key() { "(set)".to_string() } else { "(not set)".dimmed().to_string() }
+                    );
+                }
+                println!("  {}: {}", "Trash retention (days)".bold(), config.trash_retention_days);
+                println!("  {}: {}", "Secrets policy".bold(), secret_policy);
+                println!("  {}: {}", "Encrypt all snippets".bold(), encrypt_all);
+                println!("  {}: {}", "Sync folder".bold(), sync_folder.unwrap_or_else(|| "(not set)".dimmed().to_string()));
+                println!("  {}: {}", "GitHub API".bold(), github_api);
+                println!("  {}: {}", "GitHub token".bold(), if github_token { "(set)".to_string() } else { "(not set)".dimmed().to_string() });
                 return Ok(());
             }
             
@@ -548,8 +1830,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     "light" => Theme::Light,
                     "system" => Theme::System,
                     _ => {
-                        eprintln!("{} Invalid theme: {}. Use 'dark', 'light', or 'system'.", "Error:".red().bold(), th);
-                        return Ok(());
+                        return Err(Failure::invalid(format!("Invalid theme: {}. Use 'dark', 'light', or 'system'.", th)).into());
                     }
                 };
                 config.theme = new_theme;
@@ -565,15 +1846,53 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 config.ai_base_url = Some(url);
                 changed = true;
             }
+
+            if let Some(model) = embedding_model {
+                config.embedding_model = if model.is_empty() { None } else { Some(model) };
+                changed = true;
+            }
+
+            if let Some(name) = provider {
+                if !name.is_empty() && !config.provider_registry().iter().any(|p| p.name == name) {
+                    return Err(Failure::invalid(format!(
+                        "No provider named '{}'; add a [[providers]] entry to config.toml first.",
+                        name
+                    ))
+                    .into());
+                }
+                config.default_provider = if name.is_empty() { None } else { Some(name) };
+                changed = true;
+            }
+
+            if let Some(days) = trash_days {
+                config.trash_retention_days = days;
+                changed = true;
+            }
+
+            if let Some(policy) = secrets {
+                config.secrets = Some(config::SecretsConfig { policy });
+                changed = true;
+            }
+
+            if let Some(all) = encrypt_all {
+                config.encryption = Some(config::EncryptionConfig { encrypt_all: all });
+                if all && !plaintext_gists(&conn)?.is_empty() {
+                    println!("Existing snippets stay as they are; run `gist encrypt --all` to encrypt them.");
+                }
+                changed = true;
+            }
+
+            if let Some(dir) = sync_dir {
+                config.sync = Some(config::SyncConfig { dir: (!dir.as_os_str().is_empty()).then_some(dir) });
+                changed = true;
+            }
             
             if changed {
                 match save_config(&config) {
                     Ok(_) => {
                         print_success("Configuration updated");
                     }
-                    Err(e) => {
-                        eprintln!("{} {}", "Error saving configuration:".red().bold(), e);
-                    }
+                    Err(e) => return Err(Failure::failed(format!("Could not save configuration: {}", e)).into()),
                 }
             } else {
                 println!("No configuration changes specified. Use --show to view current config.");
@@ -586,13 +1905,538 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 Ok(_) => {
                     print_success("Database optimized");
                 }
-                Err(e) => {
-                    eprintln!("{} {}", "Error optimizing database:".red().bold(), e);
+                Err(e) => return Err(Failure::failed(format!("Could not optimize database: {}", e)).into()),
+            }
+        },
+
+        Commands::AuditSecrets { history } => {
+            let gists = list_gists(&conn, usize::MAX, "id")?;
+            let mut records = Vec::new();
+            for g in gists.iter().rev() {
+                // Each distinct secret once per snippet, from the newest text it appears in
+                let mut seen = HashSet::new();
+                let mut texts = vec![(None, g.content.clone())];
+                if history {
+                    texts.extend(list_revisions(&conn, g.id)?.into_iter().skip(1).map(|r| (Some(r.rev), r.content)));
                 }
+                for (rev, text) in texts.iter().filter(|(_, t)| !crypto::is_encrypted(t)) {
+                    for f in secrets::scan(text) {
+                        if seen.insert(text[f.range.clone()].to_string()) {
+                            records.push(serde_json::json!({
+                                "id": g.id,
+                                "title": g.title,
+                                "rev": rev,
+                                "kind": f.kind.label(),
+                                "line": f.line,
+                                "masked": f.masked,
+                            }));
+                        }
+                    }
+                }
+            }
+            if output::is_machine() {
+                output::emit_list(&records, &["id", "rev", "line", "kind", "masked", "title"]);
+                return Ok(());
+            }
+
+            let mut last = None;
+            for r in &records {
+                if last != r["id"].as_i64() {
+                    last = r["id"].as_i64();
+                    println!("{} {}", format!("#{}", r["id"]).green(), r["title"].as_str().unwrap_or_default().bold());
+                }
+                let at = match r["rev"].as_i64() {
+                    Some(rev) => format!("rev {}, line {}", rev, r["line"]),
+                    None => format!("line {}", r["line"]),
+                };
+                println!("  {}: {} {}", at, r["kind"].as_str().unwrap_or_default().red(), r["masked"].as_str().unwrap_or_default().dimmed());
+            }
+            if records.is_empty() {
+                print_success(&format!("No secrets found in {} snippet(s)", gists.len()));
+            } else {
+                let flagged: HashSet<_> = records.iter().map(|r| r["id"].as_i64()).collect();
+                println!(
+                    "{} possible secret(s) in {} snippet(s). Edit them with `gist update <id>` or seal them with `gist encrypt <id>`{}.",
+                    records.len(),
+                    flagged.len(),
+                    if history { "; older revisions stay until the snippet is deleted" } else { "" }
+                );
             }
         },
 
-        Commands::Codewalk { scope, model, prompt, notes, output, path, #[cfg(feature = "meerkat")] meerkat_spike, #[cfg(feature = "meerkat")] no_meerkat, mode, resume, list_sessions, purge_sessions } => {
+        Commands::Encrypt { ids, all } => {
+            let mut keyring = library_keyring(&conn)?;
+            if all {
+                let count = seal_all(&conn, &mut keyring)?;
+                print_success(&format!("Encrypted {} gist(s)", count));
+                return Ok(());
+            }
+            let mut missing = 0;
+            let mut shared = 0;
+            for id in ids {
+                let Some(gist) = get_gist(&conn, id)? else {
+                    output::emit_error(&Failure::gist_not_found(id));
+                    missing += 1;
+                    continue;
+                };
+                if let Some(library) = &gist.library {
+                    output::emit_error(&Failure::invalid(format!(
+                        "Gist #{} is shared in team library '{}' and stays plain text",
+                        id, library
+                    )));
+                    shared += 1;
+                    continue;
+                }
+                if !gist.is_encrypted() {
+                    seal_gist(&conn, id, &keyring.encrypt(&gist.content)?)?;
+                }
+                output::emit_success(&format!("Encrypted gist #{}", id), Some(id));
+            }
+            some_missing(missing)?;
+            if shared > 0 {
+                return Err(Failure::invalid(format!("{} team library gist(s) not encrypted", shared)).into());
+            }
+        },
+
+        Commands::Decrypt { ids } => {
+            let mut keyring = crypto::Keyring::new(&utils::prompt_passphrase(false)?);
+            let mut missing = 0;
+            for id in ids {
+                let Some(gist) = get_gist(&conn, id)? else {
+                    output::emit_error(&Failure::gist_not_found(id));
+                    missing += 1;
+                    continue;
+                };
+                if gist.is_encrypted() {
+                    let content = keyring.decrypt(&gist.content).map_err(Failure::invalid)?;
+                    update_gist(&conn, id, &content, &gist.tags)?;
+                    carry_run_approval(&conn, id, &gist.content)?;
+                    semantic::index_gist(&conn, &config, id).await;
+                }
+                output::emit_success(&format!("Decrypted gist #{}", id), Some(id));
+            }
+            some_missing(missing)?;
+        },
+
+        Commands::Db { action } => match action {
+            DbCommands::Migrate { dry_run } => {
+                let current = migrations::current_version(&conn)?;
+                let todo = migrations::pending(&conn)?;
+                println!(
+                    "{} v{} (latest v{})",
+                    "Schema version:".bold(),
+                    current,
+                    migrations::latest_version()
+                );
+                if todo.is_empty() {
+                    println!("Database is up to date.");
+                    return Ok(());
+                }
+                
+                let verb = if dry_run { "Would apply" } else { "Applying" };
+                println!("{} {} migration(s):", verb, todo.len());
+                for m in &todo {
+                    println!("  v{} {}", m.version.to_string().green(), m.description);
+                }
+                if dry_run {
+                    return Ok(());
+                }
+                
+                match migrations::migrate(&conn, Some(&get_db_path()?)) {
+                    Ok(report) => {
+                        if let Some(backup) = &report.backup {
+                            println!("{} {}", "Backup:".bold(), backup.display());
+                        }
+                        print_success(&format!("Database migrated to v{}", report.to_version));
+                    }
+                    Err(e) => return Err(Failure::failed(format!("Migration failed: {}", e)).into()),
+                }
+            }
+        },
+
+        Commands::Trash { action } => match action {
+            TrashCommands::List => {
+                let trash = list_trash(&conn)?;
+                if output::is_machine() {
+                    let records: Vec<GistRecord> = trash
+                        .iter()
+                        .map(|t| GistRecord { deleted_at: Some(t.deleted_at.clone()), ..GistRecord::from(&t.gist) })
+                        .collect();
+                    output::emit_list(&records, output::GIST_COLUMNS);
+                    return Ok(());
+                }
+                if trash.is_empty() {
+                    println!("Trash is empty.");
+                    return Ok(());
+                }
+                
+                println!("{} gist(s) in the trash:", trash.len());
+                for t in &trash {
+                    println!("{} {}", "Deleted:".bold(), format_timestamp(&t.deleted_at).red());
+                    display_gist_preview(&t.gist);
+                }
+                if config.trash_retention_days > 0 {
+                    println!("Trashed gists are removed after {} days.", config.trash_retention_days);
+                }
+            }
+            TrashCommands::Restore { ids } => {
+                let mut missing = 0;
+                for id in ids {
+                    match restore_gist(&conn, id)? {
+                        true => output::emit_success(&format!("Restored gist #{}", id), Some(id)),
+                        false => {
+                            output::emit_error(&Failure::not_found(format!("Gist #{} is not in the trash", id)));
+                            missing += 1;
+                        }
+                    }
+                }
+                some_missing(missing)?;
+            }
+            TrashCommands::Empty { force } => {
+                let count = list_trash(&conn)?.len();
+                if count == 0 {
+                    println!("Trash is empty.");
+                    return Ok(());
+                }
+                if !force && !prompt_confirm(&format!("Permanently delete {} gist(s)?", count)) {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+                let removed = empty_trash(&conn, None)?;
+                print_success(&format!("Permanently deleted {} gist(s)", removed));
+            }
+        },
+
+        Commands::Tags { action } => match action {
+            TagCommands::List => {
+                let tags = list_tags(&conn)?;
+                if output::is_machine() {
+                    let records: Vec<_> = tags.iter().map(|(name, count)| serde_json::json!({ "name": name, "count": count })).collect();
+                    output::emit_list(&records, &["name", "count"]);
+                    return Ok(());
+                }
+                if tags.is_empty() {
+                    println!("No tags.");
+                    return Ok(());
+                }
+                let width = tags.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+                for (name, count) in &tags {
+                    println!("{:width$}  {}", name.cyan(), count.to_string().dimmed(), width = width);
+                }
+            }
+            TagCommands::Rename { old, new } => match rename_tag(&conn, &old, &new) {
+                Ok(n) => print_success(&format!("Renamed '{}' to '{}' on {} gist(s)", old, new, n)),
+                Err(e) => return Err(Failure::invalid(e.to_string()).into()),
+            },
+            TagCommands::Merge { sources, into } => match merge_tags(&conn, &sources, &into) {
+                Ok(n) => print_success(&format!("Merged {} into '{}' on {} gist(s)", sources.join(", "), into, n)),
+                Err(e) => return Err(Failure::invalid(e.to_string()).into()),
+            },
+            TagCommands::Delete { name, force } => {
+                if !force && !prompt_confirm(&format!("Remove tag '{}' from every gist?", name)) {
+                    println!("Deletion cancelled.");
+                    return Ok(());
+                }
+                match delete_tag(&conn, &name) {
+                    Ok(n) => print_success(&format!("Removed '{}' from {} gist(s)", name, n)),
+                    Err(e) => return Err(Failure::invalid(e.to_string()).into()),
+                }
+            }
+        },
+
+        Commands::Collection { action } => {
+            let path = match &action {
+                CollectionCommands::List | CollectionCommands::Create { .. } => None,
+                CollectionCommands::Add { path, .. }
+                | CollectionCommands::Remove { path, .. }
+                | CollectionCommands::Show { path }
+                | CollectionCommands::Delete { path, .. } => Some(path.as_str()),
+            };
+            let id = resolve_collection(&conn, path)?.unwrap_or_default();
+            match action {
+                CollectionCommands::Create { path } => match create_collection(&conn, &path) {
+                    Ok(id) => output::emit_success(&format!("Created collection '{}'", path), Some(id)),
+                    Err(e) => return Err(Failure::invalid(e.to_string()).into()),
+                },
+                CollectionCommands::Add { path, ids } => {
+                    let (found, missing): (Vec<i64>, Vec<i64>) =
+                        ids.into_iter().partition(|id| matches!(get_gist(&conn, *id), Ok(Some(_))));
+                    for &id in &missing {
+                        output::emit_error(&Failure::gist_not_found(id));
+                    }
+                    let added = add_to_collection(&conn, id, &found)?;
+                    print_success(&format!("Added {} gist(s) to '{}'", added, path));
+                    some_missing(missing.len())?;
+                }
+                CollectionCommands::Remove { path, ids } => {
+                    let removed = remove_from_collection(&conn, id, &ids)?;
+                    print_success(&format!("Removed {} gist(s) from '{}'", removed, path));
+                }
+                CollectionCommands::List => {
+                    let collections = list_collections(&conn)?;
+                    if output::is_machine() {
+                        let records: Vec<_> = collections
+                            .iter()
+                            .map(|col| serde_json::json!({
+                                "id": col.id,
+                                "name": col.name,
+                                "path": col.path,
+                                "parent_id": col.parent_id,
+                                "gist_count": col.gist_count,
+                            }))
+                            .collect();
+                        output::emit_list(&records, &["id", "path", "name", "parent_id", "gist_count"]);
+                        return Ok(());
+                    }
+                    if collections.is_empty() {
+                        println!("No collections. Create one with `gist collection create <path>`.");
+                        return Ok(());
+                    }
+                    for col in &collections {
+                        println!("{}{}  {}", "  ".repeat(col.depth()), col.name.cyan(), col.gist_count.to_string().dimmed());
+                    }
+                }
+                CollectionCommands::Show { path } => {
+                    let results = list_gists_in(&conn, usize::MAX, "created_at", Some(id))?;
+                    if output::is_machine() {
+                        output::emit_list(&results.iter().map(GistRecord::from).collect::<Vec<_>>(), output::GIST_COLUMNS);
+                        return Ok(());
+                    }
+                    if results.is_empty() {
+                        println!("Collection '{}' is empty.", path);
+                        return Ok(());
+                    }
+                    println!("{} gist(s) in '{}':", results.len(), path);
+                    for gist in &results {
+                        display_gist_preview(gist);
+                    }
+                }
+                CollectionCommands::Delete { path, force } => {
+                    if !force && !prompt_confirm(&format!("Delete collection '{}' and everything nested in it?", path)) {
+                        println!("Deletion cancelled.");
+                        return Ok(());
+                    }
+                    let removed = delete_collection(&conn, id)?;
+                    print_success(&format!("Deleted {} collection(s); their snippets were kept", removed));
+                }
+            }
+        },
+
+        Commands::Sync { dir } => {
+            let dir = dir
+                .or_else(|| config.sync.as_ref().and_then(|s| s.dir.clone()))
+                .ok_or_else(|| Failure::invalid("No sync folder: pass --dir or set [sync] dir in config.toml"))?;
+            let report = sync::sync_dir(&conn, &dir)?;
+            if report.received > 0 {
+                if let Ok((provider, _)) = semantic::embedding_provider(&config) {
+                    let _ = semantic::reindex(&conn, provider.as_ref(), false, |_, _| {}).await;
+                }
+            }
+            if output::is_machine() {
+                let conflicts: Vec<_> = report
+                    .conflicts
+                    .iter()
+                    .map(|(id, copy)| serde_json::json!({ "id": id, "copy": copy }))
+                    .collect();
+                let record = serde_json::json!({
+                    "sent": report.sent,
+                    "received": report.received,
+                    "devices": report.devices,
+                    "conflicts": conflicts,
+                });
+                output::emit_one(&record, &["sent", "received", "devices", "conflicts"]);
+                return Ok(());
+            }
+            for (id, copy) in &report.conflicts {
+                eprintln!(
+                    "{} Gist #{} was also changed on another machine; your version is kept as #{}",
+                    "Conflict:".yellow().bold(),
+                    id,
+                    copy
+                );
+            }
+            print_success(&format!(
+                "Sent {} change(s), merged {} from {} other device(s){}",
+                report.sent,
+                report.received,
+                report.devices,
+                if report.git { " (git)" } else { "" }
+            ));
+        },
+
+        Commands::Remote { action } => match action {
+            RemoteCommands::Add { name, url } => {
+                if config.remotes.iter().any(|r| r.name == name) {
+                    return Err(Failure::invalid(format!("A team library named '{}' already exists", name)).into());
+                }
+                let mut remote = config::RemoteConfig { name, url };
+                let report = library::add(&conn, &mut remote)?;
+                let mut config = config;
+                config.remotes.push(remote.clone());
+                save_config(&config).map_err(|e| Failure::failed(format!("Could not save configuration: {}", e)))?;
+                if report.changed() {
+                    if let Ok((provider, _)) = semantic::embedding_provider(&config) {
+                        let _ = semantic::reindex(&conn, provider.as_ref(), false, |_, _| {}).await;
+                    }
+                }
+                print_success(&format!("Added team library '{}' with {} snippet(s)", remote.name, report.added));
+            }
+            RemoteCommands::List => {
+                let counts = library_counts(&conn)?;
+                let count = |name: &str| counts.get(name).copied().unwrap_or(0);
+                if output::is_machine() {
+                    let records: Vec<_> = config
+                        .remotes
+                        .iter()
+                        .map(|r| serde_json::json!({ "name": r.name, "url": r.url, "count": count(&r.name) }))
+                        .collect();
+                    output::emit_list(&records, &["name", "url", "count"]);
+                    return Ok(());
+                }
+                if config.remotes.is_empty() {
+                    println!("No team libraries.");
+                    return Ok(());
+                }
+                let width = config.remotes.iter().map(|r| r.name.len()).max().unwrap_or(0);
+                for r in &config.remotes {
+                    println!("{:width$}  {}  {}", r.name.cyan(), r.url, format!("{} snippet(s)", count(&r.name)).dimmed(), width = width);
+                }
+            }
+            RemoteCommands::Remove { name, force } => {
+                let remote = config.remote(Some(&name)).map_err(Failure::not_found)?.clone();
+                if !force && !prompt_confirm(&format!("Remove team library '{}' and its snippets from this machine?", name)) {
+                    println!("Cancelled.");
+                    return Ok(());
+                }
+                let removed = drop_library(&conn, &remote.name)?;
+                let dir = library::clone_dir(&remote.name)?;
+                if dir.exists() {
+                    std::fs::remove_dir_all(&dir)?;
+                }
+                let mut config = config;
+                config.remotes.retain(|r| r.name != remote.name);
+                save_config(&config).map_err(|e| Failure::failed(format!("Could not save configuration: {}", e)))?;
+                print_success(&format!("Removed team library '{}' ({} snippet(s))", name, removed));
+            }
+        },
+
+        Commands::Pull { name } => {
+            let names: Vec<String> = match (&name, config.remotes.as_slice()) {
+                (None, remotes) if !remotes.is_empty() => remotes.iter().map(|r| r.name.clone()).collect(),
+                _ => vec![config.remote(name.as_deref()).map_err(Failure::invalid)?.name.clone()],
+            };
+            let mut reports = Vec::new();
+            for name in &names {
+                reports.push(library::pull(&conn, name)?);
+            }
+            if reports.iter().any(|r| r.changed()) {
+                if let Ok((provider, _)) = semantic::embedding_provider(&config) {
+                    let _ = semantic::reindex(&conn, provider.as_ref(), false, |_, _| {}).await;
+                }
+            }
+            if output::is_machine() {
+                let records: Vec<_> = reports
+                    .iter()
+                    .map(|r| serde_json::json!({
+                        "library": r.library,
+                        "added": r.added,
+                        "updated": r.updated,
+                        "removed": r.removed,
+                        "shadowed": r.shadowed,
+                        "kept": r.kept,
+                    }))
+                    .collect();
+                output::emit_list(&records, &["library", "added", "updated", "removed", "shadowed", "kept"]);
+                return Ok(());
+            }
+            for r in &reports {
+                for path in &r.kept {
+                    eprintln!(
+                        "{} {}/{} was edited here; keeping your version (send it with `gist push`)",
+                        "Kept:".yellow().bold(),
+                        r.library,
+                        path
+                    );
+                }
+                print_success(&format!(
+                    "{}: {} new, {} updated, {} removed, {} already in your library",
+                    r.library, r.added, r.updated, r.removed, r.shadowed
+                ));
+            }
+        },
+
+        Commands::Push { ids, to, allow_secrets } => {
+            let name = config.remote(to.as_deref()).map_err(Failure::invalid)?.name.clone();
+            // Pull first so the push lands on top of the latest files
+            library::pull(&conn, &name)?;
+            let targets = library::push_targets(&conn, &name, &ids)?;
+            if targets.is_empty() && !output::is_machine() {
+                println!("Nothing to push: no snippet of '{}' was edited here.", name);
+                return Ok(());
+            }
+            for (g, _) in &targets {
+                check_secrets(&config, &g.content, allow_secrets)?;
+            }
+            let report = library::push(&conn, &name, &targets)?;
+            if output::is_machine() {
+                let records: Vec<_> = report
+                    .written
+                    .iter()
+                    .map(|(id, path)| serde_json::json!({ "id": id, "library": name, "path": path }))
+                    .collect();
+                output::emit_list(&records, &["id", "library", "path"]);
+                return Ok(());
+            }
+            for (id, path) in &report.written {
+                println!("  #{} -> {}/{}", id, name, path);
+            }
+            match report.committed {
+                true => print_success(&format!("Pushed {} snippet(s) to '{}'", report.written.len(), name)),
+                false => println!("'{}' already has these snippets; nothing to commit.", name),
+            }
+        },
+
+        Commands::Github { api_url, action } => {
+            let client = github::GithubClient::new(&config.github_api_url(api_url.as_deref()), config.github_token());
+            let result = match action {
+                GithubCommands::Pull { ids, user } => {
+                    if ids.is_empty() && user.is_none() && !client.has_token() {
+                        return Err(Failure::invalid("Set GITHUB_TOKEN or [github] token in config.toml, or pass --user.").into());
+                    }
+                    // A store that encrypts everything seals what it pulls
+                    let mut keyring = match config.encrypt_all() {
+                        true => Some(library_keyring(&conn)?),
+                        false => None,
+                    };
+                    github::pull(&conn, &client, user.as_deref(), &ids, keyring.as_mut()).await
+                }
+                GithubCommands::Push { ids, public, force } => {
+                    if !client.has_token() {
+                        return Err(Failure::invalid("Pushing needs GITHUB_TOKEN or [github] token in config.toml.").into());
+                    }
+                    github::push(&conn, &client, &ids, public, force).await
+                }
+            };
+            match result {
+                Ok(report) => {
+                    for id in report.created.iter().chain(&report.updated) {
+                        semantic::index_gist(&conn, &config, *id).await;
+                    }
+                    for reason in &report.skipped {
+                        eprintln!("{} {}", "Skipped:".yellow().bold(), reason);
+                    }
+                    print_success(&format!(
+                        "{} new, {} updated, {} unchanged",
+                        report.created.len(),
+                        report.updated.len(),
+                        report.unchanged
+                    ));
+                }
+                Err(e) => return Err(Failure::failed(e.to_string()).into()),
+            }
+        },
+
+        Commands::Codewalk { scope, model, provider, prompt, notes, output, path, #[cfg(feature = "meerkat")] meerkat_spike, #[cfg(feature = "meerkat")] no_meerkat, mode, resume, headless, steps, list_sessions, purge_sessions } => {
             // --list-sessions: print all saved sessions and exit
             if list_sessions {
                 let sessions = codewalk::session::list_sessions();
@@ -618,21 +2462,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
             }
 
             if !path.exists() || !path.is_dir() {
-                eprintln!("{} Repository path does not exist or is not a directory: {:?}", "Error:".red().bold(), path);
-                return Ok(());
+                return Err(Failure::not_found(format!("Repository path does not exist or is not a directory: {:?}", path)).into());
             }
 
             #[cfg(feature = "meerkat")]
             if meerkat_spike {
-                let api_key = config.tag_api_key.as_deref().unwrap_or("");
-                let base_url = config.ai_base_url.as_deref().unwrap_or("https://api.z.ai/api/coding/paas/v4");
-                let model_name = model.as_deref()
-                    .or(config.ai_model.as_deref())
-                    .unwrap_or("glm-5-turbo");
-                if let Err(e) = codewalk::meerkat_spike::run_spike(api_key, base_url, model_name, &path).await {
-                    eprintln!("{} {}", "Spike error:".red().bold(), e);
-                }
-                return Ok(());
+                let api_config = config
+                    .resolve_api_config(provider.as_deref(), model.as_deref())
+                    .map_err(|e| Failure::invalid(e.to_string()))?;
+                return codewalk::meerkat_spike::run_spike(&api_config, &path)
+                    .await
+                    .map_err(|e| Failure::failed(format!("Spike failed: {}", e)).into());
             }
 
             #[cfg(feature = "meerkat")]
@@ -651,10 +2491,11 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
             });
 
+            let options = codewalk::WalkOptions { provider, model, headless_steps: headless.then_some(steps) };
             if let Err(e) = codewalk::run_codewalk(
                 scope,
                 path,
-                model,
+                options,
                 prompt,
                 notes,
                 output,
@@ -663,7 +2504,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 walk_mode,
                 resume,
             ).await {
-                eprintln!("{} {}", "CodeWalk error:".red().bold(), e);
+                return Err(Failure::failed(format!("CodeWalk failed: {}", e)).into());
             }
         },
     }
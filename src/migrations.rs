@@ -0,0 +1,796 @@
+//! Versioned schema migrations for the gist database.
+//!
+//! The schema version lives in `PRAGMA user_version`. Each migration runs once,
+//! in order, and all pending migrations are applied inside a single transaction
+//! so a failure leaves the database at its previous version. Before upgrading an
+//! existing database a copy is written next to it.
+
+use rusqlite::{Connection, Result as SqlResult};
+use std::{error::Error, path::{Path, PathBuf}};
+
+/// A single schema change.
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub apply: fn(&Connection) -> SqlResult<()>,
+}
+
+/// All migrations, ordered by version. Append only — never edit a released entry.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create gists table",
+        apply: create_gists_table,
+    },
+    Migration {
+        version: 2,
+        description: "Add FTS5 search index",
+        apply: add_fts_index,
+    },
+    Migration {
+        version: 3,
+        description: "Normalize tags into tags/gist_tags tables",
+        apply: add_tag_tables,
+    },
+    Migration {
+        version: 4,
+        description: "Add title, description, language, source and updated_at columns",
+        apply: add_metadata_columns,
+    },
+    Migration {
+        version: 5,
+        description: "Add revision history",
+        apply: add_revisions_table,
+    },
+    Migration {
+        version: 6,
+        description: "Add deleted_at for soft delete",
+        apply: add_deleted_at,
+    },
+    Migration {
+        version: 7,
+        description: "Add embeddings table for semantic search",
+        apply: add_embeddings_table,
+    },
+    Migration {
+        version: 8,
+        description: "Add github_links table mapping snippets to GitHub gist files",
+        apply: add_github_links_table,
+    },
+    Migration {
+        version: 9,
+        description: "Add provenance table for snippets extracted from files",
+        apply: add_provenance_table,
+    },
+    Migration {
+        version: 10,
+        description: "Add gist_runs table for runnable snippets and their last run",
+        apply: add_runs_table,
+    },
+    Migration {
+        version: 11,
+        description: "Add nestable collections",
+        apply: add_collections_tables,
+    },
+    Migration {
+        version: 12,
+        description: "Add gist_usage table for pins and view/copy counts",
+        apply: add_usage_table,
+    },
+    Migration {
+        version: 13,
+        description: "Add gist UUIDs and the sync changelog with a Lamport clock",
+        apply: add_sync_log,
+    },
+    Migration {
+        version: 14,
+        description: "Add team libraries mirrored from git repositories",
+        apply: add_libraries,
+    },
+    Migration {
+        version: 15,
+        description: "Tie run approval to the content hash it was given for",
+        apply: add_run_approval_hash,
+    },
+];
+
+/// Outcome of a migration run.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub applied: Vec<u32>,
+    pub backup: Option<PathBuf>,
+}
+
+/// The version the database is currently at.
+pub fn current_version(conn: &Connection) -> SqlResult<u32> {
+    conn.query_row("PRAGMA user_version", [], |r| r.get(0))
+}
+
+/// The newest version this build knows about.
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Migrations that have not been applied yet.
+pub fn pending(conn: &Connection) -> SqlResult<Vec<&'static Migration>> {
+    let current = current_version(conn)?;
+    Ok(MIGRATIONS.iter().filter(|m| m.version > current).collect())
+}
+
+/// Apply all pending migrations.
+///
+/// If `db_path` is given and the database already holds data, it is copied to
+/// `<db>.v<from>-<timestamp>.bak` before anything changes.
+pub fn migrate(conn: &Connection, db_path: Option<&Path>) -> Result<MigrationReport, Box<dyn Error>> {
+    let from_version = current_version(conn)?;
+    // Checked first: a newer database has nothing pending, but still can't be used
+    if from_version > latest_version() {
+        return Err(format!(
+            "Database schema v{} is newer than this build supports (v{})",
+            from_version,
+            latest_version()
+        )
+        .into());
+    }
+
+    let todo = pending(conn)?;
+    let mut report = MigrationReport {
+        from_version,
+        to_version: from_version,
+        ..Default::default()
+    };
+
+    if todo.is_empty() {
+        return Ok(report);
+    }
+
+    if let Some(path) = db_path {
+        if has_user_tables(conn)? {
+            report.backup = Some(backup(conn, path, from_version)?);
+        }
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    for m in &todo {
+        (m.apply)(&tx).map_err(|e| format!("Migration v{} ({}) failed: {}", m.version, m.description, e))?;
+        tx.pragma_update(None, "user_version", m.version)?;
+        report.applied.push(m.version);
+        report.to_version = m.version;
+    }
+    tx.commit()?;
+
+    Ok(report)
+}
+
+fn has_user_tables(conn: &Connection) -> SqlResult<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%')",
+        [],
+        |r| r.get(0),
+    )
+}
+
+/// Write a consistent copy of the database with `VACUUM INTO`.
+fn backup(conn: &Connection, db_path: &Path, version: u32) -> Result<PathBuf, Box<dyn Error>> {
+    let ts = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let file_name = db_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "gists.db".to_string());
+    let target = db_path.with_file_name(format!("{}.v{}-{}.bak", file_name, version, ts));
+    conn.execute("VACUUM INTO ?1", [target.to_string_lossy().as_ref()])?;
+    Ok(target)
+}
+
+// ── Migrations ───────────────────────────────────────────────────────────────
+
+fn create_gists_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gists (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            content TEXT NOT NULL,
+            tags TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_gists_tags ON gists(tags);",
+    )
+}
+
+fn add_fts_index(conn: &Connection) -> SqlResult<()> {
+    // A b-tree index on content can't serve '%q%' lookups; FTS replaces it
+    conn.execute_batch(
+        "DROP INDEX IF EXISTS idx_gists_content;
+        CREATE VIRTUAL TABLE IF NOT EXISTS gists_fts USING fts5(
+            content, tags,
+            content='gists', content_rowid='id',
+            tokenize='unicode61'
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_fts_ai AFTER INSERT ON gists BEGIN
+            INSERT INTO gists_fts(rowid, content, tags) VALUES (new.id, new.content, new.tags);
+        END;
+        CREATE TRIGGER IF NOT EXISTS gists_fts_ad AFTER DELETE ON gists BEGIN
+            INSERT INTO gists_fts(gists_fts, rowid, content, tags) VALUES ('delete', old.id, old.content, old.tags);
+        END;
+        CREATE TRIGGER IF NOT EXISTS gists_fts_au AFTER UPDATE ON gists BEGIN
+            INSERT INTO gists_fts(gists_fts, rowid, content, tags) VALUES ('delete', old.id, old.content, old.tags);
+            INSERT INTO gists_fts(rowid, content, tags) VALUES (new.id, new.content, new.tags);
+        END;
+        INSERT INTO gists_fts(gists_fts) VALUES ('rebuild');",
+    )
+}
+
+fn add_tag_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE IF NOT EXISTS gist_tags (
+            gist_id INTEGER NOT NULL REFERENCES gists(id) ON DELETE CASCADE,
+            tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (gist_id, tag_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_gist_tags_tag ON gist_tags(tag_id);
+        CREATE TRIGGER IF NOT EXISTS gists_tags_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM gist_tags WHERE gist_id = old.id;
+        END;",
+    )?;
+
+    // Backfill from the comma-joined column, normalizing it on the way. The
+    // rules are spelled out here so later changes to tag handling can't
+    // change what this migration does to old databases.
+    let rows: Vec<(i64, Option<String>)> = {
+        let mut stmt = conn.prepare("SELECT id, tags FROM gists")?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect::<SqlResult<_>>()?
+    };
+    for (id, tags) in rows {
+        let mut names: Vec<String> = Vec::new();
+        for tag in tags.as_deref().unwrap_or("").split(',') {
+            let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+            if !tag.is_empty() && !names.contains(&tag) {
+                names.push(tag);
+            }
+            if names.len() == 10 {
+                break;
+            }
+        }
+        for name in &names {
+            conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [name])?;
+            conn.execute(
+                "INSERT OR IGNORE INTO gist_tags (gist_id, tag_id) SELECT ?1, id FROM tags WHERE name = ?2",
+                rusqlite::params![id, name],
+            )?;
+        }
+        conn.execute(
+            "UPDATE gists SET tags = ?1 WHERE id = ?2 AND IFNULL(tags, '') != ?1",
+            rusqlite::params![names.join(", "), id],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_metadata_columns(conn: &Connection) -> SqlResult<()> {
+    // ADD COLUMN can't take a CURRENT_TIMESTAMP default, so updated_at is backfilled
+    conn.execute_batch(
+        "ALTER TABLE gists ADD COLUMN title TEXT;
+        ALTER TABLE gists ADD COLUMN description TEXT;
+        ALTER TABLE gists ADD COLUMN language TEXT;
+        ALTER TABLE gists ADD COLUMN source_path TEXT;
+        ALTER TABLE gists ADD COLUMN source_url TEXT;
+        ALTER TABLE gists ADD COLUMN updated_at DATETIME;
+        UPDATE gists SET updated_at = created_at;
+        CREATE INDEX IF NOT EXISTS idx_gists_language ON gists(language);",
+    )
+}
+
+fn add_revisions_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gist_revisions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            gist_id INTEGER NOT NULL REFERENCES gists(id) ON DELETE CASCADE,
+            rev INTEGER NOT NULL,
+            content TEXT NOT NULL,
+            tags TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (gist_id, rev)
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_revisions_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM gist_revisions WHERE gist_id = old.id;
+        END;
+        INSERT INTO gist_revisions (gist_id, rev, content, tags, created_at)
+            SELECT id, 1, content, tags, IFNULL(updated_at, created_at) FROM gists;",
+    )
+}
+
+fn add_deleted_at(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "ALTER TABLE gists ADD COLUMN deleted_at DATETIME;
+        CREATE INDEX IF NOT EXISTS idx_gists_deleted_at ON gists(deleted_at);",
+    )
+}
+
+fn add_embeddings_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gist_embeddings (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            model TEXT NOT NULL,
+            dims INTEGER NOT NULL,
+            vector BLOB NOT NULL,
+            text_hash TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_embeddings_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM gist_embeddings WHERE gist_id = old.id;
+        END;",
+    )
+}
+
+fn add_github_links_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS github_links (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            remote_id TEXT NOT NULL,
+            filename TEXT NOT NULL,
+            synced_hash TEXT NOT NULL,
+            synced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (remote_id, filename)
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_github_links_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM github_links WHERE gist_id = old.id;
+        END;",
+    )
+}
+
+fn add_provenance_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS provenance (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            start_line INTEGER,
+            end_line INTEGER,
+            symbol TEXT,
+            git_commit TEXT,
+            source_hash TEXT NOT NULL,
+            captured_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_provenance_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM provenance WHERE gist_id = old.id;
+        END;",
+    )
+}
+
+fn add_runs_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gist_runs (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            runnable INTEGER NOT NULL DEFAULT 0,
+            exit_code INTEGER,
+            timed_out INTEGER NOT NULL DEFAULT 0,
+            output TEXT,
+            duration_ms INTEGER,
+            ran_at DATETIME,
+            last_success_at DATETIME
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_runs_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM gist_runs WHERE gist_id = old.id;
+        END;",
+    )
+}
+
+fn add_collections_tables(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS collections (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL,
+            parent_id INTEGER REFERENCES collections(id) ON DELETE CASCADE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_collections_parent_name
+            ON collections(COALESCE(parent_id, 0), name);
+        CREATE TABLE IF NOT EXISTS collection_gists (
+            collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+            gist_id INTEGER NOT NULL REFERENCES gists(id) ON DELETE CASCADE,
+            PRIMARY KEY (collection_id, gist_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_collection_gists_gist ON collection_gists(gist_id);
+        CREATE TRIGGER IF NOT EXISTS gists_collections_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM collection_gists WHERE gist_id = old.id;
+        END;
+        CREATE TRIGGER IF NOT EXISTS collections_ad AFTER DELETE ON collections BEGIN
+            DELETE FROM collection_gists WHERE collection_id = old.id;
+        END;",
+    )
+}
+
+fn add_usage_table(conn: &Connection) -> SqlResult<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS gist_usage (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            pinned INTEGER NOT NULL DEFAULT 0,
+            view_count INTEGER NOT NULL DEFAULT 0,
+            copy_count INTEGER NOT NULL DEFAULT 0,
+            last_used_at DATETIME
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_usage_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM gist_usage WHERE gist_id = old.id;
+        END;",
+    )
+}
+
+/// A random version 4 UUID as an SQL expression, evaluated per row.
+pub const NEW_UUID_SQL: &str = "lower(hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || \
+    substr(hex(randomblob(2)), 2) || '-' || substr('89ab', 1 + abs(random()) % 4, 1) || \
+    substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)))";
+
+/// The synced fields of gist row `row` as a JSON object.
+fn snapshot_sql(row: &str) -> String {
+    format!(
+        "json_object('content', {r}.content, 'tags', {r}.tags, 'title', {r}.title, \
+         'description', {r}.description, 'language', {r}.language, 'source_path', {r}.source_path, \
+         'source_url', {r}.source_url, 'created_at', {r}.created_at, 'updated_at', {r}.updated_at, \
+         'deleted_at', {r}.deleted_at)",
+        r = row
+    )
+}
+
+fn add_sync_log(conn: &Connection) -> SqlResult<()> {
+    // Triggers log every local change; `applying` is set while changes from
+    // other devices are written so they aren't logged as local ones. New rows
+    // get their UUID from an UPDATE inside the insert trigger, which may run
+    // before the row is indexed, so the FTS update trigger is narrowed to the
+    // indexed columns.
+    conn.execute_batch(&format!(
+        "DROP TRIGGER IF EXISTS gists_fts_au;
+        CREATE TRIGGER gists_fts_au AFTER UPDATE OF content, tags ON gists BEGIN
+            INSERT INTO gists_fts(gists_fts, rowid, content, tags) VALUES ('delete', old.id, old.content, old.tags);
+            INSERT INTO gists_fts(rowid, content, tags) VALUES (new.id, new.content, new.tags);
+        END;
+        ALTER TABLE gists ADD COLUMN uuid TEXT;
+        UPDATE gists SET uuid = {uuid};
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_gists_uuid ON gists(uuid);
+        CREATE TABLE IF NOT EXISTS sync_state (
+            id INTEGER PRIMARY KEY CHECK (id = 1),
+            device TEXT NOT NULL,
+            clock INTEGER NOT NULL DEFAULT 0,
+            applying INTEGER NOT NULL DEFAULT 0
+        );
+        INSERT INTO sync_state (id, device, clock) VALUES (1, {uuid}, 1);
+        CREATE TABLE IF NOT EXISTS sync_log (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            uuid TEXT NOT NULL,
+            lamport INTEGER NOT NULL,
+            device TEXT NOT NULL,
+            base TEXT,
+            op TEXT NOT NULL,
+            gist TEXT,
+            UNIQUE (uuid, lamport, device)
+        );
+        CREATE INDEX IF NOT EXISTS idx_sync_log_device ON sync_log(device, seq);
+        CREATE TABLE IF NOT EXISTS sync_versions (
+            uuid TEXT PRIMARY KEY,
+            version TEXT NOT NULL
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_sync_ai AFTER INSERT ON gists BEGIN
+            UPDATE gists SET uuid = {uuid} WHERE id = new.id AND uuid IS NULL;
+            UPDATE sync_state SET clock = clock + 1 WHERE applying = 0;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT g.uuid, s.clock, s.device, NULL, 'put', {snapshot_g}
+                FROM gists g, sync_state s WHERE g.id = new.id AND s.applying = 0;
+            INSERT OR REPLACE INTO sync_versions (uuid, version)
+                SELECT g.uuid, s.clock || '@' || s.device
+                FROM gists g, sync_state s WHERE g.id = new.id AND s.applying = 0;
+        END;
+        CREATE TRIGGER IF NOT EXISTS gists_sync_au
+        AFTER UPDATE OF content, tags, title, description, language, source_path, source_url, deleted_at ON gists
+        WHEN (SELECT applying FROM sync_state) = 0 AND (
+            old.content IS NOT new.content OR old.tags IS NOT new.tags OR old.title IS NOT new.title
+            OR old.description IS NOT new.description OR old.language IS NOT new.language
+            OR old.source_path IS NOT new.source_path OR old.source_url IS NOT new.source_url
+            OR old.deleted_at IS NOT new.deleted_at
+        )
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT new.uuid, clock, device, (SELECT version FROM sync_versions WHERE uuid = new.uuid), 'put', {snapshot_new}
+                FROM sync_state;
+            INSERT OR REPLACE INTO sync_versions (uuid, version) SELECT new.uuid, clock || '@' || device FROM sync_state;
+        END;
+        CREATE TRIGGER IF NOT EXISTS gists_sync_ad AFTER DELETE ON gists
+        WHEN (SELECT applying FROM sync_state) = 0
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT old.uuid, clock, device, (SELECT version FROM sync_versions WHERE uuid = old.uuid), 'purge', NULL
+                FROM sync_state;
+            INSERT OR REPLACE INTO sync_versions (uuid, version) SELECT old.uuid, clock || '@' || device FROM sync_state;
+        END;
+        INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+            SELECT g.uuid, 1, s.device, NULL, 'put', {snapshot_g} FROM gists g, sync_state s ORDER BY g.id;
+        INSERT INTO sync_versions (uuid, version) SELECT g.uuid, '1@' || s.device FROM gists g, sync_state s;",
+        uuid = NEW_UUID_SQL,
+        snapshot_g = snapshot_sql("g"),
+        snapshot_new = snapshot_sql("new"),
+    ))
+}
+
+fn add_libraries(conn: &Connection) -> SqlResult<()> {
+    // Team snippets are mirrored from their repository on every machine, so
+    // the sync triggers are recreated to leave them out of the changelog.
+    conn.execute_batch(&format!(
+        "ALTER TABLE gists ADD COLUMN library TEXT;
+        CREATE TABLE IF NOT EXISTS library_files (
+            gist_id INTEGER PRIMARY KEY REFERENCES gists(id) ON DELETE CASCADE,
+            library TEXT NOT NULL,
+            path TEXT NOT NULL,
+            synced_hash TEXT NOT NULL,
+            synced_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE (library, path)
+        );
+        CREATE TRIGGER IF NOT EXISTS gists_library_files_ad AFTER DELETE ON gists BEGIN
+            DELETE FROM library_files WHERE gist_id = old.id;
+        END;
+        DROP TRIGGER IF EXISTS gists_sync_ai;
+        DROP TRIGGER IF EXISTS gists_sync_au;
+        DROP TRIGGER IF EXISTS gists_sync_ad;
+        CREATE TRIGGER gists_sync_ai AFTER INSERT ON gists BEGIN
+            UPDATE gists SET uuid = {uuid} WHERE id = new.id AND uuid IS NULL;
+            UPDATE sync_state SET clock = clock + 1 WHERE applying = 0 AND new.library IS NULL;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT g.uuid, s.clock, s.device, NULL, 'put', {snapshot_g}
+                FROM gists g, sync_state s WHERE g.id = new.id AND s.applying = 0 AND new.library IS NULL;
+            INSERT OR REPLACE INTO sync_versions (uuid, version)
+                SELECT g.uuid, s.clock || '@' || s.device
+                FROM gists g, sync_state s WHERE g.id = new.id AND s.applying = 0 AND new.library IS NULL;
+        END;
+        CREATE TRIGGER gists_sync_au
+        AFTER UPDATE OF content, tags, title, description, language, source_path, source_url, deleted_at ON gists
+        WHEN new.library IS NULL AND (SELECT applying FROM sync_state) = 0 AND (
+            old.content IS NOT new.content OR old.tags IS NOT new.tags OR old.title IS NOT new.title
+            OR old.description IS NOT new.description OR old.language IS NOT new.language
+            OR old.source_path IS NOT new.source_path OR old.source_url IS NOT new.source_url
+            OR old.deleted_at IS NOT new.deleted_at
+        )
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT new.uuid, clock, device, (SELECT version FROM sync_versions WHERE uuid = new.uuid), 'put', {snapshot_new}
+                FROM sync_state;
+            INSERT OR REPLACE INTO sync_versions (uuid, version) SELECT new.uuid, clock || '@' || device FROM sync_state;
+        END;
+        CREATE TRIGGER gists_sync_ad AFTER DELETE ON gists
+        WHEN old.library IS NULL AND (SELECT applying FROM sync_state) = 0
+        BEGIN
+            UPDATE sync_state SET clock = clock + 1;
+            INSERT INTO sync_log (uuid, lamport, device, base, op, gist)
+                SELECT old.uuid, clock, device, (SELECT version FROM sync_versions WHERE uuid = old.uuid), 'purge', NULL
+                FROM sync_state;
+            INSERT OR REPLACE INTO sync_versions (uuid, version) SELECT old.uuid, clock || '@' || device FROM sync_state;
+        END;",
+        uuid = NEW_UUID_SQL,
+        snapshot_g = snapshot_sql("g"),
+        snapshot_new = snapshot_sql("new"),
+    ))
+}
+
+fn add_run_approval_hash(conn: &Connection) -> SqlResult<()> {
+    // Existing approvals carry over to the content as it is now. The hash is
+    // computed here rather than through the live helper so this migration
+    // stays as it was released.
+    use sha2::{Digest, Sha256};
+    conn.execute_batch("ALTER TABLE gist_runs ADD COLUMN approved_hash TEXT;")?;
+    let rows: Vec<(i64, String)> = {
+        let mut stmt = conn.prepare(
+            "SELECT r.gist_id, g.content FROM gist_runs r JOIN gists g ON g.id = r.gist_id WHERE r.runnable",
+        )?;
+        let rows = stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?;
+        rows.collect::<SqlResult<_>>()?
+    };
+    for (id, content) in rows {
+        conn.execute(
+            "UPDATE gist_runs SET approved_hash = ?1 WHERE gist_id = ?2",
+            rusqlite::params![format!("{:x}", Sha256::digest(content.as_bytes())), id],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE gists (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                content TEXT NOT NULL,
+                tags TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            );
+            CREATE INDEX idx_gists_content ON gists(content);
+            INSERT INTO gists (content, tags) VALUES ('legacy', 'old');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrations_are_strictly_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[test]
+    fn fresh_database_reaches_latest() {
+        let conn = Connection::open_in_memory().unwrap();
+        let report = migrate(&conn, None).unwrap();
+        assert_eq!(report.from_version, 0);
+        assert_eq!(report.to_version, latest_version());
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert!(pending(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rerun_is_noop() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn, None).unwrap();
+        let report = migrate(&conn, None).unwrap();
+        assert!(report.applied.is_empty());
+    }
+
+    #[test]
+    fn newer_schema_is_refused() {
+        let conn = Connection::open_in_memory().unwrap();
+        migrate(&conn, None).unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        let err = migrate(&conn, None).unwrap_err().to_string();
+        assert!(err.contains("newer than this build supports"), "{}", err);
+    }
+
+    #[test]
+    fn legacy_database_keeps_rows() {
+        let conn = legacy_db();
+        migrate(&conn, None).unwrap();
+        let n: i64 = conn
+            .query_row("SELECT COUNT(*) FROM gists_fts WHERE gists_fts MATCH 'legacy'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(n, 1);
+    }
+
+    #[test]
+    fn legacy_tags_are_normalized() {
+        let conn = legacy_db();
+        conn.execute("INSERT INTO gists (content, tags) VALUES ('x', ' Rust,CLI , rust')", []).unwrap();
+        migrate(&conn, None).unwrap();
+
+        let tags: String = conn
+            .query_row("SELECT tags FROM gists WHERE content = 'x'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(tags, "rust, cli");
+        let n: i64 = conn.query_row("SELECT COUNT(*) FROM tags", [], |r| r.get(0)).unwrap();
+        assert_eq!(n, 3); // old, rust, cli
+    }
+
+    #[test]
+    fn legacy_rows_get_updated_at() {
+        let conn = legacy_db();
+        migrate(&conn, None).unwrap();
+        let (created, updated, title): (String, String, Option<String>) = conn
+            .query_row("SELECT created_at, updated_at, title FROM gists", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(created, updated);
+        assert!(title.is_none());
+    }
+
+    #[test]
+    fn legacy_rows_get_first_revision() {
+        let conn = legacy_db();
+        migrate(&conn, None).unwrap();
+        let (rev, content): (i64, String) = conn
+            .query_row("SELECT rev, content FROM gist_revisions", [], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap();
+        assert_eq!(rev, 1);
+        assert_eq!(content, "legacy");
+    }
+
+    #[test]
+    fn legacy_rows_get_uuids_and_changelog_entries() {
+        let conn = legacy_db();
+        migrate(&conn, None).unwrap();
+        let (uuid, lamport, op): (String, i64, String) = conn
+            .query_row("SELECT l.uuid, l.lamport, l.op FROM sync_log l JOIN gists g ON g.uuid = l.uuid", [], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })
+            .unwrap();
+        assert_eq!((uuid.len(), &uuid[14..15], lamport, op.as_str()), (36, "4", 1, "put"));
+
+        // Later changes are logged with a rising clock, each on top of the last version
+        conn.execute("UPDATE gists SET content = 'edited'", []).unwrap();
+        conn.execute("UPDATE gists SET created_at = created_at", []).unwrap();
+        conn.execute("INSERT INTO gists (content) VALUES ('new')", []).unwrap();
+        let log: Vec<(i64, Option<String>, String)> = conn
+            .prepare("SELECT lamport, base, json_extract(gist, '$.content') FROM sync_log ORDER BY seq")
+            .unwrap()
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        let device: String = conn.query_row("SELECT device FROM sync_state", [], |r| r.get(0)).unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[1], (2, Some(format!("1@{}", device)), "edited".to_string()));
+        assert_eq!((log[2].0, log[2].1.as_deref()), (3, None));
+        let new_uuid: Option<String> = conn.query_row("SELECT uuid FROM gists WHERE content = 'new'", [], |r| r.get(0)).unwrap();
+        assert!(new_uuid.is_some_and(|u| u != uuid));
+    }
+
+    #[test]
+    fn run_approvals_keep_their_content() {
+        let conn = legacy_db();
+        for m in &MIGRATIONS[..14] {
+            (m.apply)(&conn).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 14).unwrap();
+        conn.execute("INSERT INTO gist_runs (gist_id, runnable) SELECT id, 1 FROM gists", []).unwrap();
+        migrate(&conn, None).unwrap();
+        let hash: String = conn.query_row("SELECT approved_hash FROM gist_runs", [], |r| r.get(0)).unwrap();
+        assert_eq!(hash, crate::utils::content_hash("legacy"));
+    }
+
+    #[test]
+    fn team_library_rows_stay_out_of_the_changelog() {
+        let conn = legacy_db();
+        migrate(&conn, None).unwrap();
+        conn.execute("INSERT INTO gists (content, library) VALUES ('shared', 'team')", []).unwrap();
+        conn.execute("UPDATE gists SET content = 'shared, edited' WHERE library = 'team'", []).unwrap();
+        conn.execute("DELETE FROM gists WHERE library = 'team'", []).unwrap();
+        conn.execute("UPDATE gists SET content = 'mine'", []).unwrap();
+        let logged: Vec<String> = conn
+            .prepare("SELECT json_extract(gist, '$.content') FROM sync_log ORDER BY seq")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<SqlResult<_>>()
+            .unwrap();
+        assert_eq!(logged, vec!["legacy", "mine"]);
+        let clock: i64 = conn.query_row("SELECT clock FROM sync_state", [], |r| r.get(0)).unwrap();
+        assert_eq!(clock, 2);
+    }
+
+    #[test]
+    fn upgrade_writes_backup() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("gists.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE gists (id INTEGER PRIMARY KEY, content TEXT NOT NULL, tags TEXT, created_at TEXT);
+                 INSERT INTO gists (content) VALUES ('keep me');",
+            )
+            .unwrap();
+        }
+        let conn = Connection::open(&path).unwrap();
+        let report = migrate(&conn, Some(&path)).unwrap();
+        let backup = report.backup.expect("backup path");
+        assert!(backup.exists());
+
+        let copy = Connection::open(&backup).unwrap();
+        assert_eq!(current_version(&copy).unwrap(), 0);
+        let content: String = copy.query_row("SELECT content FROM gists", [], |r| r.get(0)).unwrap();
+        assert_eq!(content, "keep me");
+    }
+
+    #[test]
+    fn fresh_database_skips_backup() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("gists.db");
+        let conn = Connection::open(&path).unwrap();
+        let report = migrate(&conn, Some(&path)).unwrap();
+        assert!(report.backup.is_none());
+    }
+}
@@ -1,9 +1,85 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Gist {
+    #[serde(default)]
     pub id: i64,
     pub content: String,
+    #[serde(default)]
+    pub tags: String,
+    #[serde(default)]
+    pub created_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+    /// Team library the snippet is mirrored from; None for your own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+}
+
+/// Optional descriptive fields set when a gist is created or edited.
+#[derive(Debug, Clone, Default)]
+pub struct GistMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub source_path: Option<String>,
+    pub source_url: Option<String>,
+}
+
+impl Gist {
+    /// Title if set, otherwise the first non-empty line of content.
+    pub fn display_title(&self) -> String {
+        match &self.title {
+            Some(t) if !t.trim().is_empty() => t.trim().to_string(),
+            _ if self.is_encrypted() => "(encrypted)".to_string(),
+            _ => self
+                .content
+                .lines()
+                .map(str::trim)
+                .find(|l| !l.is_empty())
+                .unwrap_or("")
+                .to_string(),
+        }
+    }
+
+    /// Whether the content is sealed with a passphrase.
+    pub fn is_encrypted(&self) -> bool {
+        crate::crypto::is_encrypted(&self.content)
+    }
+
+    /// Where the snippet came from, preferring the URL.
+    pub fn source(&self) -> Option<&str> {
+        self.source_url.as_deref().or(self.source_path.as_deref())
+    }
+
+    /// The metadata fields of this gist.
+    pub fn meta(&self) -> GistMeta {
+        GistMeta {
+            title: self.title.clone(),
+            description: self.description.clone(),
+            language: self.language.clone(),
+            source_path: self.source_path.clone(),
+            source_url: self.source_url.clone(),
+        }
+    }
+}
+
+/// A saved state of a gist's content and tags. Revision 1 is the original.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub gist_id: i64,
+    pub rev: i64,
+    pub content: String,
     pub tags: String,
     pub created_at: String,
 }
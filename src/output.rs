@@ -0,0 +1,277 @@
+//! `gist --output`: how results and errors are printed, and exit statuses.
+//!
+//! `text` is the coloured human format and `plain` the same without colour.
+//! `json`, `jsonl` and `tsv` print records with stable field names on stdout
+//! and errors as objects, so scripts never have to scrape ANSI codes.
+
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{error::Error, fmt, str::FromStr, sync::OnceLock};
+use crate::models::Gist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Plain,
+    Json,
+    Jsonl,
+    Tsv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "plain" => Ok(OutputFormat::Plain),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            "tsv" => Ok(OutputFormat::Tsv),
+            other => Err(format!("Unknown output format '{}'. Use text, plain, json, jsonl or tsv", other)),
+        }
+    }
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Choose the format for the rest of the process.
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+    if format != OutputFormat::Text {
+        colored::control::set_override(false);
+    }
+}
+
+pub fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Whether results should be records rather than human-readable text.
+pub fn is_machine() -> bool {
+    matches!(format(), OutputFormat::Json | OutputFormat::Jsonl | OutputFormat::Tsv)
+}
+
+/// What went wrong, which decides the exit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// I/O, database, network or provider failures (exit 1)
+    Failed,
+    /// Bad arguments, queries or input (exit 2, like argument errors)
+    Invalid,
+    /// No such snippet, revision, collection or file (exit 3)
+    NotFound,
+}
+
+/// An error a command reports to the user and turns into an exit status.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl Failure {
+    pub fn failed(message: impl Into<String>) -> Self {
+        Failure { kind: ErrorKind::Failed, message: message.into() }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Failure { kind: ErrorKind::Invalid, message: message.into() }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Failure { kind: ErrorKind::NotFound, message: message.into() }
+    }
+
+    pub fn gist_not_found(id: i64) -> Self {
+        Failure::not_found(format!("Gist #{} not found", id))
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self.kind {
+            ErrorKind::Failed => 1,
+            ErrorKind::Invalid => 2,
+            ErrorKind::NotFound => 3,
+        }
+    }
+
+    /// Any error from a command; ones that aren't a `Failure` count as `Failed`.
+    pub fn from_error(e: Box<dyn Error>) -> Self {
+        match e.downcast::<Failure>() {
+            Ok(f) => *f,
+            Err(e) => Failure::failed(e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Failure {}
+
+/// A snippet as printed by `--output json|jsonl|tsv`.
+#[derive(Debug, Serialize)]
+pub struct GistRecord {
+    pub id: i64,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub tags: Vec<String>,
+    pub content: String,
+    pub source_path: Option<String>,
+    pub source_url: Option<String>,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+    /// Search relevance: BM25 rank (lower is better) or cosine similarity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<String>,
+    /// Stored encrypted; `content` is sealed except from `gist view`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub encrypted: bool,
+    /// Team library it is mirrored from; absent for your own snippets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+}
+
+impl From<&Gist> for GistRecord {
+    fn from(g: &Gist) -> Self {
+        GistRecord {
+            id: g.id,
+            title: g.title.clone(),
+            description: g.description.clone(),
+            language: g.language.clone(),
+            tags: g.tags.split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect(),
+            content: g.content.clone(),
+            source_path: g.source_path.clone(),
+            source_url: g.source_url.clone(),
+            created_at: g.created_at.clone(),
+            updated_at: g.updated_at.clone(),
+            score: None,
+            deleted_at: None,
+            encrypted: g.is_encrypted(),
+            library: g.library.clone(),
+        }
+    }
+}
+
+/// TSV columns for gist records, content last since it is the longest.
+pub const GIST_COLUMNS: &[&str] = &[
+    "id", "title", "language", "tags", "created_at", "updated_at", "source_path", "source_url",
+    "description", "score", "deleted_at", "library", "content",
+];
+
+/// One TSV cell: tabs, newlines and backslashes escaped, lists comma-joined.
+fn tsv_cell(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(tsv_cell).collect::<Vec<_>>().join(","),
+        other => other.to_string(),
+    };
+    text.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn tsv_row(value: &Value, columns: &[&str]) -> String {
+    columns.iter().map(|c| tsv_cell(value.get(c).unwrap_or(&Value::Null))).collect::<Vec<_>>().join("\t")
+}
+
+/// Print records: a JSON array, one JSON object per line, or TSV with a header.
+pub fn emit_list<T: Serialize>(items: &[T], columns: &[&str]) {
+    let values: Vec<Value> = items.iter().map(|i| serde_json::to_value(i).unwrap_or(Value::Null)).collect();
+    match format() {
+        OutputFormat::Jsonl => {
+            for v in &values {
+                println!("{}", v);
+            }
+        }
+        OutputFormat::Tsv => {
+            println!("{}", columns.join("\t"));
+            for v in &values {
+                println!("{}", tsv_row(v, columns));
+            }
+        }
+        _ => println!("{}", serde_json::to_string_pretty(&values).unwrap_or_default()),
+    }
+}
+
+/// Print a single record; JSON gets an object rather than a one-element array.
+pub fn emit_one<T: Serialize>(item: &T, columns: &[&str]) {
+    let value = serde_json::to_value(item).unwrap_or(Value::Null);
+    match format() {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value).unwrap_or_default()),
+        _ => emit_list(std::slice::from_ref(&value), columns),
+    }
+}
+
+/// Report success: a status record in machine formats, a green line otherwise.
+pub fn emit_success(message: &str, id: Option<i64>) {
+    if is_machine() {
+        let mut record = json!({ "status": "ok", "message": message });
+        if let Some(id) = id {
+            record["id"] = json!(id);
+        }
+        emit_one(&record, &["status", "id", "message"]);
+    } else {
+        println!("{} {}", "Success:".green().bold(), message);
+    }
+}
+
+/// Report an error: an error record on stdout in machine formats, so it
+/// arrives in the same stream as results; a red line on stderr otherwise.
+pub fn emit_error(failure: &Failure) {
+    if is_machine() {
+        let record = json!({
+            "status": "error",
+            "error": { "kind": failure.kind, "message": failure.message },
+        });
+        match format() {
+            OutputFormat::Tsv => println!("status\tkind\tmessage\nerror\t{}\t{}", tsv_cell(&json!(failure.kind)), tsv_cell(&json!(failure.message))),
+            _ => emit_one(&record, &[]),
+        }
+    } else {
+        eprintln!("{} {}", "Error:".red().bold(), failure.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_formats() {
+        assert_eq!("JSON".parse::<OutputFormat>(), Ok(OutputFormat::Json));
+        assert_eq!("tsv".parse::<OutputFormat>(), Ok(OutputFormat::Tsv));
+        assert!("yaml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn gist_records_split_tags_and_escape_tsv() {
+        let gist = Gist { id: 3, content: "a\tb\nc\\d".into(), tags: "rust, cli".into(), ..Default::default() };
+        let record = serde_json::to_value(GistRecord::from(&gist)).unwrap();
+        assert_eq!(record["tags"], json!(["rust", "cli"]));
+        assert!(record.get("score").is_none());
+        assert_eq!(
+            tsv_row(&record, &["id", "tags", "title", "content"]),
+            "3\trust,cli\t\ta\\tb\\nc\\\\d"
+        );
+    }
+
+    #[test]
+    fn failures_map_to_exit_codes() {
+        assert_eq!(Failure::gist_not_found(1).exit_code(), 3);
+        assert_eq!(Failure::invalid("x").exit_code(), 2);
+        let other: Box<dyn Error> = "disk full".into();
+        let f = Failure::from_error(other);
+        assert_eq!((f.kind, f.exit_code(), f.message.as_str()), (ErrorKind::Failed, 1, "disk full"));
+        let boxed: Box<dyn Error> = Box::new(Failure::gist_not_found(9));
+        assert_eq!(Failure::from_error(boxed).kind, ErrorKind::NotFound);
+    }
+}
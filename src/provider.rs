@@ -0,0 +1,882 @@
+//! AI providers: one interface over OpenAI-compatible, Anthropic and
+//! Ollama-style local backends. Tagging, semantic search and CodeWalk all
+//! talk to models through `Provider`; `Config::provider` picks which one.
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{error::Error, fmt, sync::Arc};
+
+/// Wire protocol spoken by a provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// `/chat/completions` and `/embeddings` (OpenAI, OpenRouter, z.ai, vLLM, ...)
+    #[serde(alias = "openai-compatible", alias = "openrouter")]
+    OpenAi,
+    /// Anthropic Messages API
+    Anthropic,
+    /// Ollama's native `/api/chat` and `/api/embed`
+    Ollama,
+}
+
+impl ProviderKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "openai",
+            ProviderKind::Anthropic => "anthropic",
+            ProviderKind::Ollama => "ollama",
+        }
+    }
+
+    pub fn default_base_url(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "https://api.z.ai/api/coding/paas/v4",
+            ProviderKind::Anthropic => "https://api.anthropic.com",
+            ProviderKind::Ollama => "http://localhost:11434",
+        }
+    }
+
+    /// Embedding model used when none is configured; `None` if the API has no embeddings.
+    pub fn default_embedding_model(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::OpenAi => Some("text-embedding-3-small"),
+            ProviderKind::Anthropic => None,
+            ProviderKind::Ollama => Some("nomic-embed-text"),
+        }
+    }
+
+    /// Local servers run without credentials; hosted APIs need a key.
+    pub fn needs_key(&self) -> bool {
+        !matches!(self, ProviderKind::Ollama)
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// ── Requests and responses ──────────────────────────────────────────────────
+
+/// One turn of a conversation, independent of any wire format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatMessage {
+    System(String),
+    User(String),
+    Assistant { text: String, tool_calls: Vec<ToolCall> },
+    ToolResult { call_id: String, content: String },
+}
+
+impl ChatMessage {
+    pub fn assistant(text: impl Into<String>) -> Self {
+        ChatMessage::Assistant { text: text.into(), tool_calls: Vec::new() }
+    }
+}
+
+/// A tool the model may call; `parameters` is a JSON Schema object.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: Value,
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    pub tools: Vec<ToolSpec>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+impl ChatRequest {
+    pub fn new(model: impl Into<String>, messages: Vec<ChatMessage>) -> Self {
+        Self {
+            model: model.into(),
+            messages,
+            tools: Vec::new(),
+            max_tokens: None,
+            temperature: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatResponse {
+    pub text: String,
+    pub tool_calls: Vec<ToolCall>,
+    pub usage: Usage,
+}
+
+/// `max_tokens` when a request doesn't set one (Anthropic requires it).
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The request never got a response
+    Network(String),
+    /// The server answered with a non-success status
+    Http { status: u16, body: String },
+    /// The response wasn't in the shape the protocol promises
+    Decode(String),
+    /// The backend can't do what was asked (e.g. Anthropic embeddings)
+    Unsupported(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProviderError::Network(msg) => write!(f, "Network error: {}", msg),
+            ProviderError::Http { status, body } => write!(f, "API error {}: {}", status, body.trim()),
+            ProviderError::Decode(msg) => write!(f, "Unexpected API response: {}", msg),
+            ProviderError::Unsupported(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for ProviderError {}
+
+// ── The provider trait ──────────────────────────────────────────────────────
+
+/// Where and as what a provider connects.
+#[derive(Debug, Clone)]
+pub struct Endpoint {
+    /// Registry name, e.g. `default` or `local`
+    pub name: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    /// Chat model used when the caller doesn't pick one
+    pub model: String,
+    pub embedding_model: Option<String>,
+}
+
+#[async_trait]
+pub trait Provider: fmt::Debug + Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    fn endpoint(&self) -> &Endpoint;
+
+    fn name(&self) -> &str {
+        &self.endpoint().name
+    }
+
+    fn model(&self) -> &str {
+        &self.endpoint().model
+    }
+
+    fn embedding_model(&self) -> Option<&str> {
+        self.endpoint().embedding_model.as_deref()
+    }
+
+    /// One complete response, including any tool calls.
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError>;
+
+    /// Stream the response text, calling `on_text` for each fragment as it arrives.
+    async fn stream(
+        &self,
+        request: &ChatRequest,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<(), ProviderError>;
+
+    /// Embed `inputs`; vectors come back in the same order.
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError>;
+}
+
+/// Build the backend for `kind`.
+pub fn build(kind: ProviderKind, endpoint: Endpoint) -> Arc<dyn Provider> {
+    let http = reqwest::Client::new();
+    match kind {
+        ProviderKind::OpenAi => Arc::new(OpenAiCompatible { endpoint, http }),
+        ProviderKind::Anthropic => Arc::new(Anthropic { endpoint, http }),
+        ProviderKind::Ollama => Arc::new(Ollama { endpoint, http }),
+    }
+}
+
+fn url(endpoint: &Endpoint, path: &str) -> String {
+    format!("{}{}", endpoint.base_url.trim_end_matches('/'), path)
+}
+
+/// Send `body` and turn transport failures and error statuses into `ProviderError`.
+async fn send(request: reqwest::RequestBuilder, body: &Value) -> Result<reqwest::Response, ProviderError> {
+    let resp = request
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| ProviderError::Network(e.to_string()))?;
+    if !resp.status().is_success() {
+        let status = resp.status().as_u16();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(ProviderError::Http { status, body });
+    }
+    Ok(resp)
+}
+
+async fn read_json(resp: reqwest::Response) -> Result<Value, ProviderError> {
+    resp.json().await.map_err(|e| ProviderError::Decode(e.to_string()))
+}
+
+/// Feed each line of a streamed body to `on_line` until it returns `Ok(false)`.
+async fn for_each_line(
+    resp: reqwest::Response,
+    mut on_line: impl FnMut(&str) -> Result<bool, ProviderError> + Send,
+) -> Result<(), ProviderError> {
+    let mut stream = resp.bytes_stream();
+    let mut buffer = String::new();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| ProviderError::Network(e.to_string()))?;
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(line_end) = buffer.find('\n') {
+            let line = buffer[..line_end].trim_end_matches('\r').to_string();
+            buffer.drain(..=line_end);
+            if !line.is_empty() && !on_line(&line)? {
+                return Ok(());
+            }
+        }
+    }
+    if !buffer.trim().is_empty() {
+        on_line(buffer.trim_end())?;
+    }
+    Ok(())
+}
+
+/// The payload of an SSE `data:` line; comments and other fields yield `None`.
+fn sse_data(line: &str) -> Option<&str> {
+    line.strip_prefix("data:").map(str::trim_start)
+}
+
+fn parse_arguments(raw: &Value) -> Value {
+    match raw {
+        Value::String(s) => serde_json::from_str(s).unwrap_or_else(|_| json!({})),
+        Value::Null => json!({}),
+        other => other.clone(),
+    }
+}
+
+// ── OpenAI-compatible ───────────────────────────────────────────────────────
+
+/// OpenAI Chat Completions and Embeddings, as served by OpenAI, OpenRouter,
+/// z.ai and most self-hosted gateways.
+#[derive(Debug)]
+pub struct OpenAiCompatible {
+    endpoint: Endpoint,
+    http: reqwest::Client,
+}
+
+impl OpenAiCompatible {
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let req = self.http.post(url(&self.endpoint, path));
+        match &self.endpoint.api_key {
+            Some(key) => req.bearer_auth(key),
+            None => req,
+        }
+    }
+}
+
+fn openai_messages(messages: &[ChatMessage]) -> Vec<Value> {
+    messages
+        .iter()
+        .map(|m| match m {
+            ChatMessage::System(text) => json!({"role": "system", "content": text}),
+            ChatMessage::User(text) => json!({"role": "user", "content": text}),
+            ChatMessage::Assistant { text, tool_calls } => {
+                let mut obj = json!({
+                    "role": "assistant",
+                    "content": if text.is_empty() { Value::Null } else { json!(text) },
+                });
+                if !tool_calls.is_empty() {
+                    obj["tool_calls"] = tool_calls
+                        .iter()
+                        .map(|tc| json!({
+                            "id": tc.id,
+                            "type": "function",
+                            "function": {"name": tc.name, "arguments": tc.arguments.to_string()},
+                        }))
+                        .collect();
+                }
+                obj
+            }
+            ChatMessage::ToolResult { call_id, content } => {
+                json!({"role": "tool", "tool_call_id": call_id, "content": content})
+            }
+        })
+        .collect()
+}
+
+fn openai_body(request: &ChatRequest, stream: bool) -> Value {
+    let mut body = json!({
+        "model": request.model,
+        "messages": openai_messages(&request.messages),
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "stream": stream,
+    });
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|t| json!({
+                "type": "function",
+                "function": {"name": t.name, "description": t.description, "parameters": t.parameters},
+            }))
+            .collect();
+        body["tool_choice"] = json!("auto");
+    }
+    if let Some(temp) = request.temperature {
+        body["temperature"] = json!(temp);
+    }
+    body
+}
+
+fn parse_openai_response(data: &Value) -> Result<ChatResponse, ProviderError> {
+    let message = data["choices"]
+        .get(0)
+        .map(|c| &c["message"])
+        .ok_or_else(|| ProviderError::Decode("no choices in response".to_string()))?;
+
+    let tool_calls = message["tool_calls"]
+        .as_array()
+        .map(|calls| {
+            calls
+                .iter()
+                .map(|tc| ToolCall {
+                    id: tc["id"].as_str().unwrap_or_default().to_string(),
+                    name: tc["function"]["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: parse_arguments(&tc["function"]["arguments"]),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(ChatResponse {
+        text: message["content"].as_str().unwrap_or_default().to_string(),
+        tool_calls,
+        usage: Usage {
+            input_tokens: data["usage"]["prompt_tokens"].as_u64().unwrap_or(0),
+            output_tokens: data["usage"]["completion_tokens"].as_u64().unwrap_or(0),
+        },
+    })
+}
+
+/// Text carried by one streamed chat completion chunk.
+fn openai_delta(chunk: &Value) -> Option<&str> {
+    chunk["choices"].get(0)?["delta"]["content"].as_str()
+}
+
+#[async_trait]
+impl Provider for OpenAiCompatible {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::OpenAi
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let resp = send(self.post("/chat/completions"), &openai_body(request, false)).await?;
+        parse_openai_response(&read_json(resp).await?)
+    }
+
+    async fn stream(
+        &self,
+        request: &ChatRequest,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<(), ProviderError> {
+        let resp = send(self.post("/chat/completions"), &openai_body(request, true)).await?;
+        for_each_line(resp, |line| {
+            let Some(data) = sse_data(line) else { return Ok(true) };
+            if data.trim() == "[DONE]" {
+                return Ok(false);
+            }
+            if let Ok(chunk) = serde_json::from_str::<Value>(data) {
+                if let Some(text) = openai_delta(&chunk).filter(|t| !t.is_empty()) {
+                    on_text(text);
+                }
+            }
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let model = self.embedding_model().ok_or_else(|| {
+            ProviderError::Unsupported(format!("Provider '{}' has no embedding model", self.name()))
+        })?;
+        let body = json!({"model": model, "input": inputs});
+        let data = read_json(send(self.post("/embeddings"), &body).await?).await?;
+
+        let mut rows: Vec<(u64, Vec<f32>)> = data["data"]
+            .as_array()
+            .ok_or_else(|| ProviderError::Decode("missing `data` array".to_string()))?
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (d["index"].as_u64().unwrap_or(i as u64), float_vec(&d["embedding"])))
+            .collect();
+        rows.sort_by_key(|r| r.0);
+        check_count(inputs.len(), rows.into_iter().map(|r| r.1).collect())
+    }
+}
+
+fn float_vec(v: &Value) -> Vec<f32> {
+    v.as_array()
+        .map(|a| a.iter().filter_map(|x| x.as_f64()).map(|x| x as f32).collect())
+        .unwrap_or_default()
+}
+
+fn check_count(expected: usize, vectors: Vec<Vec<f32>>) -> Result<Vec<Vec<f32>>, ProviderError> {
+    if vectors.len() != expected {
+        return Err(ProviderError::Decode(format!(
+            "expected {} embeddings, got {}",
+            expected,
+            vectors.len()
+        )));
+    }
+    Ok(vectors)
+}
+
+// ── Anthropic ───────────────────────────────────────────────────────────────
+
+/// Anthropic Messages API (`/v1/messages`).
+#[derive(Debug)]
+pub struct Anthropic {
+    endpoint: Endpoint,
+    http: reqwest::Client,
+}
+
+impl Anthropic {
+    fn post(&self) -> reqwest::RequestBuilder {
+        self.http
+            .post(url(&self.endpoint, "/v1/messages"))
+            .header("x-api-key", self.endpoint.api_key.as_deref().unwrap_or_default())
+            .header("anthropic-version", "2023-06-01")
+    }
+}
+
+/// Anthropic takes the system prompt separately and wants tool results as
+/// `tool_result` blocks in a user turn, one turn for consecutive results.
+fn anthropic_messages(messages: &[ChatMessage]) -> (Option<String>, Vec<Value>) {
+    let mut system: Vec<&str> = Vec::new();
+    let mut out: Vec<Value> = Vec::new();
+
+    for m in messages {
+        match m {
+            ChatMessage::System(text) => system.push(text),
+            ChatMessage::User(text) => out.push(json!({"role": "user", "content": text})),
+            ChatMessage::Assistant { text, tool_calls } => {
+                let mut blocks: Vec<Value> = Vec::new();
+                if !text.is_empty() {
+                    blocks.push(json!({"type": "text", "text": text}));
+                }
+                for tc in tool_calls {
+                    blocks.push(json!({"type": "tool_use", "id": tc.id, "name": tc.name, "input": tc.arguments}));
+                }
+                out.push(json!({"role": "assistant", "content": blocks}));
+            }
+            ChatMessage::ToolResult { call_id, content } => {
+                let block = json!({"type": "tool_result", "tool_use_id": call_id, "content": content});
+                let merged = out.last_mut().is_some_and(|last| {
+                    let is_results = last["role"] == "user"
+                        && last["content"][0]["type"] == "tool_result";
+                    if is_results {
+                        if let Some(blocks) = last["content"].as_array_mut() {
+                            blocks.push(block.clone());
+                        }
+                    }
+                    is_results
+                });
+                if !merged {
+                    out.push(json!({"role": "user", "content": [block]}));
+                }
+            }
+        }
+    }
+
+    let system = if system.is_empty() { None } else { Some(system.join("\n\n")) };
+    (system, out)
+}
+
+fn anthropic_body(request: &ChatRequest, stream: bool) -> Value {
+    let (system, messages) = anthropic_messages(&request.messages);
+    let mut body = json!({
+        "model": request.model,
+        "max_tokens": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+        "stream": stream,
+    });
+    if let Some(system) = system {
+        body["system"] = json!(system);
+    }
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|t| json!({"name": t.name, "description": t.description, "input_schema": t.parameters}))
+            .collect();
+    }
+    if let Some(temp) = request.temperature {
+        body["temperature"] = json!(temp);
+    }
+    body
+}
+
+fn parse_anthropic_response(data: &Value) -> Result<ChatResponse, ProviderError> {
+    let blocks = data["content"]
+        .as_array()
+        .ok_or_else(|| ProviderError::Decode("missing `content` array".to_string()))?;
+
+    let mut response = ChatResponse::default();
+    let mut texts: Vec<&str> = Vec::new();
+    for block in blocks {
+        match block["type"].as_str() {
+            Some("text") => texts.extend(block["text"].as_str()),
+            Some("tool_use") => response.tool_calls.push(ToolCall {
+                id: block["id"].as_str().unwrap_or_default().to_string(),
+                name: block["name"].as_str().unwrap_or_default().to_string(),
+                arguments: parse_arguments(&block["input"]),
+            }),
+            _ => {}
+        }
+    }
+    response.text = texts.join("\n");
+    response.usage = Usage {
+        input_tokens: data["usage"]["input_tokens"].as_u64().unwrap_or(0),
+        output_tokens: data["usage"]["output_tokens"].as_u64().unwrap_or(0),
+    };
+    Ok(response)
+}
+
+/// Text carried by one streamed `content_block_delta` event.
+fn anthropic_delta(event: &Value) -> Option<&str> {
+    if event["type"] == "content_block_delta" && event["delta"]["type"] == "text_delta" {
+        return event["delta"]["text"].as_str();
+    }
+    None
+}
+
+#[async_trait]
+impl Provider for Anthropic {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Anthropic
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let resp = send(self.post(), &anthropic_body(request, false)).await?;
+        parse_anthropic_response(&read_json(resp).await?)
+    }
+
+    async fn stream(
+        &self,
+        request: &ChatRequest,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<(), ProviderError> {
+        let resp = send(self.post(), &anthropic_body(request, true)).await?;
+        for_each_line(resp, |line| {
+            let Some(event) = sse_data(line).and_then(|d| serde_json::from_str::<Value>(d).ok()) else {
+                return Ok(true);
+            };
+            match event["type"].as_str() {
+                Some("message_stop") => Ok(false),
+                Some("error") => Err(ProviderError::Decode(event["error"]["message"].to_string())),
+                _ => {
+                    if let Some(text) = anthropic_delta(&event).filter(|t| !t.is_empty()) {
+                        on_text(text);
+                    }
+                    Ok(true)
+                }
+            }
+        })
+        .await
+    }
+
+    async fn embed(&self, _inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "Provider '{}' (anthropic) has no embeddings API; pick another provider for semantic search",
+            self.name()
+        )))
+    }
+}
+
+// ── Ollama ──────────────────────────────────────────────────────────────────
+
+/// Ollama's native API: `/api/chat` (newline-delimited JSON when streaming)
+/// and `/api/embed`. No key is needed; one is sent as a bearer token if set,
+/// for servers behind an authenticating proxy.
+#[derive(Debug)]
+pub struct Ollama {
+    endpoint: Endpoint,
+    http: reqwest::Client,
+}
+
+impl Ollama {
+    fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        let req = self.http.post(url(&self.endpoint, path));
+        match &self.endpoint.api_key {
+            Some(key) => req.bearer_auth(key),
+            None => req,
+        }
+    }
+}
+
+fn ollama_body(request: &ChatRequest, stream: bool) -> Value {
+    let messages: Vec<Value> = request
+        .messages
+        .iter()
+        .map(|m| match m {
+            ChatMessage::System(text) => json!({"role": "system", "content": text}),
+            ChatMessage::User(text) => json!({"role": "user", "content": text}),
+            ChatMessage::Assistant { text, tool_calls } => {
+                let mut obj = json!({"role": "assistant", "content": text});
+                if !tool_calls.is_empty() {
+                    obj["tool_calls"] = tool_calls
+                        .iter()
+                        .map(|tc| json!({"function": {"name": tc.name, "arguments": tc.arguments}}))
+                        .collect();
+                }
+                obj
+            }
+            ChatMessage::ToolResult { content, .. } => json!({"role": "tool", "content": content}),
+        })
+        .collect();
+
+    let mut options = json!({"num_predict": request.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS)});
+    if let Some(temp) = request.temperature {
+        options["temperature"] = json!(temp);
+    }
+    let mut body = json!({
+        "model": request.model,
+        "messages": messages,
+        "stream": stream,
+        "options": options,
+    });
+    if !request.tools.is_empty() {
+        body["tools"] = request
+            .tools
+            .iter()
+            .map(|t| json!({
+                "type": "function",
+                "function": {"name": t.name, "description": t.description, "parameters": t.parameters},
+            }))
+            .collect();
+    }
+    body
+}
+
+/// Ollama doesn't number tool calls, so ids are synthesized from their position.
+fn parse_ollama_response(data: &Value) -> Result<ChatResponse, ProviderError> {
+    if let Some(err) = data["error"].as_str() {
+        return Err(ProviderError::Decode(err.to_string()));
+    }
+    let message = data
+        .get("message")
+        .ok_or_else(|| ProviderError::Decode("missing `message`".to_string()))?;
+
+    let tool_calls = message["tool_calls"]
+        .as_array()
+        .map(|calls| {
+            calls
+                .iter()
+                .enumerate()
+                .map(|(i, tc)| ToolCall {
+                    id: tc["id"].as_str().map(str::to_string).unwrap_or_else(|| format!("call_{}", i)),
+                    name: tc["function"]["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: parse_arguments(&tc["function"]["arguments"]),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(ChatResponse {
+        text: message["content"].as_str().unwrap_or_default().to_string(),
+        tool_calls,
+        usage: Usage {
+            input_tokens: data["prompt_eval_count"].as_u64().unwrap_or(0),
+            output_tokens: data["eval_count"].as_u64().unwrap_or(0),
+        },
+    })
+}
+
+#[async_trait]
+impl Provider for Ollama {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Ollama
+    }
+
+    fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    async fn chat(&self, request: &ChatRequest) -> Result<ChatResponse, ProviderError> {
+        let resp = send(self.post("/api/chat"), &ollama_body(request, false)).await?;
+        parse_ollama_response(&read_json(resp).await?)
+    }
+
+    async fn stream(
+        &self,
+        request: &ChatRequest,
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<(), ProviderError> {
+        let resp = send(self.post("/api/chat"), &ollama_body(request, true)).await?;
+        for_each_line(resp, |line| {
+            let chunk: Value = serde_json::from_str(line).map_err(|e| ProviderError::Decode(e.to_string()))?;
+            if let Some(err) = chunk["error"].as_str() {
+                return Err(ProviderError::Decode(err.to_string()));
+            }
+            if let Some(text) = chunk["message"]["content"].as_str().filter(|t| !t.is_empty()) {
+                on_text(text);
+            }
+            Ok(chunk["done"] != true)
+        })
+        .await
+    }
+
+    async fn embed(&self, inputs: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let model = self.embedding_model().ok_or_else(|| {
+            ProviderError::Unsupported(format!("Provider '{}' has no embedding model", self.name()))
+        })?;
+        let body = json!({"model": model, "input": inputs});
+        let data = read_json(send(self.post("/api/embed"), &body).await?).await?;
+        let vectors = data["embeddings"]
+            .as_array()
+            .ok_or_else(|| ProviderError::Decode("missing `embeddings` array".to_string()))?
+            .iter()
+            .map(float_vec)
+            .collect();
+        check_count(inputs.len(), vectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool_conversation() -> ChatRequest {
+        let mut req = ChatRequest::new(
+            "m",
+            vec![
+                ChatMessage::System("be brief".to_string()),
+                ChatMessage::User("read it".to_string()),
+                ChatMessage::Assistant {
+                    text: String::new(),
+                    tool_calls: vec![
+                        ToolCall { id: "a".to_string(), name: "read_file".to_string(), arguments: json!({"path": "x"}) },
+                        ToolCall { id: "b".to_string(), name: "read_file".to_string(), arguments: json!({"path": "y"}) },
+                    ],
+                },
+                ChatMessage::ToolResult { call_id: "a".to_string(), content: "X".to_string() },
+                ChatMessage::ToolResult { call_id: "b".to_string(), content: "Y".to_string() },
+            ],
+        );
+        req.tools.push(ToolSpec {
+            name: "read_file".to_string(),
+            description: "Read a file".to_string(),
+            parameters: json!({"type": "object"}),
+        });
+        req
+    }
+
+    #[test]
+    fn openai_request_carries_tools_and_results() {
+        let body = openai_body(&tool_conversation(), false);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[2]["content"], Value::Null);
+        assert_eq!(messages[2]["tool_calls"][1]["function"]["arguments"], "{\"path\":\"y\"}");
+        assert_eq!(messages[4]["tool_call_id"], "b");
+        assert_eq!(body["tools"][0]["function"]["name"], "read_file");
+        assert_eq!(body["tool_choice"], "auto");
+    }
+
+    #[test]
+    fn anthropic_request_splits_system_and_merges_results() {
+        let body = anthropic_body(&tool_conversation(), true);
+        assert_eq!(body["system"], "be brief");
+        assert_eq!(body["stream"], true);
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][0]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][0]["input"]["path"], "x");
+        assert_eq!(messages[2]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(messages[2]["content"][1]["tool_use_id"], "b");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+    }
+
+    #[test]
+    fn ollama_request_uses_options() {
+        let mut req = tool_conversation();
+        req.temperature = Some(0.5);
+        let body = ollama_body(&req, false);
+        assert_eq!(body["options"]["temperature"], 0.5);
+        assert_eq!(body["messages"][2]["tool_calls"][0]["function"]["arguments"]["path"], "x");
+        assert_eq!(body["messages"][3]["role"], "tool");
+    }
+
+    #[test]
+    fn parses_tool_calls_from_each_format() {
+        let openai = parse_openai_response(&json!({
+            "choices": [{"message": {"content": null, "tool_calls": [
+                {"id": "c1", "function": {"name": "f", "arguments": "{\"n\": 1}"}}
+            ]}}],
+            "usage": {"prompt_tokens": 3, "completion_tokens": 4}
+        }))
+        .unwrap();
+        assert_eq!(openai.tool_calls[0].arguments["n"], 1);
+        assert_eq!(openai.usage, Usage { input_tokens: 3, output_tokens: 4 });
+
+        let anthropic = parse_anthropic_response(&json!({
+            "content": [
+                {"type": "text", "text": "let me look"},
+                {"type": "tool_use", "id": "t1", "name": "f", "input": {"n": 2}}
+            ],
+            "usage": {"input_tokens": 5, "output_tokens": 6}
+        }))
+        .unwrap();
+        assert_eq!(anthropic.text, "let me look");
+        assert_eq!(anthropic.tool_calls[0].id, "t1");
+        assert_eq!(anthropic.tool_calls[0].arguments["n"], 2);
+
+        let ollama = parse_ollama_response(&json!({
+            "message": {"content": "", "tool_calls": [{"function": {"name": "f", "arguments": {"n": 3}}}]},
+            "prompt_eval_count": 7, "eval_count": 8
+        }))
+        .unwrap();
+        assert_eq!(ollama.tool_calls[0].id, "call_0");
+        assert_eq!(ollama.usage.output_tokens, 8);
+
+        assert!(parse_openai_response(&json!({"choices": []})).is_err());
+    }
+
+    #[test]
+    fn extracts_stream_deltas() {
+        assert_eq!(sse_data("data: {\"x\":1}"), Some("{\"x\":1}"));
+        assert_eq!(sse_data(": keep-alive"), None);
+        assert_eq!(openai_delta(&json!({"choices": [{"delta": {"content": "hi"}}]})), Some("hi"));
+        assert_eq!(openai_delta(&json!({"choices": []})), None);
+        let event = json!({"type": "content_block_delta", "delta": {"type": "text_delta", "text": "yo"}});
+        assert_eq!(anthropic_delta(&event), Some("yo"));
+        assert_eq!(anthropic_delta(&json!({"type": "message_start"})), None);
+    }
+}
@@ -0,0 +1,428 @@
+//! The `gist search` query language, shared by the CLI and the viewer's `/`.
+//!
+//! ```text
+//! tag:rust -tag:old lang:sql created:>2025-01 "exact phrase" pinned:true
+//! ```
+//!
+//! Bare words, `"phrases"` and `prefix*` are full-text terms. `field:value`
+//! filters are recognised only for the known fields below, so text such as
+//! `std::io` or `http://host` stays a plain term. Terms are AND-ed; `OR`,
+//! `NOT` (or a leading `-`) and parentheses combine them. Dates are `YYYY`,
+//! `YYYY-MM` or `YYYY-MM-DD`, optionally after `>`, `>=`, `<`, `<=` or `=`.
+
+use chrono::{Datelike, NaiveDate};
+use crate::ai::normalize_tag;
+use crate::db::build_fts_query;
+use crate::expr::{self, Expr, Names, Token};
+use crate::language;
+use crate::models::Gist;
+use crate::tags::TagQuery;
+
+/// Days from `from` (inclusive) until `until` (exclusive); either end may be open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    fn contains(&self, day: NaiveDate) -> bool {
+        self.from.is_none_or(|from| day >= from) && self.until.is_none_or(|until| day < until)
+    }
+}
+
+/// A single condition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Word(String),
+    Prefix(String),
+    Phrase(String),
+    Tag(String),
+    Lang(String),
+    Created(DateRange),
+    Updated(DateRange),
+    Pinned(bool),
+}
+
+/// Parsed search query.
+pub type Query = Expr<Term>;
+
+const NAMES: Names = Names { query: "search query", term: "a search term" };
+
+/// `YYYY`, `YYYY-MM` or `YYYY-MM-DD` as the days it covers.
+fn parse_period(s: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let parts: Vec<&str> = s.split('-').collect();
+    let num = |i: usize| parts.get(i).and_then(|p| p.parse::<u32>().ok());
+    let year = parts.first().filter(|y| y.len() == 4).and_then(|y| y.parse::<i32>().ok())?;
+    match parts.len() {
+        1 => Some((NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year + 1, 1, 1)?)),
+        2 => {
+            let start = NaiveDate::from_ymd_opt(year, num(1)?, 1)?;
+            let end = match start.month() {
+                12 => NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+                m => NaiveDate::from_ymd_opt(year, m + 1, 1)?,
+            };
+            Some((start, end))
+        }
+        3 => {
+            let day = NaiveDate::from_ymd_opt(year, num(1)?, num(2)?)?;
+            Some((day, day.succ_opt()?))
+        }
+        _ => None,
+    }
+}
+
+fn parse_date_range(field: &str, value: &str) -> Result<DateRange, String> {
+    let (op, date) = [">=", "<=", ">", "<", "="]
+        .iter()
+        .find_map(|op| value.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("=", value));
+    let (start, end) = parse_period(date).ok_or_else(|| {
+        format!("Invalid date '{}' in {}: (use YYYY, YYYY-MM or YYYY-MM-DD)", date, field)
+    })?;
+    Ok(match op {
+        ">" => DateRange { from: Some(end), until: None },
+        ">=" => DateRange { from: Some(start), until: None },
+        "<" => DateRange { from: None, until: Some(start) },
+        "<=" => DateRange { from: None, until: Some(end) },
+        _ => DateRange { from: Some(start), until: Some(end) },
+    })
+}
+
+fn is_field(name: &str) -> bool {
+    matches!(name.to_lowercase().as_str(), "tag" | "lang" | "language" | "created" | "updated" | "pinned")
+}
+
+/// The filter for `field:value`, or None when `field` is not a known field.
+fn field_term(field: &str, value: &str) -> Option<Result<Term, String>> {
+    if !is_field(field) {
+        return None;
+    }
+    let field = field.to_lowercase();
+    if value.is_empty() {
+        return Some(Err(format!("{}: needs a value, e.g. {}", field, match field.as_str() {
+            "tag" => "tag:rust",
+            "lang" | "language" => "lang:sql",
+            "pinned" => "pinned:true",
+            _ => "created:>2025-01",
+        })));
+    }
+    Some(match field.as_str() {
+        "tag" => Ok(Term::Tag(normalize_tag(value))),
+        "lang" | "language" => Ok(Term::Lang(language::normalize(value))),
+        "created" => parse_date_range("created", value).map(Term::Created),
+        "updated" => parse_date_range("updated", value).map(Term::Updated),
+        _ => match value.to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(Term::Pinned(true)),
+            "false" | "no" | "0" => Ok(Term::Pinned(false)),
+            _ => Err(format!("pinned: expects true or false, not '{}'", value)),
+        },
+    })
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    chars.next();
+    let mut text = String::new();
+    for ch in chars.by_ref() {
+        if ch == '"' {
+            return Ok(text);
+        }
+        text.push(ch);
+    }
+    Err(format!("Unterminated quote in search query: \"{}", text))
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token<Term>>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    let mut depth = 0usize;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                depth += 1;
+                tokens.push(Token::Open);
+            }
+            ')' if depth > 0 => {
+                chars.next();
+                depth -= 1;
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                let phrase = read_quoted(&mut chars)?;
+                if !phrase.trim().is_empty() {
+                    tokens.push(Token::Term(Term::Phrase(phrase.trim().to_string())));
+                }
+            }
+            '-' => {
+                chars.next();
+                match chars.peek() {
+                    Some(ch) if !ch.is_whitespace() => tokens.push(Token::Not),
+                    _ => tokens.push(Token::Term(Term::Word("-".to_string()))),
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || ch == '"' || (ch == ')' && depth > 0) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                let token = match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => {
+                        let field = word.split_once(':').filter(|(field, _)| is_field(field)).and_then(|(field, value)| {
+                            let value = match value.is_empty() && chars.peek() == Some(&'"') {
+                                true => match read_quoted(&mut chars) {
+                                    Ok(quoted) => quoted,
+                                    Err(e) => return Some(Err(e)),
+                                },
+                                false => value.to_string(),
+                            };
+                            field_term(field, value.trim())
+                        });
+                        match field {
+                            Some(term) => Token::Term(term?),
+                            None => match word.strip_suffix('*').filter(|stem| !stem.is_empty()) {
+                                Some(stem) => Token::Term(Term::Prefix(stem.to_string())),
+                                None => Token::Term(Term::Word(word)),
+                            },
+                        }
+                    }
+                };
+                tokens.push(token);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// The full-text MATCH expression for one text term.
+fn fts_text(term: &Term) -> Option<String> {
+    let fts = match term {
+        Term::Word(w) => build_fts_query(w),
+        Term::Prefix(stem) => build_fts_query(&format!("{}*", stem)),
+        Term::Phrase(p) => build_fts_query(&format!("\"{}\"", p.replace('"', ""))),
+        _ => return None,
+    };
+    Some(fts)
+}
+
+fn date_sql(column: &str, range: &DateRange, params: &mut Vec<String>) -> String {
+    let mut parts = Vec::new();
+    if let Some(from) = range.from {
+        params.push(from.to_string());
+        parts.push(format!("date({}) >= ?{}", column, params.len()));
+    }
+    if let Some(until) = range.until {
+        params.push(until.to_string());
+        parts.push(format!("date({}) < ?{}", column, params.len()));
+    }
+    format!("({})", parts.join(" AND "))
+}
+
+/// Lowercased runs of letters and digits, as the `unicode61` full-text
+/// tokenizer splits text.
+fn fts_tokens(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|t| !t.is_empty()).map(str::to_lowercase).collect()
+}
+
+/// Whether `text` holds the tokens of `needle` in a row, the last one as a
+/// prefix if `prefix` is set, the way an FTS phrase query matches.
+fn has_phrase(text: &str, needle: &str, prefix: bool) -> bool {
+    let needle = fts_tokens(needle);
+    !needle.is_empty()
+        && fts_tokens(text).windows(needle.len()).any(|window| {
+            window.iter().zip(&needle).enumerate().all(|(i, (token, want))| match prefix && i + 1 == needle.len() {
+                true => token.starts_with(want.as_str()),
+                false => token == want,
+            })
+        })
+}
+
+fn gist_day(timestamp: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(timestamp.get(..10)?, "%Y-%m-%d").ok()
+}
+
+impl Query {
+    /// Parse a search query.
+    pub fn parse(input: &str) -> Result<Query, String> {
+        expr::parse(tokenize(input)?, &NAMES)
+    }
+
+    /// Compile to a SQL condition over the `gists` table, appending bound values to `params`.
+    pub fn to_sql(&self, params: &mut Vec<String>) -> String {
+        match self {
+            Query::Term(term) => match term {
+                Term::Word(_) | Term::Prefix(_) | Term::Phrase(_) => {
+                    let fts = fts_text(term).unwrap_or_default();
+                    if fts.is_empty() {
+                        return "0".to_string();
+                    }
+                    params.push(fts);
+                    format!("gists.id IN (SELECT rowid FROM gists_fts WHERE gists_fts MATCH ?{})", params.len())
+                }
+                Term::Tag(name) => TagQuery::Term(name.clone()).to_sql(params),
+                Term::Lang(lang) => {
+                    let tagged = TagQuery::Term(lang.clone()).to_sql(params);
+                    format!("COALESCE(LOWER(gists.language) = ?{}, {})", params.len(), tagged)
+                }
+                Term::Created(range) => date_sql("gists.created_at", range, params),
+                Term::Updated(range) => date_sql("COALESCE(gists.updated_at, gists.created_at)", range, params),
+                Term::Pinned(pinned) => format!(
+                    "COALESCE((SELECT pinned FROM gist_usage WHERE gist_id = gists.id), 0) = {}",
+                    i32::from(*pinned)
+                ),
+            },
+            Query::Not(inner) => format!("NOT ({})", inner.to_sql(params)),
+            Query::And(a, b) => format!("({} AND {})", a.to_sql(params), b.to_sql(params)),
+            Query::Or(a, b) => format!("({} OR {})", a.to_sql(params), b.to_sql(params)),
+        }
+    }
+
+    /// A MATCH expression for ranking: the text terms that aren't negated, OR-ed.
+    pub fn rank_query(&self) -> Option<String> {
+        fn collect(q: &Query, out: &mut Vec<String>) {
+            match q {
+                Query::Term(term) => out.extend(fts_text(term).filter(|f| !f.is_empty())),
+                Query::Not(_) => {}
+                Query::And(a, b) | Query::Or(a, b) => {
+                    collect(a, out);
+                    collect(b, out);
+                }
+            }
+        }
+        let mut terms = Vec::new();
+        collect(self, &mut terms);
+        (!terms.is_empty()).then(|| terms.join(" OR "))
+    }
+
+    /// Evaluate against a gist in memory, as the viewer does, with the same
+    /// result as `to_sql`: text terms match whole words (or word prefixes) of
+    /// the content or tags, like the full-text index.
+    pub fn matches(&self, gist: &Gist, pinned: bool) -> bool {
+        match self {
+            Query::Term(term) => match term {
+                Term::Word(w) | Term::Prefix(w) | Term::Phrase(w) => {
+                    let prefix = matches!(term, Term::Prefix(_));
+                    has_phrase(&gist.content, w, prefix) || has_phrase(&gist.tags, w, prefix)
+                }
+                Term::Tag(name) => gist.tags.split(',').any(|t| t.trim() == name),
+                Term::Lang(lang) => match &gist.language {
+                    Some(own) => own.to_lowercase() == *lang,
+                    None => gist.tags.split(',').any(|t| t.trim() == lang),
+                },
+                Term::Created(range) => gist_day(&gist.created_at).is_some_and(|d| range.contains(d)),
+                Term::Updated(range) => gist_day(gist.updated_at.as_deref().unwrap_or(&gist.created_at))
+                    .is_some_and(|d| range.contains(d)),
+                Term::Pinned(want) => pinned == *want,
+            },
+            Query::Not(inner) => !inner.matches(gist, pinned),
+            Query::And(a, b) => a.matches(gist, pinned) && b.matches(gist, pinned),
+            Query::Or(a, b) => a.matches(gist, pinned) || b.matches(gist, pinned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(t: Term) -> Box<Query> {
+        Box::new(Query::Term(t))
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn parses_fields_phrases_and_negation() {
+        let q = Query::parse("tag:Rust -tag:old lang:sql created:>2025-01 \"exact phrase\" pinned:true").unwrap();
+        let expected = [
+            *term(Term::Tag("rust".into())),
+            Query::Not(term(Term::Tag("old".into()))),
+            *term(Term::Lang("sql".into())),
+            *term(Term::Created(DateRange { from: day(2025, 2, 1), until: None })),
+            *term(Term::Phrase("exact phrase".into())),
+            *term(Term::Pinned(true)),
+        ]
+        .into_iter()
+        .reduce(|a, b| Query::And(Box::new(a), Box::new(b)))
+        .unwrap();
+        assert_eq!(q, expected);
+    }
+
+    #[test]
+    fn words_prefixes_and_unknown_fields_are_text() {
+        assert_eq!(
+            Query::parse("std::io retr* OR (a b)").unwrap(),
+            Query::Or(
+                Box::new(Query::And(term(Term::Word("std::io".into())), term(Term::Prefix("retr".into())))),
+                Box::new(Query::And(term(Term::Word("a".into())), term(Term::Word("b".into())))),
+            )
+        );
+        assert_eq!(Query::parse("foo()").unwrap(), *term(Term::Word("foo()".into())));
+        assert_eq!(Query::parse("tag:\"async io\"").unwrap(), *term(Term::Tag("async io".into())));
+    }
+
+    #[test]
+    fn date_ranges() {
+        let range = |v: &str| match Query::parse(&format!("created:{}", v)).unwrap() {
+            Query::Term(Term::Created(r)) => r,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(range("2024"), DateRange { from: day(2024, 1, 1), until: day(2025, 1, 1) });
+        assert_eq!(range(">=2024-12"), DateRange { from: day(2024, 12, 1), until: None });
+        assert_eq!(range("<2024-03-05"), DateRange { from: None, until: day(2024, 3, 5) });
+        assert_eq!(range("<=2024-03-05"), DateRange { from: None, until: day(2024, 3, 6) });
+    }
+
+    #[test]
+    fn reports_malformed_queries() {
+        let err = |q: &str| Query::parse(q).unwrap_err();
+        assert_eq!(err(""), "Search query is empty");
+        assert_eq!(err("rust AND"), "Search query ends after AND; add a term");
+        assert_eq!(err("OR rust"), "Search query can't start with OR");
+        assert!(err("(rust").contains("Missing closing ')'"));
+        assert!(err("\"open").contains("Unterminated quote"));
+        assert!(err("created:>2025-13").contains("Invalid date '2025-13'"));
+        assert!(err("pinned:maybe").contains("expects true or false"));
+        assert!(err("tag:").contains("needs a value"));
+    }
+
+    #[test]
+    fn matches_in_memory() {
+        let gist = Gist {
+            id: 7,
+            content: "SELECT * FROM users".into(),
+            tags: "sql,db".into(),
+            created_at: "2025-03-04 10:00:00".into(),
+            ..Default::default()
+        };
+        let m = |q: &str, pinned: bool| Query::parse(q).unwrap().matches(&gist, pinned);
+        assert!(m("tag:sql lang:sql users", false));
+        assert!(!m("tag:sql -tag:db", false));
+        assert!(m("created:2025-03 \"from users\"", false));
+        assert!(!m("created:<2025", false));
+        assert!(m("pinned:true OR tag:rust", true));
+        assert!(!m("pinned:true", false));
+    }
+
+    #[test]
+    fn compiles_to_parameterized_sql() {
+        let mut params = Vec::new();
+        let sql = Query::parse("tag:rust -\"x'y\" created:2025").unwrap().to_sql(&mut params);
+        assert!(!sql.contains("rust") && !sql.contains("x'y"), "{}", sql);
+        assert_eq!(params, vec!["rust", "\"x'y\"", "2025-01-01", "2026-01-01"]);
+    }
+}
@@ -0,0 +1,262 @@
+//! `gist run`: execute a runnable snippet with an interpreter chosen from its
+//! language, under a timeout.
+//!
+//! The sandbox is deliberately modest: the snippet runs from a scratch
+//! directory that is deleted afterwards, with a minimal environment, and is
+//! killed (with its process group on Unix) when the timeout expires. It is
+//! not isolation from the network or the rest of the filesystem, which is
+//! why snippets must be marked runnable first.
+
+use std::{
+    error::Error,
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use crate::config::Config;
+use crate::language;
+use crate::models::Gist;
+
+pub const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+/// Most output kept for `gist view` and the viewer; the tail is what matters.
+pub const MAX_STORED_OUTPUT: usize = 64 * 1024;
+
+/// Exit status reported when a run is killed for taking too long, as timeout(1) does.
+pub const TIMEOUT_EXIT_CODE: i32 = 124;
+
+/// Environment variables a sandboxed run keeps.
+const KEPT_ENV: &[&str] = &["PATH", "HOME", "USER", "LANG", "LC_ALL", "TERM", "TMPDIR", "SYSTEMROOT"];
+
+/// Interpreter command for each language that can be run.
+fn builtin_interpreter(language: &str) -> Option<&'static [&'static str]> {
+    Some(match language {
+        "bash" => &["bash"],
+        "python" => &["python3"],
+        "javascript" => &["node"],
+        "typescript" => &["npx", "--yes", "tsx"],
+        "ruby" => &["ruby"],
+        "perl" => &["perl"],
+        "lua" => &["lua"],
+        "php" => &["php"],
+        "powershell" => &["pwsh", "-NoProfile", "-File"],
+        _ => return None,
+    })
+}
+
+/// The command that runs `gist`: its shebang, then `[run.interpreters]`,
+/// then the built-in choice for its language.
+pub fn interpreter(gist: &Gist, config: &Config) -> Result<Vec<String>, String> {
+    if let Some(shebang) = gist.content.lines().next().and_then(|l| l.strip_prefix("#!")) {
+        let words: Vec<String> = shebang.split_whitespace().map(str::to_string).collect();
+        if !words.is_empty() {
+            return Ok(words);
+        }
+    }
+
+    let language = gist
+        .language
+        .clone()
+        .or_else(|| language::from_tags(&gist.tags))
+        .ok_or("Snippet has no language; set one with `gist update <id> --language <lang>`")?;
+    let language = language::normalize(&language);
+
+    if let Some(custom) = config.run.as_ref().and_then(|r| r.interpreters.get(&language)) {
+        return Ok(custom.split_whitespace().map(str::to_string).collect());
+    }
+    builtin_interpreter(&language)
+        .map(|cmd| cmd.iter().map(|s| s.to_string()).collect())
+        .ok_or_else(|| format!("Don't know how to run {} snippets; add it under [run.interpreters]", language))
+}
+
+pub struct RunOptions {
+    /// Passed to the snippet after its path
+    pub args: Vec<String>,
+    pub timeout: Duration,
+    /// Keep the whole environment instead of the minimal one
+    pub inherit_env: bool,
+    /// Working directory; a scratch directory when None
+    pub cwd: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct RunOutcome {
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// Combined stdout and stderr, at most MAX_STORED_OUTPUT bytes
+    pub output: String,
+    pub duration: Duration,
+}
+
+impl RunOutcome {
+    /// Exit status for `gist run` itself.
+    pub fn status(&self) -> i32 {
+        if self.timed_out {
+            TIMEOUT_EXIT_CODE
+        } else {
+            self.exit_code.unwrap_or(1)
+        }
+    }
+}
+
+/// Copies a child's stream to ours while keeping a copy of what passed.
+fn tee(mut from: impl Read + Send + 'static, mut to: impl Write + Send + 'static, sink: Arc<Mutex<Vec<u8>>>, done: mpsc::Sender<()>) {
+    thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        while let Ok(n) = from.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            let _ = to.write_all(&buf[..n]);
+            let _ = to.flush();
+            let mut captured = sink.lock().unwrap();
+            captured.extend_from_slice(&buf[..n]);
+            if captured.len() > 2 * MAX_STORED_OUTPUT {
+                let excess = captured.len() - MAX_STORED_OUTPUT;
+                captured.drain(..excess);
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
+/// The last `max` bytes of `bytes` as text, starting on a character boundary.
+fn tail(bytes: &[u8], max: usize) -> String {
+    let text = String::from_utf8_lossy(&bytes[bytes.len().saturating_sub(max)..]).into_owned();
+    match text.strip_prefix('\u{FFFD}') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    }
+}
+
+/// Run `gist` with `command`, passing stdin through and echoing its output.
+pub fn run(gist: &Gist, command: &[String], opts: &RunOptions) -> Result<RunOutcome, Box<dyn Error>> {
+    let (program, interpreter_args) = command.split_first().ok_or("Empty interpreter command")?;
+    let scratch = tempfile::TempDir::new()?;
+    let extension = gist.language.as_deref().and_then(language::file_extension).unwrap_or("txt");
+    let script = scratch.path().join(format!("gist-{}.{}", gist.id, extension));
+    fs::write(&script, &gist.content)?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(interpreter_args)
+        .arg(&script)
+        .args(&opts.args)
+        .current_dir(opts.cwd.as_deref().unwrap_or(scratch.path()))
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if !opts.inherit_env {
+        cmd.env_clear();
+        for key in KEPT_ENV {
+            if let Ok(value) = std::env::var(key) {
+                cmd.env(key, value);
+            }
+        }
+    }
+    cmd.env("GIST_ID", gist.id.to_string());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    let started = Instant::now();
+    let mut child = cmd.spawn().map_err(|e| format!("Could not start '{}': {}", program, e))?;
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let (done_tx, done_rx) = mpsc::channel();
+    tee(child.stdout.take().unwrap(), io::stdout(), Arc::clone(&captured), done_tx.clone());
+    tee(child.stderr.take().unwrap(), io::stderr(), Arc::clone(&captured), done_tx);
+
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= opts.timeout {
+            timed_out = true;
+            kill_tree(&mut child);
+            break child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    let duration = started.elapsed();
+
+    // Background processes may hold the pipes open; don't wait on them for long
+    for _ in 0..2 {
+        if done_rx.recv_timeout(Duration::from_secs(1)).is_err() {
+            break;
+        }
+    }
+
+    let output = tail(&captured.lock().unwrap(), MAX_STORED_OUTPUT);
+    Ok(RunOutcome { exit_code: status.code(), timed_out, output, duration })
+}
+
+fn kill_tree(child: &mut std::process::Child) {
+    #[cfg(unix)]
+    {
+        let _ = Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", child.id())])
+            .stderr(Stdio::null())
+            .status();
+    }
+    let _ = child.kill();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gist(content: &str, language: Option<&str>) -> Gist {
+        Gist { id: 1, content: content.into(), language: language.map(str::to_string), ..Default::default() }
+    }
+
+    fn opts(timeout_ms: u64) -> RunOptions {
+        RunOptions { args: vec!["one two".into()], timeout: Duration::from_millis(timeout_ms), inherit_env: false, cwd: None }
+    }
+
+    #[test]
+    fn picks_interpreter_from_shebang_config_and_language() {
+        let mut config = Config::default();
+        assert_eq!(interpreter(&gist("print(1)", Some("python")), &config).unwrap(), vec!["python3"]);
+        assert_eq!(interpreter(&gist("#!/usr/bin/env bash\necho", Some("python")), &config).unwrap(), vec!["/usr/bin/env", "bash"]);
+        assert!(interpreter(&gist("SELECT 1", Some("sql")), &config).is_err());
+        assert!(interpreter(&gist("x", None), &config).is_err());
+
+        let mut run = crate::config::RunConfig::default();
+        run.interpreters.insert("sql".into(), "sqlite3 :memory: -init".into());
+        config.run = Some(run);
+        assert_eq!(interpreter(&gist("SELECT 1", Some("sql")), &config).unwrap(), vec!["sqlite3", ":memory:", "-init"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runs_with_args_and_captures_output() {
+        let g = gist("echo \"arg=$1\"; echo oops >&2; exit 3", Some("bash"));
+        let outcome = run(&g, &["sh".to_string()], &opts(10_000)).unwrap();
+        assert_eq!(outcome.exit_code, Some(3));
+        assert!(!outcome.timed_out);
+        assert!(outcome.output.contains("arg=one two") && outcome.output.contains("oops"), "{}", outcome.output);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn kills_runs_that_time_out() {
+        let g = gist("sleep 5; echo late", Some("bash"));
+        let outcome = run(&g, &["sh".to_string()], &opts(200)).unwrap();
+        assert!(outcome.timed_out);
+        assert_eq!(outcome.status(), TIMEOUT_EXIT_CODE);
+        assert!(outcome.duration < Duration::from_secs(3));
+        assert!(!outcome.output.contains("late"));
+    }
+
+    #[test]
+    fn keeps_the_tail_of_long_output() {
+        let bytes = "é".repeat(10).into_bytes();
+        assert_eq!(tail(&bytes, 5), "éé");
+    }
+}
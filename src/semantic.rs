@@ -0,0 +1,215 @@
+//! Semantic search: snippets are embedded by the configured provider and
+//! ranked by cosine similarity to the query.
+
+use rusqlite::Connection;
+use std::{collections::HashSet, error::Error, sync::Arc};
+use crate::config::{Config, ProviderRole};
+use crate::db::{embedding_hashes, get_gist, list_embeddings, list_gists, store_embedding};
+use crate::models::Gist;
+use crate::provider::Provider;
+use crate::secrets;
+use crate::utils::content_hash;
+
+/// Gists sent per embeddings request during a reindex.
+const BATCH_SIZE: usize = 32;
+
+/// Longest text sent for one gist; keeps large files under typical input limits.
+const MAX_EMBED_CHARS: usize = 8_000;
+
+/// The text a gist is embedded from: its metadata followed by its content,
+/// with suspected secrets redacted. Encrypted content is left out.
+pub fn embed_text(g: &Gist) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    if let Some(title) = &g.title {
+        parts.push(title);
+    }
+    if let Some(description) = &g.description {
+        parts.push(description);
+    }
+    if !g.tags.is_empty() {
+        parts.push(&g.tags);
+    }
+    if !g.is_encrypted() {
+        parts.push(&g.content);
+    }
+    secrets::redact(&parts.join("\n")).chars().take(MAX_EMBED_CHARS).collect()
+}
+
+/// Cosine similarity in [-1, 1]; 0 for mismatched or zero-length vectors.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() || a.is_empty() {
+        return 0.0;
+    }
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    if na == 0.0 || nb == 0.0 {
+        return 0.0;
+    }
+    dot / (na.sqrt() * nb.sqrt())
+}
+
+/// Rank `(id, vector)` candidates against `query`, best first, keeping `limit`.
+pub fn rank(query: &[f32], candidates: Vec<(i64, Vec<f32>)>, limit: usize) -> Vec<(i64, f32)> {
+    let mut scored: Vec<(i64, f32)> = candidates
+        .into_iter()
+        .map(|(id, v)| (id, cosine(query, &v)))
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(limit);
+    scored
+}
+
+/// Live gists whose embedding is missing or was made from different text.
+pub fn stale_gists(c: &Connection, model: &str) -> Result<Vec<Gist>, Box<dyn Error>> {
+    let hashes = embedding_hashes(c, model)?;
+    Ok(list_gists(c, usize::MAX, "id")?
+        .into_iter()
+        .filter(|g| hashes.get(&g.id) != Some(&content_hash(&embed_text(g))))
+        .collect())
+}
+
+/// Embed every gist that needs it (all of them with `force`), reporting
+/// `(done, total)` after each batch. Returns the number embedded.
+pub async fn reindex(
+    c: &Connection,
+    provider: &dyn Provider,
+    force: bool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize, Box<dyn Error>> {
+    let model = provider_model(provider)?;
+    let todo = if force {
+        list_gists(c, usize::MAX, "id")?
+    } else {
+        stale_gists(c, &model)?
+    };
+
+    let mut done = 0;
+    for batch in todo.chunks(BATCH_SIZE) {
+        let texts: Vec<String> = batch.iter().map(embed_text).collect();
+        let vectors = provider.embed(&texts).await?;
+        for ((g, text), vector) in batch.iter().zip(&texts).zip(vectors) {
+            store_embedding(c, g.id, &model, &vector, &content_hash(text))?;
+        }
+        done += batch.len();
+        progress(done, todo.len());
+    }
+    Ok(done)
+}
+
+/// The configured embeddings provider and the model it embeds with.
+pub fn embedding_provider(config: &Config) -> Result<(Arc<dyn Provider>, String), Box<dyn Error>> {
+    let provider = config.provider(ProviderRole::Embeddings)?;
+    let model = provider_model(provider.as_ref())?;
+    Ok((provider, model))
+}
+
+fn provider_model(provider: &dyn Provider) -> Result<String, Box<dyn Error>> {
+    provider
+        .embedding_model()
+        .map(str::to_string)
+        .ok_or_else(|| format!("Provider '{}' ({}) has no embeddings API", provider.name(), provider.kind()).into())
+}
+
+/// Embed one gist if a provider is configured. Failures are ignored; `gist reindex` catches up.
+pub async fn index_gist(c: &Connection, config: &Config, id: i64) {
+    let Ok(provider) = config.provider(ProviderRole::Embeddings) else {
+        return;
+    };
+    let Some(model) = provider.embedding_model() else {
+        return;
+    };
+    if let Ok(Some(g)) = get_gist(c, id) {
+        let text = embed_text(&g);
+        if let Ok(mut vectors) = provider.embed(std::slice::from_ref(&text)).await {
+            if let Some(vector) = vectors.pop() {
+                let _ = store_embedding(c, id, model, &vector, &content_hash(&text));
+            }
+        }
+    }
+}
+
+/// Gists most similar in meaning to `query`, with their similarity scores.
+pub async fn search(
+    c: &Connection,
+    provider: &dyn Provider,
+    query: &str,
+    limit: usize,
+    only: Option<&HashSet<i64>>,
+) -> Result<Vec<(Gist, f32)>, Box<dyn Error>> {
+    let model = provider_model(provider)?;
+    let query_vec = provider
+        .embed(&[query.to_string()])
+        .await?
+        .pop()
+        .ok_or("Empty embeddings response")?;
+
+    let mut stored = list_embeddings(c, &model)?;
+    if let Some(ids) = only {
+        stored.retain(|(id, _)| ids.contains(id));
+    }
+    let mut out = Vec::new();
+    for (id, score) in rank(&query_vec, stored, limit) {
+        if let Some(g) = get_gist(c, id)? {
+            out.push((g, score));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{delete_gist, init_schema, insert_gist, update_gist};
+
+    fn setup_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn cosine_similarity() {
+        assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert!((cosine(&[1.0, 1.0], &[-1.0, -1.0]) + 1.0).abs() < 1e-6);
+        assert_eq!(cosine(&[1.0], &[1.0, 2.0]), 0.0);
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 2.0]), 0.0);
+    }
+
+    #[test]
+    fn ranks_stored_vectors() {
+        let conn = setup_db();
+        let a = insert_gist(&conn, "retry with backoff", "net").unwrap();
+        let b = insert_gist(&conn, "parse csv", "io").unwrap();
+        let trashed = insert_gist(&conn, "retry forever", "net").unwrap();
+        store_embedding(&conn, a, "m", &[0.9, 0.1], "x").unwrap();
+        store_embedding(&conn, b, "m", &[0.0, 1.0], "x").unwrap();
+        store_embedding(&conn, trashed, "m", &[1.0, 0.0], "x").unwrap();
+        delete_gist(&conn, trashed).unwrap();
+
+        let ranked = rank(&[1.0, 0.0], list_embeddings(&conn, "m").unwrap(), 10);
+        let ids: Vec<i64> = ranked.iter().map(|r| r.0).collect();
+        assert_eq!(ids, vec![a, b]);
+        assert_eq!(rank(&[1.0, 0.0], list_embeddings(&conn, "m").unwrap(), 1).len(), 1);
+        assert!(list_embeddings(&conn, "other-model").unwrap().is_empty());
+    }
+
+    #[test]
+    fn edits_make_embeddings_stale() {
+        let conn = setup_db();
+        let id = insert_gist(&conn, "one", "t").unwrap();
+        assert_eq!(stale_gists(&conn, "m").unwrap().len(), 1);
+
+        let g = get_gist(&conn, id).unwrap().unwrap();
+        store_embedding(&conn, id, "m", &[1.0], &content_hash(&embed_text(&g))).unwrap();
+        assert!(stale_gists(&conn, "m").unwrap().is_empty());
+        assert_eq!(stale_gists(&conn, "other").unwrap().len(), 1);
+
+        update_gist(&conn, id, "two", "t").unwrap();
+        assert_eq!(stale_gists(&conn, "m").unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,337 @@
+//! `gist sync`: merge libraries on several machines through a shared folder.
+//!
+//! Every insert, edit, trash and purge is appended to `sync_log` by database
+//! triggers, stamped with the gist's UUID, this device's ID and a Lamport
+//! timestamp. Each device appends its own changes to `<dir>/<device>.jsonl`
+//! and merges everyone else's, so the folder can be synced by any file sync
+//! tool or be a git repository: no file is ever written by two devices.
+//!
+//! A change made on top of the version we have is applied. When both sides
+//! changed a gist, the later version (by Lamport time, then device ID) wins
+//! everywhere, and the device whose edit lost keeps it as a conflict copy.
+//! An edit always beats a concurrent delete.
+
+use rusqlite::Connection;
+use std::{
+    cmp::Ordering,
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::Command,
+};
+use crate::db::{
+    has_sync_change, insert_gist_with_meta, local_changes, merge_sync_change, sync_device, sync_state_of,
+    GistSnapshot, SyncChange,
+};
+use crate::models::GistMeta;
+use crate::output::Failure;
+
+/// Tag added to conflict copies, so `tag:conflict` finds them.
+pub const CONFLICT_TAG: &str = "conflict";
+
+/// What a sync did.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Local changes written to this device's changelog
+    pub sent: usize,
+    /// Changes from other devices merged here
+    pub received: usize,
+    /// Other devices whose changelogs were read
+    pub devices: usize,
+    /// `(gist, copy)`: gists edited on both sides, and the copy keeping the local edit
+    pub conflicts: Vec<(i64, i64)>,
+    /// Whether the folder is a git repository that was pulled and pushed
+    pub git: bool,
+}
+
+/// How to merge one change from another device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Merge {
+    Apply,
+    /// Apply it, after saving our own concurrent edit as a conflict copy
+    ApplyKeepingCopy,
+    Skip,
+}
+
+/// `lamport@device`, ordered by Lamport time and then device ID.
+fn parse_version(version: &str) -> (i64, &str) {
+    match version.split_once('@') {
+        Some((lamport, device)) => (lamport.parse().unwrap_or(0), device),
+        None => (0, version),
+    }
+}
+
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    parse_version(a).cmp(&parse_version(b))
+}
+
+fn is_live(g: Option<&GistSnapshot>) -> bool {
+    g.is_some_and(|g| g.deleted_at.is_none())
+}
+
+/// Whether two states hold the same snippet, ignoring timestamps.
+fn same_state(a: Option<&GistSnapshot>, b: Option<&GistSnapshot>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            (&a.content, &a.tags, &a.title, &a.description, &a.language, &a.source_path, &a.source_url, a.deleted_at.is_some())
+                == (&b.content, &b.tags, &b.title, &b.description, &b.language, &b.source_path, &b.source_url, b.deleted_at.is_some())
+        }
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Decide how to merge `change` given our `version` of the gist and its
+/// row (None once purged), or no `version` if we have never seen it.
+fn decide(local: Option<(&str, Option<&GistSnapshot>)>, change: &SyncChange, device: &str) -> Merge {
+    let Some((version, ours)) = local else { return Merge::Apply };
+    if change.base.as_deref() == Some(version) {
+        return Merge::Apply;
+    }
+    // Both sides changed it
+    let newer = compare_versions(&change.version(), version) == Ordering::Greater;
+    let theirs = change.gist.as_ref();
+    if same_state(ours, theirs) {
+        return if newer { Merge::Apply } else { Merge::Skip };
+    }
+    match (is_live(ours), is_live(theirs)) {
+        (true, false) => Merge::Skip,
+        (false, true) => Merge::Apply,
+        _ if !newer => Merge::Skip,
+        // Only the device that made the losing edit keeps a copy, so there is one
+        (true, true) if parse_version(version).1 == device => Merge::ApplyKeepingCopy,
+        _ => Merge::Apply,
+    }
+}
+
+/// Save our version of a gist that lost a conflict as a new gist.
+fn save_conflict_copy(c: &Connection, ours: &GistSnapshot) -> Result<i64, Box<dyn Error>> {
+    let title = ours
+        .title
+        .clone()
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or_else(|| ours.content.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or("").to_string());
+    let tags = match ours.tags.as_deref().filter(|t| !t.trim().is_empty()) {
+        Some(tags) => format!("{}, {}", tags, CONFLICT_TAG),
+        None => CONFLICT_TAG.to_string(),
+    };
+    let meta = GistMeta {
+        title: Some(format!("{} (conflict copy)", title)),
+        description: ours.description.clone(),
+        language: ours.language.clone(),
+        source_path: ours.source_path.clone(),
+        source_url: ours.source_url.clone(),
+    };
+    Ok(insert_gist_with_meta(c, &ours.content, &tags, &meta)?)
+}
+
+/// Read a changelog file; a line that isn't a change is an error naming it.
+fn read_changelog(path: &Path) -> Result<Vec<SyncChange>, Box<dyn Error>> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut changes = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let change = serde_json::from_str(&line)
+            .map_err(|e| Failure::invalid(format!("{}:{}: not a sync change: {}", path.display(), i + 1, e)))?;
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+/// Merge changes from other devices into the library, oldest first.
+fn merge_changes(c: &Connection, mut changes: Vec<SyncChange>, report: &mut SyncReport) -> Result<(), Box<dyn Error>> {
+    // Lamport order respects causality, so a change arrives after the ones it was made on
+    changes.sort_by(|a, b| (a.lamport, &a.device).cmp(&(b.lamport, &b.device)));
+    let device = sync_device(c)?;
+    let tx = c.unchecked_transaction()?;
+    for change in &changes {
+        if has_sync_change(&tx, change)? {
+            continue;
+        }
+        let local = sync_state_of(&tx, &change.uuid)?;
+        let ours = local.as_ref().and_then(|(_, row)| row.as_ref());
+        let merge = decide(
+            local.as_ref().map(|(version, _)| (version.as_str(), ours.map(|(_, g)| g))),
+            change,
+            &device,
+        );
+        if let (Merge::ApplyKeepingCopy, Some((id, ours))) = (merge, ours) {
+            let copy = save_conflict_copy(&tx, ours)?;
+            report.conflicts.push((*id, copy));
+        }
+        merge_sync_change(&tx, change, merge != Merge::Skip)?;
+        report.received += 1;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Run git in `dir`, failing with its message.
+pub fn git(dir: &Path, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| Failure::failed(format!("Could not run git: {}", e)))?;
+    if !output.status.success() {
+        return Err(Failure::failed(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Pull `dir` from its remote, unless the remote has no branch yet.
+pub fn git_pull(dir: &Path) -> Result<(), Box<dyn Error>> {
+    if !git(dir, &["ls-remote", "--heads"])?.is_empty() {
+        git(dir, &["pull", "--rebase", "--quiet"])?;
+    }
+    Ok(())
+}
+
+/// Only a folder that is itself a repository's root is committed to, never
+/// a folder somewhere inside an unrelated checkout.
+fn is_git_repo(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Exchange changes with the other devices using `dir`: pull if it is a git
+/// repository with a remote, merge their changelogs, append ours, then
+/// commit and push.
+pub fn sync_dir(c: &Connection, dir: &Path) -> Result<SyncReport, Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    let mut report = SyncReport { git: is_git_repo(dir), ..Default::default() };
+    let has_remote = report.git && !git(dir, &["remote"])?.is_empty();
+    if has_remote {
+        git_pull(dir)?;
+    }
+
+    let device = sync_device(c)?;
+    let own_file = format!("{}.jsonl", device);
+    let mut incoming = Vec::new();
+    let mut entries: Vec<_> = fs::read_dir(dir)?.flatten().map(|e| e.path()).collect();
+    entries.sort();
+    for path in entries {
+        let is_changelog = path.extension().is_some_and(|e| e == "jsonl");
+        if !is_changelog || path.file_name().is_some_and(|n| n == own_file.as_str()) {
+            continue;
+        }
+        incoming.extend(read_changelog(&path)?);
+        report.devices += 1;
+    }
+    merge_changes(c, incoming, &mut report)?;
+
+    // Our changelog only ever grows, so whatever is past its end is new
+    let own_path = dir.join(&own_file);
+    let written = if own_path.exists() { read_changelog(&own_path)?.len() } else { 0 };
+    let mut out = fs::OpenOptions::new().create(true).append(true).open(&own_path)?;
+    for change in local_changes(c)?.iter().skip(written) {
+        serde_json::to_writer(&mut out, change)?;
+        out.write_all(b"\n")?;
+        report.sent += 1;
+    }
+    out.flush()?;
+
+    if report.git && report.sent > 0 {
+        git(dir, &["add", &own_file])?;
+        git(dir, &["commit", "--quiet", "-m", &format!("gist sync from {}", device)])?;
+        if has_remote {
+            git(dir, &["push", "--quiet"])?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{delete_gist, get_gist, init_schema, insert_gist, list_gists, update_gist};
+    use tempfile::TempDir;
+
+    fn library() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    fn change(lamport: i64, device: &str, base: Option<&str>, content: Option<&str>) -> SyncChange {
+        SyncChange {
+            uuid: "u".into(),
+            lamport,
+            device: device.into(),
+            base: base.map(str::to_string),
+            op: if content.is_some() { "put" } else { "purge" }.into(),
+            gist: content.map(|c| GistSnapshot { content: c.into(), ..Default::default() }),
+        }
+    }
+
+    #[test]
+    fn decides_fast_forwards_conflicts_and_deletes() {
+        let ours = GistSnapshot { content: "mine".into(), ..Default::default() };
+        let trashed = GistSnapshot { deleted_at: Some("now".into()), ..ours.clone() };
+        let local = Some(("5@a", Some(&ours)));
+
+        assert_eq!(decide(None, &change(1, "b", None, Some("x")), "a"), Merge::Apply);
+        assert_eq!(decide(local, &change(6, "b", Some("5@a"), Some("x")), "a"), Merge::Apply);
+        // Concurrent edits: the later one wins, and only its loser keeps a copy
+        assert_eq!(decide(local, &change(6, "b", Some("4@a"), Some("x")), "a"), Merge::ApplyKeepingCopy);
+        assert_eq!(decide(local, &change(6, "b", Some("4@a"), Some("x")), "c"), Merge::Apply);
+        assert_eq!(decide(local, &change(5, "0", Some("4@a"), Some("x")), "a"), Merge::Skip);
+        assert_eq!(decide(local, &change(6, "b", Some("4@a"), Some("mine")), "a"), Merge::Apply);
+        // Edits beat concurrent deletes either way round
+        assert_eq!(decide(local, &change(9, "b", Some("4@a"), None), "a"), Merge::Skip);
+        assert_eq!(decide(Some(("5@a", Some(&trashed))), &change(2, "b", Some("1@b"), Some("x")), "a"), Merge::Apply);
+        assert_eq!(decide(Some(("5@a", None)), &change(2, "b", Some("1@b"), Some("x")), "a"), Merge::Apply);
+    }
+
+    #[test]
+    fn two_libraries_converge_with_a_conflict_copy() {
+        let dir = TempDir::new().unwrap();
+        let (a, b) = (library(), library());
+        let shared = insert_gist(&a, "echo one", "sh").unwrap();
+        let gone = insert_gist(&a, "old", "tmp").unwrap();
+        sync_dir(&a, dir.path()).unwrap();
+        let report = sync_dir(&b, dir.path()).unwrap();
+        assert_eq!((report.received, report.devices, report.sent), (2, 1, 0));
+        let contents = |c: &Connection| -> Vec<String> {
+            let mut v: Vec<String> = list_gists(c, usize::MAX, "id").unwrap().into_iter().map(|g| g.content).collect();
+            v.sort();
+            v
+        };
+        assert_eq!(contents(&b), vec!["echo one", "old"]);
+        let shared_b = list_gists(&b, usize::MAX, "id").unwrap().into_iter().find(|g| g.content == "echo one").unwrap().id;
+
+        // Both edit the same gist; `a` trashes the other one
+        update_gist(&a, shared, "echo from a", "sh").unwrap();
+        delete_gist(&a, gone).unwrap();
+        update_gist(&b, shared_b, "echo from b", "sh").unwrap();
+        update_gist(&b, shared_b, "echo from b, again", "sh").unwrap();
+
+        sync_dir(&a, dir.path()).unwrap();
+        let report = sync_dir(&b, dir.path()).unwrap();
+        assert!(report.conflicts.is_empty());
+        let report = sync_dir(&a, dir.path()).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        let (kept, copy) = report.conflicts[0];
+        sync_dir(&b, dir.path()).unwrap();
+
+        // `b` made the later edit, so it wins on both; `a`'s edit survives as a copy
+        assert_eq!(get_gist(&a, kept).unwrap().unwrap().content, "echo from b, again");
+        let copy = get_gist(&a, copy).unwrap().unwrap();
+        assert_eq!((copy.content.as_str(), copy.tags.as_str()), ("echo from a", "sh, conflict"));
+        assert_eq!(copy.title.as_deref(), Some("echo from a (conflict copy)"));
+        assert_eq!(contents(&a), vec!["echo from a", "echo from b, again"]);
+        assert_eq!(contents(&a), contents(&b));
+
+        // Nothing new: syncing again changes nothing
+        let report = sync_dir(&b, dir.path()).unwrap();
+        assert_eq!((report.received, report.sent), (0, 0));
+    }
+}
@@ -0,0 +1,152 @@
+//! Boolean tag queries, e.g. `rust AND (async OR tokio) AND NOT old`.
+//!
+//! Tags match exactly (after normalization), so `rs` never matches `rust`.
+//! Adjacent terms without an operator are AND-ed, and a comma behaves like AND
+//! so the old `--tags-only "rust, cli"` form keeps working.
+
+use crate::ai::normalize_tag;
+use crate::expr::{self, Expr, Names, Token};
+
+/// Parsed tag expression.
+pub type TagQuery = Expr<String>;
+
+const NAMES: Names = Names { query: "tag query", term: "a tag" };
+
+fn tag_token(tag: &str) -> Result<Token<String>, String> {
+    match normalize_tag(tag) {
+        tag if tag.is_empty() => Err("Empty tag in tag query".to_string()),
+        tag => Ok(Token::Term(tag)),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token<String>>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::And);
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut tag = String::new();
+                let mut closed = false;
+                for ch in chars.by_ref() {
+                    if ch == '"' {
+                        closed = true;
+                        break;
+                    }
+                    tag.push(ch);
+                }
+                if !closed {
+                    return Err("Unterminated quote in tag query".to_string());
+                }
+                tokens.push(tag_token(&tag)?);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if ch.is_whitespace() || matches!(ch, ',' | '(' | ')' | '"') {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" | "and" => Token::And,
+                    "OR" | "or" => Token::Or,
+                    "NOT" | "not" => Token::Not,
+                    _ => tag_token(&word)?,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+impl TagQuery {
+    /// Parse a tag expression.
+    pub fn parse(input: &str) -> Result<TagQuery, String> {
+        expr::parse(tokenize(input)?, &NAMES)
+    }
+
+    /// Compile to a SQL condition over the `gists` table, appending bound values to `params`.
+    pub fn to_sql(&self, params: &mut Vec<String>) -> String {
+        match self {
+            TagQuery::Term(name) => {
+                params.push(name.clone());
+                format!(
+                    "EXISTS (SELECT 1 FROM gist_tags gt JOIN tags t ON t.id = gt.tag_id \
+                     WHERE gt.gist_id = gists.id AND t.name = ?{})",
+                    params.len()
+                )
+            }
+            TagQuery::Not(inner) => format!("NOT ({})", inner.to_sql(params)),
+            TagQuery::And(a, b) => format!("({} AND {})", a.to_sql(params), b.to_sql(params)),
+            TagQuery::Or(a, b) => format!("({} OR {})", a.to_sql(params), b.to_sql(params)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(s: &str) -> Box<TagQuery> {
+        Box::new(TagQuery::Term(s.to_string()))
+    }
+
+    #[test]
+    fn parses_precedence() {
+        let q = TagQuery::parse("rust AND async OR go").unwrap();
+        assert_eq!(
+            q,
+            TagQuery::Or(Box::new(TagQuery::And(tag("rust"), tag("async"))), tag("go"))
+        );
+    }
+
+    #[test]
+    fn implicit_and_comma_and_not() {
+        let q = TagQuery::parse("Rust, cli NOT old").unwrap();
+        assert_eq!(
+            q,
+            TagQuery::And(
+                Box::new(TagQuery::And(tag("rust"), tag("cli"))),
+                Box::new(TagQuery::Not(tag("old")))
+            )
+        );
+    }
+
+    #[test]
+    fn quoted_and_grouped() {
+        let q = TagQuery::parse("\"Async  IO\" AND (a OR b)").unwrap();
+        assert_eq!(
+            q,
+            TagQuery::And(tag("async io"), Box::new(TagQuery::Or(tag("a"), tag("b"))))
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert!(TagQuery::parse("").is_err());
+        assert!(TagQuery::parse("rust AND").is_err());
+        assert!(TagQuery::parse("(rust").is_err());
+        assert!(TagQuery::parse("rust)").is_err());
+        assert!(TagQuery::parse("\"open").is_err());
+        assert!(TagQuery::parse("rust, \" \"").is_err());
+    }
+}
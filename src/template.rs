@@ -0,0 +1,184 @@
+//! Template snippets: `{{name}}` and `{{name:default}}` placeholders that
+//! `gist use` and the viewer's expand form fill in.
+//!
+//! Only `{{identifier}}` with an optional `:default` counts as a placeholder,
+//! so Handlebars/Jinja blocks such as `{{#each}}` or `{{ x | upper }}` are
+//! left alone. Write `\{{` for a literal `{{`.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+}
+
+/// One `{{...}}` occurrence: its byte span in the content and what it names.
+struct Occurrence {
+    start: usize,
+    end: usize,
+    placeholder: Placeholder,
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn parse_inner(inner: &str) -> Option<Placeholder> {
+    let (name, default) = match inner.split_once(':') {
+        Some((name, default)) => (name.trim(), Some(default.to_string())),
+        None => (inner.trim(), None),
+    };
+    is_name(name).then(|| Placeholder { name: name.to_string(), default })
+}
+
+/// Placeholders in order of appearance, plus escaped `\{{` positions.
+fn scan(content: &str) -> (Vec<Occurrence>, Vec<usize>) {
+    let mut found = Vec::new();
+    let mut escapes = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = content[pos..].find("{{") {
+        let start = pos + offset;
+        if content[..start].ends_with('\\') {
+            escapes.push(start - 1);
+            pos = start + 2;
+            continue;
+        }
+        let Some(close) = content[start + 2..].find("}}") else { break };
+        let end = start + 2 + close + 2;
+        let inner = &content[start + 2..end - 2];
+        match parse_inner(inner).filter(|_| !inner.contains('\n')) {
+            Some(placeholder) => {
+                found.push(Occurrence { start, end, placeholder });
+                pos = end;
+            }
+            None => pos = start + 2,
+        }
+    }
+    (found, escapes)
+}
+
+/// The distinct placeholders of a snippet, in order of first appearance. A
+/// default given on any occurrence applies to all of them.
+pub fn placeholders(content: &str) -> Vec<Placeholder> {
+    let mut out: Vec<Placeholder> = Vec::new();
+    for occ in scan(content).0 {
+        match out.iter_mut().find(|p| p.name == occ.placeholder.name) {
+            Some(existing) => {
+                if existing.default.is_none() {
+                    existing.default = occ.placeholder.default;
+                }
+            }
+            None => out.push(occ.placeholder),
+        }
+    }
+    out
+}
+
+/// Fill in every placeholder from `values`, falling back to defaults. Fails
+/// with the names that have neither.
+pub fn expand(content: &str, values: &HashMap<String, String>) -> Result<String, Vec<String>> {
+    let defaults: HashMap<String, Option<String>> =
+        placeholders(content).into_iter().map(|p| (p.name, p.default)).collect();
+    let (occurrences, escapes) = scan(content);
+
+    let mut missing = Vec::new();
+    let mut out = String::with_capacity(content.len());
+    let mut pos = 0;
+    let mut escapes = escapes.into_iter().peekable();
+    for occ in occurrences {
+        while let Some(&e) = escapes.peek().filter(|&&e| e < occ.start) {
+            out.push_str(&content[pos..e]);
+            pos = e + 1;
+            escapes.next();
+        }
+        out.push_str(&content[pos..occ.start]);
+        let name = &occ.placeholder.name;
+        match values.get(name).or(defaults[name].as_ref()) {
+            Some(value) => out.push_str(value),
+            None => {
+                if !missing.contains(name) {
+                    missing.push(name.clone());
+                }
+            }
+        }
+        pos = occ.end;
+    }
+    for e in escapes {
+        out.push_str(&content[pos..e]);
+        pos = e + 1;
+    }
+    out.push_str(&content[pos..]);
+
+    if missing.is_empty() {
+        Ok(out)
+    } else {
+        Err(missing)
+    }
+}
+
+/// Parse a `--var key=value` argument.
+pub fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((k, v)) if is_name(k.trim()) => Ok((k.trim().to_string(), v.to_string())),
+        _ => Err(format!("Invalid variable '{}'. Use NAME=VALUE", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn finds_placeholders_with_defaults() {
+        let sql = "SELECT {{columns:*}} FROM {{ table }}\nWHERE id = {{id}} AND t = '{{table}}' {{#each}}";
+        assert_eq!(
+            placeholders(sql),
+            vec![
+                Placeholder { name: "columns".into(), default: Some("*".into()) },
+                Placeholder { name: "table".into(), default: None },
+                Placeholder { name: "id".into(), default: None },
+            ]
+        );
+        // A default on a later occurrence counts too; empty defaults are allowed
+        let p = placeholders("{{a}} {{a:x}} {{b:}}");
+        assert_eq!(p[0].default.as_deref(), Some("x"));
+        assert_eq!(p[1].default.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn expands_values_and_defaults() {
+        let sql = "SELECT {{columns:*}} FROM {{table}} WHERE t = '{{table}}'";
+        assert_eq!(
+            expand(sql, &vars(&[("table", "users")])).unwrap(),
+            "SELECT * FROM users WHERE t = 'users'"
+        );
+        assert_eq!(expand(sql, &vars(&[])).unwrap_err(), vec!["table".to_string()]);
+        // Defaults may contain colons
+        assert_eq!(expand("{{url:http://localhost:8080}}", &vars(&[])).unwrap(), "http://localhost:8080");
+    }
+
+    #[test]
+    fn leaves_escapes_and_other_braces_alone() {
+        let tpl = "\\{{literal}} {{name}} {{ x | upper }} {{#if}}";
+        assert_eq!(placeholders(tpl).len(), 1);
+        assert_eq!(
+            expand(tpl, &vars(&[("name", "n")])).unwrap(),
+            "{{literal}} n {{ x | upper }} {{#if}}"
+        );
+    }
+
+    #[test]
+    fn parses_var_arguments() {
+        assert_eq!(parse_var("table=users"), Ok(("table".into(), "users".into())));
+        assert_eq!(parse_var("q=a=b"), Ok(("q".into(), "a=b".into())));
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=x").is_err());
+    }
+}
@@ -0,0 +1,453 @@
+//! Export/import formats: the single JSON file, a JSONL stream, a Markdown
+//! vault with front-matter, and a directory of raw source files.
+
+use rusqlite::Connection;
+use serde_json::{json, Map, Value};
+use std::{
+    error::Error,
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use crate::crypto::Keyring;
+use crate::db::{export_gists, for_each_gist, import_gists, import_rows, ImportError, ImportReport, ImportStrategy};
+use crate::language;
+use crate::models::Gist;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON document (`gist export` default)
+    Json,
+    /// One JSON object per line
+    Jsonl,
+    /// A directory of `.md` files with YAML front-matter
+    MarkdownDir,
+    /// A directory of raw files named by language
+    Files,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(Format::Json),
+            "jsonl" | "ndjson" => Ok(Format::Jsonl),
+            "markdown-dir" | "markdown" | "md" => Ok(Format::MarkdownDir),
+            "files" => Ok(Format::Files),
+            other => Err(format!("Unknown format '{}'. Use: json, jsonl, markdown-dir, files", other)),
+        }
+    }
+}
+
+impl Format {
+    /// Guess an import format: directories by their contents, files by extension.
+    pub fn infer(path: &Path) -> Format {
+        if path.is_dir() {
+            let has_markdown = fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .flatten()
+                        .any(|e| e.path().extension().is_some_and(|ext| ext == "md"))
+                })
+                .unwrap_or(false);
+            return if has_markdown { Format::MarkdownDir } else { Format::Files };
+        }
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl") | Some("ndjson") => Format::Jsonl,
+            _ => Format::Json,
+        }
+    }
+}
+
+/// Write every live gist to `path` in `format`. Returns the number written.
+/// Encrypted snippets stay sealed, and import back as they are, unless a
+/// `keyring` is given to write them decrypted.
+pub fn export(
+    c: &Connection,
+    format: Format,
+    path: &Path,
+    collection: Option<i64>,
+    mut keyring: Option<&mut Keyring>,
+) -> Result<usize, Box<dyn Error>> {
+    match format {
+        Format::Json => export_gists(c, &path.to_path_buf(), collection, keyring),
+        Format::Jsonl => {
+            let mut out = BufWriter::new(fs::File::create(path)?);
+            let count = for_each_gist(c, collection, |g| {
+                let g = opened(&mut keyring, g)?;
+                serde_json::to_writer(&mut out, &g)?;
+                out.write_all(b"\n")?;
+                Ok(())
+            })?;
+            out.flush()?;
+            Ok(count)
+        }
+        Format::MarkdownDir => {
+            fs::create_dir_all(path)?;
+            for_each_gist(c, collection, |g| {
+                let g = opened(&mut keyring, g)?;
+                fs::write(path.join(format!("{}.md", file_stem(&g))), to_markdown(&g))?;
+                Ok(())
+            })
+        }
+        Format::Files => {
+            fs::create_dir_all(path)?;
+            for_each_gist(c, collection, |g| {
+                let g = opened(&mut keyring, g)?;
+                let ext = g.language.as_deref().and_then(language::file_extension).unwrap_or("txt");
+                fs::write(path.join(format!("{}.{}", file_stem(&g), ext)), &g.content)?;
+                Ok(())
+            })
+        }
+    }
+}
+
+/// `g` with its content decrypted when there is a keyring.
+fn opened(keyring: &mut Option<&mut Keyring>, mut g: Gist) -> Result<Gist, Box<dyn Error>> {
+    if let Some(keyring) = keyring {
+        g.content = keyring.decrypt(&g.content)?;
+    }
+    Ok(g)
+}
+
+/// Import `path` in `format` with the usual duplicate handling; see `import_rows`.
+pub fn import(
+    c: &Connection,
+    format: Format,
+    path: &Path,
+    strategy: ImportStrategy,
+    dry_run: bool,
+    keyring: Option<&mut Keyring>,
+) -> Result<ImportReport, Box<dyn Error>> {
+    match format {
+        Format::Json => import_gists(c, &path.to_path_buf(), strategy, dry_run, keyring),
+        Format::Jsonl => {
+            let reader = BufReader::new(fs::File::open(path)?);
+            let rows = reader
+                .lines()
+                .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+                .map(|line| {
+                    let line = line.map_err(|e| row_error("(unreadable)", e))?;
+                    serde_json::from_str(&line).map_err(|e| row_error(&line, format!("invalid JSON: {}", e)))
+                });
+            import_rows(c, rows, strategy, dry_run, keyring)
+        }
+        Format::MarkdownDir => {
+            let rows = dir_files(path)?
+                .into_iter()
+                .filter(|p| p.extension().is_some_and(|e| e == "md"))
+                .map(|p| {
+                    let text = fs::read_to_string(&p).map_err(|e| row_error(&display_name(&p), e))?;
+                    from_markdown(&text).map_err(|e| row_error(&display_name(&p), e))
+                })
+                .collect::<Vec<_>>();
+            import_rows(c, rows, strategy, dry_run, keyring)
+        }
+        Format::Files => {
+            let rows = dir_files(path)?
+                .into_iter()
+                .map(|p| {
+                    let content = fs::read_to_string(&p).map_err(|e| row_error(&display_name(&p), e))?;
+                    Ok(file_row(&p, content))
+                })
+                .collect::<Vec<_>>();
+            import_rows(c, rows, strategy, dry_run, keyring)
+        }
+    }
+}
+
+fn row_error(label: &str, message: impl ToString) -> ImportError {
+    ImportError {
+        row: 0,
+        label: label.chars().take(40).collect(),
+        message: message.to_string(),
+    }
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Regular, non-hidden files directly inside `dir`, sorted by name.
+fn dir_files(dir: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && !display_name(p).starts_with('.'))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// `<id>-<slug>`: unique per gist, and readable in a file browser.
+pub fn file_stem(g: &Gist) -> String {
+    let mut slug = String::new();
+    for ch in g.display_title().to_lowercase().chars() {
+        if ch.is_alphanumeric() {
+            slug.push(ch);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.chars().count() >= 40 {
+            break;
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        g.id.to_string()
+    } else {
+        format!("{}-{}", g.id, slug)
+    }
+}
+
+/// A raw file as an import row. Names written by `--format files` lose their
+/// `<id>-` prefix; other names are kept whole as the title.
+fn file_row(path: &Path, content: String) -> Value {
+    let name = display_name(path);
+    let title = match name.split_once('-') {
+        Some((id, rest)) if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) => rest.to_string(),
+        _ => name,
+    };
+    let lang = language::from_path(path);
+    let source = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    json!({
+        "content": content,
+        "tags": lang.clone().unwrap_or_default(),
+        "title": title,
+        "language": lang,
+        "source_path": source.to_string_lossy(),
+    })
+}
+
+/// Front-matter keys, in the order they are written.
+const FRONT_MATTER: &[&str] = &["title", "description", "tags", "language", "source_path", "source_url", "created_at", "updated_at"];
+
+/// A gist as Markdown: YAML front-matter, then the content in a code fence.
+/// Scalars are written as JSON strings and tags as a JSON list, both valid YAML.
+pub fn to_markdown(g: &Gist) -> String {
+    let tags: Vec<&str> = g.tags.split(',').map(str::trim).filter(|t| !t.is_empty()).collect();
+    let mut out = String::from("---\n");
+    for key in FRONT_MATTER {
+        let value = match *key {
+            "title" => g.title.as_ref().map(|v| json!(v)),
+            "description" => g.description.as_ref().map(|v| json!(v)),
+            "tags" => Some(json!(tags)),
+            "language" => g.language.as_ref().map(|v| json!(v)),
+            "source_path" => g.source_path.as_ref().map(|v| json!(v)),
+            "source_url" => g.source_url.as_ref().map(|v| json!(v)),
+            "created_at" => Some(json!(g.created_at)),
+            "updated_at" => g.updated_at.as_ref().map(|v| json!(v)),
+            _ => None,
+        };
+        if let Some(value) = value {
+            out.push_str(&format!("{}: {}\n", key, value));
+        }
+    }
+    out.push_str("---\n\n");
+
+    // A fence longer than any backtick run inside the content
+    let longest = g
+        .content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    out.push_str(&fence);
+    out.push_str(g.language.as_deref().unwrap_or(""));
+    out.push('\n');
+    out.push_str(&g.content);
+    if !g.content.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str(&fence);
+    out.push('\n');
+    out
+}
+
+/// Parse a Markdown note back into an import row. Front-matter is optional;
+/// the first code fence is the content, or the whole body if there is none.
+pub fn from_markdown(text: &str) -> Result<Value, String> {
+    let text = text.replace("\r\n", "\n");
+    let mut row = Map::new();
+    let body = match text.strip_prefix("---\n") {
+        Some(rest) => {
+            let (front, body) = rest
+                .split_once("\n---\n")
+                .or_else(|| rest.strip_suffix("\n---").map(|f| (f, "")))
+                .ok_or("front-matter is not closed with ---")?;
+            for line in front.lines().filter(|l| !l.trim().is_empty() && !l.trim_start().starts_with('#')) {
+                let (key, value) = line.split_once(':').ok_or_else(|| format!("bad front-matter line: {}", line))?;
+                let key = key.trim();
+                let value = yaml_value(value.trim());
+                let value = match (key, value) {
+                    ("tags", Value::Array(items)) => {
+                        Value::String(items.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "))
+                    }
+                    (_, value) => value,
+                };
+                row.insert(key.to_string(), value);
+            }
+            body
+        }
+        None => text.as_str(),
+    };
+
+    let content = fenced_block(body).unwrap_or_else(|| body.trim_matches('\n').to_string() + "\n");
+    if row.get("language").is_none_or(Value::is_null) {
+        if let Some(lang) = fence_language(body) {
+            row.insert("language".to_string(), json!(language::normalize(&lang)));
+        }
+    }
+    row.insert("content".to_string(), Value::String(content));
+    Ok(Value::Object(row))
+}
+
+/// A front-matter value: JSON when it parses, a bare `[a, b]` list, or plain text.
+fn yaml_value(raw: &str) -> Value {
+    if raw.is_empty() || raw == "~" || raw == "null" {
+        return Value::Null;
+    }
+    if let Ok(v) = serde_json::from_str::<Value>(raw) {
+        return v;
+    }
+    if let Some(inner) = raw.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        let items: Vec<Value> = inner
+            .split(',')
+            .map(|t| t.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|t| !t.is_empty())
+            .map(Value::String)
+            .collect();
+        return Value::Array(items);
+    }
+    Value::String(raw.trim_matches('\'').to_string())
+}
+
+fn fence_of(line: &str) -> Option<&str> {
+    let ticks = line.len() - line.trim_start_matches('`').len();
+    (ticks >= 3).then(|| &line[..ticks])
+}
+
+fn fence_language(body: &str) -> Option<String> {
+    let line = body.lines().find(|l| fence_of(l).is_some())?;
+    let lang = line.trim_start_matches('`').trim();
+    (!lang.is_empty()).then(|| lang.to_string())
+}
+
+/// Text inside the first code fence, up to the last matching closing fence.
+fn fenced_block(body: &str) -> Option<String> {
+    let lines: Vec<&str> = body.lines().collect();
+    let start = lines.iter().position(|l| fence_of(l).is_some())?;
+    let fence = fence_of(lines[start])?;
+    let end = lines.iter().rposition(|l| l.trim_end() == fence).filter(|&e| e > start)?;
+    let mut content = lines[start + 1..end].join("\n");
+    content.push('\n');
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{init_schema, insert_gist_with_meta, list_gists};
+    use crate::models::GistMeta;
+
+    fn setup_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        init_schema(&conn).unwrap();
+        conn
+    }
+
+    fn seed(conn: &Connection) {
+        let meta = GistMeta {
+            title: Some("Retry: with backoff!".into()),
+            description: Some("Waits \"longer\" each time".into()),
+            language: Some("python".into()),
+            ..Default::default()
+        };
+        insert_gist_with_meta(conn, "def retry():\n    ```not a fence```\n", "net, python", &meta).unwrap();
+        insert_gist_with_meta(conn, "echo hi", "bash", &GistMeta::default()).unwrap();
+    }
+
+    #[test]
+    fn markdown_roundtrip() {
+        let conn = setup_db();
+        seed(&conn);
+        let g = &list_gists(&conn, 10, "id").unwrap()[1];
+        let md = to_markdown(g);
+        assert!(md.starts_with("---\ntitle: \"Retry: with backoff!\"\n"));
+        assert!(md.contains("tags: [\"net\",\"python\"]\n"));
+        assert!(md.contains("\n````python\n"));
+
+        let row = from_markdown(&md).unwrap();
+        assert_eq!(row["content"], g.content.as_str());
+        assert_eq!(row["tags"], "net, python");
+        assert_eq!(row["description"], "Waits \"longer\" each time");
+        assert_eq!(row["created_at"], g.created_at.as_str());
+    }
+
+    #[test]
+    fn hand_written_markdown() {
+        let row = from_markdown("---\ntitle: Loop\ntags: [bash, cli]\n---\nSome notes\n\n```sh\nfor f in *; do echo $f; done\n```\n").unwrap();
+        assert_eq!(row["title"], "Loop");
+        assert_eq!(row["tags"], "bash, cli");
+        assert_eq!(row["language"], "bash");
+        assert_eq!(row["content"], "for f in *; do echo $f; done\n");
+
+        let row = from_markdown("just text\n").unwrap();
+        assert_eq!(row["content"], "just text\n");
+        assert!(from_markdown("---\ntitle: x\n").is_err());
+    }
+
+    #[test]
+    fn every_format_reimports_without_duplicates() {
+        let dir = tempfile::TempDir::new().unwrap();
+        for (format, name) in [
+            (Format::Json, "all.json"),
+            (Format::Jsonl, "all.jsonl"),
+            (Format::MarkdownDir, "vault"),
+            (Format::Files, "files"),
+        ] {
+            let path = dir.path().join(name);
+            let src = setup_db();
+            seed(&src);
+            assert_eq!(export(&src, format, &path, None, None).unwrap(), 2, "{:?}", format);
+            assert_eq!(Format::infer(&path), format);
+
+            let dst = setup_db();
+            let report = import(&dst, format, &path, ImportStrategy::Skip, false, None).unwrap();
+            assert_eq!((report.added, report.errors.len()), (2, 0), "{:?}", format);
+            let report = import(&src, format, &path, ImportStrategy::Skip, false, None).unwrap();
+            assert_eq!((report.added, report.skipped), (0, 2), "{:?}", format);
+
+            let imported = list_gists(&dst, 10, "id").unwrap();
+            assert!(imported.iter().any(|g| g.language.as_deref() == Some("python")), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn files_are_named_by_language() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let conn = setup_db();
+        seed(&conn);
+        export(&conn, Format::Files, dir.path(), None, None).unwrap();
+        let mut names: Vec<String> = dir_files(dir.path()).unwrap().iter().map(|p| display_name(p)).collect();
+        names.sort();
+        assert_eq!(names, vec!["1-retry-with-backoff.py", "2-echo-hi.txt"]);
+
+        let row = file_row(&dir.path().join("1-retry-with-backoff.py"), "x".into());
+        assert_eq!(row["title"], "retry-with-backoff.py");
+        assert_eq!(row["language"], "python");
+    }
+
+    #[test]
+    fn jsonl_reports_bad_lines() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("rows.jsonl");
+        fs::write(&path, "{\"content\":\"a\"}\n\nnot json\n{\"content\":\"b\"}\n").unwrap();
+        let conn = setup_db();
+        let report = import(&conn, Format::Jsonl, &path, ImportStrategy::Skip, false, None).unwrap();
+        assert_eq!(report.added, 2);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!((report.errors[0].row, report.errors[0].label.as_str()), (2, "not json"));
+    }
+}
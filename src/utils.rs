@@ -1,7 +1,9 @@
-use std::{error::Error, fs, io::{self, Read, Write}, process::Command};
+use std::{error::Error, fs, io::{self, IsTerminal, Read, Write}, process::Command};
 use tempfile::NamedTempFile;
 use colored::*;
 use crate::config::get_editor;
+use crate::output::Failure;
+use sha2::{Digest, Sha256};
 
 pub fn validate_content(content: &str) -> Result<(), String> {
     if content.trim().is_empty() {
@@ -46,6 +48,11 @@ pub fn edit_content(initial: Option<&str>) -> Result<String, Box<dyn Error>> {
     Ok(buf)
 }
 
+/// Hex SHA-256 of a string, used to notice when text has changed.
+pub fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
 pub fn prompt_confirm(message: &str) -> bool {
     print!("{} {} [y/N]: ", "Confirm:".yellow().bold(), message);
     io::stdout().flush().ok();
@@ -58,6 +65,48 @@ pub fn prompt_confirm(message: &str) -> bool {
     input.trim().to_lowercase() == "y"
 }
 
+/// Ask for a value on stdin, offering `default`. Returns None (use the
+/// default) for an empty answer or when stdin is closed.
+pub fn prompt_input(label: &str, default: Option<&str>) -> Option<String> {
+    match default {
+        Some(d) => print!("{} [{}]: ", label.bold(), d),
+        None => print!("{}: ", label.bold()),
+    }
+    io::stdout().flush().ok();
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+        return None;
+    }
+    let input = input.trim_end_matches(['\n', '\r']);
+    if input.is_empty() && default.is_some() {
+        None
+    } else {
+        Some(input.to_string())
+    }
+}
+
+/// The passphrase for encrypted snippets: GIST_PASSPHRASE if set, otherwise
+/// asked for without echo. `confirm` asks twice, for a passphrase being chosen.
+pub fn prompt_passphrase(confirm: bool) -> Result<String, Box<dyn Error>> {
+    if let Ok(p) = std::env::var("GIST_PASSPHRASE") {
+        if !p.is_empty() {
+            return Ok(p);
+        }
+    }
+    if !io::stdin().is_terminal() {
+        return Err(Failure::invalid("A passphrase is needed: set GIST_PASSPHRASE or run in a terminal").into());
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(Failure::invalid("The passphrase cannot be empty").into());
+    }
+    if confirm && rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(Failure::invalid("Passphrases do not match").into());
+    }
+    Ok(passphrase)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -71,4 +120,13 @@ mod tests {
         let large = "a".repeat(1_000_001);
         assert!(validate_content(&large).is_err());
     }
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(
+            content_hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(content_hash("abc"), content_hash("abd"));
+    }
 }
@@ -1,7 +1,15 @@
-use crate::models::{Gist, Theme};
+use crate::models::{Gist, Revision, Theme};
 use crate::config::Config;
-use crate::db::{delete_gist, get_gist, insert_gist, update_gist, list_gists};
+use crate::secrets::{self, SecretPolicy};
+use crate::crypto;
+use crate::db::{
+    carry_run_approval, collection_memberships, delete_gist, get_gist, insert_gist, list_collections, list_gists, list_revisions, list_run_info,
+    list_usage, record_usage, restore_revision, set_pinned, update_gist, Collection, RunInfo, Usage, UsageKind,
+};
+use crate::diff::{line_kind, unified_diff, LineKind};
+use crate::query::Query;
 use crate::ai::get_tags;
+use crate::template::{self, Placeholder};
 use chrono::Local;
 use clipboard::{ClipboardContext, ClipboardProvider};
 use colored::Colorize;
@@ -14,12 +22,13 @@ use ratatui::{
     backend::{Backend, CrosstermBackend},
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Text},
+    text::{Line, Span, Text},
     widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 use rusqlite::Connection;
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
     io,
     process::Command,
@@ -43,13 +52,16 @@ enum InputMode {
     Searching,
     Confirming(ConfirmAction),
     TagEditing,
+    History,
     Help,
+    Expanding,
 }
 
 // ----- Confirmation actions -----
 #[derive(Debug, PartialEq, Clone)]
 enum ConfirmAction {
     Delete(i64),
+    Restore(i64, i64),
     Quit,
 }
 
@@ -67,10 +79,26 @@ struct AppState {
     help_scroll: u16,
     config: Config,
     focused_panel: Panel,
+    revisions: Vec<Revision>,
+    revision_state: ListState,
+    template_fields: Vec<(Placeholder, String)>,
+    template_focus: usize,
+    /// Runnable flag and last run, by gist id
+    runs: HashMap<i64, RunInfo>,
+    /// Pin state and view/copy counts, by gist id
+    usage: HashMap<i64, Usage>,
+    collections: Vec<Collection>,
+    /// Gists directly in each collection, by collection id
+    collection_members: HashMap<i64, HashSet<i64>>,
+    /// Row 0 is "All snippets", then `collections` in order
+    collection_state: ListState,
+    /// Gists in the selected collection and below it; None shows everything
+    collection_filter: Option<HashSet<i64>>,
 }
 
 #[derive(Debug, PartialEq)]
 enum Panel {
+    Collections,
     List,
     Content,
 }
@@ -90,6 +118,16 @@ impl AppState {
             help_scroll: 0,
             config,
             focused_panel: Panel::List,
+            revisions: Vec::new(),
+            revision_state: ListState::default(),
+            template_fields: Vec::new(),
+            template_focus: 0,
+            runs: HashMap::new(),
+            usage: HashMap::new(),
+            collections: Vec::new(),
+            collection_members: HashMap::new(),
+            collection_state: ListState::default().with_selected(Some(0)),
+            collection_filter: None,
         };
         if !s.filtered_gists.is_empty() {
             s.list_state.select(Some(0));
@@ -99,35 +137,83 @@ impl AppState {
     
     fn reload(&mut self, gists: Vec<Gist>) {
         self.all_gists = gists;
-        self.filtered_gists = self.all_gists.clone();
-        self.selected = 0;
-        if !self.filtered_gists.is_empty() {
-            self.list_state.select(Some(0));
-        } else {
-            self.list_state.select(None);
+        self.search_query.clear();
+        self.do_search();
+    }
+
+    /// Replace the collection tree, keeping the selected collection if it still exists.
+    fn set_collections(&mut self, collections: Vec<Collection>, memberships: Vec<(i64, i64)>) {
+        let selected = self.selected_collection().map(|c| c.id);
+        self.collections = collections;
+        self.collection_members.clear();
+        for (collection_id, gist_id) in memberships {
+            self.collection_members.entry(collection_id).or_default().insert(gist_id);
         }
+        let row = selected
+            .and_then(|id| self.collections.iter().position(|c| c.id == id))
+            .map_or(0, |i| i + 1);
+        self.select_collection(row);
+    }
+
+    fn selected_collection(&self) -> Option<&Collection> {
+        let row = self.collection_state.selected().unwrap_or(0);
+        row.checked_sub(1).and_then(|i| self.collections.get(i))
+    }
+
+    /// Select a row of the collection tree and filter the list by it.
+    fn select_collection(&mut self, row: usize) {
+        self.collection_state.select(Some(row.min(self.collections.len())));
+        self.collection_filter = self.selected_collection().map(|selected| {
+            let prefix = format!("{}/", selected.path);
+            self.collections
+                .iter()
+                .filter(|c| c.id == selected.id || c.path.starts_with(&prefix))
+                .filter_map(|c| self.collection_members.get(&c.id))
+                .flatten()
+                .copied()
+                .collect()
+        });
+        self.do_search();
+    }
+
+    fn move_collection(&mut self, forward: bool) {
+        let row = self.collection_state.selected().unwrap_or(0);
+        let row = if forward {
+            if row >= self.collections.len() { 0 } else { row + 1 }
+        } else if row == 0 {
+            self.collections.len()
+        } else {
+            row - 1
+        };
+        self.select_collection(row);
     }
     
     fn reset_filter(&mut self) {
-        self.filtered_gists = self.all_gists.clone();
-        self.selected = 0;
-        if !self.filtered_gists.is_empty() {
-            self.list_state.select(Some(0));
-        } else {
-            self.list_state.select(None);
-        }
         self.search_query.clear();
+        self.do_search();
     }
     
     fn do_search(&mut self) {
-        let q = self.search_query.to_lowercase();
+        let query = if self.search_query.trim().is_empty() {
+            None
+        } else {
+            match Query::parse(&self.search_query) {
+                Ok(q) => Some(q),
+                Err(e) => {
+                    self.set_status(format!("Invalid search: {}", e));
+                    return;
+                }
+            }
+        };
+        let collection = self.collection_filter.as_ref();
+        let usage = &self.usage;
         self.filtered_gists = self
             .all_gists
             .iter()
+            .filter(|g| collection.is_none_or(|ids| ids.contains(&g.id)))
             .filter(|g| {
-                g.content.to_lowercase().contains(&q) || 
-                g.tags.to_lowercase().contains(&q) ||
-                g.id.to_string().contains(&q)
+                let pinned = usage.get(&g.id).is_some_and(|u| u.pinned);
+                query.as_ref().is_none_or(|q| q.matches(g, pinned))
             })
             .cloned()
             .collect();
@@ -140,6 +226,48 @@ impl AppState {
         }
     }
     
+    /// Suspected secrets in `content`, described, unless the policy is off
+    /// or the content is encrypted.
+    fn secret_findings(&self, content: &str) -> Option<String> {
+        if self.config.secret_policy() == SecretPolicy::Off || crypto::is_encrypted(content) {
+            return None;
+        }
+        let found = secrets::scan(content);
+        (!found.is_empty()).then(|| secrets::describe(&found))
+    }
+
+    /// Status after saving `content`: `done`, plus any secrets warning.
+    fn set_saved_status(&mut self, done: String, content: &str) {
+        match self.secret_findings(content) {
+            Some(found) => self.set_status(format!("{}; warning, possible secrets: {}", done, found)),
+            None => self.set_status(done),
+        }
+    }
+
+    /// Whether the secrets policy refuses to save `content`; says why if so.
+    fn secrets_block(&mut self, content: &str) -> bool {
+        if self.config.secret_policy() != SecretPolicy::Block {
+            return false;
+        }
+        match self.secret_findings(content) {
+            Some(found) => {
+                self.set_status(format!("Not saved: possible secrets ({})", found));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the selected gist is encrypted, which the viewer can't open
+    /// since it has no way to ask for the passphrase; says so if it is.
+    fn encrypted_block(&mut self) -> bool {
+        let Some(id) = self.current_gist().filter(|g| g.is_encrypted()).map(|g| g.id) else {
+            return false;
+        };
+        self.set_status(format!("Gist #{} is encrypted; open it with `gist view {}`", id, id));
+        true
+    }
+
     fn set_status(&mut self, msg: String) {
         self.status_message = Some((msg, Instant::now()));
     }
@@ -199,10 +327,116 @@ impl AppState {
         self.current_gist().map(|g| g.id)
     }
     
+    fn open_history(&mut self, revisions: Vec<Revision>) {
+        self.revisions = revisions;
+        self.revision_state.select(if self.revisions.is_empty() { None } else { Some(0) });
+        self.mode = InputMode::History;
+    }
+    
+    fn select_revision(&mut self, forward: bool) {
+        if self.revisions.is_empty() {
+            return;
+        }
+        let last = self.revisions.len() - 1;
+        let i = match (self.revision_state.selected(), forward) {
+            (Some(i), true) if i < last => i + 1,
+            (Some(i), false) if i > 0 => i - 1,
+            (Some(i), _) => i,
+            (None, _) => 0,
+        };
+        self.revision_state.select(Some(i));
+    }
+    
+    fn current_revision(&self) -> Option<&Revision> {
+        self.revision_state.selected().and_then(|i| self.revisions.get(i))
+    }
+    
+    /// Diff of the selected revision against the one before it.
+    fn revision_diff(&self) -> Text<'static> {
+        let Some(i) = self.revision_state.selected() else {
+            return Text::from("(no revisions)");
+        };
+        let rev = &self.revisions[i];
+        let Some(prev) = self.revisions.get(i + 1) else {
+            return Text::from(format!("Revision {} (original)\n\n{}", rev.rev, rev.content));
+        };
+        
+        let mut diff = unified_diff(
+            &prev.content,
+            &rev.content,
+            &format!("rev {}", prev.rev),
+            &format!("rev {}", rev.rev),
+        );
+        if prev.tags != rev.tags {
+            diff.push_str(&format!("Tags: {} -> {}\n", prev.tags, rev.tags));
+        }
+        if diff.is_empty() {
+            return Text::from("(no changes)");
+        }
+        
+        let lines: Vec<Line> = diff
+            .lines()
+            .map(|l| {
+                let style = match line_kind(l) {
+                    LineKind::Header => Style::default().add_modifier(Modifier::BOLD),
+                    LineKind::Hunk => Style::default().fg(Color::Cyan),
+                    LineKind::Added => Style::default().fg(Color::Green),
+                    LineKind::Removed => Style::default().fg(Color::Red),
+                    LineKind::Context => Style::default(),
+                };
+                Line::styled(l.to_string(), style)
+            })
+            .collect();
+        Text::from(lines)
+    }
+    
+    /// Open the expand form for the selected gist's placeholders.
+    fn open_expand(&mut self) -> bool {
+        let Some(gist) = self.current_gist() else {
+            return false;
+        };
+        let fields: Vec<_> = template::placeholders(&gist.content)
+            .into_iter()
+            .map(|p| (p, String::new()))
+            .collect();
+        if fields.is_empty() {
+            return false;
+        }
+        self.template_fields = fields;
+        self.template_focus = 0;
+        self.mode = InputMode::Expanding;
+        true
+    }
+
+    /// Values typed into the expand form; blank fields fall back to defaults.
+    fn template_values(&self) -> std::collections::HashMap<String, String> {
+        self.template_fields
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(p, value)| (p.name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// The selected gist expanded with the form's values, leaving
+    /// placeholders that have no value yet visible.
+    fn template_preview(&self) -> String {
+        let Some(gist) = self.current_gist() else {
+            return String::new();
+        };
+        let mut values = self.template_values();
+        for (p, _) in &self.template_fields {
+            if p.default.is_none() {
+                values.entry(p.name.clone()).or_insert_with(|| format!("{{{{{}}}}}", p.name));
+            }
+        }
+        template::expand(&gist.content, &values).unwrap_or_default()
+    }
+
     fn toggle_panel(&mut self) {
         self.focused_panel = match self.focused_panel {
+            Panel::Collections => Panel::List,
             Panel::List => Panel::Content,
-            Panel::Content => Panel::List,
+            Panel::Content => Panel::Collections,
         };
     }
 }
@@ -237,7 +471,10 @@ fn render_ui(f: &mut Frame, state: &mut AppState) {
             // Determine the text based on the cloned action
             let text = match action_clone {
                 ConfirmAction::Delete(id) => {
-                    format!("Are you sure you want to delete gist #{}?\n\nPress y to confirm or Esc to cancel.", id)
+                    format!("Move gist #{} to the trash?\n\nPress y to confirm or Esc to cancel.", id)
+                }
+                ConfirmAction::Restore(id, rev) => {
+                    format!("Restore gist #{} to revision {}?\n\nPress y to confirm or Esc to cancel.", id, rev)
                 }
                 ConfirmAction::Quit => {
                     if state.modified {
@@ -256,10 +493,64 @@ fn render_ui(f: &mut Frame, state: &mut AppState) {
                 
             f.render_widget(paragraph, inner);
         },
+        InputMode::Expanding => {
+            render_main(f, state);
+            render_expand(f, state);
+        },
         _ => render_main(f, state),
     }
 }
 
+fn render_expand(f: &mut Frame, state: &AppState) {
+    let area = centered_rect(70, 70, f.area());
+    f.render_widget(Clear, area);
+    let title = match state.current_gist() {
+        Some(g) => format!("Expand #{}", g.id),
+        None => "Expand".to_string(),
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow))
+        .style(Style::default().bg(Color::Black));
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let parts = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(state.template_fields.len() as u16 + 1), Constraint::Min(0)])
+        .split(inner);
+
+    let fields: Vec<Line> = state
+        .template_fields
+        .iter()
+        .enumerate()
+        .map(|(i, (p, value))| {
+            let focused = i == state.template_focus;
+            let label_style = if focused {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default().add_modifier(Modifier::BOLD)
+            };
+            let mut spans = vec![Span::styled(format!("{}: ", p.name), label_style)];
+            match (&p.default, value.is_empty()) {
+                (Some(default), true) => spans.push(Span::styled(default.clone(), Style::default().fg(Color::DarkGray))),
+                _ => spans.push(Span::raw(value.clone())),
+            }
+            if focused {
+                spans.push(Span::styled("_", Style::default().fg(Color::Yellow)));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    f.render_widget(Paragraph::new(Text::from(fields)), parts[0]);
+
+    let preview = Paragraph::new(state.template_preview())
+        .block(Block::default().borders(Borders::TOP).title("Preview (Enter copies, Esc cancels)"))
+        .wrap(Wrap { trim: false });
+    f.render_widget(preview, parts[1]);
+}
+
 fn render_main(f: &mut Frame, state: &mut AppState) {
     let size = f.area();
     
@@ -271,9 +562,96 @@ fn render_main(f: &mut Frame, state: &mut AppState) {
     
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .constraints([Constraint::Percentage(20), Constraint::Percentage(25), Constraint::Percentage(55)])
         .split(vert[0]);
     
+    render_collections(f, state, chunks[0]);
+    let showing_history = state.mode == InputMode::History
+        || matches!(state.mode, InputMode::Confirming(ConfirmAction::Restore(..)));
+    if showing_history {
+        render_history(f, state, chunks[1], chunks[2]);
+    } else {
+        render_gists(f, state, chunks[1], chunks[2]);
+    }
+    
+    // Render status bar
+    let status = if let Some(msg) = state.get_status() {
+        msg
+    } else if state.mode == InputMode::Searching {
+        format!("/ {}", state.search_query)
+    } else if state.mode == InputMode::TagEditing {
+        format!("Edit Tags: {}", state.edit_buffer)
+    } else if showing_history {
+        "↑↓ j/k:Select Revision  Enter:Restore  Esc/h:Back".to_string()
+    } else if state.mode == InputMode::Expanding {
+        "Tab/↑↓:Next Field  Enter:Copy Result  Esc:Cancel".to_string()
+    } else {
+        "↑↓ j/k:Navigate  Tab:Switch Panel  a:Add  e:Edit  d:Delete  t:Edit Tags  h:History  y:Copy  p:Pin  x:Expand  s/:Search  ?:Help  q:Quit".to_string()
+    };
+    
+    let status_style = if state.mode == InputMode::Normal {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::Green)
+    };
+    
+    let bar = Paragraph::new(status).style(status_style);
+    f.render_widget(bar, vert[1]);
+}
+
+fn render_collections(f: &mut Frame, state: &mut AppState, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Collections")
+        .border_style(match state.focused_panel {
+            Panel::Collections => Style::default().fg(Color::Yellow),
+            _ => Style::default(),
+        });
+
+    let mut items = vec![ListItem::new(format!("All snippets ({})", state.all_gists.len()))];
+    items.extend(state.collections.iter().map(|c| {
+        ListItem::new(format!("{}{} ({})", "  ".repeat(c.depth() + 1), c.name, c.gist_count))
+    }));
+
+    let list = List::new(items)
+        .block(block)
+        .highlight_style(Style::default().bg(Color::Blue).add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(list, area, &mut state.collection_state);
+}
+
+fn render_history(f: &mut Frame, state: &mut AppState, list_area: Rect, content_area: Rect) {
+    let id = state.current_gist().map(|g| g.id).unwrap_or_default();
+    let list_block = Block::default()
+        .borders(Borders::ALL)
+        .title(format!("History #{}", id))
+        .border_style(Style::default().fg(Color::Yellow));
+    
+    let items: Vec<_> = state
+        .revisions
+        .iter()
+        .enumerate()
+        .map(|(i, r)| {
+            let current = if i == 0 { " (current)" } else { "" };
+            ListItem::new(format!("rev {}  {}{}", r.rev, r.created_at, current))
+        })
+        .collect();
+    
+    let list = List::new(items)
+        .block(list_block)
+        .highlight_style(Style::default().bg(Color::Blue).add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(list, list_area, &mut state.revision_state);
+    
+    let title = match state.current_revision() {
+        Some(r) => format!("Changes in rev {}", r.rev),
+        None => "Changes".to_string(),
+    };
+    let paragraph = Paragraph::new(state.revision_diff())
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .wrap(Wrap { trim: false });
+    f.render_widget(paragraph, content_area);
+}
+
+fn render_gists(f: &mut Frame, state: &mut AppState, list_area: Rect, content_area: Rect) {
     // Render list panel
     let list_block = Block::default()
         .borders(Borders::ALL)
@@ -287,7 +665,17 @@ fn render_main(f: &mut Frame, state: &mut AppState) {
         .filtered_gists
         .iter()
         .map(|g| {
-            let display = format!("#{} {}", g.id, g.tags);
+            let library = g.library.as_ref().map(|l| format!(" ({})", l)).unwrap_or_default();
+            let mut display = match &g.title {
+                Some(title) => format!("#{}{} {} [{}]", g.id, library, title, g.tags),
+                None => format!("#{}{} {}", g.id, library, g.tags),
+            };
+            if state.runs.get(&g.id).is_some_and(|r| r.runnable) {
+                display.insert_str(0, "▶ ");
+            }
+            if state.usage.get(&g.id).is_some_and(|u| u.pinned) {
+                display.insert_str(0, "★ ");
+            }
             ListItem::new(display)
         })
         .collect();
@@ -296,11 +684,52 @@ fn render_main(f: &mut Frame, state: &mut AppState) {
         .block(list_block)
         .highlight_style(Style::default().bg(Color::Blue).add_modifier(Modifier::BOLD));
     
-    f.render_stateful_widget(list, chunks[0], &mut state.list_state);
+    f.render_stateful_widget(list, list_area, &mut state.list_state);
     
     // Render content panel
     let content_text = if let Some(gist) = state.current_gist() {
-        format!("{}\n\n{}", gist.content, format!("Created: {}", gist.created_at))
+        let mut text = String::new();
+        if let Some(description) = &gist.description {
+            text.push_str(&format!("{}\n\n", description));
+        }
+        if gist.is_encrypted() {
+            text.push_str(&format!("(encrypted; open it with `gist view {}`)", gist.id));
+        } else {
+            text.push_str(&gist.content);
+        }
+        text.push_str(&format!("\n\nCreated: {}", gist.created_at));
+        if let Some(updated) = gist.updated_at.as_ref().filter(|u| **u != gist.created_at) {
+            text.push_str(&format!("\nUpdated: {}", updated));
+        }
+        if let Some(source) = gist.source() {
+            text.push_str(&format!("\nSource: {}", source));
+        }
+        if let Some(library) = &gist.library {
+            text.push_str(&format!("\nLibrary: {} (shared; `gist push` sends your edits)", library));
+        }
+        if let Some(run) = state.runs.get(&gist.id) {
+            if let Some(ran_at) = &run.ran_at {
+                let result = match (run.timed_out, run.exit_code) {
+                    (true, _) => "timed out".to_string(),
+                    (false, Some(code)) => format!("exit {}", code),
+                    (false, None) => "killed".to_string(),
+                };
+                text.push_str(&format!("\nLast run: {} ({})", ran_at, result));
+            }
+            match &run.last_success_at {
+                Some(ok) => text.push_str(&format!("\nLast successful run: {}", ok)),
+                None if run.runnable => text.push_str("\nRunnable: never run successfully"),
+                None => {}
+            }
+            if let Some(output) = run.output.as_deref().filter(|o| !o.trim().is_empty()) {
+                let last_lines: Vec<&str> = output.lines().rev().take(5).collect();
+                text.push_str("\nLast output:");
+                for line in last_lines.into_iter().rev() {
+                    text.push_str(&format!("\n  {}", line));
+                }
+            }
+        }
+        text
     } else {
         "(no gists)".to_string()
     };
@@ -308,7 +737,14 @@ fn render_main(f: &mut Frame, state: &mut AppState) {
     let content_block = Block::default()
         .borders(Borders::ALL)
         .title(if let Some(gist) = state.current_gist() {
-            format!("Content (ID: {})", gist.id)
+            let mut title = format!("Content (ID: {})", gist.id);
+            if let Some(t) = &gist.title {
+                title = format!("{} - {}", title, t);
+            }
+            if let Some(lang) = &gist.language {
+                title = format!("{} [{}]", title, lang);
+            }
+            title
         } else {
             "Content".to_string()
         })
@@ -321,27 +757,7 @@ fn render_main(f: &mut Frame, state: &mut AppState) {
         .block(content_block)
         .wrap(Wrap { trim: false });
     
-    f.render_widget(paragraph, chunks[1]);
-    
-    // Render status bar
-    let status = if let Some(msg) = state.get_status() {
-        msg
-    } else if state.mode == InputMode::Searching {
-        format!("/ {}", state.search_query)
-    } else if state.mode == InputMode::TagEditing {
-        format!("Edit Tags: {}", state.edit_buffer)
-    } else {
-        "↑↓ j/k:Navigate  Tab:Switch Panel  a:Add  e:Edit  d:Delete  t:Edit Tags  y:Copy  s/:Search  ?:Help  q:Quit".to_string()
-    };
-    
-    let status_style = if state.mode == InputMode::Normal {
-        Style::default().fg(Color::Yellow)
-    } else {
-        Style::default().fg(Color::Green)
-    };
-    
-    let bar = Paragraph::new(status).style(status_style);
-    f.render_widget(bar, vert[1]);
+    f.render_widget(paragraph, content_area);
 }
 
 fn render_help(f: &mut Frame, state: &mut AppState) {
@@ -360,20 +776,26 @@ fn render_help(f: &mut Frame, state: &mut AppState) {
         "  ↑/↓, j/k     - Move selection up/down",
         "  PgUp/PgDown  - Move by page",
         "  Home/End     - Jump to start/end",
-        "  Tab          - Switch between list and content panels",
+        "  Tab          - Cycle between collection, list and content panels",
+        "  ↑/↓ in the collection panel picks a collection to filter by",
         "",
         "Actions:",
         "  a            - Add new snippet",
         "  e            - Edit selected snippet",
-        "  d            - Delete selected snippet (with confirmation)",
+        "  d            - Move selected snippet to the trash (with confirmation)",
         "  y            - Copy snippet content to clipboard",
+        "  p            - Pin or unpin the selected snippet (pinned ones open at the top)",
+        "  x            - Fill in a template's {{placeholders}} and copy the result",
         "  t            - Edit tags for the selected snippet",
+        "  h            - Browse revisions (Enter restores the selected one)",
         "  r            - Refresh snippet list",
         "",
         "Search:",
         "  s, /         - Start search mode",
         "  Esc          - Exit search/help mode or cancel action",
         "  Enter        - Execute search",
+        "                 words, \"phrases\", prefix*, -word, OR, ( )",
+        "                 tag:rust -tag:old lang:sql created:>2025-01 updated:2025 pinned:true",
         "",
         "UI:",
         "  ?            - Toggle this help screen",
@@ -443,7 +865,9 @@ pub fn run_ui(
                     let _ = sender.send(result.map_err(|e| e.to_string()));
                 }
                 DbOperation::Update(id, content, tags, sender) => {
-                    let result = update_gist(&conn_lock, id, &content, &tags);
+                    let before = get_gist(&conn_lock, id).ok().flatten().map(|g| g.content);
+                    let result = update_gist(&conn_lock, id, &content, &tags)
+                        .and_then(|_| before.map_or(Ok(()), |b| carry_run_approval(&conn_lock, id, &b)));
                     let _ = sender.send(result.map_err(|e| e.to_string()));
                 }
                 DbOperation::Delete(id, sender) => {
@@ -454,6 +878,10 @@ pub fn run_ui(
                     let result = get_gist(&conn_lock, id);
                     let _ = sender.send(result.map_err(|e| e.to_string()));
                 }
+                DbOperation::Restore(id, rev, sender) => {
+                    let result = restore_revision(&conn_lock, id, rev);
+                    let _ = sender.send(result.map_err(|e| e.to_string()));
+                }
             }
         }
     });
@@ -461,6 +889,15 @@ pub fn run_ui(
     // Setup initial state
     let initial_data = gists_storage.clone();
     let mut state = AppState::new(initial_data, config);
+    {
+        let conn_lock = conn_ui.lock().unwrap();
+        state.runs = list_run_info(&conn_lock).unwrap_or_default();
+        state.usage = list_usage(&conn_lock).unwrap_or_default();
+        state.set_collections(
+            list_collections(&conn_lock).unwrap_or_default(),
+            collection_memberships(&conn_lock).unwrap_or_default(),
+        );
+    }
     
     // Set initial status
     state.set_status(format!("Loaded {} gists", state.all_gists.len()));
@@ -478,11 +915,11 @@ pub fn run_ui(
                 OperationResult::Add(id) => {
                     let conn_lock = conn_ui.lock().unwrap();
                     if let Ok(Some(gist)) = get_gist(&conn_lock, id) {
+                        state.set_saved_status(format!("Added gist #{}", id), &gist.content);
                         state.all_gists.push(gist.clone());
                         gists_storage.push(gist);
                         state.reset_filter();
                         state.modified = true;
-                        state.set_status(format!("Added gist #{}", id));
                     }
                 }
                 OperationResult::Update(id) => {
@@ -508,7 +945,7 @@ pub fn run_ui(
                             }
                         }
                         state.modified = true;
-                        state.set_status(format!("Updated gist #{}", id));
+                        state.set_saved_status(format!("Updated gist #{}", id), &gist.content);
                     }
                 }
                 OperationResult::Delete(id, success) => {
@@ -525,7 +962,7 @@ pub fn run_ui(
                         }
                         
                         state.modified = true;
-                        state.set_status(format!("Deleted gist #{}", id));
+                        state.set_status(format!("Moved gist #{} to the trash (gist trash restore {})", id, id));
                     } else {
                         state.set_status(format!("Failed to delete gist #{}", id));
                     }
@@ -557,6 +994,9 @@ pub fn run_ui(
                                 state.mode = InputMode::Searching;
                                 state.search_query.clear();
                             },
+                            KeyCode::Char('a') if state.config.encrypt_all() => {
+                                state.set_status("Every snippet is encrypted; add it with `gist add`".to_string());
+                            },
                             KeyCode::Char('a') => {
                                 // Add new gist
                                 disable_raw_mode()?;
@@ -573,7 +1013,7 @@ pub fn run_ui(
                                 let _ = Command::new(command).args(&args).arg(&tmp).status();
                                 if let Ok(content) = std::fs::read_to_string(&tmp) {
                                     let _ = std::fs::remove_file(&tmp);
-                                    if !content.trim().is_empty() {
+                                    if !content.trim().is_empty() && !state.secrets_block(&content) {
                                         // Add in background
                                         let db_sender = db_tx.clone();
                                         let sender = tx.clone();
@@ -610,6 +1050,7 @@ pub fn run_ui(
                                 }
                                 enable_raw_mode()?;
                             },
+                            KeyCode::Char('e' | 'y' | 'x') if state.encrypted_block() => {},
                             KeyCode::Char('e') => {
                                 if let Some(gist) = state.current_gist().cloned() {
                                     disable_raw_mode()?;
@@ -627,7 +1068,7 @@ pub fn run_ui(
                                     let _ = Command::new(command).args(&args).arg(&tmp).status();
                                     if let Ok(updated) = std::fs::read_to_string(&tmp) {
                                         let _ = std::fs::remove_file(&tmp);
-                                        if !updated.trim().is_empty() && updated != gist.content {
+                                        if !updated.trim().is_empty() && updated != gist.content && !state.secrets_block(&updated) {
                                             // Update in background
                                             let db_sender = db_tx.clone();
                                             let sender = tx.clone();
@@ -698,6 +1139,9 @@ pub fn run_ui(
                                 if let Some(gist) = state.current_gist() {
                                     if let Ok(mut ctx) = ClipboardContext::new() {
                                         if let Ok(_) = ctx.set_contents(gist.content.clone()) {
+                                            let conn_lock = conn_ui.lock().unwrap();
+                                            let _ = record_usage(&conn_lock, gist.id, UsageKind::Copy);
+                                            state.usage = list_usage(&conn_lock).unwrap_or_default();
                                             state.set_status("Copied to clipboard".to_string());
                                         } else {
                                             state.set_status("Failed to copy to clipboard".to_string());
@@ -709,14 +1153,57 @@ pub fn run_ui(
                                     state.set_status("No gist selected".to_string());
                                 }
                             },
+                            KeyCode::Char('x') => {
+                                if state.current_gist().is_none() {
+                                    state.set_status("No gist selected".to_string());
+                                } else if !state.open_expand() {
+                                    state.set_status("No {{placeholders}} in this snippet".to_string());
+                                }
+                            },
+                            KeyCode::Char('h') => {
+                                if let Some(id) = state.selected_id() {
+                                    let revisions = {
+                                        let conn_lock = conn_ui.lock().unwrap();
+                                        list_revisions(&conn_lock, id)
+                                    };
+                                    match revisions {
+                                        Ok(revisions) => state.open_history(revisions),
+                                        Err(e) => state.set_status(format!("Error: {}", e)),
+                                    }
+                                } else {
+                                    state.set_status("No gist selected".to_string());
+                                }
+                            },
+                            KeyCode::Char('p') => {
+                                if let Some(id) = state.selected_id() {
+                                    let pinned = !state.usage.get(&id).is_some_and(|u| u.pinned);
+                                    let conn_lock = conn_ui.lock().unwrap();
+                                    match set_pinned(&conn_lock, id, pinned) {
+                                        Ok(()) => {
+                                            state.usage = list_usage(&conn_lock).unwrap_or_default();
+                                            let verb = if pinned { "Pinned" } else { "Unpinned" };
+                                            state.set_status(format!("{} gist #{}", verb, id));
+                                        }
+                                        Err(e) => state.set_status(format!("Error: {}", e)),
+                                    }
+                                } else {
+                                    state.set_status("No gist selected".to_string());
+                                }
+                            },
                             KeyCode::Char('r') => {
                                 // Reload from database
                                 let conn_lock = conn_ui.lock().unwrap();
-                                let result = list_gists(&conn_lock, usize::MAX, "created_at");
+                                let result = list_gists(&conn_lock, usize::MAX, "frecency");
                                 match result {
                                     Ok(gists) => {
                                         *gists_storage = gists.clone();
                                         state.reload(gists);
+                                        state.runs = list_run_info(&conn_lock).unwrap_or_default();
+                                        state.usage = list_usage(&conn_lock).unwrap_or_default();
+                                        state.set_collections(
+                                            list_collections(&conn_lock).unwrap_or_default(),
+                                            collection_memberships(&conn_lock).unwrap_or_default(),
+                                        );
                                         state.set_status(format!("Reloaded {} gists", gists_storage.len()));
                                     }
                                     Err(e) => {
@@ -724,6 +1211,12 @@ pub fn run_ui(
                                     }
                                 }
                             },
+                            KeyCode::Down | KeyCode::Char('j') if state.focused_panel == Panel::Collections => {
+                                state.move_collection(true);
+                            },
+                            KeyCode::Up | KeyCode::Char('k') if state.focused_panel == Panel::Collections => {
+                                state.move_collection(false);
+                            },
                             KeyCode::Down | KeyCode::Char('j') => {
                                 state.select_next();
                             },
@@ -832,6 +1325,71 @@ pub fn run_ui(
                             _ => {}
                         }
                     },
+                    InputMode::Expanding => {
+                        let last = state.template_fields.len().saturating_sub(1);
+                        match key.code {
+                            KeyCode::Esc => {
+                                state.mode = InputMode::Normal;
+                            },
+                            KeyCode::Tab | KeyCode::Down => {
+                                state.template_focus = (state.template_focus + 1).min(last);
+                            },
+                            KeyCode::BackTab | KeyCode::Up => {
+                                state.template_focus = state.template_focus.saturating_sub(1);
+                            },
+                            KeyCode::Enter if state.template_focus < last => {
+                                state.template_focus += 1;
+                            },
+                            KeyCode::Enter => {
+                                let content = state.current_gist().map(|g| g.content.clone()).unwrap_or_default();
+                                match template::expand(&content, &state.template_values()) {
+                                    Ok(expanded) => {
+                                        let copied = ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(expanded));
+                                        state.mode = InputMode::Normal;
+                                        state.set_status(match copied {
+                                            Ok(_) => "Expanded snippet copied to clipboard".to_string(),
+                                            Err(_) => "Clipboard not available".to_string(),
+                                        });
+                                    }
+                                    Err(missing) => state.set_status(format!("No value for {}", missing.join(", "))),
+                                }
+                            },
+                            KeyCode::Backspace => {
+                                if let Some((_, value)) = state.template_fields.get_mut(state.template_focus) {
+                                    value.pop();
+                                }
+                            },
+                            KeyCode::Char(c) => {
+                                if let Some((_, value)) = state.template_fields.get_mut(state.template_focus) {
+                                    value.push(c);
+                                }
+                            },
+                            _ => {}
+                        }
+                    },
+                    InputMode::History => {
+                        match key.code {
+                            KeyCode::Esc | KeyCode::Char('h') | KeyCode::Char('q') => {
+                                state.mode = InputMode::Normal;
+                            },
+                            KeyCode::Down | KeyCode::Char('j') => {
+                                state.select_revision(true);
+                            },
+                            KeyCode::Up | KeyCode::Char('k') => {
+                                state.select_revision(false);
+                            },
+                            KeyCode::Enter => {
+                                match (state.selected_id(), state.revision_state.selected(), state.current_revision()) {
+                                    (_, Some(0), _) => state.set_status("Already the current revision".to_string()),
+                                    (Some(id), _, Some(r)) => {
+                                        state.mode = InputMode::Confirming(ConfirmAction::Restore(id, r.rev));
+                                    }
+                                    _ => {}
+                                }
+                            },
+                            _ => {}
+                        }
+                    },
                     InputMode::Help => {
                         match key.code {
                             KeyCode::Esc | KeyCode::Char('?') => {
@@ -861,10 +1419,36 @@ pub fn run_ui(
                                         InputMode::Confirming(action) => {
                         match key.code {
                             KeyCode::Esc => {
-                                state.mode = InputMode::Normal;
+                                state.mode = match action {
+                                    ConfirmAction::Restore(..) => InputMode::History,
+                                    _ => InputMode::Normal,
+                                };
                             },
                             KeyCode::Char('y') => {
                                 match action {
+                                    ConfirmAction::Restore(id, rev) => {
+                                        let db_sender = db_tx.clone();
+                                        let sender = tx.clone();
+                                        thread::spawn(move || {
+                                            let (response_tx, response_rx) = mpsc::channel();
+                                            let _ = db_sender.send(DbOperation::Restore(id, rev, response_tx));
+                                            
+                                            match response_rx.recv() {
+                                                Ok(Ok(_)) => {
+                                                    let _ = sender.send(OperationResult::Update(id));
+                                                }
+                                                Ok(Err(e)) => {
+                                                    let _ = sender.send(OperationResult::Error(e));
+                                                }
+                                                Err(_) => {
+                                                    let _ = sender.send(OperationResult::Error(
+                                                        "Failed to communicate with database thread".to_string()
+                                                    ));
+                                                }
+                                            }
+                                        });
+                                        state.mode = InputMode::Normal;
+                                    },
                                     ConfirmAction::Quit => {
                                         // Exit the UI
                                         if state.modified {
@@ -928,6 +1512,7 @@ enum DbOperation {
     Update(i64, String, String, mpsc::Sender<Result<(), String>>),
     Delete(i64, mpsc::Sender<Result<bool, String>>),
     Get(i64, mpsc::Sender<Result<Option<Gist>, String>>),
+    Restore(i64, i64, mpsc::Sender<Result<i64, String>>),
 }
 
 // Operation result types
@@ -1,5 +1,8 @@
+mod common;
+
 use std::process::Command;
 use std::path::PathBuf;
+use common::TestEnv;
 
 fn get_binary_path() -> PathBuf {
     let mut path = std::env::current_exe().unwrap();
@@ -33,3 +36,410 @@ fn test_version() {
 
     assert!(output.status.success());
 }
+
+#[test]
+fn test_data_dir_isolates_libraries() {
+    let env = TestEnv::new();
+    let work = env.home.path().join("work");
+    let file = env.file("note.txt", "work-only snippet\n");
+
+    env.gist_ok(&["--data-dir", work.to_str().unwrap(), "add", "-t", "work", "-f", file.to_str().unwrap()]);
+    assert!(work.join("gists.db").exists());
+    assert!(work.join("config.toml").exists());
+    assert!(!env.config_dir().join("gists.db").exists());
+
+    // The flag is global, so it may follow the subcommand too
+    let listed = env.gist_ok(&["list", "--data-dir", work.to_str().unwrap()]);
+    assert!(listed.contains("work-only snippet"));
+    assert!(!env.gist_ok(&["list"]).contains("work-only snippet"));
+}
+
+#[test]
+fn test_gist_home_env() {
+    let env = TestEnv::new();
+    let personal = env.home.path().join("personal");
+    let other = env.home.path().join("other");
+    let file = env.file("note.txt", "personal snippet\n");
+
+    let out = env.command()
+        .env("GIST_HOME", &personal)
+        .args(["add", "-t", "home", "-f", file.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert!(personal.join("gists.db").exists());
+
+    let listed = env.command().env("GIST_HOME", &personal).arg("list").output().unwrap();
+    assert!(String::from_utf8_lossy(&listed.stdout).contains("personal snippet"));
+
+    // --data-dir beats GIST_HOME
+    let listed = env.command()
+        .env("GIST_HOME", &personal)
+        .args(["--data-dir", other.to_str().unwrap(), "list"])
+        .output()
+        .unwrap();
+    assert!(!String::from_utf8_lossy(&listed.stdout).contains("personal snippet"));
+    assert!(other.join("gists.db").exists());
+}
+
+#[test]
+fn test_add_from_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let env = TestEnv::new();
+    let mut child = env.command()
+        .args(["add", "-", "-t", "piped", "--title", "from pipe"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"echo piped in\n").unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("Saved as gist #1"));
+
+    let listed = env.gist_ok(&["list"]);
+    assert!(listed.contains("echo piped in") && listed.contains("Tags: piped"), "{}", listed);
+}
+
+#[test]
+fn test_add_symbol_and_lines_with_provenance() {
+    let env = TestEnv::new();
+    let source = "use std::fs;\n\n/// Load settings.\npub fn parse_config(path: &str) -> String {\n    fs::read_to_string(path).unwrap()\n}\n\nfn main() {}\n";
+    let file = env.file("lib.rs", source);
+    let path = file.to_str().unwrap();
+
+    env.gist_ok(&["add", "-t", "rust", "-f", path, "--symbol", "parse_config"]);
+    env.gist_ok(&["add", "-t", "rust", "-f", path, "--lines", "1-2"]);
+
+    let view = env.gist_ok(&["view", "1"]);
+    assert!(view.contains("Title: parse_config"), "{}", view);
+    assert!(view.contains("Extracted: parse_config (lines 3-6)"), "{}", view);
+    assert!(view.contains("Original: unchanged"), "{}", view);
+    assert!(!view.contains("fn main"), "{}", view);
+    assert!(env.gist_ok(&["view", "2"]).contains("Extracted: lines 1-2"));
+
+    // Shifting the function down is a move; editing its body is a change
+    std::fs::write(&file, format!("// new header\n{}", source)).unwrap();
+    assert!(env.gist_ok(&["view", "1"]).contains("Original: unchanged, now at lines 4-7"));
+    std::fs::write(&file, source.replace("unwrap()", "expect(\"settings\")")).unwrap();
+    assert!(env.gist_ok(&["view", "1"]).contains("Original: changed since it was saved"));
+
+    let out = env.gist(&["add", "-t", "rust", "-f", path, "--symbol", "missing"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Symbol 'missing' not found"));
+}
+
+#[test]
+fn test_use_expands_template() {
+    let env = TestEnv::new();
+    let file = env.file("q.sql", "SELECT {{columns:*}} FROM {{table}} WHERE id = {{id:1}};\n");
+    env.gist_ok(&["add", "-t", "sql", "-f", file.to_str().unwrap()]);
+
+    let out = env.gist_ok(&["use", "1", "--var", "table=users", "--var", "id=42"]);
+    assert_eq!(out, "SELECT * FROM users WHERE id = 42;\n");
+
+    let target = env.home.path().join("out.sql");
+    env.gist_ok(&["use", "1", "--var", "table=orders", "--defaults", "-o", target.to_str().unwrap()]);
+    assert_eq!(std::fs::read_to_string(&target).unwrap(), "SELECT * FROM orders WHERE id = 1;\n");
+
+    // Without a terminal there is no one to prompt, so a required value is an error
+    let out = env.gist(&["use", "1"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No value for table"));
+}
+
+#[test]
+fn test_collections_filter_list_search_and_export() {
+    let env = TestEnv::new();
+    for (name, content) in [("a.sql", "SELECT a FROM t;\n"), ("b.sql", "SELECT b FROM t;\n"), ("c.sh", "echo SELECT\n")] {
+        let file = env.file(name, content);
+        env.gist_ok(&["add", "-t", "code", "-f", file.to_str().unwrap()]);
+    }
+    env.gist_ok(&["collection", "create", "work/sql"]);
+    env.gist_ok(&["collection", "add", "work/sql", "1", "2"]);
+    env.gist_ok(&["collection", "add", "work", "3"]);
+
+    let tree = env.gist_ok(&["collection", "list"]);
+    assert!(tree.contains("work") && tree.contains("  sql"), "{}", tree);
+
+    let listed = env.gist_ok(&["list", "--collection", "work/sql"]);
+    assert!(listed.contains("Showing 2 gists"), "{}", listed);
+    let found = env.gist_ok(&["search", "SELECT", "--collection", "work/sql"]);
+    assert!(found.contains("Found 2 results"), "{}", found);
+    let shown = env.gist_ok(&["collection", "show", "work"]);
+    assert!(shown.contains("3 gist(s)"), "{}", shown);
+
+    let target = env.home.path().join("sql.jsonl");
+    env.gist_ok(&["export", "--format", "jsonl", "--collection", "work/sql", "-o", target.to_str().unwrap()]);
+    assert_eq!(std::fs::read_to_string(&target).unwrap().lines().count(), 2);
+
+    let out = env.gist(&["list", "--collection", "nope"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("No collection named 'nope'"));
+}
+
+#[test]
+fn test_pin_and_view_counts_order_frecency() {
+    let env = TestEnv::new();
+    for content in ["first\n", "second\n", "third\n"] {
+        let file = env.file("s.txt", content);
+        env.gist_ok(&["add", "-t", "note", "-f", file.to_str().unwrap()]);
+    }
+    env.gist_ok(&["view", "1"]);
+    let viewed = env.gist_ok(&["view", "1"]);
+    assert!(viewed.contains("2 view(s)"), "{}", viewed);
+    env.gist_ok(&["pin", "2"]);
+
+    let listed = env.gist_ok(&["list", "--sort-by", "frecency"]);
+    let order: Vec<usize> = ["ID 2", "ID 1", "ID 3"].iter().map(|id| listed.find(id).unwrap()).collect();
+    assert!(order.windows(2).all(|w| w[0] < w[1]), "{}", listed);
+
+    env.gist_ok(&["unpin", "2"]);
+    let listed = env.gist_ok(&["list", "--sort-by", "used"]);
+    assert!(listed.find("ID 1").unwrap() < listed.find("ID 2").unwrap(), "{}", listed);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_run_requires_runnable_and_records_result() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let env = TestEnv::new();
+    let file = env.file("greet.sh", "read name\necho \"hello $name $1\"\nexit ${2:-0}\n");
+    env.gist_ok(&["add", "-t", "shell", "-f", file.to_str().unwrap()]);
+
+    let out = env.gist(&["run", "1"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not marked runnable"));
+
+    // Metadata-only updates never open the editor (`false` would fail the update)
+    let out = env.command().env("EDITOR", "false").args(["update", "1", "--runnable", "true"]).output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let mut child = env.command()
+        .args(["run", "1", "--", "--loud", "7"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"ada\n").unwrap();
+    let out = child.wait_with_output().unwrap();
+    assert_eq!(out.status.code(), Some(7));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "hello ada --loud\n");
+
+    let view = env.gist_ok(&["view", "1"]);
+    assert!(view.contains("Runnable: yes") && view.contains("(exit 7)"), "{}", view);
+
+    let timeout = env.file("slow.sh", "sleep 10\n");
+    env.gist_ok(&["add", "-t", "shell", "--runnable", "-f", timeout.to_str().unwrap()]);
+    let out = env.gist(&["run", "2", "--timeout", "1"]);
+    assert_eq!(out.status.code(), Some(124));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("timed out"));
+}
+
+#[test]
+fn test_machine_output_and_exit_codes() {
+    let env = TestEnv::new();
+    let file = env.file("q.sql", "SELECT 1;\tdone\n");
+    let added = env.gist_ok(&["--output", "json", "add", "-t", "sql,db", "-f", file.to_str().unwrap()]);
+    let status: serde_json::Value = serde_json::from_str(&added).unwrap();
+    assert_eq!((status["status"].as_str(), status["id"].as_i64()), (Some("ok"), Some(1)));
+
+    let listed: serde_json::Value = serde_json::from_str(&env.gist_ok(&["--output", "json", "list"])).unwrap();
+    assert_eq!(listed[0]["id"], 1);
+    assert_eq!(listed[0]["tags"], serde_json::json!(["sql", "db"]));
+    assert_eq!(listed[0]["language"], "sql");
+    assert_eq!(listed[0]["content"], "SELECT 1;\tdone\n");
+
+    let hits = env.gist_ok(&["--output", "jsonl", "search", "select"]);
+    let hit: serde_json::Value = serde_json::from_str(hits.lines().next().unwrap()).unwrap();
+    assert!(hit["score"].is_number(), "{}", hits);
+
+    let tsv = env.gist_ok(&["--output", "tsv", "list"]);
+    let mut rows = tsv.lines();
+    assert!(rows.next().unwrap().starts_with("id\ttitle\tlanguage\ttags"));
+    let row = rows.next().unwrap();
+    assert!(row.starts_with("1\t\tsql\tsql,db\t") && row.ends_with("SELECT 1;\\tdone\\n"), "{}", row);
+
+    // Failures print an error object on stdout and exit non-zero
+    let out = env.gist(&["--output", "json", "view", "42"]);
+    assert_eq!(out.status.code(), Some(3));
+    let error: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(error["status"], "error");
+    assert_eq!(error["error"]["kind"], "not_found");
+    assert_eq!(error["error"]["message"], "Gist #42 not found");
+
+    let out = env.gist(&["search", "tag:"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Error:"));
+
+    // Plain text has no colour codes
+    let out = env.gist(&["--output", "plain", "list"]);
+    assert!(!String::from_utf8_lossy(&out.stdout).contains('\x1b'));
+
+    // --output-format also works after the command, next to the command's own flags
+    let before: serde_json::Value = serde_json::from_str(&env.gist_ok(&["--output", "json", "list"])).unwrap();
+    let after: serde_json::Value = serde_json::from_str(&env.gist_ok(&["list", "--output-format", "json", "--limit", "5"])).unwrap();
+    assert_eq!(before, after);
+    let target = env.home.path().join("out.json");
+    env.gist_ok(&["export", "--output", target.to_str().unwrap(), "--output-format", "json"]);
+    assert!(target.exists());
+}
+
+#[test]
+fn test_sync_through_git_repository() {
+    let root = tempfile::TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        let out = Command::new("git").args(args).current_dir(root.path()).output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    };
+    git(&["init", "--quiet", "--bare", "shared.git"]);
+    git(&["clone", "--quiet", "shared.git", "a"]);
+    git(&["clone", "--quiet", "shared.git", "b"]);
+    let (a, b) = (TestEnv::new(), TestEnv::new());
+    let sync = |env: &TestEnv, clone: &str| {
+        let out = env.command()
+            .env("GIT_AUTHOR_NAME", "t").env("GIT_AUTHOR_EMAIL", "t@example.com")
+            .env("GIT_COMMITTER_NAME", "t").env("GIT_COMMITTER_EMAIL", "t@example.com")
+            .args(["sync", "--dir", root.path().join(clone).to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        String::from_utf8_lossy(&out.stderr).into_owned()
+    };
+    let contents = |env: &TestEnv| {
+        let listed: serde_json::Value = serde_json::from_str(&env.gist_ok(&["--output", "json", "list"])).unwrap();
+        let mut v: Vec<(String, bool)> = listed
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| (g["content"].as_str().unwrap().to_string(), g["tags"].as_array().unwrap().contains(&"conflict".into())))
+            .collect();
+        v.sort();
+        v
+    };
+
+    let file = a.file("hello.sh", "echo one\n");
+    a.gist_ok(&["add", "-t", "sh", "-f", file.to_str().unwrap()]);
+    sync(&a, "a");
+    sync(&b, "b");
+    assert_eq!(contents(&b), vec![("echo one\n".to_string(), false)]);
+
+    // Both machines edit the snippet before syncing again
+    for (env, word) in [(&a, "from-a"), (&b, "from-b")] {
+        let out = env.command().env("EDITOR", format!("sed -i s/one/{}/", word)).args(["update", "1", "--edit", "-t", "sh"]).output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    }
+    let mut messages = String::new();
+    for (env, clone) in [(&a, "a"), (&b, "b"), (&a, "a"), (&b, "b")] {
+        messages.push_str(&sync(env, clone));
+    }
+    assert_eq!(messages.matches("Conflict:").count(), 1, "{}", messages);
+
+    // One version won everywhere and the other is kept as a tagged copy
+    let merged = contents(&a);
+    assert_eq!(merged, contents(&b));
+    assert_eq!(merged.len(), 2);
+    assert_eq!(merged.iter().filter(|(_, conflict)| *conflict).count(), 1);
+    assert!(merged.iter().any(|(c, _)| c == "echo from-a\n") && merged.iter().any(|(c, _)| c == "echo from-b\n"));
+}
+
+#[test]
+fn test_team_library_pull_push_and_private_priority() {
+    let root = tempfile::TempDir::new().unwrap();
+    let git = |args: &[&str]| {
+        let out = Command::new("git")
+            .args(["-c", "user.name=t", "-c", "user.email=t@example.com"])
+            .args(args)
+            .current_dir(root.path())
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    };
+    git(&["init", "--quiet", "--bare", "team.git"]);
+    git(&["clone", "--quiet", "team.git", "seed"]);
+    std::fs::create_dir_all(root.path().join("seed/sql")).unwrap();
+    std::fs::write(root.path().join("seed/sql/vacuum.sql"), "VACUUM;\n").unwrap();
+    std::fs::write(root.path().join("seed/deploy.sh"), "make deploy\n").unwrap();
+    git(&["-C", "seed", "add", "."]);
+    git(&["-C", "seed", "commit", "--quiet", "-m", "seed"]);
+    git(&["-C", "seed", "push", "--quiet"]);
+    let url = root.path().join("team.git");
+    let run = |env: &TestEnv, args: &[&str], editor: &str| {
+        let out = env.command()
+            .env("GIT_AUTHOR_NAME", "t").env("GIT_AUTHOR_EMAIL", "t@example.com")
+            .env("GIT_COMMITTER_NAME", "t").env("GIT_COMMITTER_EMAIL", "t@example.com")
+            .env("EDITOR", editor)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(out.status.success(), "gist {:?}: {}", args, String::from_utf8_lossy(&out.stderr));
+        String::from_utf8_lossy(&out.stdout).into_owned()
+    };
+    let listed = |env: &TestEnv| -> Vec<(String, String)> {
+        let list: serde_json::Value = serde_json::from_str(&env.gist_ok(&["--output", "json", "list"])).unwrap();
+        let mut rows: Vec<_> = list
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|g| (g["library"].as_str().unwrap_or("").to_string(), g["content"].as_str().unwrap().to_string()))
+            .collect();
+        rows.sort();
+        rows
+    };
+
+    // Your own copy of a team file wins; the rest show up labelled
+    let (alice, bob) = (TestEnv::new(), TestEnv::new());
+    let deploy = alice.file("deploy.sh", "make deploy\n");
+    alice.gist_ok(&["add", "-t", "sh", "-f", deploy.to_str().unwrap()]);
+    let out = run(&alice, &["remote", "add", "team", url.to_str().unwrap()], "true");
+    assert!(out.contains("with 1 snippet(s)"), "{}", out);
+    assert_eq!(listed(&alice), vec![("".into(), "make deploy\n".into()), ("team".into(), "VACUUM;\n".into())]);
+    let hits = alice.gist_ok(&["search", "VACUUM"]);
+    assert!(hits.contains("[team]") && hits.contains("sql/vacuum.sql"), "{}", hits);
+    assert!(alice.gist_ok(&["remote", "list"]).contains("1 snippet(s)"));
+
+    // Share a snippet of your own, and an edit to a team snippet
+    let reindex = alice.file("reindex.sql", "REINDEX;\n");
+    let mine = alice.gist_ok(&["add", "-t", "sql", "--title", "reindex", "-f", reindex.to_str().unwrap()]);
+    let mine = mine.trim().rsplit('#').next().unwrap().to_string();
+    let team_id = serde_json::from_str::<serde_json::Value>(&alice.gist_ok(&["--output", "json", "search", "VACUUM"])).unwrap()[0]["id"].to_string();
+    run(&alice, &["update", &team_id, "--edit", "-t", "sql"], "sed -i s/VACUUM/VACUUM-FULL/");
+    run(&alice, &["push"], "true");
+    run(&alice, &["push", &mine], "true");
+
+    run(&bob, &["remote", "add", "team", url.to_str().unwrap()], "true");
+    assert_eq!(
+        listed(&bob),
+        vec![
+            ("team".into(), "REINDEX;\n".into()),
+            ("team".into(), "VACUUM-FULL;\n".into()),
+            ("team".into(), "make deploy\n".into()),
+        ]
+    );
+
+    // Upstream edits reach alice, except where her own library already has the content
+    let bob_deploy = serde_json::from_str::<serde_json::Value>(&bob.gist_ok(&["--output", "json", "search", "deploy"])).unwrap()[0]["id"].to_string();
+    run(&bob, &["update", &bob_deploy, "--edit", "-t", "sh"], "sed -i s/deploy/release/");
+    run(&bob, &["push"], "true");
+    let out = run(&alice, &["pull"], "true");
+    assert!(out.contains("team: 1 new, 0 updated, 0 removed, 1 already in your library"), "{}", out);
+    assert_eq!(
+        listed(&alice),
+        vec![
+            ("".into(), "REINDEX;\n".into()),
+            ("".into(), "make deploy\n".into()),
+            ("team".into(), "VACUUM-FULL;\n".into()),
+            ("team".into(), "make release\n".into()),
+        ]
+    );
+
+    // Team snippets stay plain text; the rest of the batch is still encrypted
+    let out = alice.command().env("GIST_PASSPHRASE", "pw").args(["encrypt", &team_id, &mine]).output().unwrap();
+    assert_eq!(out.status.code(), Some(2), "{}", String::from_utf8_lossy(&out.stderr));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("stays plain text"));
+    assert!(String::from_utf8_lossy(&out.stdout).contains(&format!("Encrypted gist #{}", mine)));
+    alice.command().env("GIST_PASSPHRASE", "pw").args(["decrypt", &mine]).output().unwrap();
+
+    run(&alice, &["remote", "remove", "team", "--force"], "true");
+    assert_eq!(listed(&alice), vec![("".into(), "REINDEX;\n".into()), ("".into(), "make deploy\n".into())]);
+}
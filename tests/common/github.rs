@@ -0,0 +1,217 @@
+//! A stateful stand-in for the GitHub Gists REST API.
+
+use super::{http_response, read_request, Recorded};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Token the mock accepts.
+pub const TOKEN: &str = "gh-test-token";
+
+#[derive(Default)]
+struct State {
+    /// Gists by id: `{id, description, public, files: {name: content}}`
+    gists: BTreeMap<String, Value>,
+    requests: Vec<Recorded>,
+    next_id: usize,
+}
+
+/// Serves `GET /gists`, `GET /users/{user}/gists`, `GET /gists/{id}`,
+/// `POST /gists` and `PATCH /gists/{id}` from memory. Writes, and listing
+/// your own gists, need `Bearer TOKEN`.
+pub struct MockGitHub {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockGitHub {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = Arc::clone(&state);
+                        thread::spawn(move || handle_connection(stream, addr, &state));
+                    }
+                }
+            })
+        };
+
+        Self { addr, state, stop, handle: Some(handle) }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Add (or replace) a gist owned by the token's user.
+    pub fn seed(&self, id: &str, description: &str, files: &[(&str, &str)]) {
+        let files: Map<String, Value> = files.iter().map(|(n, c)| (n.to_string(), json!(c))).collect();
+        self.state.lock().unwrap().gists.insert(
+            id.to_string(),
+            json!({"id": id, "description": description, "public": false, "files": files}),
+        );
+    }
+
+    /// Current content of one file.
+    pub fn file(&self, id: &str, name: &str) -> Option<String> {
+        let st = self.state.lock().unwrap();
+        st.gists.get(id)?["files"][name].as_str().map(str::to_string)
+    }
+
+    /// Every stored gist, as `{id, description, public, files}`.
+    pub fn gists(&self) -> Vec<Value> {
+        self.state.lock().unwrap().gists.values().cloned().collect()
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Requests with this method.
+    pub fn requests_with(&self, method: &str) -> Vec<Recorded> {
+        self.requests().into_iter().filter(|r| r.method == method).collect()
+    }
+}
+
+impl Drop for MockGitHub {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn api_gist(g: &Value, addr: SocketAddr, with_content: bool) -> Value {
+    let id = g["id"].as_str().unwrap();
+    let files: Map<String, Value> = g["files"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(name, content)| {
+            let mut file = json!({
+                "filename": name,
+                "raw_url": format!("http://{}/raw/{}/{}", addr, id, name),
+                "size": content.as_str().unwrap().len(),
+            });
+            if with_content {
+                file["content"] = content.clone();
+                file["truncated"] = json!(false);
+            }
+            (name.clone(), file)
+        })
+        .collect();
+    json!({
+        "id": id,
+        "description": g["description"],
+        "public": g["public"],
+        "html_url": format!("https://gist.example/{}", id),
+        "files": files,
+    })
+}
+
+fn handle_connection(stream: TcpStream, addr: SocketAddr, state: &Mutex<State>) {
+    let Some(request) = read_request(&stream) else { return };
+    let authed = request.header("authorization") == Some(&format!("Bearer {}", TOKEN));
+    let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    let param = |name: &str, default: usize| -> usize {
+        query
+            .split('&')
+            .find_map(|kv| kv.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let (page, per_page) = (param("page", 1), param("per_page", 30));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let mut st = state.lock().unwrap();
+    st.requests.push(request.clone());
+    let unauthorized = || http_response(401, "application/json", "{\"message\":\"Requires authentication\"}");
+    let not_found = || http_response(404, "application/json", "{\"message\":\"Not Found\"}");
+
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["gists"]) | ("GET", ["users", _, "gists"]) => {
+            if segments[0] == "gists" && !authed {
+                unauthorized()
+            } else {
+                let list: Vec<Value> = st
+                    .gists
+                    .values()
+                    .filter(|g| segments[0] == "gists" || g["public"] == true)
+                    .skip((page - 1) * per_page)
+                    .take(per_page)
+                    .map(|g| api_gist(g, addr, false))
+                    .collect();
+                http_response(200, "application/json", &Value::Array(list).to_string())
+            }
+        }
+        ("GET", ["gists", id]) => match st.gists.get(*id) {
+            Some(g) => http_response(200, "application/json", &api_gist(g, addr, true).to_string()),
+            None => not_found(),
+        },
+        ("POST", ["gists"]) if !authed => unauthorized(),
+        ("POST", ["gists"]) => {
+            st.next_id += 1;
+            let id = format!("new{}", st.next_id);
+            let files: Map<String, Value> = request.body["files"]
+                .as_object()
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, f)| (name, f["content"].clone()))
+                .collect();
+            let g = json!({
+                "id": id,
+                "description": request.body["description"],
+                "public": request.body["public"],
+                "files": files,
+            });
+            let body = api_gist(&g, addr, true).to_string();
+            st.gists.insert(id, g);
+            http_response(201, "application/json", &body)
+        }
+        ("PATCH", ["gists", _]) if !authed => unauthorized(),
+        ("PATCH", ["gists", id]) => match st.gists.get_mut(*id) {
+            Some(g) => {
+                if let Some(description) = request.body.get("description") {
+                    g["description"] = description.clone();
+                }
+                if let Some(files) = request.body["files"].as_object() {
+                    for (name, f) in files {
+                        if f.is_null() {
+                            g["files"].as_object_mut().unwrap().remove(name);
+                        } else {
+                            g["files"][name] = f["content"].clone();
+                        }
+                    }
+                }
+                http_response(200, "application/json", &api_gist(g, addr, true).to_string())
+            }
+            None => not_found(),
+        },
+        _ => not_found(),
+    };
+    drop(st);
+
+    let mut stream = stream;
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
@@ -0,0 +1,556 @@
+//! Shared helpers for integration tests: an isolated `gist` environment and
+//! a deterministic stand-in for OpenAI-, Anthropic- and Ollama-style LLM APIs.
+#![allow(dead_code)]
+
+pub mod github;
+
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+pub fn get_binary_path() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.join("gist")
+}
+
+// ── Isolated environment ────────────────────────────────────────────────────
+
+/// A throwaway HOME so tests never touch the real config, database or sessions.
+pub struct TestEnv {
+    pub home: tempfile::TempDir,
+}
+
+impl TestEnv {
+    pub fn new() -> Self {
+        Self { home: tempfile::TempDir::new().unwrap() }
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.home.path().join(".config").join("gist")
+    }
+
+    /// Write `config.toml` verbatim.
+    pub fn write_config(&self, toml: &str) {
+        std::fs::create_dir_all(self.config_dir()).unwrap();
+        std::fs::write(self.config_dir().join("config.toml"), toml).unwrap();
+    }
+
+    /// Config pointing the legacy OpenAI-compatible settings at `mock`.
+    pub fn use_openai_mock(&self, mock: &MockLlm) {
+        self.write_config(&format!(
+            "editor = \"true\"\ndefault_tags = [\"snippet\"]\ntheme = \"Dark\"\n\
+             auto_generate_tags = true\ntag_api_key = \"test-key\"\nai_model = \"mock-model\"\n\
+             ai_base_url = \"{}\"\n",
+            mock.url()
+        ));
+    }
+
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(get_binary_path());
+        cmd.env("HOME", self.home.path())
+            .env_remove("XDG_CONFIG_HOME")
+            .env_remove("GIST_HOME")
+            .env_remove("GITHUB_TOKEN")
+            .env_remove("ANTHROPIC_API_KEY")
+            .env_remove("GIST_PASSPHRASE")
+            .env("EDITOR", "true")
+            .env("NO_COLOR", "1");
+        cmd
+    }
+
+    /// Run `gist` with `args` and fail the test with its output if it can't start.
+    pub fn gist(&self, args: &[&str]) -> Output {
+        self.command().args(args).output().expect("failed to execute gist")
+    }
+
+    /// Run `gist` and return stdout, asserting success.
+    pub fn gist_ok(&self, args: &[&str]) -> String {
+        let out = self.gist(args);
+        assert!(
+            out.status.success(),
+            "gist {:?} failed\nstdout: {}\nstderr: {}",
+            args,
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        String::from_utf8(out.stdout).unwrap()
+    }
+
+    /// Write `content` to `name` inside the temp HOME and return its path.
+    pub fn file(&self, name: &str, content: &str) -> PathBuf {
+        let path = self.home.path().join(name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+/// A code step as CodeWalk expects it: a JSON envelope, then the explanation.
+pub fn walk_step(file: &str, explanation: &str, next_file: Option<&str>) -> String {
+    let meta = json!({
+        "file": file,
+        "line_start": if file == "OVERVIEW" { 0 } else { 1 },
+        "line_end": if file == "OVERVIEW" { 0 } else { 3 },
+        "deep_dives": [],
+        "next_file": next_file,
+    });
+    format!("```json\n{}\n```\n\n{}", meta, explanation)
+}
+
+/// A tiny repository for CodeWalk runs.
+pub fn sample_repo(dir: &Path) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("Cargo.toml"), "[package]\nname = \"demo\"\nversion = \"0.1.0\"\n").unwrap();
+    std::fs::write(dir.join("src/main.rs"), "mod util;\n\nfn main() {\n    util::greet();\n}\n").unwrap();
+    std::fs::write(dir.join("src/util.rs"), "pub fn greet() {\n    println!(\"hi\");\n}\n").unwrap();
+}
+
+// ── Mock LLM server ─────────────────────────────────────────────────────────
+
+/// What the mock answers with. The wire format follows the request: OpenAI
+/// (`/chat/completions`), Anthropic (`/v1/messages`) or Ollama (`/api/chat`),
+/// as an SSE or NDJSON stream when the request sets `"stream": true`.
+#[derive(Debug, Clone)]
+pub enum Reply {
+    Text(String),
+    ToolCall { name: String, arguments: Value },
+    Status(u16, String),
+}
+
+impl Reply {
+    pub fn text(text: impl Into<String>) -> Self {
+        Reply::Text(text.into())
+    }
+
+    pub fn tool(name: &str, arguments: Value) -> Self {
+        Reply::ToolCall { name: name.to_string(), arguments }
+    }
+}
+
+/// Reply sent to chat requests that no script matches.
+pub const DEFAULT_REPLY: &str = "OK";
+
+/// Dimensions of the mock's embeddings: one per letter a-z.
+pub const EMBEDDING_DIMS: usize = 26;
+
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+}
+
+impl Recorded {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+struct Script {
+    needle: Option<String>,
+    reply: Reply,
+}
+
+#[derive(Default)]
+struct State {
+    scripts: Vec<Script>,
+    requests: Vec<Recorded>,
+    calls: usize,
+}
+
+/// A local HTTP server on a random port that replays scripted replies.
+///
+/// Chat requests take the first unused script whose needle occurs in the raw
+/// JSON body (scripts without a needle match anything); each script is used
+/// once. Unscripted chat requests get `DEFAULT_REPLY`. Embeddings are always
+/// letter counts of each input, so similar texts get similar vectors.
+pub struct MockLlm {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockLlm {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = Arc::clone(&state);
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = Arc::clone(&state);
+                        thread::spawn(move || handle_connection(stream, &state));
+                    }
+                }
+            })
+        };
+
+        Self { addr, state, stop, handle: Some(handle) }
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Queue a reply for the next chat request.
+    pub fn reply(&self, reply: Reply) -> &Self {
+        self.state.lock().unwrap().scripts.push(Script { needle: None, reply });
+        self
+    }
+
+    /// Queue a reply for the next chat request whose body contains `needle`.
+    pub fn reply_when(&self, needle: &str, reply: Reply) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .scripts
+            .push(Script { needle: Some(needle.to_string()), reply });
+        self
+    }
+
+    /// Every request received so far, in arrival order.
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Requests whose path ends with `suffix`.
+    pub fn requests_to(&self, suffix: &str) -> Vec<Recorded> {
+        self.requests().into_iter().filter(|r| r.path.ends_with(suffix)).collect()
+    }
+
+    /// Scripts not yet consumed.
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().scripts.len()
+    }
+}
+
+impl Drop for MockLlm {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the accept loop so it sees the stop flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) {
+    let Some(request) = read_request(&stream) else { return };
+    let raw_body = request.body.to_string();
+    let path = request.path.clone();
+    let streaming = request.body["stream"] == true;
+
+    let (reply, call) = {
+        let mut st = state.lock().unwrap();
+        st.requests.push(request.clone());
+        st.calls += 1;
+        let call = st.calls;
+        let reply = if is_chat(&path) {
+            let idx = st.scripts.iter().position(|s| match &s.needle {
+                Some(needle) => raw_body.contains(needle.as_str()),
+                None => true,
+            });
+            idx.map(|i| st.scripts.remove(i).reply)
+        } else {
+            None
+        };
+        (reply.unwrap_or_else(|| Reply::Text(DEFAULT_REPLY.to_string())), call)
+    };
+
+    let model = request.body["model"].as_str().unwrap_or("mock").to_string();
+    let usage = (raw_body.len() / 4) as u64;
+    let response = if let Reply::Status(code, body) = &reply {
+        http_response(*code, "application/json", body)
+    } else if path.ends_with("/embeddings") {
+        let inputs = inputs(&request.body["input"]);
+        let data: Vec<Value> = inputs
+            .iter()
+            .enumerate()
+            .map(|(i, t)| json!({"object": "embedding", "index": i, "embedding": letter_vector(t)}))
+            .collect();
+        http_response(200, "application/json", &json!({"object": "list", "data": data, "model": model}).to_string())
+    } else if path.ends_with("/api/embed") {
+        let vectors: Vec<Vec<f32>> = inputs(&request.body["input"]).iter().map(|t| letter_vector(t)).collect();
+        http_response(200, "application/json", &json!({"model": model, "embeddings": vectors}).to_string())
+    } else if path.ends_with("/chat/completions") {
+        if streaming {
+            http_response(200, "text/event-stream", &openai_stream(&reply, call))
+        } else {
+            http_response(200, "application/json", &openai_json(&reply, &model, call, usage).to_string())
+        }
+    } else if path.ends_with("/v1/messages") {
+        if streaming {
+            http_response(200, "text/event-stream", &anthropic_stream(&reply, &model, call))
+        } else {
+            http_response(200, "application/json", &anthropic_json(&reply, &model, call, usage).to_string())
+        }
+    } else if path.ends_with("/api/chat") {
+        if streaming {
+            http_response(200, "application/x-ndjson", &ollama_stream(&reply, &model))
+        } else {
+            http_response(200, "application/json", &ollama_json(&reply, &model, usage).to_string())
+        }
+    } else {
+        http_response(404, "application/json", "{\"error\":\"not found\"}")
+    };
+
+    let mut stream = stream;
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.flush();
+}
+
+fn is_chat(path: &str) -> bool {
+    path.ends_with("/chat/completions") || path.ends_with("/v1/messages") || path.ends_with("/api/chat")
+}
+
+fn read_request(stream: &TcpStream) -> Option<Recorded> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    let mut content_length = 0usize;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            break;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = header.split_once(':') {
+            let (k, v) = (k.trim().to_string(), v.trim().to_string());
+            if k.eq_ignore_ascii_case("content-length") {
+                content_length = v.parse().unwrap_or(0);
+            }
+            headers.push((k, v));
+        }
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).ok()?;
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    Some(Recorded { method, path, headers, body })
+}
+
+fn http_response(status: u16, content_type: &str, body: &str) -> String {
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        _ if status >= 500 => "Server Error",
+        _ => "Error",
+    };
+    format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+fn inputs(v: &Value) -> Vec<String> {
+    match v {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(items) => items.iter().map(|i| i.as_str().unwrap_or_default().to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Letter counts a-z: texts sharing words land close together.
+pub fn letter_vector(text: &str) -> Vec<f32> {
+    let mut v = vec![0.0f32; EMBEDDING_DIMS];
+    for c in text.to_lowercase().chars() {
+        if c.is_ascii_lowercase() {
+            v[(c as u8 - b'a') as usize] += 1.0;
+        }
+    }
+    v
+}
+
+/// Split text into small pieces so streams arrive in several deltas.
+fn pieces(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars.chunks(12).map(|c| c.iter().collect()).collect()
+}
+
+fn sse(event: Option<&str>, data: &Value) -> String {
+    match event {
+        Some(name) => format!("event: {}\ndata: {}\n\n", name, data),
+        None => format!("data: {}\n\n", data),
+    }
+}
+
+fn openai_json(reply: &Reply, model: &str, call: usize, usage: u64) -> Value {
+    let (message, finish) = match reply {
+        Reply::ToolCall { name, arguments } => (
+            json!({"role": "assistant", "content": null, "tool_calls": [{
+                "id": format!("call_{}", call),
+                "type": "function",
+                "function": {"name": name, "arguments": arguments.to_string()},
+            }]}),
+            "tool_calls",
+        ),
+        Reply::Text(text) => (json!({"role": "assistant", "content": text}), "stop"),
+        Reply::Status(..) => unreachable!(),
+    };
+    json!({
+        "id": format!("chatcmpl-{}", call),
+        "object": "chat.completion",
+        "model": model,
+        "choices": [{"index": 0, "message": message, "finish_reason": finish}],
+        "usage": {"prompt_tokens": usage, "completion_tokens": 10, "total_tokens": usage + 10},
+    })
+}
+
+fn openai_stream(reply: &Reply, call: usize) -> String {
+    let mut out = String::from(": mock stream\n\n");
+    match reply {
+        Reply::Text(text) => {
+            out.push_str(&sse(None, &json!({"choices": [{"index": 0, "delta": {"role": "assistant"}}]})));
+            for piece in pieces(text) {
+                out.push_str(&sse(None, &json!({"choices": [{"index": 0, "delta": {"content": piece}}]})));
+            }
+        }
+        Reply::ToolCall { name, arguments } => {
+            let delta = json!({"tool_calls": [{
+                "index": 0,
+                "id": format!("call_{}", call),
+                "type": "function",
+                "function": {"name": name, "arguments": arguments.to_string()},
+            }]});
+            out.push_str(&sse(None, &json!({"choices": [{"index": 0, "delta": delta}]})));
+        }
+        Reply::Status(..) => unreachable!(),
+    }
+    out.push_str(&sse(None, &json!({"choices": [{"index": 0, "delta": {}, "finish_reason": "stop"}]})));
+    out.push_str("data: [DONE]\n\n");
+    out
+}
+
+fn anthropic_json(reply: &Reply, model: &str, call: usize, usage: u64) -> Value {
+    let (content, stop) = match reply {
+        Reply::Text(text) => (json!([{"type": "text", "text": text}]), "end_turn"),
+        Reply::ToolCall { name, arguments } => (
+            json!([{"type": "tool_use", "id": format!("toolu_{}", call), "name": name, "input": arguments}]),
+            "tool_use",
+        ),
+        Reply::Status(..) => unreachable!(),
+    };
+    json!({
+        "id": format!("msg_{}", call),
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop,
+        "usage": {"input_tokens": usage, "output_tokens": 10},
+    })
+}
+
+fn anthropic_stream(reply: &Reply, model: &str, call: usize) -> String {
+    let mut out = sse(
+        Some("message_start"),
+        &json!({"type": "message_start", "message": {"id": format!("msg_{}", call), "type": "message",
+            "role": "assistant", "model": model, "content": [], "usage": {"input_tokens": 1, "output_tokens": 0}}}),
+    );
+    out.push_str(&sse(Some("ping"), &json!({"type": "ping"})));
+    match reply {
+        Reply::Text(text) => {
+            out.push_str(&sse(
+                Some("content_block_start"),
+                &json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            ));
+            for piece in pieces(text) {
+                out.push_str(&sse(
+                    Some("content_block_delta"),
+                    &json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": piece}}),
+                ));
+            }
+        }
+        Reply::ToolCall { name, arguments } => {
+            out.push_str(&sse(
+                Some("content_block_start"),
+                &json!({"type": "content_block_start", "index": 0, "content_block":
+                    {"type": "tool_use", "id": format!("toolu_{}", call), "name": name, "input": {}}}),
+            ));
+            out.push_str(&sse(
+                Some("content_block_delta"),
+                &json!({"type": "content_block_delta", "index": 0,
+                    "delta": {"type": "input_json_delta", "partial_json": arguments.to_string()}}),
+            ));
+        }
+        Reply::Status(..) => unreachable!(),
+    }
+    out.push_str(&sse(Some("content_block_stop"), &json!({"type": "content_block_stop", "index": 0})));
+    out.push_str(&sse(
+        Some("message_delta"),
+        &json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 10}}),
+    ));
+    out.push_str(&sse(Some("message_stop"), &json!({"type": "message_stop"})));
+    out
+}
+
+fn ollama_json(reply: &Reply, model: &str, usage: u64) -> Value {
+    let message = match reply {
+        Reply::Text(text) => json!({"role": "assistant", "content": text}),
+        Reply::ToolCall { name, arguments } => json!({"role": "assistant", "content": "",
+            "tool_calls": [{"function": {"name": name, "arguments": arguments}}]}),
+        Reply::Status(..) => unreachable!(),
+    };
+    json!({"model": model, "message": message, "done": true, "prompt_eval_count": usage, "eval_count": 10})
+}
+
+fn ollama_stream(reply: &Reply, model: &str) -> String {
+    let mut out = String::new();
+    match reply {
+        Reply::Text(text) => {
+            for piece in pieces(text) {
+                let chunk = json!({"model": model, "message": {"role": "assistant", "content": piece}, "done": false});
+                out.push_str(&chunk.to_string());
+                out.push('\n');
+            }
+        }
+        Reply::ToolCall { .. } => {
+            out.push_str(&json!({"model": model, "message": ollama_json(reply, model, 0)["message"], "done": false}).to_string());
+            out.push('\n');
+        }
+        Reply::Status(..) => unreachable!(),
+    }
+    out.push_str(&json!({"model": model, "message": {"role": "assistant", "content": ""}, "done": true}).to_string());
+    out.push('\n');
+    out
+}
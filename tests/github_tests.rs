@@ -0,0 +1,146 @@
+//! `gist github pull/push` against the mock Gists API.
+
+mod common;
+
+use common::github::{MockGitHub, TOKEN};
+use common::TestEnv;
+
+/// Config for `mock`. The editor is left to $EDITOR so tests can script edits.
+fn github_env(mock: &MockGitHub) -> TestEnv {
+    let env = TestEnv::new();
+    env.write_config(&format!(
+        "editor = \"\"\ndefault_tags = [\"snippet\"]\ntheme = \"Dark\"\nauto_generate_tags = false\n\n\
+         [github]\napi_url = \"{}\"\ntoken = \"{}\"\n",
+        mock.url(),
+        TOKEN
+    ));
+    env
+}
+
+/// An $EDITOR that appends `line` to the file it is given.
+fn appending_editor(env: &TestEnv, line: &str) -> String {
+    let script = env.file("append.sh", &format!("#!/bin/sh\necho '{}' >> \"$1\"\n", line));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    script.to_string_lossy().into_owned()
+}
+
+#[test]
+fn pull_maps_files_to_snippets_without_duplicates() {
+    let mock = MockGitHub::start();
+    mock.seed("aaa", "Shell helpers", &[("backup.sh", "tar czf backup.tgz ~/docs\n"), ("notes.md", "# Notes\n")]);
+    mock.seed("bbb", "", &[("retry.py", "import time\n")]);
+    let env = github_env(&mock);
+
+    let out = env.gist_ok(&["github", "pull"]);
+    assert!(out.contains("3 new, 0 updated, 0 unchanged"), "{}", out);
+    assert!(mock.requests_with("GET").iter().all(|r| r.header("authorization") == Some("Bearer gh-test-token")));
+
+    let list = env.gist_ok(&["list"]);
+    assert!(list.contains("backup.sh") && list.contains("notes.md") && list.contains("retry.py"), "{}", list);
+    assert!(list.contains("github, bash"), "{}", list);
+
+    let out = env.gist_ok(&["github", "pull"]);
+    assert!(out.contains("0 new, 0 updated, 3 unchanged"), "{}", out);
+
+    mock.seed("bbb", "Retry", &[("retry.py", "import time, random\n")]);
+    let out = env.gist_ok(&["github", "pull", "bbb"]);
+    assert!(out.contains("0 new, 1 updated, 0 unchanged"), "{}", out);
+    let view = env.gist_ok(&["view", "3"]);
+    assert!(view.contains("random"), "{}", view);
+    assert!(view.contains("Description: Retry"), "{}", view);
+}
+
+#[test]
+fn push_creates_then_updates_the_same_gist() {
+    let mock = MockGitHub::start();
+    let env = github_env(&mock);
+    let file = env.file("deploy.sh", "echo deploy\n");
+    env.gist_ok(&["add", "-t", "ops", "-f", file.to_str().unwrap(), "--title", "deploy"]);
+
+    let out = env.gist_ok(&["github", "push", "1", "--public"]);
+    assert!(out.contains("1 new"), "{}", out);
+    let gists = mock.gists();
+    assert_eq!(gists.len(), 1);
+    assert_eq!(gists[0]["public"], true);
+    assert_eq!(mock.file("new1", "deploy.sh").as_deref(), Some("echo deploy\n"));
+
+    // Nothing changed locally: a bare push has nothing to send
+    let out = env.gist_ok(&["github", "push"]);
+    assert!(out.contains("0 new, 0 updated, 1 unchanged"), "{}", out);
+
+    let editor = appending_editor(&env, "echo done");
+    let out = env.command().env("EDITOR", &editor).args(["update", "1", "--edit", "-t", "ops"]).output().unwrap();
+    assert!(out.status.success());
+
+    let out = env.gist_ok(&["github", "push"]);
+    assert!(out.contains("0 new, 1 updated"), "{}", out);
+    assert_eq!(mock.gists().len(), 1);
+    assert_eq!(mock.file("new1", "deploy.sh").as_deref(), Some("echo deploy\necho done\n"));
+    assert_eq!(mock.requests_with("PATCH").len(), 1);
+}
+
+#[test]
+fn push_refuses_to_overwrite_remote_changes() {
+    let mock = MockGitHub::start();
+    mock.seed("ccc", "", &[("a.txt", "one\n")]);
+    let env = github_env(&mock);
+    env.gist_ok(&["github", "pull"]);
+
+    let editor = appending_editor(&env, "local");
+    env.command().env("EDITOR", &editor).args(["update", "1", "--edit", "-t", "x"]).output().unwrap();
+    mock.seed("ccc", "", &[("a.txt", "one\nremote\n")]);
+
+    let out = env.gist(&["github", "push"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("changed on GitHub"));
+    assert_eq!(mock.file("ccc", "a.txt").as_deref(), Some("one\nremote\n"));
+
+    env.gist_ok(&["github", "push", "--force"]);
+    assert_eq!(mock.file("ccc", "a.txt").as_deref(), Some("one\nlocal\n"));
+}
+
+#[test]
+fn encrypted_snippets_are_not_pushed_and_pulls_stay_sealed() {
+    let mock = MockGitHub::start();
+    let env = github_env(&mock);
+    let with_passphrase = |args: &[&str]| env.command().env("GIST_PASSPHRASE", "s3cret").args(args).output().unwrap();
+    let file = env.file("deploy.sh", "echo deploy\n");
+    env.gist_ok(&["add", "-t", "ops", "-f", file.to_str().unwrap(), "--title", "deploy"]);
+    env.gist_ok(&["github", "push", "1"]);
+    assert!(with_passphrase(&["encrypt", "1"]).status.success());
+
+    // Neither a bare push nor a named one uploads the sealed text
+    for args in [&["github", "push"][..], &["github", "push", "1"]] {
+        let out = env.gist(args);
+        assert!(out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("#1: encrypted"), "{}", String::from_utf8_lossy(&out.stderr));
+        assert!(String::from_utf8_lossy(&out.stdout).contains("0 new, 0 updated"));
+    }
+    assert_eq!(mock.file("new1", "deploy.sh").as_deref(), Some("echo deploy\n"));
+    assert_eq!(mock.requests_with("PATCH").len(), 0);
+
+    // With encrypt-all, pulled changes and new files are sealed
+    env.gist_ok(&["config", "--encrypt-all", "true"]);
+    mock.seed("new1", "", &[("deploy.sh", "echo deploy --prod\n"), ("notes.md", "# Notes\n")]);
+    let out = with_passphrase(&["github", "pull", "new1"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("1 new, 1 updated"), "{}", String::from_utf8_lossy(&out.stdout));
+    let records: serde_json::Value = serde_json::from_str(&env.gist_ok(&["--output", "json", "list"])).unwrap();
+    assert!(records.as_array().unwrap().iter().all(|g| g["encrypted"] == true), "{}", records);
+    assert!(String::from_utf8_lossy(&with_passphrase(&["view", "1"]).stdout).contains("--prod"));
+}
+
+#[test]
+fn push_without_token_is_an_error() {
+    let mock = MockGitHub::start();
+    let env = TestEnv::new();
+    env.write_config(&format!(
+        "editor = \"true\"\ndefault_tags = []\ntheme = \"Dark\"\nauto_generate_tags = false\n\n[github]\napi_url = \"{}\"\n",
+        mock.url()
+    ));
+    let out = env.gist(&["github", "push", "1"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("GITHUB_TOKEN"));
+    assert!(mock.requests().is_empty());
+}